@@ -213,6 +213,12 @@ pub fn bind(_attr: TokenStream, input: TokenStream) -> TokenStream {
             })
         },
     ));
+    let instance_binding = getter.clone().map(|getter| getter.map_or_else(
+        || quote! { false },
+        |Function { name, .. }| quote! {
+            vm::bind::instance::<_, W>(&vm::Bind(#self_ty::#name, std::marker::PhantomData))
+        },
+    ));
     let get_context = getter.clone().flatten().map(|getter| {
         let receivers = getter.receivers.iter();
         quote! { #(&'r mut #receivers,)* }
@@ -261,7 +267,7 @@ pub fn bind(_attr: TokenStream, input: TokenStream) -> TokenStream {
 
                 #({
                     let symbol = Symbol::intern(stringify!(#member).as_bytes());
-                    let item = gml::Item::Member(#get_binding, #set_binding);
+                    let item = gml::Item::Member(#get_binding, #set_binding, #instance_binding);
                     items.insert(symbol, item);
                 })*
             }
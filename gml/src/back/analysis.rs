@@ -40,6 +40,21 @@ impl ControlFlow {
     }
 }
 
+/// Find the blocks that can be reached from the entry block.
+pub fn reachable(program: &ssa::Function) -> BitVec {
+    let mut reachable = BitVec::new();
+    let mut work = vec![ssa::ENTRY];
+    reachable.set(ssa::ENTRY.index());
+    while let Some(block) = work.pop() {
+        for &succ in program.successors(block) {
+            if !reachable.set(succ.index()) {
+                work.push(succ);
+            }
+        }
+    }
+    reachable
+}
+
 /// Live value analysis.
 ///
 /// A value is live from the point of its definition until its last use. `Liveness` tracks values
@@ -1,5 +1,5 @@
-use std::{i8, u8, u16, u32, slice};
-use std::collections::{hash_map::Entry, HashMap, VecDeque};
+use std::{i8, u32, slice};
+use std::collections::{hash_map::Entry, HashMap, HashSet, VecDeque};
 
 use crate::bit_vec::BitVec;
 use crate::handle_map::{Handle, HandleMap};
@@ -55,7 +55,7 @@ impl<'p> Codegen<'p> {
 
         self.edge_block = program.blocks.len();
 
-        self.emit_blocks(program, ssa::ENTRY);
+        self.emit_blocks(program, &liveness, ssa::ENTRY);
         self.fixup_jumps();
 
         self.function.params = param_count as u32;
@@ -64,10 +64,23 @@ impl<'p> Codegen<'p> {
         (self.function, self.locations)
     }
 
-    fn emit_blocks(&mut self, program: &ssa::Function, block: ssa::Label) {
+    fn emit_blocks(&mut self, program: &ssa::Function, liveness: &Liveness, block: ssa::Label) {
         self.visited.set(block.index());
         self.block_offsets.insert(block, self.function.instructions.len());
 
+        // Call arguments that are used again afterwards must be copied rather than moved.
+        let mut live_after_calls = HashMap::new();
+        let mut live = liveness.out[block].clone();
+        for &value in program.blocks[block].instructions.iter().rev() {
+            if let ssa::Instruction::Call { .. } = program.values[value] {
+                live_after_calls.insert(value, live.clone());
+            }
+            for def in program.defs(value) {
+                live.remove(&def);
+            }
+            live.extend(program.uses(value));
+        }
+
         let mut last_location = u32::MAX;
 
         for &value in &program.blocks[block].instructions {
@@ -83,15 +96,15 @@ impl<'p> Codegen<'p> {
 
             // TODO: move this logic to live range splitting
             if let Unary { op: ssa::Opcode::Return, arg } = program.values[value] {
-                self.emit_phis(slice::from_ref(&program.return_def), slice::from_ref(&arg));
+                let return_def = slice::from_ref(&program.return_def);
+                self.emit_phis(return_def, slice::from_ref(&arg), &HashSet::new());
 
-                let inst = inst(code::Op::Ret).encode();
-                self.function.instructions.push(inst);
+                inst(code::Op::Ret).encode(&mut self.function.instructions);
 
                 continue;
             }
             if let Call { op, symbol: a, ref args, ref parameters } = program.values[value] {
-                self.emit_phis(parameters, args);
+                self.emit_phis(parameters, args, &live_after_calls[&value]);
 
                 let op = code::Op::from(op);
                 let a = match self.prototypes.get(&a) {
@@ -100,8 +113,7 @@ impl<'p> Codegen<'p> {
                 };
                 let b = self.registers[parameters[0]];
                 let c = args.len();
-                let inst = inst(op).index(a).index(b).index(c).encode();
-                self.function.instructions.push(inst);
+                inst(op).index(a).index(b).index(c).encode(&mut self.function.instructions);
 
                 // Collect call defs into a fixed-size array, initialized with dummy values,
                 // to avoid allocating a `Vec` or pulling in a "small vector" dependency.
@@ -111,13 +123,13 @@ impl<'p> Codegen<'p> {
                     results[len] = result;
                     len += 1;
                 }
-                self.emit_phis(&results[..len], &parameters[..len]);
+                self.emit_phis(&results[..len], &parameters[..len], &HashSet::new());
                 continue;
             }
 
             // TODO: solve the block scheduling problem more explicitly
             if let Jump { op: ssa::Opcode::Jump, target, ref args } = program.values[value] {
-                self.emit_edge(program, target, args);
+                self.emit_edge(program, liveness, target, args);
                 continue;
             }
             if let Branch {
@@ -134,18 +146,18 @@ impl<'p> Codegen<'p> {
                 self.jump_offsets.insert(self.function.instructions.len(), edge_block);
 
                 let a = args[0];
-                let inst = inst(code::Op::BranchFalse).index(self.registers[a]).encode();
-                self.function.instructions.push(inst);
+                let cond = self.registers[a];
+                inst(code::Op::BranchFalse).index(cond).encode(&mut self.function.instructions);
 
                 let true_start = 1;
                 let true_end = true_start + true_args as usize;
-                self.emit_edge(program, true_block, &args[true_start..true_end]);
+                self.emit_edge(program, liveness, true_block, &args[true_start..true_end]);
 
                 self.block_offsets.insert(edge_block, self.function.instructions.len());
 
                 let false_start = true_end;
                 let false_end = false_start + false_args as usize;
-                self.emit_edge(program, false_block, &args[false_start..false_end]);
+                self.emit_edge(program, liveness, false_block, &args[false_start..false_end]);
                 continue;
             }
 
@@ -173,26 +185,28 @@ impl<'p> Codegen<'p> {
                 _ => {}
             }
 
-            self.function.instructions.push(inst.encode());
+            inst.encode(&mut self.function.instructions);
         }
     }
 
     /// Fall through or jump to the unvisited CFG nodes starting with `target`.
-    fn emit_edge(&mut self, program: &ssa::Function, target: ssa::Label, arguments: &[ssa::Value]) {
+    fn emit_edge(
+        &mut self, program: &ssa::Function, liveness: &Liveness,
+        target: ssa::Label, arguments: &[ssa::Value]
+    ) {
         // TODO: move this logic to live range splitting
         let parameters = &program.blocks[target].parameters;
-        self.emit_phis(parameters, arguments);
+        self.emit_phis(parameters, arguments, &liveness.in_[target]);
 
         if self.visited.get(target.index()) {
             self.jump_offsets.insert(self.function.instructions.len(), target);
 
-            let inst = inst(code::Op::Jump).encode();
-            self.function.instructions.push(inst);
+            inst(code::Op::Jump).encode(&mut self.function.instructions);
 
             return;
         }
 
-        self.emit_blocks(program, target);
+        self.emit_blocks(program, liveness, target);
     }
 
     /// Move the values in `arguments` into `parameters`.
@@ -216,8 +230,13 @@ impl<'p> Codegen<'p> {
     /// A topological sort of this graph produces an ordering that preserves the correct values.
     /// Cycles are broken by introducing an extra register and moving an arbitrary source value
     /// into it.
+    ///
+    /// `Op::Move` clears its source, so a source that is still needed afterwards, either because
+    /// it is in `live` or because it feeds another phi, is copied with `Op::Copy` instead.
     // TODO: replace this with live range splitting
-    fn emit_phis(&mut self, parameters: &[ssa::Value], arguments: &[ssa::Value]) {
+    fn emit_phis(
+        &mut self, parameters: &[ssa::Value], arguments: &[ssa::Value], live: &HashSet<ssa::Value>
+    ) {
         // the graph representation
         // - `phis` stores the vertices, which are uniquely identified by their targets
         // - `uses` stores only in-degrees; edges are not kept explicitly
@@ -233,6 +252,18 @@ impl<'p> Codegen<'p> {
                 .collect()
         };
 
+        // A source is moved by its last read, unless it is still live afterwards. Reads include
+        // the single-vertex cycles left out above.
+        let mut reads = HashMap::new();
+        let mut kept = HashSet::new();
+        for &argument in arguments {
+            let source = self.registers[argument];
+            *reads.entry(source).or_insert(0) += 1;
+            if live.contains(&argument) {
+                kept.insert(source);
+            }
+        }
+
         let mut uses = HashMap::new();
         for (_, &source) in phis.iter().filter(|&(_, source)| phis.contains_key(&source)) {
             *uses.entry(source).or_insert(0) += 1;
@@ -244,8 +275,14 @@ impl<'p> Codegen<'p> {
             .collect();
         loop {
             while let Some((target, source)) = work.pop_front() {
-                let inst = inst(code::Op::Move).index(target).index(source).encode();
-                self.function.instructions.push(inst);
+                let count = reads.get_mut(&source).unwrap();
+                *count -= 1;
+                let op = if *count == 0 && !kept.contains(&source) {
+                    code::Op::Move
+                } else {
+                    code::Op::Copy
+                };
+                inst(op).index(target).index(source).encode(&mut self.function.instructions);
 
                 if let Entry::Occupied(mut entry) = uses.entry(source) {
                     *entry.get_mut() -= 1;
@@ -269,8 +306,9 @@ impl<'p> Codegen<'p> {
             let (&used, &count) = uses.iter().nth(0).unwrap();
             assert_eq!(count, 1);
 
-            let inst = inst(code::Op::Move).index(temp).index(used).encode();
-            self.function.instructions.push(inst);
+            inst(code::Op::Move).index(temp).index(used).encode(&mut self.function.instructions);
+            let count = reads.remove(&used).unwrap();
+            reads.insert(temp, count);
 
             // TODO: track edges to make this quicker? there can only be one use by this point
             uses.remove(&used);
@@ -301,73 +339,174 @@ impl<'p> Codegen<'p> {
         })
     }
 
+    /// Fill in jump targets, now that every block has been placed.
+    ///
+    /// A target that does not fit in a jump needs another `Op::Wide` prefix, which moves every
+    /// later instruction and may push other targets out of range, so this repeats until every
+    /// jump fits.
     fn fixup_jumps(&mut self) {
-        for (&offset, &block) in &self.jump_offsets {
-            match self.function.instructions[offset].decode() {
-                (code::Op::Jump, 0, 0, 0) => {
-                    let target = self.block_offsets[&block];
-
-                    let inst = inst(code::Op::Jump).wide_index(target).encode();
-                    self.function.instructions[offset] = inst;
+        let mut encoded = Vec::new();
+        loop {
+            // Where to insert prefixes, and how many, in order.
+            let mut inserts = Vec::new();
+
+            let mut jumps: Vec<_> = self.jump_offsets.iter()
+                .map(|(&start, &block)| (start, block))
+                .collect();
+            jumps.sort_by_key(|&(start, _)| start);
+            for (start, block) in jumps {
+                let ((op, cond, _, _), end) = self.function.decode(start);
+                let target = self.block_offsets[&block];
+
+                let mut inst = inst(op);
+                inst.prefixes(end - start - 1);
+                match op {
+                    code::Op::Jump => {}
+                    code::Op::BranchFalse => { inst.index(cond); }
+                    _ => unreachable!("corrupt jump instruction"),
+                }
+                inst.wide_index(target);
+
+                encoded.clear();
+                inst.encode(&mut encoded);
+                if encoded.len() > end - start {
+                    inserts.push((start, encoded.len() - (end - start)));
+                } else {
+                    self.function.instructions[start..end].copy_from_slice(&encoded);
                 }
+            }
 
-                (code::Op::BranchFalse, cond, 0, 0) => {
-                    let target = self.block_offsets[&block];
+            if inserts.is_empty() {
+                break;
+            }
+            self.insert_prefixes(&inserts);
+        }
+    }
 
-                    let inst = inst(code::Op::BranchFalse).index(cond).wide_index(target).encode();
-                    self.function.instructions[offset] = inst;
-                }
+    /// Insert placeholder prefixes before the instructions at `inserts`, and move everything that
+    /// refers to later instructions. The prefixes become part of the instruction they precede.
+    fn insert_prefixes(&mut self, inserts: &[(usize, usize)]) {
+        // The number of prefixes inserted before each entry in `inserts`, and after the last.
+        let mut totals = vec![0];
+        for &(_, count) in inserts {
+            totals.push(totals[totals.len() - 1] + count);
+        }
+        let moved = |offset: usize| -> usize {
+            match inserts.binary_search_by_key(&offset, |&(start, _)| start) {
+                Ok(i) | Err(i) => offset + totals[i],
+            }
+        };
 
-                _ => unreachable!("corrupt jump instruction"),
+        let wide = code::Inst(code::Op::Wide as u32);
+        let mut instructions = Vec::with_capacity(moved(self.function.instructions.len()));
+        let mut inserts = inserts.iter().peekable();
+        for (offset, &instruction) in self.function.instructions.iter().enumerate() {
+            if let Some(&&(start, count)) = inserts.peek() {
+                if start == offset {
+                    instructions.extend((0..count).map(|_| wide));
+                    inserts.next();
+                }
             }
+            instructions.push(instruction);
+        }
+        self.function.instructions = instructions;
+
+        for offset in self.block_offsets.values_mut() {
+            *offset = moved(*offset);
+        }
+        self.jump_offsets = self.jump_offsets.drain()
+            .map(|(start, block)| (moved(start), block))
+            .collect();
+        for map in &mut self.locations.mappings {
+            map.offset = moved(map.offset as usize) as u32;
         }
     }
 }
 
+/// An instruction under construction, with operands of any width up to 32 bits.
 struct InstBuilder {
-    fields: [u8; 4],
+    op: code::Op,
+    fields: [usize; 3],
     filled: usize,
+    /// The field holding the low half of a jump target. The high half goes in the next field.
+    wide: Option<usize>,
+    prefixes: usize,
 }
 
 fn inst(op: code::Op) -> InstBuilder {
     InstBuilder {
-        fields: [op as u8, 0, 0, 0],
-        filled: 1,
+        op,
+        fields: [0, 0, 0],
+        filled: 0,
+        wide: None,
+        prefixes: 0,
     }
 }
 
 impl InstBuilder {
     fn index(&mut self, index: usize) -> &mut Self {
-        assert!(index <= u8::MAX as usize);
-        self.fields[self.filled] = index as u8;
+        self.fields[self.filled] = index;
         self.filled += 1;
         self
     }
 
     fn wide_index(&mut self, index: usize) -> &mut Self {
-        assert!(index <= u16::MAX as usize);
-        self.fields[self.filled] = index as u8;
-        self.filled += 1;
-        self.fields[self.filled] = (index >> 8) as u8;
-        self.filled += 1;
+        self.wide = Some(self.filled);
+        self.fields[self.filled] = index;
+        self.filled += 2;
         self
     }
 
     fn int(&mut self, int: i32) -> &mut Self {
         assert!(int <= i8::MAX as i32);
         assert!(int >= i8::MIN as i32);
-        self.fields[self.filled] = int as i8 as u8;
+        self.fields[self.filled] = int as i8 as u8 as usize;
         self.filled += 1;
         self
     }
 
-    fn encode(&mut self) -> code::Inst {
-        code::Inst(
-            (self.fields[0] as u32) |
-            (self.fields[1] as u32) << 8 |
-            (self.fields[2] as u32) << 16 |
-            (self.fields[3] as u32) << 24
-        )
+    /// Use at least `prefixes` prefixes, even if the operands would fit in fewer.
+    fn prefixes(&mut self, prefixes: usize) -> &mut Self {
+        self.prefixes = prefixes;
+        self
+    }
+
+    /// Append the instruction to `instructions`, preceded by as many `Op::Wide` prefixes as its
+    /// operands need.
+    fn encode(&self, instructions: &mut Vec<code::Inst>) {
+        let mut prefixes = self.prefixes;
+        while !self.fits(prefixes) {
+            prefixes += 1;
+        }
+        assert!(prefixes <= code::MAX_PREFIXES);
+
+        let bits = 8 * (prefixes + 1);
+        let mut fields = self.fields;
+        if let Some(low) = self.wide {
+            fields[low + 1] = fields[low] >> bits;
+            fields[low] &= (1 << bits) - 1;
+        }
+
+        // Prefixes hold the more significant bytes, so they come first.
+        for prefix in (0..=prefixes).rev() {
+            let op = if prefix == 0 { self.op } else { code::Op::Wide };
+            let byte = |field: usize| (field >> (8 * prefix)) as u8 as u32;
+            instructions.push(code::Inst(
+                (op as u32) |
+                byte(fields[0]) << 8 |
+                byte(fields[1]) << 16 |
+                byte(fields[2]) << 24
+            ));
+        }
+    }
+
+    /// Check whether every operand fits in the fields of an instruction with `prefixes` prefixes.
+    fn fits(&self, prefixes: usize) -> bool {
+        let bits = 8 * (prefixes + 1);
+        self.fields.iter().enumerate().all(|(i, &field)| {
+            let bits = if self.wide == Some(i) { 2 * bits } else { bits };
+            field.checked_shr(bits as u32).unwrap_or(0) == 0
+        })
     }
 }
 
@@ -383,6 +522,13 @@ impl From<ssa::Opcode> for code::Op {
             ssa::Opcode::ToArray => code::Op::ToArray,
             ssa::Opcode::ToScalar => code::Op::ToScalar,
             ssa::Opcode::ReleaseOwned => code::Op::ReleaseOwned,
+            ssa::Opcode::Share => code::Op::Share,
+            ssa::Opcode::ToUnique => code::Op::ToUnique,
+            ssa::Opcode::MarkOwned => code::Op::MarkOwned,
+            ssa::Opcode::ReleaseOwnedTo => code::Op::ReleaseOwnedTo,
+            ssa::Opcode::ReleaseCall => code::Op::ReleaseCall,
+            ssa::Opcode::OwnLocal => code::Op::OwnLocal,
+            ssa::Opcode::StoreOwned => code::Op::StoreOwned,
 
             ssa::Opcode::With => code::Op::With,
             ssa::Opcode::ReleaseWith => code::Op::ReleaseWith,
@@ -0,0 +1,646 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use crate::symbol::Symbol;
+use crate::handle_map::Handle;
+use crate::back::{ssa, analysis};
+use crate::vm;
+
+/// Simplify a function before generating code for it.
+///
+/// The passes here only make changes that cannot be observed by a running program, so errors
+/// like division by zero or type mismatches are left for the VM to report. They are run
+/// repeatedly until none of them finds anything left to do, since each can expose work for the
+/// others: a folded condition makes a block dead, which leaves a parameter with a single
+/// incoming value, which may in turn be folded.
+pub fn optimize(function: &mut ssa::Function) {
+    loop {
+        let mut changed = false;
+        changed |= remove_dead_blocks(function);
+        changed |= fold_constants(function);
+        changed |= propagate_parameters(function);
+        changed |= remove_dead_values(function);
+        if !changed {
+            break;
+        }
+    }
+}
+
+/// A compile-time constant, as produced by `Opcode::Constant`.
+#[derive(Copy, Clone)]
+enum Constant {
+    Real(f64),
+    String(Symbol),
+}
+
+impl Constant {
+    fn boolean(value: bool) -> Constant { Constant::Real(value as i32 as f64) }
+
+    /// The value the VM would hold at runtime, for bitwise comparisons.
+    fn value(self) -> vm::Value {
+        match self {
+            Constant::Real(real) => vm::Value::from(real),
+            Constant::String(symbol) => vm::Value::from(symbol),
+        }
+    }
+
+    fn instruction(self) -> ssa::Instruction {
+        let op = ssa::Opcode::Constant;
+        match self {
+            Constant::Real(real) => ssa::Instruction::UnaryReal { op, real },
+            Constant::String(symbol) => ssa::Instruction::UnarySymbol { op, symbol },
+        }
+    }
+}
+
+fn constant(function: &ssa::Function, value: ssa::Value) -> Option<Constant> {
+    match function.values[value] {
+        ssa::Instruction::UnaryReal { op: ssa::Opcode::Constant, real } =>
+            Some(Constant::Real(real)),
+        ssa::Instruction::UnarySymbol { op: ssa::Opcode::Constant, symbol } =>
+            Some(Constant::String(symbol)),
+        _ => None,
+    }
+}
+
+/// Clear out blocks that can never run.
+///
+/// Their terminators may still refer to live blocks, so leaving them in place would keep
+/// otherwise-unused values and parameters around.
+fn remove_dead_blocks(function: &mut ssa::Function) -> bool {
+    let reachable = analysis::reachable(function);
+
+    let mut changed = false;
+    for block in function.blocks.keys() {
+        if reachable.get(block.index()) || function.blocks[block].instructions.is_empty() {
+            continue;
+        }
+
+        let block = &mut function.blocks[block];
+        block.parameters.clear();
+        block.instructions.clear();
+        changed = true;
+    }
+    changed
+}
+
+/// The result of simplifying a single instruction.
+enum Fold {
+    Keep,
+    Replace(ssa::Instruction),
+    Alias(ssa::Value),
+    Remove,
+}
+
+/// Evaluate instructions whose operands are known, and drop conversions and checks that are
+/// known to have no effect.
+fn fold_constants(function: &mut ssa::Function) -> bool {
+    let scalars = Scalars::compute(function);
+
+    let mut changed = false;
+    for block in function.blocks.keys() {
+        let instructions = mem::take(&mut function.blocks[block].instructions);
+        let mut kept = Vec::with_capacity(instructions.len());
+        for value in instructions {
+            replace_aliases(function, value);
+            match fold(function, &scalars, value) {
+                Fold::Keep => kept.push(value),
+                Fold::Replace(instruction) => {
+                    function.values[value] = instruction;
+                    kept.push(value);
+                    changed = true;
+                }
+                Fold::Alias(arg) => {
+                    function.values[value] = ssa::Instruction::Alias { arg };
+                    changed = true;
+                }
+                Fold::Remove => changed = true,
+            }
+        }
+        function.blocks[block].instructions = kept;
+    }
+
+    resolve_aliases(function);
+    changed
+}
+
+fn fold(function: &ssa::Function, scalars: &Scalars, value: ssa::Value) -> Fold {
+    use crate::back::ssa::Instruction::*;
+    use self::Constant::Real;
+
+    match function.values[value] {
+        Unary { op: ssa::Opcode::ToScalar, arg } if scalars.contains(function, arg) =>
+            Fold::Alias(arg),
+        Unary { op: ssa::Opcode::Share, arg } if scalars.contains(function, arg) =>
+            Fold::Alias(arg),
+
+        Unary { op, arg } => {
+            let real = match constant(function, arg) {
+                Some(Real(real)) => real,
+                _ => return Fold::Keep,
+            };
+            let constant = match op {
+                ssa::Opcode::Negate => Real(-real),
+                ssa::Opcode::Invert => Constant::boolean(!vm::to_bool(real)),
+                ssa::Opcode::BitInvert => Real(!vm::to_i32(real) as f64),
+                _ => return Fold::Keep,
+            };
+            Fold::Replace(constant.instruction())
+        }
+
+        // Writing to a scalar just replaces it.
+        Binary { op: ssa::Opcode::Write, args: [value, old] } if scalars.contains(function, old) =>
+            Fold::Alias(value),
+
+        Binary { op, args: [a, b] } => {
+            match (constant(function, a), constant(function, b)) {
+                (Some(a), Some(b)) => match fold_binary(op, a, b) {
+                    Some(constant) => Fold::Replace(constant.instruction()),
+                    None => Fold::Keep,
+                },
+                _ => Fold::Keep,
+            }
+        }
+
+        // Reading a local that is known to be initialized can never fail.
+        BinarySymbol { op: ssa::Opcode::Read, arg, .. } => match constant(function, arg) {
+            Some(Real(flag)) if vm::to_bool(flag) => Fold::Remove,
+            _ => Fold::Keep,
+        },
+
+        Branch { targets: [true_block, false_block], arg_lens: [true_args, _], ref args, .. } => {
+            let flag = match constant(function, args[0]) {
+                Some(Real(flag)) => flag,
+                _ => return Fold::Keep,
+            };
+
+            let true_end = 1 + true_args as usize;
+            let (target, args) = if vm::to_bool(flag) {
+                (true_block, &args[1..true_end])
+            } else {
+                (false_block, &args[true_end..])
+            };
+            let op = ssa::Opcode::Jump;
+            Fold::Replace(Jump { op, target, args: args.to_vec() })
+        }
+
+        _ => Fold::Keep,
+    }
+}
+
+/// Evaluate a binary operator the way the VM would, unless doing so would raise an error.
+fn fold_binary(op: ssa::Opcode, a: Constant, b: Constant) -> Option<Constant> {
+    use crate::back::ssa::Opcode::*;
+    use self::Constant::{Real, String};
+
+    let boolean = self::Constant::boolean;
+    let constant = match (op, a, b) {
+        (Lt, Real(a), Real(b)) => boolean(a < b),
+        (Lt, String(a), String(b)) => boolean(a < b),
+        (Le, Real(a), Real(b)) => boolean(a <= b),
+        (Le, String(a), String(b)) => boolean(a <= b),
+        (Ge, Real(a), Real(b)) => boolean(a >= b),
+        (Ge, String(a), String(b)) => boolean(a >= b),
+        (Gt, Real(a), Real(b)) => boolean(a > b),
+        (Gt, String(a), String(b)) => boolean(a > b),
+
+        (Eq, a, b) => boolean(a.value() == b.value()),
+        (Ne, a, b) => boolean(a.value() != b.value()),
+
+        (Add, Real(a), Real(b)) => Real(a + b),
+        (Add, String(a), String(b)) => String(Symbol::intern(&[&a[..], &b[..]].concat())),
+        (Subtract, Real(a), Real(b)) => Real(a - b),
+        (Multiply, Real(a), Real(b)) => Real(a * b),
+        (Divide, Real(a), Real(b)) if b != 0.0 => Real(a / b),
+        (Div, Real(a), Real(b)) if b != 0.0 => Real(vm::to_i32(a / b) as f64),
+        (Mod, Real(a), Real(b)) if b != 0.0 => Real(a % b),
+
+        (And, Real(a), Real(b)) => boolean(vm::to_bool(a) && vm::to_bool(b)),
+        (Or, Real(a), Real(b)) => boolean(vm::to_bool(a) || vm::to_bool(b)),
+        (Xor, Real(a), Real(b)) => boolean(vm::to_bool(a) != vm::to_bool(b)),
+
+        (BitAnd, Real(a), Real(b)) => Real((vm::to_i32(a) & vm::to_i32(b)) as f64),
+        (BitOr, Real(a), Real(b)) => Real((vm::to_i32(a) | vm::to_i32(b)) as f64),
+        (BitXor, Real(a), Real(b)) => Real((vm::to_i32(a) ^ vm::to_i32(b)) as f64),
+
+        // Out-of-range shifts would overflow here, so leave them to the VM.
+        (ShiftLeft, Real(a), Real(b)) if (0..32).contains(&vm::to_i32(b)) =>
+            Real((vm::to_i32(a) << vm::to_i32(b)) as f64),
+        (ShiftRight, Real(a), Real(b)) if (0..32).contains(&vm::to_i32(b)) =>
+            Real((vm::to_i32(a) >> vm::to_i32(b)) as f64),
+
+        _ => return None,
+    };
+    Some(constant)
+}
+
+/// The values known to never hold arrays.
+///
+/// Block parameters are assumed to be scalars until an incoming value shows otherwise, so that
+/// loops which only ever pass scalars around are recognized.
+struct Scalars {
+    parameters: HashSet<ssa::Value>,
+}
+
+impl Scalars {
+    fn compute(function: &ssa::Function) -> Scalars {
+        let mut parameters = HashSet::new();
+        for block in function.blocks.keys() {
+            if block == ssa::ENTRY {
+                continue;
+            }
+            parameters.extend(function.blocks[block].parameters.iter().cloned());
+        }
+
+        let mut scalars = Scalars { parameters };
+        let edges = edges(function);
+        let mut dirty = true;
+        while dirty {
+            dirty = false;
+            for &(target, args) in &edges {
+                let parameters = &function.blocks[target].parameters;
+                for (parameter, &arg) in parameters.iter().zip(args) {
+                    if scalars.parameters.contains(parameter) && !scalars.contains(function, arg) {
+                        scalars.parameters.remove(parameter);
+                        dirty = true;
+                    }
+                }
+            }
+        }
+
+        scalars
+    }
+
+    fn contains(&self, function: &ssa::Function, mut value: ssa::Value) -> bool {
+        use crate::back::ssa::Opcode::*;
+
+        // A long run of assignments to one local forms a chain of writes, so follow it with a
+        // loop rather than recursion.
+        loop {
+            return match function.values[value] {
+                ssa::Instruction::Parameter => self.parameters.contains(&value),
+                ssa::Instruction::UnaryReal { op: Constant, .. } |
+                ssa::Instruction::UnarySymbol { op: Constant, .. } => true,
+                ssa::Instruction::Unary { op: Share, arg } => {
+                    value = arg;
+                    continue;
+                }
+                ssa::Instruction::Unary { op, .. } =>
+                    matches!(op, ToScalar | Negate | Invert | BitInvert),
+                ssa::Instruction::Binary { op: Write, args: [new, old] } => {
+                    if !self.contains(function, new) {
+                        return false;
+                    }
+                    value = old;
+                    continue;
+                }
+                ssa::Instruction::Binary { op, .. } => matches!(op,
+                    Lt | Le | Eq | Ne | Ge | Gt |
+                    Add | Subtract | Multiply | Divide | Div | Mod |
+                    And | Or | Xor | BitAnd | BitOr | BitXor | ShiftLeft | ShiftRight
+                ),
+                _ => false,
+            };
+        }
+    }
+}
+
+/// Replace block parameters that always receive the same value with that value.
+fn propagate_parameters(function: &mut ssa::Function) -> bool {
+    let mut incoming: HashMap<_, Vec<_>> = HashMap::new();
+    for (target, args) in edges(function) {
+        let parameters = &function.blocks[target].parameters;
+        for (&parameter, &arg) in parameters.iter().zip(args) {
+            incoming.entry(parameter).or_default().push(arg);
+        }
+    }
+
+    let mut changed = false;
+    for block in function.blocks.keys() {
+        if block == ssa::ENTRY {
+            continue;
+        }
+
+        let mut index = 0;
+        while index < function.blocks[block].parameters.len() {
+            let parameter = function.blocks[block].parameters[index];
+            let args = incoming.get(&parameter).map(|args| &args[..]).unwrap_or(&[]);
+
+            // Arguments may be parameters that have already been replaced in this pass.
+            let args: Vec<_> = args.iter()
+                .map(|&arg| resolve_alias(function, arg))
+                .filter(|&arg| arg != parameter)
+                .collect();
+            let first = match args.first() {
+                Some(&first) => first,
+                None => { index += 1; continue; }
+            };
+
+            if args.iter().all(|&arg| arg == first) {
+                function.values[parameter] = ssa::Instruction::Alias { arg: first };
+            } else if let Some(constant) = same_constant(function, &args) {
+                // Equal constants from different blocks need a single definition here.
+                let block_start = function.blocks[block].instructions[0];
+                let location = function.locations[block_start];
+                function.values[parameter] = constant.instruction();
+                *function.locations.ensure(parameter) = location;
+                function.blocks[block].instructions.insert(0, parameter);
+            } else {
+                index += 1;
+                continue;
+            }
+
+            remove_parameter(function, block, index);
+            changed = true;
+        }
+    }
+
+    resolve_aliases(function);
+    changed
+}
+
+/// The constant held by all of `values`, if there is one.
+fn same_constant(function: &ssa::Function, values: &[ssa::Value]) -> Option<Constant> {
+    let (&first, rest) = values.split_first()?;
+    let constant = constant(function, first)?;
+    let value = constant.value();
+    for &other in rest {
+        match self::constant(function, other) {
+            Some(other) if other.value() == value => {}
+            _ => return None,
+        }
+    }
+    Some(constant)
+}
+
+/// Remove values with no observable effect that nothing depends on.
+///
+/// Liveness starts from the operands of instructions with side effects and flows backwards,
+/// including from block parameters to the values passed to them.
+fn remove_dead_values(function: &mut ssa::Function) -> bool {
+    let mut incoming: HashMap<_, Vec<_>> = HashMap::new();
+    for (target, args) in edges(function) {
+        let parameters = &function.blocks[target].parameters;
+        for (&parameter, &arg) in parameters.iter().zip(args) {
+            incoming.entry(parameter).or_default().push(arg);
+        }
+    }
+
+    let mut live = HashSet::new();
+    let mut work = Vec::new();
+    for block in function.blocks.keys() {
+        for &value in &function.blocks[block].instructions {
+            if is_pure(function, value) {
+                continue;
+            }
+
+            // Values passed along control flow edges are only needed if their parameters are.
+            let uses = match function.values[value] {
+                ssa::Instruction::Jump { .. } => &[][..],
+                ssa::Instruction::Branch { ref args, .. } => &args[..1],
+                _ => function.uses(value),
+            };
+            for &used in uses {
+                if live.insert(used) {
+                    work.push(used);
+                }
+            }
+        }
+    }
+
+    while let Some(value) = work.pop() {
+        let uses = match function.values[value] {
+            ssa::Instruction::Parameter => match incoming.get(&value) {
+                Some(args) => &args[..],
+                None => &[][..],
+            },
+            ssa::Instruction::Alias { .. } | ssa::Instruction::Project { .. } => &[][..],
+            _ => function.uses(value),
+        };
+        for &used in uses {
+            if live.insert(used) {
+                work.push(used);
+            }
+        }
+    }
+
+    let mut changed = false;
+    for block in function.blocks.keys() {
+        let len = function.blocks[block].instructions.len();
+        let mut instructions = mem::take(&mut function.blocks[block].instructions);
+        instructions.retain(|&value| live.contains(&value) || !is_pure(function, value));
+        changed |= instructions.len() != len;
+        function.blocks[block].instructions = instructions;
+
+        if block == ssa::ENTRY {
+            continue;
+        }
+        let mut index = 0;
+        while index < function.blocks[block].parameters.len() {
+            let parameter = function.blocks[block].parameters[index];
+            if live.contains(&parameter) {
+                index += 1;
+                continue;
+            }
+
+            remove_parameter(function, block, index);
+            changed = true;
+        }
+    }
+    changed
+}
+
+/// Whether an instruction can be removed when its result is unused.
+fn is_pure(function: &ssa::Function, value: ssa::Value) -> bool {
+    use crate::back::ssa::Opcode::*;
+
+    match function.values[value] {
+        ssa::Instruction::UnaryReal { op: Constant, .. } |
+        ssa::Instruction::UnarySymbol { op: Constant, .. } |
+        ssa::Instruction::UnarySymbol { op: Lookup, .. } |
+        ssa::Instruction::UnaryInt { op: LoadScope, .. } => true,
+        ssa::Instruction::Unary { op, .. } => matches!(op, LoadPointer | NextPointer),
+        ssa::Instruction::Binary { op, .. } => matches!(op, Eq | Ne | NePointer),
+        _ => false,
+    }
+}
+
+/// Remove a block parameter, along with the values passed to it by each predecessor.
+fn remove_parameter(function: &mut ssa::Function, block: ssa::Label, index: usize) {
+    function.blocks[block].parameters.remove(index);
+
+    for pred in function.blocks.keys() {
+        let terminator = match function.blocks[pred].instructions.last() {
+            Some(&terminator) => terminator,
+            None => continue,
+        };
+        match function.values[terminator] {
+            ssa::Instruction::Jump { target, ref mut args, .. } if target == block => {
+                args.remove(index);
+            }
+            ssa::Instruction::Branch {
+                targets: [true_block, false_block],
+                arg_lens: [ref mut true_args, ref mut false_args],
+                ref mut args,
+                ..
+            } => {
+                if true_block == block {
+                    args.remove(1 + index);
+                    *true_args -= 1;
+                }
+                if false_block == block {
+                    args.remove(1 + *true_args as usize + index);
+                    *false_args -= 1;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Each control flow edge, as its target block and the values passed to its parameters.
+fn edges(function: &ssa::Function) -> Vec<(ssa::Label, &[ssa::Value])> {
+    let mut edges = Vec::new();
+    for block in function.blocks.keys() {
+        let terminator = match function.blocks[block].instructions.last() {
+            Some(&terminator) => terminator,
+            None => continue,
+        };
+        match function.values[terminator] {
+            ssa::Instruction::Jump { target, ref args, .. } => edges.push((target, &args[..])),
+            ssa::Instruction::Branch {
+                targets: [true_block, false_block],
+                arg_lens: [true_args, _],
+                ref args,
+                ..
+            } => {
+                let true_end = 1 + true_args as usize;
+                edges.push((true_block, &args[1..true_end]));
+                edges.push((false_block, &args[true_end..]));
+            }
+            _ => {}
+        }
+    }
+    edges
+}
+
+fn resolve_aliases(function: &mut ssa::Function) {
+    for block in function.blocks.keys() {
+        for i in 0..function.blocks[block].instructions.len() {
+            let value = function.blocks[block].instructions[i];
+            replace_aliases(function, value);
+        }
+    }
+}
+
+fn replace_aliases(function: &mut ssa::Function, value: ssa::Value) {
+    for i in 0..function.uses(value).len() {
+        let arg = function.uses(value)[i];
+        let resolved = resolve_alias(function, arg);
+        if arg != resolved {
+            function.uses_mut(value)[i] = resolved;
+        }
+    }
+}
+
+fn resolve_alias(function: &ssa::Function, mut value: ssa::Value) -> ssa::Value {
+    while let ssa::Instruction::Alias { arg } = function.values[value] {
+        value = arg;
+    }
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io;
+
+    use crate::{ErrorPrinter, FunctionDisplay, vm};
+    use crate::symbol::Symbol;
+    use crate::front::{Lexer, Parser, Codegen, Lines};
+    use crate::back::ssa;
+    use super::optimize;
+
+    fn compile(source: &[u8]) -> ssa::Function {
+        let mut prototypes = HashMap::default();
+        let native = ssa::Prototype::Native { arity: 1, variadic: false };
+        prototypes.insert(Symbol::intern(b"string"), native);
+        let native = ssa::Prototype::Native { arity: 0, variadic: false };
+        prototypes.insert(Symbol::intern(b"random"), native);
+
+        let lines = Lines::from_code(source);
+        let name = FunctionDisplay::Script { script: Symbol::intern(b"test") };
+        let mut errors = ErrorPrinter::new(name, &lines, io::sink());
+        let program = Parser::new(Lexer::new(source, 0), &mut errors).parse_program();
+        let mut function = Codegen::new(&prototypes, &mut errors).compile_program(&program);
+        assert_eq!(errors.count, 0);
+        optimize(&mut function);
+        function
+    }
+
+    /// All the instructions left in the function, as their opcodes.
+    fn ops(function: &ssa::Function) -> Vec<ssa::Opcode> {
+        function.blocks.keys()
+            .flat_map(|block| function.blocks[block].instructions.iter())
+            .map(|&value| function.op(value))
+            .collect()
+    }
+
+    #[test]
+    fn constants() {
+        let function = compile(b"{
+            var a, b;
+            a = 2 * 3 + 1
+            b = \"x\" + \"y\"
+            return string(a) + b
+        }");
+
+        let constants: Vec<_> = function.blocks[ssa::ENTRY].instructions.iter()
+            .filter_map(|&value| match function.values[value] {
+                ssa::Instruction::UnaryReal { op: ssa::Opcode::Constant, real } =>
+                    Some(vm::Value::from(real)),
+                ssa::Instruction::UnarySymbol { op: ssa::Opcode::Constant, symbol } =>
+                    Some(vm::Value::from(symbol)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(constants, [vm::Value::from(7), vm::Value::from(Symbol::intern(b"xy"))]);
+
+        let ops = ops(&function);
+        assert!(!ops.contains(&ssa::Opcode::Read));
+        assert!(!ops.contains(&ssa::Opcode::Multiply));
+    }
+
+    #[test]
+    fn branches() {
+        let function = compile(b"{
+            var a;
+            if 1 < 2 { a = 3 } else { a = random() }
+            return a
+        }");
+
+        let ops = ops(&function);
+        assert!(!ops.contains(&ssa::Opcode::Branch));
+        assert!(!ops.contains(&ssa::Opcode::Call));
+    }
+
+    #[test]
+    fn parameters() {
+        let function = compile(b"{
+            var i, s;
+            s = 0
+            for (i = 0; i < argument0; i += 1) { s += i }
+            return s
+        }");
+
+        // The loop still needs its counter and sum, but not their initialization flags.
+        let ops = ops(&function);
+        assert!(!ops.contains(&ssa::Opcode::Read));
+        assert!(!ops.contains(&ssa::Opcode::Write));
+        let parameters: usize = function.blocks.keys()
+            .filter(|&block| block != ssa::ENTRY)
+            .map(|block| function.blocks[block].parameters.len())
+            .sum();
+        assert_eq!(parameters, 2);
+    }
+}
@@ -98,6 +98,24 @@ pub enum Opcode {
     ToScalar,
     /// Drop the top of the thread owned value stack.
     ReleaseOwned,
+    /// Push another reference to an array onto the thread owned-value stack. Scalars are left
+    /// alone.
+    Share,
+    /// Ensure a value is an array that no other variable holds. Scalars are replaced by new
+    /// arrays, and shared arrays are copied unless `IN_PLACE` is set.
+    /// Push the result onto the thread owned-value stack.
+    ToUnique,
+    /// Produce the height of the thread owned-value stack, to release back down to later.
+    MarkOwned,
+    /// Drop the values pushed onto the thread owned-value stack since a `MarkOwned`.
+    ReleaseOwnedTo,
+    /// Drop the values pushed onto the thread owned-value stack since a `MarkOwned`, except for
+    /// the result of the call made since then when it is an array.
+    ReleaseCall,
+    /// Push a slot onto the thread owned-value stack for a local variable, producing its index.
+    OwnLocal,
+    /// Replace the value in a slot made by `OwnLocal`.
+    StoreOwned,
 
     /// Build an iterator over a scope, producing a tuple of start and end.
     /// Push ownership of the iterator onto the thread iterator stack.
@@ -192,14 +210,15 @@ pub enum Opcode {
 
 /// A declaration of some external entity.
 ///
-/// This contains just enough information to generate code for the caller.
+/// This contains just enough information to generate code for the caller, and to check how the
+/// caller uses it.
 // TODO: gms tracks function arity
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum Prototype {
     /// A native API function.
     Native { arity: usize, variadic: bool },
-    /// A built-in member accessed via getter and setter.
-    Member,
+    /// A built-in member accessed via getter and setter. Read-only members have no setter.
+    Member { settable: bool },
     /// A GML script.
     Script { id: i32 },
 }
@@ -253,7 +272,11 @@ impl Function {
     pub fn successors(&self, block: Label) -> &[Label] {
         use self::Instruction::*;
 
-        let value = self.terminator(block);
+        // Blocks emptied by `optimize` no longer have a terminator.
+        let value = match self.blocks[block].instructions.last() {
+            Some(&value) => value,
+            None => return &[],
+        };
         match self.values[value] {
             Jump { ref target, .. } => slice::from_ref(target),
             Branch { ref targets, .. } => targets,
@@ -295,6 +318,9 @@ impl Function {
 
             // Zero-valued instructions:
             Nullary { op: Opcode::ReleaseWith } |
+            Unary { op: Opcode::ReleaseOwnedTo, .. } |
+            Unary { op: Opcode::ReleaseCall, .. } |
+            Binary { op: Opcode::StoreOwned, .. } |
             Unary { op: Opcode::ReleaseOwned, .. } |
             Unary { op: Opcode::ScopeError, .. } |
             Unary { op: Opcode::Return, .. } |
@@ -2,13 +2,15 @@ use std::{slice, str, str::FromStr};
 
 use project::{Action, action_kind, action_type, argument_type};
 
-use crate::ErrorPrinter;
+use crate::{ErrorPrinter, Dialect};
 use crate::symbol::Symbol;
-use crate::front::{ast, Lexer, Parser, Span};
+use crate::front::{ast, Lexer, Parser, Definitions, Span};
 
 pub struct ActionParser<'s, 'e, 'f> {
     reader: slice::Iter<'s, Action<'s>>,
     errors: &'e mut ErrorPrinter<'f>,
+    dialect: Dialect,
+    definitions: Definitions,
 
     current: Option<&'s Action<'s>>,
     span: Span,
@@ -18,10 +20,21 @@ impl<'s, 'e, 'f> ActionParser<'s, 'e, 'f> {
     pub fn new(
         reader: slice::Iter<'s, Action<'s>>,
         errors: &'e mut ErrorPrinter<'f>,
+    ) -> ActionParser<'s, 'e, 'f> {
+        Self::with_dialect(reader, Dialect::default(), errors)
+    }
+
+    /// Parse actions whose code is written in `dialect`.
+    pub fn with_dialect(
+        reader: slice::Iter<'s, Action<'s>>,
+        dialect: Dialect,
+        errors: &'e mut ErrorPrinter<'f>,
     ) -> ActionParser<'s, 'e, 'f> {
         let mut parser = ActionParser {
             reader,
             errors,
+            dialect,
+            definitions: Definitions::default(),
 
             current: None,
             span: Span { low: 0, high: 0 },
@@ -31,6 +44,17 @@ impl<'s, 'e, 'f> ActionParser<'s, 'e, 'f> {
         parser
     }
 
+    /// Parse code with the macros and enums in `definitions` in scope.
+    pub fn with_definitions(mut self, definitions: Definitions) -> ActionParser<'s, 'e, 'f> {
+        self.definitions = definitions;
+        self
+    }
+
+    /// The macros and enums defined so far, including those this parser started with.
+    pub fn definitions(&self) -> Definitions {
+        self.definitions.clone()
+    }
+
     pub fn parse_event(&mut self) -> (ast::Action, Span) {
         let low = self.span.low;
         let mut high = low;
@@ -102,9 +126,11 @@ impl<'s, 'e, 'f> ActionParser<'s, 'e, 'f> {
             }
 
             action_type::CODE => {
-                let reader = Lexer::new(&action.code, offset);
-                let mut parser = Parser::new(reader, self.errors);
+                let reader = Lexer::with_dialect(&action.code, offset, self.dialect);
+                let definitions = self.definitions.clone();
+                let mut parser = Parser::with_definitions(reader, definitions, self.errors);
                 let program = Box::new(parser.parse_program());
+                self.definitions = parser.definitions();
                 offset += action.code.len();
 
                 ast::Exec::Code(program)
@@ -169,8 +195,9 @@ impl<'s, 'e, 'f> ActionParser<'s, 'e, 'f> {
 
         let argument = match param {
             argument_type::EXPR => {
-                let reader = Lexer::new(source, offset);
-                let mut parser = Parser::new(reader, self.errors);
+                let reader = Lexer::with_dialect(source, offset, self.dialect);
+                let definitions = self.definitions.clone();
+                let mut parser = Parser::with_definitions(reader, definitions, self.errors);
                 ast::Argument::Expr(Box::new(parser.parse_expression(0)))
             }
 
@@ -182,8 +209,9 @@ impl<'s, 'e, 'f> ActionParser<'s, 'e, 'f> {
             argument_type::BOTH => {
                 match source.first().copied() {
                     Some(b'"') => {
-                        let reader = Lexer::new(source, offset);
-                        let mut parser = Parser::new(reader, self.errors);
+                        let reader = Lexer::with_dialect(source, offset, self.dialect);
+                        let definitions = self.definitions.clone();
+                        let mut parser = Parser::with_definitions(reader, definitions, self.errors);
                         ast::Argument::Expr(Box::new(parser.parse_expression(0)))
                     }
                     _ => {
@@ -322,8 +350,9 @@ impl<'s, 'e, 'f> ActionParser<'s, 'e, 'f> {
         let (&parameter, &source) = arguments.next().unwrap();
         let count = match parameter {
             argument_type::EXPR => {
-                let reader = Lexer::new(source, offset);
-                let mut parser = Parser::new(reader, self.errors);
+                let reader = Lexer::with_dialect(source, offset, self.dialect);
+                let definitions = self.definitions.clone();
+                let mut parser = Parser::with_definitions(reader, definitions, self.errors);
                 Box::new(parser.parse_expression(0))
             }
 
@@ -378,8 +407,9 @@ impl<'s, 'e, 'f> ActionParser<'s, 'e, 'f> {
         let (&parameter, &source) = arguments.next().unwrap();
         let variable = match parameter {
             argument_type::STRING => {
-                let reader = Lexer::new(source, offset);
-                let mut parser = Parser::new(reader, self.errors);
+                let reader = Lexer::with_dialect(source, offset, self.dialect);
+                let definitions = self.definitions.clone();
+                let mut parser = Parser::with_definitions(reader, definitions, self.errors);
                 Box::new(parser.parse_expression(0))
             }
 
@@ -393,8 +423,9 @@ impl<'s, 'e, 'f> ActionParser<'s, 'e, 'f> {
         let (&parameter, &source) = arguments.next().unwrap();
         let value = match parameter {
             argument_type::EXPR => {
-                let reader = Lexer::new(source, 0);
-                let mut parser = Parser::new(reader, self.errors);
+                let reader = Lexer::with_dialect(source, 0, self.dialect);
+                let definitions = self.definitions.clone();
+                let mut parser = Parser::with_definitions(reader, definitions, self.errors);
                 Box::new(parser.parse_expression(0))
             }
 
@@ -438,9 +469,12 @@ impl<'s, 'e, 'f> ActionParser<'s, 'e, 'f> {
         let (&parameter, &source) = arguments.next().unwrap();
         let code = match parameter {
             argument_type::STRING => {
-                let reader = Lexer::new(source, offset);
-                let mut parser = Parser::new(reader, self.errors);
-                Box::new(parser.parse_program())
+                let reader = Lexer::with_dialect(source, offset, self.dialect);
+                let definitions = self.definitions.clone();
+                let mut parser = Parser::with_definitions(reader, definitions, self.errors);
+                let program = Box::new(parser.parse_program());
+                self.definitions = parser.definitions();
+                program
             }
 
             _ => {
@@ -23,6 +23,9 @@ pub enum Stmt {
     Jump(Jump),
     Return(Box<(Expr, Span)>),
     Case(Option<Box<(Expr, Span)>>),
+
+    /// A GMS `enum`. The parser replaces references to its members with their values.
+    Enum((Symbol, Span), Box<[((Symbol, Span), f64)]>),
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -47,6 +50,15 @@ pub enum Expr {
     Field(Box<(Expr, Span)>, (Symbol, Span)),
     Index(Box<(Expr, Span)>, Box<[(Expr, Span)]>),
     Call(Call),
+
+    /// A GMS `cond ? a : b`.
+    Conditional(Box<(Expr, Span)>, Box<(Expr, Span)>, Box<(Expr, Span)>),
+    /// A GMS `++x` or `--x`, as `Op::Add` or `Op::Subtract`.
+    Prefix((Op, Span), Box<(Expr, Span)>),
+    /// A GMS `x++` or `x--`, as `Op::Add` or `Op::Subtract`.
+    Postfix((Op, Span), Box<(Expr, Span)>),
+    /// A GMS `a[@ i]`, `list[| i]`, `map[? key]`, or `grid[# x, y]`.
+    Accessor(Accessor, Box<(Expr, Span)>, Box<[(Expr, Span)]>),
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -56,6 +68,15 @@ pub enum Value {
     String(Symbol),
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Accessor {
+    /// Write to an array in place, even when that would otherwise copy it.
+    Array,
+    List,
+    Map,
+    Grid,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum Unary {
     Positive,
@@ -1,7 +1,7 @@
 use std::{mem, cmp, iter};
 use std::collections::HashMap;
 
-use crate::ErrorPrinter;
+use crate::{ErrorPrinter, Dialect};
 use crate::symbol::{Symbol, keyword};
 use crate::front::{self, ast, Span};
 use crate::back::ssa;
@@ -11,6 +11,7 @@ pub struct Codegen<'p, 'e, 'f> {
     function: ssa::Function,
     builder: front::ssa::Builder,
     errors: &'e mut ErrorPrinter<'f>,
+    dialect: Dialect,
 
     prototypes: &'p HashMap<Symbol, ssa::Prototype>,
 
@@ -37,12 +38,14 @@ pub struct Codegen<'p, 'e, 'f> {
 /// A location that can be read from or written to.
 ///
 /// Pre-studio GML arrays are not first class values, and are instead tied to variable bindings.
-/// To accomodate this, `Place` uses a `Path` rather than an `ssa::Value`. To support GMS arrays
-/// and data structure accessors, `Path` might gain a `Value` variant.
+/// To accomodate this, `Place` uses a `Path` rather than an `ssa::Value`. GMS arrays are values,
+/// but are still copied on write through their variable, so they use the same `Path`s.
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
 struct Place {
     path: Path,
     index: Option<[ssa::Value; 2]>,
+    /// Whether an indexed store writes to a shared GMS array rather than copying it, as `[@ ]`.
+    in_place: bool,
 }
 
 /// A "path" to a variable. See `Place`.
@@ -54,6 +57,8 @@ enum Path {
     Field(ssa::Value, Symbol),
     /// A prefixed variable dynamically referencing an instance or object.
     Scope(ssa::Value, Symbol),
+    /// An element of a GMS data structure, accessed through runner functions.
+    Accessor(ast::Accessor, ssa::Value),
 }
 
 #[derive(Debug)]
@@ -66,6 +71,8 @@ struct Local {
     /// error for uninitialized uses would reject some valid GML programs.
     flag: front::ssa::Local,
     local: front::ssa::Local,
+    /// In GMS, the slot on the thread owned-value stack that keeps the local's array alive.
+    owner: Option<ssa::Value>,
 }
 
 /// The header of a `with` loop.
@@ -90,6 +97,15 @@ const LOCAL: f64 = vm::LOCAL as f64;
 impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
     pub fn new(
         prototypes: &'p HashMap<Symbol, ssa::Prototype>, errors: &'e mut ErrorPrinter<'f>
+    ) -> Self {
+        Self::with_dialect(prototypes, Dialect::default(), errors)
+    }
+
+    /// Generate code with the semantics of `dialect`, which must match the parser's.
+    pub fn with_dialect(
+        prototypes: &'p HashMap<Symbol, ssa::Prototype>,
+        dialect: Dialect,
+        errors: &'e mut ErrorPrinter<'f>,
     ) -> Self {
         let function = ssa::Function::new();
         let builder = front::ssa::Builder::new();
@@ -97,6 +113,7 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
             function,
             builder,
             errors,
+            dialect,
 
             prototypes,
 
@@ -248,6 +265,7 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
 
         let mut args = vec![];
         let loc = span.low;
+        let mark = self.emit_owned_mark(loc);
         if let Some(relative) = relative {
             args.push(self.emit_real(relative as u64 as f64, loc));
         }
@@ -273,7 +291,7 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
             args.push(value);
         }
 
-        self.emit_value_call(symbol, args)
+        self.emit_value_call(symbol, args, mark)
     }
 
     fn emit_statement(&mut self, statement: &(ast::Stmt, Span)) {
@@ -284,10 +302,11 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
             }
 
             ast::Stmt::Invoke(ast::Call(symbol, box ref args)) => {
+                let mark = self.emit_owned_mark(statement_span.low);
                 let args: Vec<_> = args.iter()
                     .map(|argument| self.emit_value(argument))
                     .collect();
-                self.emit_value_call(symbol, args);
+                self.emit_value_call(symbol, args, mark);
             }
 
             ast::Stmt::Declare(scope, box ref names) => {
@@ -346,9 +365,11 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
                 let body_block = self.make_block();
                 let exit_block = self.make_block();
 
+                let mark = self.emit_owned_mark(statement_span.low);
                 self.emit_jump(cond_block, statement_span.low);
 
                 self.current_block = cond_block;
+                self.emit_owned_release(mark, loc(expr));
                 let value = self.emit_value(expr);
                 self.emit_branch(value, body_block, exit_block, loc(expr));
                 self.seal_block(body_block);
@@ -369,9 +390,11 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
                 let cond_block = self.make_block();
                 let exit_block = self.make_block();
 
+                let mark = self.emit_owned_mark(statement_span.low);
                 self.emit_jump(body_block, statement_span.low);
 
                 self.current_block = body_block;
+                self.emit_owned_release(mark, statement_span.low);
                 self.with_loop(cond_block, exit_block, |self_| {
                     self_.emit_statement(body);
                 });
@@ -394,9 +417,11 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
                 let exit_block = self.make_block();
 
                 self.emit_statement(init);
+                let mark = self.emit_owned_mark(end_loc(init));
                 self.emit_jump(cond_block, end_loc(init));
 
                 self.current_block = cond_block;
+                self.emit_owned_release(mark, loc(expr));
                 let value = self.emit_value(expr);
                 self.emit_branch(value, body_block, exit_block, loc(expr));
                 self.seal_block(body_block);
@@ -517,7 +542,7 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
                 self.seal_block(dead_block);
             }
 
-            ast::Stmt::Error(_) => {}
+            ast::Stmt::Enum(..) | ast::Stmt::Error(_) => {}
         }
     }
 
@@ -592,9 +617,11 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
         let iter = self.builder.emit_local();
         let count = self.emit_value(expr);
         self.write_local(iter, count);
+        let mark = self.emit_owned_mark(loc(expr));
         self.emit_jump(cond_block, loc(expr));
 
         self.current_block = cond_block;
+        self.emit_owned_release(mark, loc(expr));
         let count = self.read_local(iter);
         let one = self.emit_real(1.0, loc(expr));
         let next = self.emit_binary(ssa::Opcode::Subtract, [count, one], loc(expr));
@@ -660,6 +687,9 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
         let (ref expr, expr_span) = *expression;
         let expr_loc = expr_span.low;
         match *expr {
+            // The parser has already reported the error.
+            ast::Expr::Error => self.emit_real(0.0, expr_loc),
+
             ast::Expr::Value(ast::Value::Real(real)) => self.emit_real(real, expr_loc),
             ast::Expr::Value(ast::Value::String(string)) => self.emit_string(string, expr_loc),
 
@@ -691,10 +721,48 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
             }
 
             ast::Expr::Call(ast::Call(symbol, box ref args)) => {
+                let mark = self.emit_owned_mark(expr_loc);
                 let args: Vec<_> = args.iter()
                     .map(|argument| self.emit_value(argument))
                     .collect();
-                self.emit_value_call(symbol, args)
+                self.emit_value_call(symbol, args, mark)
+            }
+
+            ast::Expr::Conditional(box ref expr, box ref true_expr, box ref false_expr) => {
+                let result = self.builder.emit_local();
+                let value = self.emit_value(expr);
+                self.emit_if(
+                    (value, expr_loc),
+                    (|self_: &mut Self| {
+                        let value = self_.emit_value(true_expr);
+                        self_.write_local(result, value);
+                    }, loc(true_expr)),
+                    Some((|self_: &mut Self| {
+                        let value = self_.emit_value(false_expr);
+                        self_.write_local(result, value);
+                    }, loc(false_expr)))
+                );
+                self.read_local(result)
+            }
+
+            ast::Expr::Prefix((op, op_span), box ref place) |
+            ast::Expr::Postfix((op, op_span), box ref place) => {
+                let (_, place_span) = *place;
+                let place = match self.emit_place(place) {
+                    Ok(place) => place,
+                    Err(PlaceError) => return self.emit_real(0.0, expr_loc),
+                };
+
+                let old = self.emit_load(place.clone(), place_span);
+                let one = self.emit_real(1.0, op_span.low);
+                let op = ssa::Opcode::from(ast::Binary::Op(op));
+                let new = self.emit_binary(op, [old, one], op_span.low);
+                self.emit_store(place, new, op_span.low);
+
+                match *expr {
+                    ast::Expr::Prefix(..) => new,
+                    _ => old,
+                }
             }
 
             _ => {
@@ -707,14 +775,18 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
                     }
                 }
 
-                let place = self.emit_place(expression)
-                    .expect("_ is not a valid expression");
-                self.emit_load(place, expr_span)
+                match self.emit_place(expression) {
+                    Ok(place) => self.emit_load(place, expr_span),
+                    Err(PlaceError) => self.emit_real(0.0, expr_loc),
+                }
             }
         }
     }
 
-    fn emit_value_call(&mut self, symbol: (Symbol, Span), args: Vec<ssa::Value>) -> ssa::Value {
+    /// Call a function or script, with `mark` from before its arguments were evaluated.
+    fn emit_value_call(
+        &mut self, symbol: (Symbol, Span), args: Vec<ssa::Value>, mark: Option<ssa::Value>
+    ) -> ssa::Value {
         let (symbol, symbol_span) = symbol;
 
         let (op, arity, variadic) = match self.prototypes.get(&symbol) {
@@ -737,7 +809,13 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
 
         let array = self.emit_call(op, symbol, args, symbol_span.low);
 
-        // TODO: this only happens pre-gms
+        // GMS scripts may return arrays, which must stay alive for as long as they are used.
+        // The arguments, on the other hand, are no longer needed.
+        if let Some(mark) = mark {
+            self.emit_unary(ssa::Opcode::ReleaseCall, mark, symbol_span.low);
+            return array;
+        }
+
         let value = self.emit_unary(ssa::Opcode::ToScalar, array, symbol_span.low);
         self.emit_nullary(ssa::Opcode::ReleaseOwned, symbol_span.low);
         value
@@ -752,7 +830,6 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
                         let symbol = Symbol::from_argument(argument);
 
                         let parameter = self.function.emit_parameter(ssa::ENTRY);
-
                         let local = self.emit_local(Some(parameter));
                         self.locals.insert(symbol, local);
                     }
@@ -760,7 +837,7 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
                 }
 
                 if self.locals.contains_key(&symbol) {
-                    Ok(Place { path: Path::Local(symbol), index: None })
+                    Ok(Place { path: Path::Local(symbol), index: None, in_place: false })
                 } else {
                     // Built-in variables are always local; globalvar cannot redeclare them.
                     // TODO: move into peephole optimizer
@@ -769,7 +846,7 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
                     } else {
                         self.emit_unary_symbol(ssa::Opcode::Lookup, symbol, expression_span.low)
                     };
-                    Ok(Place { path: Path::Field(entity, symbol), index: None })
+                    Ok(Place { path: Path::Field(entity, symbol), index: None, in_place: false })
                 }
             }
 
@@ -779,33 +856,57 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
                 (field, _field_span)
             ) => {
                 let entity = self.emit_unary_int(ssa::Opcode::LoadScope, vm::SELF, expr_span.low);
-                Ok(Place { path: Path::Field(entity, field), index: None })
+                Ok(Place { path: Path::Field(entity, field), index: None, in_place: false })
             }
             ast::Expr::Field(
                 box (ast::Expr::Value(ast::Value::Ident(keyword::Other)), expr_span),
                 (field, _field_span)
             ) => {
                 let entity = self.emit_unary_int(ssa::Opcode::LoadScope, vm::OTHER, expr_span.low);
-                Ok(Place { path: Path::Field(entity, field), index: None })
+                Ok(Place { path: Path::Field(entity, field), index: None, in_place: false })
             }
             ast::Expr::Field(
                 box (ast::Expr::Value(ast::Value::Ident(keyword::Global)), expr_span),
                 (field, _field_span)
             ) => {
                 let entity = self.emit_unary_int(ssa::Opcode::LoadScope, vm::GLOBAL, expr_span.low);
-                Ok(Place { path: Path::Field(entity, field), index: None })
+                Ok(Place { path: Path::Field(entity, field), index: None, in_place: false })
             }
 
             ast::Expr::Field(box ref expr, (field, _field_span)) => {
                 let scope = self.emit_value(expr);
-                Ok(Place { path: Path::Scope(scope, field), index: None })
+                Ok(Place { path: Path::Scope(scope, field), index: None, in_place: false })
+            }
+
+            ast::Expr::Accessor(accessor, box ref expr, box ref indices) if
+                accessor != ast::Accessor::Array
+            => {
+                let arity = if accessor == ast::Accessor::Grid { 2 } else { 1 };
+                if indices.len() != arity {
+                    self.errors.error(expression_span,
+                        format_args!("invalid number of data structure indices"));
+                }
+
+                let value = self.emit_value(expr);
+                let zero = self.emit_real(0.0, loc(expr));
+                let mut indices = indices.iter().rev()
+                    .map(|index| self.emit_value(index))
+                    .chain(iter::repeat(zero));
+
+                let j = indices.next().unwrap();
+                let i = indices.next().unwrap();
+
+                let path = Path::Accessor(accessor, value);
+                Ok(Place { path, index: Some([i, j]), in_place: false })
             }
 
-            ast::Expr::Index(box ref expr, box ref indices) => {
+            ast::Expr::Index(box ref expr, box ref indices) |
+            ast::Expr::Accessor(_, box ref expr, box ref indices) => {
                 if indices.len() < 1 || 2 < indices.len() {
                     self.errors.error(expression_span,
                         format_args!("invalid number of array indices"));
                 }
+                let in_place = matches!(*expression, ast::Expr::Accessor(..));
 
                 let array = self.emit_place(expr)?;
                 let zero = self.emit_real(0.0, loc(expr));
@@ -817,7 +918,9 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
                 let i = indices.next().unwrap();
 
                 match array {
-                    Place { path, index: None } => Ok(Place { path, index: Some([i, j]) }),
+                    Place { path, index: None, .. } => {
+                        Ok(Place { path, index: Some([i, j]), in_place })
+                    }
                     Place { index: Some(_), .. } => {
                         let (_, expr_span) = *expr;
                         self.errors.error(expr_span, format_args!("expected a variable"));
@@ -838,11 +941,26 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
     /// This handles GML's odd behavior around arrays. Before GMS:
     /// - all loads produce scalars; if the variable holds an array it loads `a[0, 0]`
     /// - indexed loads from scalar variables treat the variable as a 1x1 array
+    ///
+    /// In GMS, loads produce arrays as they are, and indexed loads from scalars are errors.
     fn emit_load(&mut self, place: Place, place_span: Span) -> ssa::Value {
         let value = match place {
+            // A data structure element: call its getter.
+            Place { path: Path::Accessor(accessor, ds), index, .. } => {
+                let [i, j] = index.expect("corrupt accessor");
+                let (name, args): (&[u8], _) = match accessor {
+                    ast::Accessor::List => (b"ds_list_find_value", vec![ds, j]),
+                    ast::Accessor::Map => (b"ds_map_find_value", vec![ds, j]),
+                    ast::Accessor::Grid => (b"ds_grid_get", vec![ds, i, j]),
+                    ast::Accessor::Array => unreachable!(),
+                };
+                let mark = self.emit_owned_mark(place_span.low);
+                return self.emit_value_call((Symbol::intern(name), place_span), args, mark);
+            }
+
             // A locally-declared variable: check for initialization, then read it.
-            Place { path: Path::Local(symbol), index } => {
-                let Local { flag, local } = self.locals[&symbol];
+            Place { path: Path::Local(symbol), index, .. } => {
+                let Local { flag, local, .. } = self.locals[&symbol];
 
                 let flag = self.read_local(flag);
                 self.emit_binary_symbol(ssa::Opcode::Read, flag, symbol, place_span.low);
@@ -855,14 +973,14 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
             }
 
             // A built-in member variable: call its getter.
-            Place { path: Path::Field(entity, field), index } if
+            Place { path: Path::Field(entity, field), index, .. } if
                 self.field_is_builtin(field) && !self.entity_is_global(entity)
             => {
                 self.emit_load_builtin(entity, field, index, place_span.low)
             }
 
             // A user-defined member variable: read it.
-            Place { path: Path::Field(entity, field), index } => {
+            Place { path: Path::Field(entity, field), index, .. } => {
                 let value = self.emit_binary_symbol(ssa::Opcode::LoadField, entity, field, place_span.low);
                 match index {
                     None => value,
@@ -874,7 +992,7 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
             // (`global` does not have built-in variables, and so must fall back to the
             // user-defined case as above.)
             // TODO: fallback only happens pre-gms.
-            Place { path: Path::Scope(scope, field), index } if
+            Place { path: Path::Scope(scope, field), index, .. } if
                 self.field_is_builtin(field)
             => {
                 let true_block = self.make_block();
@@ -910,7 +1028,7 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
             }
 
             // A user-defined member variable on a scope: locate the first entity, then read it.
-            Place { path: Path::Scope(scope, field), index } => {
+            Place { path: Path::Scope(scope, field), index, .. } => {
                 let entity = self.emit_load_scope(scope, place_span.low);
                 let value = self.emit_binary_symbol(ssa::Opcode::LoadField, entity, field, place_span.low);
                 match index {
@@ -920,8 +1038,11 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
             }
         };
 
-        // TODO: this only happens pre-gms
-        self.emit_unary(ssa::Opcode::ToScalar, value, place_span.low)
+        match self.dialect {
+            Dialect::Gm8 => self.emit_unary(ssa::Opcode::ToScalar, value, place_span.low),
+            // Keep the array alive in case its variable is overwritten while it is still in use.
+            Dialect::Gms => self.emit_unary(ssa::Opcode::Share, value, place_span.low),
+        }
     }
 
     /// Resolve a scope to its first entity for reading. (Helper for `emit_load`.)
@@ -955,13 +1076,17 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
 
     /// Load an element of an array. (Helper for `emit_load`.)
     fn emit_load_index(&mut self, value: ssa::Value, [i, j]: [ssa::Value; 2], location: usize) -> ssa::Value {
-        // TODO: this only happens pre-gms
+        // GMS does not treat scalars as arrays, so indexing one is an error in `LoadRow`.
+        if self.dialect == Dialect::Gms {
+            let row = self.emit_binary(ssa::Opcode::LoadRow, [value, i], location);
+            return self.emit_binary(ssa::Opcode::LoadIndex, [row, j], location);
+        }
+
         let array = self.emit_binary_int(ssa::Opcode::ToArray, value, vm::PUSH_ANY, location);
 
         let row = self.emit_binary(ssa::Opcode::LoadRow, [array, i], location);
         let value = self.emit_binary(ssa::Opcode::LoadIndex, [row, j], location);
 
-        // TODO: this only happens pre-gms
         self.emit_nullary(ssa::Opcode::ReleaseOwned, location);
         value
     }
@@ -974,30 +1099,60 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
     /// Before GMS:
     /// - stores to array variables do *not* overwrite the whole array, only `a[0, 0]`
     /// - indexed stores to scalar (or undefined) variables leave the scalar (or `0`) at `a[0, 0]`
-    // TODO: free overwritten arrays for gms
+    ///
+    /// In GMS, stores overwrite the whole variable, and indexed stores copy arrays that are shared
+    /// with other variables (see `emit_store_array`).
     fn emit_store(&mut self, place: Place, value: ssa::Value, location: usize) {
+        let in_place = place.in_place;
         match place {
+            // A data structure element: call its setter.
+            Place { path: Path::Accessor(accessor, ds), index, .. } => {
+                let [i, j] = index.expect("corrupt accessor");
+                let (name, args): (&[u8], _) = match accessor {
+                    ast::Accessor::List => (b"ds_list_set", vec![ds, j, value]),
+                    ast::Accessor::Map => (b"ds_map_set", vec![ds, j, value]),
+                    ast::Accessor::Grid => (b"ds_grid_set", vec![ds, i, j, value]),
+                    ast::Accessor::Array => unreachable!(),
+                };
+                let span = Span { low: location, high: location };
+                let mark = self.emit_owned_mark(location);
+                self.emit_value_call((Symbol::intern(name), span), args, mark);
+            }
+
             // A locally-declared variable: mark as initialized, then write it.
-            Place { path: Path::Local(symbol), index } => {
-                let Local { flag, local } = self.locals[&symbol];
+            Place { path: Path::Local(symbol), index, .. } => {
+                let Local { flag, local, owner } = self.locals[&symbol];
 
                 let one = self.emit_real(1.0, location);
                 self.write_local(flag, one);
 
                 match index {
                     None => {
-                        // TODO: this only happens pre-gms
-                        let array = self.read_local(local);
-                        let value = self.emit_binary(ssa::Opcode::Write, [value, array], location);
+                        let value = match self.dialect {
+                            Dialect::Gm8 => {
+                                let array = self.read_local(local);
+                                self.emit_binary(ssa::Opcode::Write, [value, array], location)
+                            }
+                            Dialect::Gms => value,
+                        };
+                        if let Some(owner) = owner {
+                            self.emit_binary(ssa::Opcode::StoreOwned, [value, owner], location);
+                        }
 
                         self.write_local(local, value);
                     }
                     Some([i, j]) => {
                         let array = self.read_local(local);
 
-                        // TODO: this only happens pre-gms; gms does need to handle undef
-                        let array = self.emit_binary_int(ssa::Opcode::ToArray, array, vm::PUSH_ARRAY, location);
+                        // A GMS local keeps its array in its own slot, so the result is released
+                        // from the top of the stack as soon as it is stored there.
+                        let push = if owner.is_some() { vm::PUSH_ANY } else { vm::PUSH_ARRAY };
+                        let array = self.emit_store_array(array, push, in_place, location);
                         self.write_local(local, array);
+                        if let Some(owner) = owner {
+                            self.emit_binary(ssa::Opcode::StoreOwned, [array, owner], location);
+                            self.emit_nullary(ssa::Opcode::ReleaseOwned, location);
+                        }
 
                         let row = self.emit_binary(ssa::Opcode::StoreRow, [array, i], location);
                         self.emit_ternary(ssa::Opcode::StoreIndex, [value, row, j], location);
@@ -1006,22 +1161,22 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
             }
 
             // A built-in member variable: call its setter.
-            Place { path: Path::Field(entity, field), index } if
+            Place { path: Path::Field(entity, field), index, .. } if
                 self.field_is_builtin(field) && !self.entity_is_global(entity)
             => {
                 self.emit_store_builtin(entity, field, index, value, location);
             }
 
             // A user-defined member variable: write it.
-            Place { path: Path::Field(entity, field), index } => {
-                self.emit_store_field(entity, field, index, value, location);
+            Place { path: Path::Field(entity, field), index, .. } => {
+                self.emit_store_field(entity, field, index, in_place, value, location);
             }
 
             // A built-in member variable on a scope: check for `global`, then call its setter.
             // (`global` does not have built-in variables, and so must fall back to the
             // user-defined case as above.)
             // TODO: fallback only happens pre-gms.
-            Place { path: Path::Scope(scope, field), index } if
+            Place { path: Path::Scope(scope, field), index, .. } if
                 self.field_is_builtin(field)
             => {
                 let true_block = self.make_block();
@@ -1042,7 +1197,7 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
 
                 self.current_block = false_block;
                 let entity = self.emit_unary_int(ssa::Opcode::LoadScope, vm::GLOBAL, location);
-                self.emit_store_field(entity, field, index, value, location);
+                self.emit_store_field(entity, field, index, in_place, value, location);
                 self.emit_jump(merge_block, location);
 
                 self.seal_block(merge_block);
@@ -1050,9 +1205,9 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
             }
 
             // A user-defined member variable on a scope: write to all entities.
-            Place { path: Path::Scope(scope, field), index } => {
+            Place { path: Path::Scope(scope, field), index, .. } => {
                 self.emit_store_scope(scope, location, |self_, entity| {
-                    self_.emit_store_field(entity, field, index, value, location);
+                    self_.emit_store_field(entity, field, index, in_place, value, location);
                 });
             }
         }
@@ -1092,22 +1247,26 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
     /// Note that this is the same pattern as `emit_store`'s `Path::Local` arm.
     fn emit_store_field(
         &mut self, entity: ssa::Value, field: Symbol, index: Option<[ssa::Value; 2]>,
+        in_place: bool,
         value: ssa::Value,
         location: usize,
     ) {
         match index {
             None => {
-                // TODO: this only happens pre-gms
-                let array = self.emit_binary_symbol(ssa::Opcode::LoadFieldDefault, entity, field, location);
-                let value = self.emit_binary(ssa::Opcode::Write, [value, array], location);
+                let value = match self.dialect {
+                    Dialect::Gm8 => {
+                        let array = self.emit_binary_symbol(ssa::Opcode::LoadFieldDefault, entity, field, location);
+                        self.emit_binary(ssa::Opcode::Write, [value, array], location)
+                    }
+                    Dialect::Gms => value,
+                };
 
                 self.emit_ternary_symbol(ssa::Opcode::StoreField, [value, entity], field, location);
             }
             Some([i, j]) => {
                 let array = self.emit_binary_symbol(ssa::Opcode::LoadFieldDefault, entity, field, location);
 
-                // TODO: this only happens pre-gms; gms does need to handle undef
-                let array = self.emit_binary_int(ssa::Opcode::ToArray, array, vm::PUSH_ANY, location);
+                let array = self.emit_store_array(array, vm::PUSH_ANY, in_place, location);
                 self.emit_ternary_symbol(ssa::Opcode::StoreField, [array, entity], field, location);
                 self.emit_nullary(ssa::Opcode::ReleaseOwned, location);
 
@@ -1117,6 +1276,41 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
         }
     }
 
+    /// Prepare a variable's value for an indexed store. (Helper for `emit_store`.)
+    ///
+    /// Before GMS, this converts scalars to arrays holding them. In GMS, it replaces scalars with
+    /// new arrays, and copies arrays that other variables still hold unless the store is
+    /// `in_place`.
+    fn emit_store_array(
+        &mut self, array: ssa::Value, push: i32, in_place: bool, location: usize
+    ) -> ssa::Value {
+        match self.dialect {
+            Dialect::Gm8 => self.emit_binary_int(ssa::Opcode::ToArray, array, push, location),
+            Dialect::Gms => {
+                let flags = if in_place { push | vm::IN_PLACE } else { push };
+                self.emit_binary_int(ssa::Opcode::ToUnique, array, flags, location)
+            }
+        }
+    }
+
+    /// Mark the thread owned-value stack, in GMS, where temporary arrays are kept alive.
+    ///
+    /// Calls release their arguments' temporaries back down to a mark, and loops release each
+    /// iteration's, so that neither piles up.
+    fn emit_owned_mark(&mut self, location: usize) -> Option<ssa::Value> {
+        match self.dialect {
+            Dialect::Gm8 => None,
+            Dialect::Gms => Some(self.emit_nullary(ssa::Opcode::MarkOwned, location)),
+        }
+    }
+
+    /// Release the temporaries kept alive since `mark`. (Helper for loop headers.)
+    fn emit_owned_release(&mut self, mark: Option<ssa::Value>, location: usize) {
+        if let Some(mark) = mark {
+            self.emit_unary(ssa::Opcode::ReleaseOwnedTo, mark, location);
+        }
+    }
+
     /// Loop header for instance iteration.
     fn emit_with_header(&mut self, scope: ssa::Value, location: usize) -> With {
         let cond_block = self.make_block();
@@ -1129,9 +1323,11 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
         let ptr = self.function.values.push(ssa::Instruction::Project { arg: with, index: 0 });
         let end = self.function.values.push(ssa::Instruction::Project { arg: with, index: 1 });
         self.write_local(iter, ptr);
+        let mark = self.emit_owned_mark(location);
         self.emit_jump(cond_block, location);
 
         self.current_block = cond_block;
+        self.emit_owned_release(mark, location);
         let ptr = self.read_local(iter);
         let expr = self.emit_binary(ssa::Opcode::NePointer, [ptr, end], location);
         self.emit_branch(expr, scan_block, exit_block, location);
@@ -1185,7 +1381,7 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
 
     fn field_is_builtin(&self, field: Symbol) -> bool {
         match self.prototypes.get(&field) {
-            Some(&ssa::Prototype::Member) => true,
+            Some(&ssa::Prototype::Member { .. }) => true,
             _ => false,
         }
     }
@@ -1229,7 +1425,20 @@ impl<'p, 'e, 'f> Codegen<'p, 'e, 'f> {
         });
         self.builder.write_local(ssa::ENTRY, local, default);
 
-        Local { flag, local }
+        // A GMS array passed to a script is also held by the caller, so writing to it in the
+        // script makes a copy.
+        let owner = match self.dialect {
+            Dialect::Gm8 => None,
+            Dialect::Gms => {
+                let op = ssa::Opcode::OwnLocal;
+                let owner = self.emit_initializer(ssa::Instruction::Nullary { op });
+                let op = ssa::Opcode::StoreOwned;
+                self.emit_initializer(ssa::Instruction::Binary { op, args: [default, owner] });
+                Some(owner)
+            }
+        };
+
+        Local { flag, local, owner }
     }
 
     fn emit_initializer(&mut self, instruction: ssa::Instruction) -> ssa::Value {
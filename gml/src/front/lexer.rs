@@ -1,18 +1,94 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::Dialect;
 use crate::symbol::Symbol;
 use crate::front::Span;
-use crate::front::token::{Token, BinOp, Delim};
+use crate::front::token::{Token, BinOp, Delim, Accessor};
 
 pub struct Lexer<'s> {
     source: &'s [u8],
     position: usize,
+    comments: Vec<Span>,
+    dialect: Dialect,
+
+    /// The tokens of each `#macro` defined so far. Macros they use are expanded along with them.
+    macros: Rc<HashMap<Symbol, Box<[Token]>>>,
+    /// The rest of the macros being expanded, in reverse order, and the span of their use.
+    /// Each token is paired with the number of macros it is nested in, from `expanding`.
+    expansion: Vec<(Token, usize)>,
+    expansion_span: Span,
+    /// The macros being expanded, outermost first. These are not expanded again.
+    expanding: Vec<Symbol>,
+    /// Whether to define and expand macros, or leave them as written.
+    expand: bool,
 }
 
 impl<'s> Lexer<'s> {
     pub fn new(source: &'s [u8], position: usize) -> Lexer<'s> {
-        Lexer { source, position }
+        Self::with_dialect(source, position, Dialect::default())
     }
 
+    pub fn with_dialect(source: &'s [u8], position: usize, dialect: Dialect) -> Lexer<'s> {
+        Lexer {
+            source,
+            position,
+            comments: vec![],
+            dialect,
+
+            macros: Rc::default(),
+            expansion: vec![],
+            expansion_span: Span { low: position, high: position },
+            expanding: vec![],
+            expand: true,
+        }
+    }
+
+    /// Leave macros unexpanded, keeping `#macro` lines as comments, so that the source can be
+    /// printed back out as it was written.
+    pub fn without_expansion(mut self) -> Lexer<'s> {
+        self.expand = false;
+        self
+    }
+
+    /// Whether macros, and so enum members, are replaced by what they stand for.
+    pub fn expands(&self) -> bool {
+        self.expand
+    }
+
+    /// Expand the macros in `macros`, such as those defined elsewhere in a project.
+    pub fn with_macros(mut self, macros: Rc<HashMap<Symbol, Box<[Token]>>>) -> Lexer<'s> {
+        self.macros = macros;
+        self
+    }
+
+    /// The macros defined so far, including those this lexer started with.
+    pub fn macros(&self) -> &Rc<HashMap<Symbol, Box<[Token]>>> {
+        &self.macros
+    }
+
+    pub fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+
+    /// The comments skipped so far, in order. This includes GMS `#region` and `#endregion`
+    /// lines, which only serve to organize the source text.
+    pub fn comments(&self) -> &[Span] {
+        &self.comments
+    }
+
+    /// Read the next token. Tokens expanded from a macro all have the span of its name.
     pub fn read_token(&mut self) -> (Token, Span) {
+        if let Some((token, depth)) = self.expansion.pop() {
+            self.expanding.truncate(depth);
+            if let Token::Ident(symbol) = token {
+                if self.expand(symbol) {
+                    return self.read_token();
+                }
+            }
+            return (token, self.expansion_span);
+        }
+
         self.scan_whitespace_or_comment();
 
         let low = self.position;
@@ -35,8 +111,29 @@ impl<'s> Lexer<'s> {
             Token::Eof
         };
         let high = self.position;
+        let span = Span { low: low, high: high };
 
-        (token, Span { low: low, high: high })
+        if let Token::Ident(symbol) = token {
+            self.expanding.clear();
+            if self.expand(symbol) {
+                self.expansion_span = span;
+                return self.read_token();
+            }
+        }
+
+        (token, span)
+    }
+
+    /// Queue up the tokens of the macro `symbol`, unless it is already being expanded.
+    fn expand(&mut self, symbol: Symbol) -> bool {
+        let tokens = match self.macros.get(&symbol) {
+            Some(tokens) if !self.expanding.contains(&symbol) => tokens,
+            _ => return false,
+        };
+        self.expanding.push(symbol);
+        let depth = self.expanding.len();
+        self.expansion.extend(tokens.iter().rev().map(|&token| (token, depth)));
+        true
     }
 
     fn scan_whitespace_or_comment(&mut self) {
@@ -45,6 +142,7 @@ impl<'s> Lexer<'s> {
                 Some(b' ') | Some(b'\t') | Some(b'\n') | Some(b'\r') => (),
 
                 Some(b'/') if self.next_char() == Some(b'/') => {
+                    let low = self.position;
                     self.advance_byte();
                     self.advance_byte();
 
@@ -54,9 +152,14 @@ impl<'s> Lexer<'s> {
                         }
                         self.advance_byte();
                     }
+
+                    let high = self.position;
+                    self.comments.push(Span { low, high });
+                    continue;
                 }
 
                 Some(b'/') if self.next_char() == Some(b'*') => {
+                    let low = self.position;
                     self.advance_byte();
                     self.advance_byte();
 
@@ -64,6 +167,7 @@ impl<'s> Lexer<'s> {
                         match self.current() {
                             None => break,
                             Some(b'*') if self.next_char() == Some(b'/') => {
+                                self.advance_byte();
                                 self.advance_byte();
                                 break;
                             }
@@ -71,8 +175,14 @@ impl<'s> Lexer<'s> {
                         }
                         self.advance_byte();
                     }
+
+                    let high = self.position;
+                    self.comments.push(Span { low, high });
+                    continue;
                 }
 
+                Some(b'#') if self.dialect == Dialect::Gms && self.scan_directive() => continue,
+
                 _ => break,
             }
 
@@ -80,6 +190,77 @@ impl<'s> Lexer<'s> {
         }
     }
 
+    /// Skip a GMS `#region`, `#endregion`, or `#macro` line, defining the macro if macros are
+    /// expanded.
+    ///
+    /// Anything else is left for `read_token` to report as an unexpected `#`.
+    fn scan_directive(&mut self) -> bool {
+        let low = self.position;
+        let is_directive = |source: &[u8], name: &[u8]| {
+            source.starts_with(name) && !is_ident_continue(source.get(name.len()).copied())
+        };
+
+        if is_directive(self.source, b"#region") || is_directive(self.source, b"#endregion") {
+            self.skip_line();
+            let high = self.position;
+            self.comments.push(Span { low: low, high: high });
+            return true;
+        }
+
+        if !is_directive(self.source, b"#macro") {
+            return false;
+        }
+        let name_start = self.source[6..].iter()
+            .take_while(|&&c| c == b' ' || c == b'\t')
+            .count() + 6;
+        if !is_ident_start(self.source.get(name_start).copied()) {
+            return false;
+        }
+
+        for _ in 0..name_start {
+            self.advance_byte();
+        }
+        let name = match self.scan_ident_or_keyword() {
+            Token::Ident(name) | Token::Keyword(name) => name,
+            _ => unreachable!(),
+        };
+
+        if !self.expand {
+            self.skip_line();
+            let high = self.position;
+            self.comments.push(Span { low, high });
+            return true;
+        }
+
+        // The body is lexed on its own, leaving any macros it uses to be expanded along with it.
+        // This way it may use macros that are defined after it, even in other scripts.
+        let source = self.source;
+        let body_low = self.position;
+        self.skip_line();
+        let body = &source[..self.position - body_low];
+        let mut lexer = Lexer::with_dialect(body, body_low, self.dialect);
+        let mut tokens = vec![];
+        loop {
+            match lexer.read_token() {
+                (Token::Eof, _) => break,
+                (token, _) => tokens.push(token),
+            }
+        }
+        self.comments.extend(lexer.comments);
+        Rc::make_mut(&mut self.macros).insert(name, tokens.into_boxed_slice());
+
+        true
+    }
+
+    fn skip_line(&mut self) {
+        while let Some(c) = self.current() {
+            if c == b'\n' {
+                break;
+            }
+            self.advance_byte();
+        }
+    }
+
     fn scan_ident_or_keyword(&mut self) -> Token {
         let source = &self.source[..];
         let low = self.position;
@@ -153,6 +334,13 @@ impl<'s> Lexer<'s> {
         match self.advance_byte() {
             Some(b'(') => Token::OpenDelim(Delim::Paren),
             Some(b')') => Token::CloseDelim(Delim::Paren),
+            Some(b'[') if self.dialect == Dialect::Gms => match self.current() {
+                Some(b'|') => { self.advance_byte(); Token::OpenAccessor(Accessor::List) }
+                Some(b'?') => { self.advance_byte(); Token::OpenAccessor(Accessor::Map) }
+                Some(b'#') => { self.advance_byte(); Token::OpenAccessor(Accessor::Grid) }
+                Some(b'@') => { self.advance_byte(); Token::OpenAccessor(Accessor::Array) }
+                _ => Token::OpenDelim(Delim::Bracket),
+            },
             Some(b'[') => Token::OpenDelim(Delim::Bracket),
             Some(b']') => Token::CloseDelim(Delim::Bracket),
             Some(b'{') => Token::OpenDelim(Delim::Brace),
@@ -178,6 +366,14 @@ impl<'s> Lexer<'s> {
                 _ => Token::Gt
             },
 
+            Some(b'+') if self.dialect == Dialect::Gms && self.current() == Some(b'+') => {
+                self.advance_byte();
+                Token::PlusPlus
+            }
+            Some(b'-') if self.dialect == Dialect::Gms && self.current() == Some(b'-') => {
+                self.advance_byte();
+                Token::MinusMinus
+            }
             Some(b'+') => self.scan_binop(BinOp::Plus),
             Some(b'-') => self.scan_binop(BinOp::Minus),
             Some(b'*') => self.scan_binop(BinOp::Star),
@@ -205,6 +401,7 @@ impl<'s> Lexer<'s> {
                 Some(b'=') => { self.advance_byte(); Token::ColonEq }
                 _ => Token::Colon
             },
+            Some(b'?') if self.dialect == Dialect::Gms => Token::Question,
 
             Some(c) => Token::Unexpected(c),
             None => Token::Eof,
@@ -257,7 +454,7 @@ fn is_digit(c: Option<u8>) -> bool {
 fn is_operator(c: Option<u8>) -> bool {
     [
         Some(b'{'), Some(b'}'), Some(b'('), Some(b')'), Some(b'['), Some(b']'),
-        Some(b'.'), Some(b','), Some(b':'), Some(b';'),
+        Some(b'.'), Some(b','), Some(b':'), Some(b';'), Some(b'?'),
         Some(b'+'), Some(b'-'), Some(b'*'), Some(b'/'),
         Some(b'|'), Some(b'&'), Some(b'^'), Some(b'~'),
         Some(b'='), Some(b'<'), Some(b'>'),
@@ -298,5 +495,58 @@ mod tests {
         assert_eq!(lexer.read_token(), (Token::Eq, span(27, 28)));
         assert_eq!(lexer.read_token(), (real(b"3"), span(29, 30)));
         assert_eq!(lexer.read_token(), (Token::Eof, span(30, 30)));
+        assert_eq!(lexer.comments(), &[span(0, 13)]);
+    }
+
+    #[test]
+    fn comments() {
+        let mut lexer = Lexer::new(b"x // line\n/* block */ y /* unterminated", 0);
+
+        assert_eq!(lexer.read_token(), (ident(b"x"), span(0, 1)));
+        assert_eq!(lexer.read_token(), (ident(b"y"), span(22, 23)));
+        assert_eq!(lexer.read_token(), (Token::Eof, span(39, 39)));
+        assert_eq!(lexer.comments(), &[span(2, 9), span(10, 21), span(24, 39)]);
+    }
+
+    #[test]
+    fn gms() {
+        let source = b"#region r\nx[| i]++ ? y : z\n#macro TWO 1 + 1\nTWO\n#endregion";
+        let mut lexer = Lexer::with_dialect(source, 0, Dialect::Gms);
+
+        assert_eq!(lexer.read_token(), (ident(b"x"), span(10, 11)));
+        assert_eq!(lexer.read_token(), (Token::OpenAccessor(Accessor::List), span(11, 13)));
+        assert_eq!(lexer.read_token(), (ident(b"i"), span(14, 15)));
+        assert_eq!(lexer.read_token(), (Token::CloseDelim(Delim::Bracket), span(15, 16)));
+        assert_eq!(lexer.read_token(), (Token::PlusPlus, span(16, 18)));
+        assert_eq!(lexer.read_token(), (Token::Question, span(19, 20)));
+        assert_eq!(lexer.read_token(), (ident(b"y"), span(21, 22)));
+        assert_eq!(lexer.read_token(), (Token::Colon, span(23, 24)));
+        assert_eq!(lexer.read_token(), (ident(b"z"), span(25, 26)));
+        assert_eq!(lexer.read_token(), (real(b"1"), span(44, 47)));
+        assert_eq!(lexer.read_token(), (Token::BinOp(BinOp::Plus), span(44, 47)));
+        assert_eq!(lexer.read_token(), (real(b"1"), span(44, 47)));
+        assert_eq!(lexer.read_token(), (Token::Eof, span(58, 58)));
+        assert_eq!(lexer.comments(), &[span(0, 9), span(48, 58)]);
+
+        let source = b"#macro A B + 1\n#macro B A\nA";
+        let mut lexer = Lexer::with_dialect(source, 0, Dialect::Gms);
+
+        assert_eq!(lexer.read_token(), (ident(b"A"), span(26, 27)));
+        assert_eq!(lexer.read_token(), (Token::BinOp(BinOp::Plus), span(26, 27)));
+        assert_eq!(lexer.read_token(), (real(b"1"), span(26, 27)));
+        assert_eq!(lexer.read_token(), (Token::Eof, span(27, 27)));
+
+        let mut lexer = Lexer::new(b"x ? y", 0);
+
+        assert_eq!(lexer.read_token(), (ident(b"x"), span(0, 1)));
+        assert_eq!(lexer.read_token(), (Token::Unexpected(b'?'), span(2, 3)));
+
+        let source = b"#macro TWO 1 + 1
+TWO";
+        let mut lexer = Lexer::with_dialect(source, 0, Dialect::Gms).without_expansion();
+
+        assert_eq!(lexer.read_token(), (ident(b"TWO"), span(17, 20)));
+        assert_eq!(lexer.read_token(), (Token::Eof, span(20, 20)));
+        assert_eq!(lexer.comments(), &[span(0, 16)]);
     }
 }
@@ -0,0 +1,448 @@
+use std::collections::HashMap;
+
+use crate::ErrorPrinter;
+use crate::symbol::Symbol;
+use crate::handle_map::Handle;
+use crate::bit_vec::BitVec;
+use crate::front::{ast, Lexer, Span};
+use crate::front::token::Token;
+use crate::back::{self, ssa};
+
+/// A pass that warns about code that compiles, but probably does not do what its author meant.
+///
+/// Lints run after `Codegen`, over both the syntax tree and the SSA built from it. They report
+/// warnings rather than errors, so they never cause a build to fail.
+pub struct Linter<'p, 'e, 'f> {
+    prototypes: &'p HashMap<Symbol, ssa::Prototype>,
+    resources: &'p Resources,
+    errors: &'e mut ErrorPrinter<'f>,
+
+    warnings: Vec<(Span, String)>,
+}
+
+/// The parts of a game that code is checked against, beyond its prototypes.
+#[derive(Default)]
+pub struct Resources {
+    /// The number of objects in the game, if it is known.
+    pub objects: Option<usize>,
+    /// The number of arguments each script uses, indexed by script id. See `arguments`.
+    pub arguments: Vec<Option<u32>>,
+}
+
+/// The ids below this are object indices, rather than instance ids.
+const FIRST_INSTANCE: f64 = 100_001.0;
+
+/// Find the number of arguments a script uses, from the highest `argumentN` it mentions.
+///
+/// Scripts that index the `argument` array may use any number of arguments, and give `None`.
+pub fn arguments(source: &[u8]) -> Option<u32> {
+    let argument = Symbol::intern(b"argument");
+
+    let mut lexer = Lexer::new(source, 0);
+    let mut arguments = 0;
+    loop {
+        match lexer.read_token() {
+            (Token::Eof, _) => return Some(arguments),
+            (Token::Ident(symbol), _) if symbol == argument => return None,
+            (Token::Ident(symbol), _) => if let Some(index) = symbol.as_argument() {
+                arguments = u32::max(arguments, index + 1);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl<'p, 'e, 'f> Linter<'p, 'e, 'f> {
+    pub fn new(
+        prototypes: &'p HashMap<Symbol, ssa::Prototype>,
+        resources: &'p Resources,
+        errors: &'e mut ErrorPrinter<'f>
+    ) -> Self {
+        Linter { prototypes, resources, errors, warnings: Vec::default() }
+    }
+
+    pub fn lint_event(mut self, event: &(ast::Action, Span), function: &ssa::Function) {
+        self.check_action(event);
+        self.check_function(function);
+        self.finish();
+    }
+
+    pub fn lint_program(mut self, program: &(ast::Stmt, Span), function: &ssa::Function) {
+        self.check_statement(program);
+        self.check_function(function);
+        self.finish();
+    }
+
+    /// Report the warnings in source order, once each.
+    fn finish(mut self) {
+        self.warnings.sort_by_key(|&(span, _)| span.low);
+        self.warnings.dedup();
+        for (span, message) in self.warnings {
+            self.errors.warning(span, format_args!("{}", message));
+        }
+    }
+
+    fn warn(&mut self, span: Span, message: String) {
+        self.warnings.push((span, message));
+    }
+
+    // Syntax tree lints:
+
+    fn check_action(&mut self, action: &(ast::Action, Span)) {
+        let (ref action, _) = *action;
+        match *action {
+            ast::Action::Normal { question: Some(box ref question), .. } => {
+                let ast::Question { ref true_action, ref false_action, .. } = *question;
+                self.check_action(true_action);
+                if let Some(ref false_action) = *false_action {
+                    self.check_action(false_action);
+                }
+            }
+
+            ast::Action::Block { box ref body } => {
+                let mut exit = false;
+                for action in body.iter() {
+                    let (ref action_ast, action_span) = *action;
+                    if exit {
+                        self.warn(action_span, String::from("unreachable action"));
+                        break;
+                    }
+                    self.check_action(action);
+                    exit = matches!(*action_ast, ast::Action::Exit);
+                }
+            }
+
+            ast::Action::Repeat { box ref body, .. } => self.check_action(body),
+            ast::Action::Code { box ref code, .. } => self.check_statement(code),
+
+            ast::Action::Normal { question: None, .. } |
+            ast::Action::Exit | ast::Action::Variable { .. } | ast::Action::Error => {}
+        }
+    }
+
+    fn check_statement(&mut self, statement: &(ast::Stmt, Span)) {
+        let (ref statement, _) = *statement;
+        match *statement {
+            ast::Stmt::Block(box ref statements) | ast::Stmt::Switch(_, box ref statements) => {
+                self.check_block(statements);
+            }
+
+            ast::Stmt::If(box ref expr, box ref true_branch, ref false_branch) => {
+                self.check_condition(expr);
+                self.check_statement(true_branch);
+                if let Some(box ref false_branch) = *false_branch {
+                    self.check_statement(false_branch);
+                }
+            }
+
+            ast::Stmt::While(box ref expr, box ref body) |
+            ast::Stmt::Do(box ref body, box ref expr) => {
+                self.check_condition(expr);
+                self.check_statement(body);
+            }
+
+            ast::Stmt::For(box ref init, box ref expr, box ref next, box ref body) => {
+                self.check_statement(init);
+                self.check_condition(expr);
+                self.check_statement(next);
+                self.check_statement(body);
+            }
+
+            ast::Stmt::Repeat(_, box ref body) | ast::Stmt::With(_, box ref body) => {
+                self.check_statement(body);
+            }
+
+            _ => {}
+        }
+    }
+
+    /// Warn about the first statement after each `exit` or `return`, up to the next `case`.
+    fn check_block(&mut self, statements: &[(ast::Stmt, Span)]) {
+        let mut exit = false;
+        for statement in statements {
+            let (ref statement_ast, statement_span) = *statement;
+            match *statement_ast {
+                ast::Stmt::Case(_) => {}
+                _ if exit => {
+                    self.warn(statement_span, String::from("unreachable code"));
+                    exit = false;
+                    continue;
+                }
+                _ => {}
+            }
+
+            self.check_statement(statement);
+            exit = matches!(
+                *statement_ast, ast::Stmt::Return(_) | ast::Stmt::Jump(ast::Jump::Exit)
+            );
+        }
+    }
+
+    /// Warn about `=` in conditions, where GML compares rather than assigns.
+    fn check_condition(&mut self, expr: &(ast::Expr, Span)) {
+        let (ref expr, _) = *expr;
+        match *expr {
+            // `==` and `:=` are wider.
+            ast::Expr::Binary((ast::Binary::Eq, span), _, _) if span.high - span.low == 1 => {
+                self.warn(span, String::from("`=` in a condition compares; use `==` instead"));
+            }
+
+            ast::Expr::Binary((ast::Binary::And, _), box ref left, box ref right) |
+            ast::Expr::Binary((ast::Binary::Or, _), box ref left, box ref right) |
+            ast::Expr::Binary((ast::Binary::Xor, _), box ref left, box ref right) => {
+                self.check_condition(left);
+                self.check_condition(right);
+            }
+
+            ast::Expr::Unary((ast::Unary::Invert, _), box ref expr) => self.check_condition(expr),
+
+            _ => {}
+        }
+    }
+
+    // SSA lints:
+
+    fn check_function(&mut self, function: &ssa::Function) {
+        let reachable = back::reachable(function);
+        let initialized = initialized(function, &reachable);
+
+        for block in function.blocks.keys() {
+            if !reachable.get(block.index()) {
+                continue;
+            }
+
+            for &value in &function.blocks[block].instructions {
+                let location = function.locations.get(value).cloned().unwrap_or(0);
+                match function.values[value] {
+                    ssa::Instruction::BinarySymbol { op: ssa::Opcode::Read, arg, symbol } => {
+                        self.check_read(symbol, initialized.state(function, arg), location);
+                    }
+
+                    ssa::Instruction::Unary { op: ssa::Opcode::With, arg } => {
+                        self.check_with(&function.values[arg], location);
+                    }
+
+                    ssa::Instruction::Call { op: ssa::Opcode::CallSet, symbol, .. } => {
+                        if let Some(&ssa::Prototype::Member { settable: false }) =
+                            self.prototypes.get(&symbol)
+                        {
+                            let span = Span { low: location, high: location };
+                            let message = format!("assignment to read-only variable `{}`", symbol);
+                            self.warn(span, message);
+                        }
+                    }
+
+                    ssa::Instruction::Call { op: ssa::Opcode::Call, symbol, ref args, .. } => {
+                        self.check_call(symbol, args.len(), location);
+                    }
+
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn check_read(&mut self, symbol: Symbol, state: u8, location: usize) {
+        let span = Span { low: location, high: location + symbol.len() };
+        if state == UNSET {
+            self.warn(span, format!("variable `{}` is read before it is assigned", symbol));
+        } else if state & UNSET != 0 {
+            self.warn(span, format!("variable `{}` may be read before it is assigned", symbol));
+        }
+    }
+
+    /// Warn about `with` statements and field accesses on constant objects that do not exist.
+    fn check_with(&mut self, target: &ssa::Instruction, location: usize) {
+        let real = match *target {
+            ssa::Instruction::UnaryReal { op: ssa::Opcode::Constant, real } => real,
+            _ => return,
+        };
+        let objects = match self.resources.objects {
+            Some(objects) => objects as f64,
+            None => return,
+        };
+
+        // The VM truncates scopes to integers, like other reals it uses as ids.
+        let object = real.trunc();
+        if objects <= object && object < FIRST_INSTANCE {
+            let span = Span { low: location, high: location };
+            self.warn(span, format!("there is no object with index {}", object));
+        }
+    }
+
+    fn check_call(&mut self, symbol: Symbol, args: usize, location: usize) {
+        let id = match self.prototypes.get(&symbol) {
+            Some(&ssa::Prototype::Script { id }) => id as usize,
+            _ => return,
+        };
+        let arguments = match self.resources.arguments.get(id) {
+            Some(&Some(arguments)) => arguments as usize,
+            _ => return,
+        };
+
+        if args != arguments {
+            let span = Span { low: location, high: location + symbol.len() };
+            let plural = if arguments == 1 { "" } else { "s" };
+            self.warn(span, format!(
+                "script `{}` uses {} argument{}, but is called with {}",
+                symbol, arguments, plural, args
+            ));
+        }
+    }
+}
+
+/// The initialization states a local's flag may be in, as a set of `UNSET` and `SET`.
+const UNSET: u8 = 1;
+const SET: u8 = 2;
+
+/// The states of the initialization flags passed to block parameters.
+struct Initialized {
+    parameters: HashMap<ssa::Value, u8>,
+}
+
+impl Initialized {
+    /// The states a flag may be in. `Codegen` only ever writes constants to a flag.
+    fn state(&self, function: &ssa::Function, value: ssa::Value) -> u8 {
+        match function.values[value] {
+            ssa::Instruction::UnaryReal { op: ssa::Opcode::Constant, real } => {
+                if real == 0.0 { UNSET } else { SET }
+            }
+            ssa::Instruction::Parameter => self.parameters.get(&value).cloned().unwrap_or(0),
+            _ => SET,
+        }
+    }
+}
+
+/// Propagate flag states through block arguments from reachable blocks, until they settle.
+fn initialized(function: &ssa::Function, reachable: &BitVec) -> Initialized {
+    let mut initialized = Initialized { parameters: HashMap::default() };
+
+    let mut edges = vec![];
+    for block in function.blocks.keys() {
+        if !reachable.get(block.index()) {
+            continue;
+        }
+
+        let value = function.terminator(block);
+        match function.values[value] {
+            ssa::Instruction::Jump { target, ref args, .. } => {
+                edges.push((target, &args[..]));
+            }
+            ssa::Instruction::Branch {
+                targets: [true_block, false_block], arg_lens, ref args, ..
+            } => {
+                let true_end = 1 + arg_lens[0] as usize;
+                let false_end = true_end + arg_lens[1] as usize;
+                edges.push((true_block, &args[1..true_end]));
+                edges.push((false_block, &args[true_end..false_end]));
+            }
+            _ => {}
+        }
+    }
+
+    let mut dirty = true;
+    while dirty {
+        dirty = false;
+        for &(target, args) in &edges {
+            let parameters = &function.blocks[target].parameters;
+            for (&parameter, &arg) in Iterator::zip(parameters.iter(), args.iter()) {
+                let state = initialized.state(function, arg);
+                let old = initialized.parameters.get(&parameter).cloned().unwrap_or(0);
+                if old | state != old {
+                    initialized.parameters.insert(parameter, old | state);
+                    dirty = true;
+                }
+            }
+        }
+    }
+
+    initialized
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io;
+
+    use crate::{ErrorPrinter, FunctionDisplay};
+    use crate::symbol::Symbol;
+    use crate::front::{Lexer, Parser, Codegen, Lines};
+    use crate::back::ssa;
+    use super::{Linter, Resources, arguments};
+
+    fn lint(source: &[u8]) -> Vec<String> {
+        let mut prototypes = HashMap::default();
+        let settable = Symbol::intern(b"x");
+        prototypes.insert(settable, ssa::Prototype::Member { settable: true });
+        let read_only = Symbol::intern(b"instance_count");
+        prototypes.insert(read_only, ssa::Prototype::Member { settable: false });
+        let script = Symbol::intern(b"double");
+        prototypes.insert(script, ssa::Prototype::Script { id: 0 });
+        let resources = Resources { objects: Some(2), arguments: vec![Some(1)] };
+
+        let lines = Lines::from_code(source);
+        let name = FunctionDisplay::Script { script };
+        let mut errors = ErrorPrinter::new(name, &lines, io::sink());
+        let program = Parser::new(Lexer::new(source, 0), &mut errors).parse_program();
+        let function = Codegen::new(&prototypes, &mut errors).compile_program(&program);
+        assert_eq!(errors.count, 0);
+        Linter::new(&prototypes, &resources, &mut errors).lint_program(&program, &function);
+
+        errors.diagnostics.into_iter().map(|(_, _, message)| message).collect()
+    }
+
+    #[test]
+    fn uninitialized() {
+        assert_eq!(lint(b"var a; return a"), [
+            "variable `a` is read before it is assigned",
+        ]);
+        assert_eq!(lint(b"var a; if (x) { a = 1 } return a"), [
+            "variable `a` may be read before it is assigned",
+        ]);
+        assert!(lint(b"var a; if (x) a = 1 else a = 2; return a").is_empty());
+        assert!(lint(b"var i; for (i = 0; i < 3; i += 1) {} return i").is_empty());
+        assert!(lint(b"return argument0").is_empty());
+    }
+
+    #[test]
+    fn unreachable() {
+        assert_eq!(lint(b"{ exit; x = 1; x = 2 }"), ["unreachable code"]);
+        assert_eq!(lint(b"switch (x) { case 1: return 1; x = 2; case 2: return 2 }"), [
+            "unreachable code",
+        ]);
+        assert!(lint(b"switch (x) { case 1: exit; default: x = 1 }").is_empty());
+    }
+
+    #[test]
+    fn conditions() {
+        assert_eq!(lint(b"if x = 1 exit"), ["`=` in a condition compares; use `==` instead"]);
+        assert_eq!(lint(b"while (x > 0 && !(x = 3)) x -= 1"), [
+            "`=` in a condition compares; use `==` instead",
+        ]);
+        assert!(lint(b"if x == 1 exit; y = x = 1").is_empty());
+    }
+
+    #[test]
+    fn members_and_objects() {
+        assert_eq!(lint(b"instance_count = 3; x = 1"), [
+            "assignment to read-only variable `instance_count`",
+        ]);
+        assert_eq!(lint(b"with (1) x = 0; with (2) x = 0; with (100001) x = 0; (5).x = 1"), [
+            "there is no object with index 2",
+            "there is no object with index 5",
+        ]);
+    }
+
+    #[test]
+    fn scripts() {
+        assert_eq!(arguments(b"return argument0 + argument3"), Some(4));
+        assert_eq!(arguments(b"return argument[0]"), None);
+        assert_eq!(arguments(b"return 1"), Some(0));
+
+        assert!(lint(b"double(1)").is_empty());
+        assert_eq!(lint(b"double(1, 2); x = double()"), [
+            "script `double` uses 1 argument, but is called with 2",
+            "script `double` uses 1 argument, but is called with 0",
+        ]);
+    }
+}
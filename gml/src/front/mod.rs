@@ -1,7 +1,14 @@
+use std::collections::HashMap;
 use std::iter::{self, FromIterator};
+use std::rc::Rc;
+use std::{fmt, io};
 
+use bstr::ByteSlice;
 use project::{action_kind, action_type};
 
+use crate::symbol::Symbol;
+use crate::vm;
+
 pub mod token;
 pub mod ast;
 mod action_ast;
@@ -11,11 +18,25 @@ mod parser;
 mod action_parser;
 mod ssa;
 mod codegen;
+mod lint;
+mod printer;
 
 pub use lexer::Lexer;
 pub use parser::Parser;
 pub use action_parser::ActionParser;
 pub use codegen::Codegen;
+pub use lint::{Linter, Resources, arguments};
+pub use printer::Printer;
+
+/// The GMS `#macro`s and `enum`s in scope for a piece of code.
+///
+/// These are global to a project, so they are collected from every script and event before any
+/// of them is compiled.
+#[derive(Clone, Default, PartialEq)]
+pub struct Definitions {
+    pub macros: Rc<HashMap<Symbol, Box<[token::Token]>>>,
+    pub enums: Rc<HashMap<Symbol, HashMap<Symbol, f64>>>,
+}
 
 /// A range of positions in an event or script.
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
@@ -35,7 +56,7 @@ pub struct Span {
 ///
 /// Converting an absolute index (relative to a whole event) into a local index (relative to the
 /// parent item) works by subtracting the absolute index of the parent item's first child.
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Lines {
     /// The byte offset of each action, and the absolute index of its first argument.
     pub actions: Vec<(usize, usize)>,
@@ -90,6 +111,22 @@ impl Lines {
         Lines { actions, arguments, lines }
     }
 
+    /// The text that the offsets of `from_actions` refer to.
+    pub fn source_from_actions(source: &[project::Action<'_>]) -> Vec<u8> {
+        let mut text = Vec::default();
+        for action in source {
+            match (action.action_kind, action.action_type) {
+                (action_kind::NORMAL, action_type::FUNCTION) => text.extend_from_slice(action.name),
+                (action_kind::NORMAL, action_type::CODE) => text.extend_from_slice(action.code),
+                (_, _) => {}
+            }
+            for argument in &action.arguments[..action.parameters_used as usize] {
+                text.extend_from_slice(argument);
+            }
+        }
+        text
+    }
+
     fn compute_lines(source: &[u8], offset: usize) -> impl Iterator<Item = usize> + '_ {
         let start = iter::once(offset);
         let newlines = source.iter().copied()
@@ -134,4 +171,56 @@ impl Lines {
 
         Position { action, argument, line, column }
     }
+
+    /// The text of the innermost action, argument, or line containing a byte offset.
+    pub fn get_text<'a>(&self, source: &'a [u8], pos: usize) -> &'a [u8] {
+        let starts = Iterator::chain(
+            self.actions.iter().chain(self.arguments.iter()).map(|&(start, _)| start),
+            self.lines.iter().copied(),
+        );
+        let (mut low, mut high) = (0, source.len());
+        for start in starts {
+            if start <= pos && start > low { low = start; }
+            if start > pos && start < high { high = start; }
+        }
+
+        let text = source.get(low..high).unwrap_or_default();
+        let text = &text[..text.find_byte(b'\n').unwrap_or(text.len())];
+        let text = &text[text.iter().take_while(|b| b.is_ascii_whitespace()).count()..];
+        &text[..text.len() - text.iter().rev().take_while(|b| b.is_ascii_whitespace()).count()]
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut separator = "";
+        if let Some(action) = self.action {
+            write!(f, "action {}", action)?;
+            separator = ", ";
+        }
+        if let (Some(argument), None) = (self.argument, self.line) {
+            write!(f, "{}argument {}", separator, argument)?;
+        }
+        if let Some(line) = self.line {
+            write!(f, "{}line {}", separator, line)?;
+        }
+        if let Some(column) = self.column {
+            write!(f, ":{}", column)?;
+        }
+        Ok(())
+    }
+}
+
+impl vm::Serialize for Lines {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        out.write(&self.actions)?;
+        out.write(&self.arguments)?;
+        out.write(&self.lines)
+    }
+}
+
+impl vm::Deserialize for Lines {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        Ok(Lines { actions: input.read()?, arguments: input.read()?, lines: input.read()? })
+    }
 }
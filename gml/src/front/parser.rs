@@ -1,24 +1,36 @@
 use std::mem;
+use std::collections::HashMap;
+use std::rc::Rc;
 use std::str::{self, FromStr};
 
-use crate::ErrorPrinter;
+use crate::{ErrorPrinter, Dialect};
 use crate::symbol::{Symbol, keyword};
-use crate::front::{ast, Lexer, Span};
-use crate::front::token::{Token, Delim, BinOp};
+use crate::front::{ast, Lexer, Definitions, Span};
+use crate::front::token::{Token, Delim, BinOp, Accessor};
 
 pub struct Parser<'s, 'e, 'f> {
     reader: Lexer<'s>,
     errors: &'e mut ErrorPrinter<'f>,
+    dialect: Dialect,
+
+    /// The members of each GMS `enum` declared so far.
+    enums: Rc<HashMap<Symbol, HashMap<Symbol, f64>>>,
 
     current: Token,
     span: Span,
 }
 
 impl<'s, 'e, 'f> Parser<'s, 'e, 'f> {
+    /// Parse the tokens from `reader`, in the same dialect. If `reader` leaves macros unexpanded,
+    /// references to enum members are left as written too.
     pub fn new(reader: Lexer<'s>, errors: &'e mut ErrorPrinter<'f>) -> Parser<'s, 'e, 'f> {
+        let dialect = reader.dialect();
         let mut parser = Parser {
             reader: reader,
             errors: errors,
+            dialect: dialect,
+
+            enums: Rc::default(),
 
             current: Token::Eof,
             span: Span { low: 0, high: 0 },
@@ -28,6 +40,20 @@ impl<'s, 'e, 'f> Parser<'s, 'e, 'f> {
         parser
     }
 
+    /// Parse the tokens from `reader`, with the macros and enums in `definitions` in scope.
+    pub fn with_definitions(
+        reader: Lexer<'s>, definitions: Definitions, errors: &'e mut ErrorPrinter<'f>
+    ) -> Parser<'s, 'e, 'f> {
+        let mut parser = Parser::new(reader.with_macros(definitions.macros), errors);
+        parser.enums = definitions.enums;
+        parser
+    }
+
+    /// The macros and enums defined so far, including those this parser started with.
+    pub fn definitions(&self) -> Definitions {
+        Definitions { macros: self.reader.macros().clone(), enums: self.enums.clone() }
+    }
+
     pub fn parse_program(&mut self) -> (ast::Stmt, Span) {
         let low = self.span.low;
         let (stmt, span) = if self.current == Token::OpenDelim(Delim::Brace) {
@@ -58,6 +84,23 @@ impl<'s, 'e, 'f> Parser<'s, 'e, 'f> {
         (stmt, Span { low: low, high: high })
     }
 
+    /// The comments skipped so far, in order.
+    pub fn comments(&self) -> &[Span] {
+        self.reader.comments()
+    }
+
+    /// Parse a lone expression, as a program that returns its value.
+    pub fn parse_expression_program(&mut self) -> (ast::Stmt, Span) {
+        let (expr, span) = self.parse_expression(0);
+
+        if self.current != Token::Eof {
+            self.errors.error(self.span,
+                format_args!("unexpected {}; expected {}", self.current, Token::Eof));
+        }
+
+        (ast::Stmt::Return(Box::new((expr, span))), span)
+    }
+
     fn parse_statement(&mut self) -> (ast::Stmt, Span) {
         let low = self.span.low;
 
@@ -77,6 +120,9 @@ impl<'s, 'e, 'f> Parser<'s, 'e, 'f> {
             Keyword(Break) | Keyword(Continue) | Keyword(Exit) => self.parse_jump(),
             Keyword(Return) => self.parse_return(),
             Keyword(Case) | Keyword(Default) => self.parse_case(),
+            Ident(symbol) if self.dialect == Dialect::Gms && &symbol[..] == b"enum" => {
+                self.parse_enum()
+            }
             _ => self.parse_assign_or_invoke(),
         };
 
@@ -96,6 +142,13 @@ impl<'s, 'e, 'f> Parser<'s, 'e, 'f> {
         match place {
             ast::Expr::Call(call) => return (ast::Stmt::Invoke(call), left_span),
             ast::Expr::Error => return (ast::Stmt::Error(place), left_span),
+
+            // A statement `x++` is the same as `x += 1`.
+            ast::Expr::Prefix((op, op_span), place) | ast::Expr::Postfix((op, op_span), place) => {
+                let one = Box::new((ast::Expr::Value(ast::Value::Real(1.0)), op_span));
+                return (ast::Stmt::Assign((Some(op), op_span), place, one), left_span);
+            }
+
             _ => (),
         }
 
@@ -139,6 +192,7 @@ impl<'s, 'e, 'f> Parser<'s, 'e, 'f> {
         };
 
         let mut idents = vec![];
+        let mut assigns = vec![];
         while self.current != Token::Semicolon && self.current != Token::Eof {
             let (symbol, span) = match self.current {
                 Token::Ident(symbol) => (symbol, self.span),
@@ -148,13 +202,24 @@ impl<'s, 'e, 'f> Parser<'s, 'e, 'f> {
             idents.push((symbol, span));
 
             self.advance_token();
+            if
+                self.dialect == Dialect::Gms &&
+                (self.current == Token::Eq || self.current == Token::ColonEq)
+            {
+                let (_, op_span) = self.advance_token();
+                let (value, value_span) = self.parse_expression(0);
+
+                let place = Box::new((ast::Expr::Value(ast::Value::Ident(symbol)), span));
+                let value = Box::new((value, value_span));
+                let span = Span { low: span.low, high: value_span.high };
+                assigns.push((ast::Stmt::Assign((None, op_span), place, value), span));
+            }
             if let Token::Comma = self.current {
                 let _ = self.advance_token();
             }
         }
 
         if self.current == Token::Eq || self.current == Token::ColonEq {
-            // TODO: gms allows this
             self.errors.error(self.span, format_args!("unexpected {}; expected ;", self.current));
 
             self.advance_token();
@@ -165,7 +230,69 @@ impl<'s, 'e, 'f> Parser<'s, 'e, 'f> {
         self.expect(Token::Semicolon);
 
         let span = Span { low: low, high: high };
-        (ast::Stmt::Declare(declare, idents.into_boxed_slice()), span)
+        let declare = ast::Stmt::Declare(declare, idents.into_boxed_slice());
+        if assigns.is_empty() {
+            return (declare, span);
+        }
+
+        // GMS initializers run after the whole declaration.
+        assigns.insert(0, (declare, span));
+        (ast::Stmt::Block(assigns.into_boxed_slice()), span)
+    }
+
+    fn parse_enum(&mut self) -> (ast::Stmt, Span) {
+        let low = self.span.low;
+        self.advance_token();
+
+        let (name, name_span) = match self.current {
+            Token::Ident(name) => {
+                let (_, name_span) = self.advance_token();
+                (name, name_span)
+            }
+            _ => {
+                self.errors.error(self.span,
+                    format_args!("unexpected {}; expected identifier", self.current));
+                let span = Span { low: low, high: self.span.low };
+                return (ast::Stmt::Error(ast::Expr::Error), span);
+            }
+        };
+        self.expect(Token::OpenDelim(Delim::Brace));
+
+        // Members may refer to the ones before them, so make each visible as soon as it is parsed.
+        Rc::make_mut(&mut self.enums).insert(name, HashMap::new());
+        let mut members = vec![];
+        let mut value = 0.0;
+        while let Token::Ident(member) = self.current {
+            let (_, member_span) = self.advance_token();
+
+            if self.current == Token::Eq || self.current == Token::ColonEq {
+                self.advance_token();
+                let (expr, expr_span) = self.parse_expression(0);
+                match constant(&expr) {
+                    Some(constant) => value = constant,
+                    // Members are left as written, so they may refer to each other.
+                    None if !self.reader.expands() => {}
+                    None => self.errors.error(expr_span,
+                        format_args!("enum members must have constant values")),
+                }
+            }
+
+            members.push(((member, member_span), value));
+            Rc::make_mut(&mut self.enums).get_mut(&name).unwrap().insert(member, value);
+            value += 1.0;
+
+            if self.current == Token::Comma {
+                self.advance_token();
+            } else {
+                break;
+            }
+        }
+
+        let high = self.span.high;
+        self.expect(Token::CloseDelim(Delim::Brace));
+
+        let span = Span { low: low, high: high };
+        (ast::Stmt::Enum((name, name_span), members.into_boxed_slice()), span)
     }
 
     fn parse_block(&mut self) -> (ast::Stmt, Span) {
@@ -398,6 +525,43 @@ impl<'s, 'e, 'f> Parser<'s, 'e, 'f> {
                     parens = false;
                 }
 
+                // Only arrays need a variable; data structures are referred to by value.
+                (&ast::Expr::Value(ast::Value::Ident(_)), Infix::Accessor(Accessor::Array)) |
+                (&ast::Expr::Field(..), Infix::Accessor(Accessor::Array))
+                if !parens => {
+                    let (args, high) = self.parse_args(Delim::Bracket);
+
+                    let accessor = ast::Accessor::Array;
+                    left = ast::Expr::Accessor(accessor, Box::new((left, left_span)), args);
+                    left_span = Span { low: low, high: high };
+                    parens = false;
+                }
+                (_, Infix::Accessor(accessor)) if accessor != Accessor::Array => {
+                    let (args, high) = self.parse_args(Delim::Bracket);
+
+                    let accessor = match accessor {
+                        Accessor::List => ast::Accessor::List,
+                        Accessor::Map => ast::Accessor::Map,
+                        Accessor::Grid => ast::Accessor::Grid,
+                        Accessor::Array => unreachable!(),
+                    };
+                    left = ast::Expr::Accessor(accessor, Box::new((left, left_span)), args);
+                    left_span = Span { low: low, high: high };
+                    parens = false;
+                }
+
+                (&ast::Expr::Value(ast::Value::Ident(_)), Infix::Postfix(op)) |
+                (&ast::Expr::Field(..), Infix::Postfix(op)) |
+                (&ast::Expr::Index(..), Infix::Postfix(op)) |
+                (&ast::Expr::Accessor(..), Infix::Postfix(op))
+                if !parens => {
+                    let (_, op_span) = self.advance_token();
+
+                    left = ast::Expr::Postfix((op, op_span), Box::new((left, left_span)));
+                    left_span = Span { low: low, high: op_span.high };
+                    parens = true;
+                }
+
                 (_, Infix::Field) => {
                     self.advance_token();
 
@@ -411,11 +575,39 @@ impl<'s, 'e, 'f> Parser<'s, 'e, 'f> {
                     };
                     let high = field_span.high;
 
-                    left = ast::Expr::Field(Box::new((left, left_span)), (field, field_span));
+                    left = match left {
+                        ast::Expr::Value(ast::Value::Ident(name)) if !parens &&
+                            self.reader.expands() && self.enums.contains_key(&name)
+                        => match self.enums[&name].get(&field) {
+                            Some(&value) => ast::Expr::Value(ast::Value::Real(value)),
+                            None => {
+                                self.errors.error(field_span,
+                                    format_args!("unknown member {} of enum {}", field, name));
+                                ast::Expr::Error
+                            }
+                        },
+                        left => ast::Expr::Field(Box::new((left, left_span)), (field, field_span)),
+                    };
                     left_span = Span { low: low, high: high };
                     parens = false;
                 }
 
+                (_, Infix::Conditional) => {
+                    self.advance_token();
+
+                    let (true_expr, true_span) = self.parse_expression(0);
+                    self.expect(Token::Colon);
+                    let (false_expr, false_span) = self.parse_expression(0);
+
+                    left = ast::Expr::Conditional(
+                        Box::new((left, left_span)),
+                        Box::new((true_expr, true_span)),
+                        Box::new((false_expr, false_span)),
+                    );
+                    left_span = Span { low: low, high: false_span.high };
+                    parens = true;
+                }
+
                 (_, Infix::Binary(op)) => {
                     let op_span = self.span;
                     self.advance_token();
@@ -495,6 +687,21 @@ impl<'s, 'e, 'f> Parser<'s, 'e, 'f> {
                 (ast::Expr::Unary((op, op_span), Box::new((expr, expr_span))), span, true)
             }
 
+            PlusPlus | MinusMinus => {
+                let (current, op_span) = self.advance_token();
+                let op = match current {
+                    PlusPlus => ast::Op::Add,
+                    MinusMinus => ast::Op::Subtract,
+                    _ => unreachable!(),
+                };
+
+                let (expr, expr_span) = self.parse_term();
+                let high = expr_span.high;
+
+                let span = Span { low: low, high: high };
+                (ast::Expr::Prefix((op, op_span), Box::new((expr, expr_span))), span, true)
+            }
+
             OpenDelim(Delim::Paren) => {
                 let (_, _) = self.advance_token();
                 let (expr, expr_span) = self.parse_expression(0);
@@ -558,6 +765,21 @@ impl<'s, 'e, 'f> Parser<'s, 'e, 'f> {
     fn advance_token(&mut self) -> (Token, Span) {
         let (token, span) = self.reader.read_token();
 
+        // GMS deprecated these GM8 spellings but still accepts them, so warn and parse them.
+        if self.dialect == Dialect::Gms {
+            let deprecated = match token {
+                Token::LtGt => Some(("<>", "!=")),
+                Token::ColonEq => Some((":=", "=")),
+                Token::Keyword(keyword::Begin) => Some(("begin", "{")),
+                Token::Keyword(keyword::End) => Some(("end", "}")),
+                _ => None,
+            };
+            if let Some((old, new)) = deprecated {
+                self.errors.warning(span,
+                    format_args!("`{}` is deprecated; use `{}` instead", old, new));
+            }
+        }
+
         let token = mem::replace(&mut self.current, token);
         let span = mem::replace(&mut self.span, span);
         return (token, span);
@@ -584,6 +806,7 @@ impl<'s, 'e, 'f> Parser<'s, 'e, 'f> {
                 Keyword(All) | Keyword(NoOne) |
                 Keyword(Global) | Keyword(Local) |
                 OpenDelim(Delim::Paren) |
+                PlusPlus | MinusMinus |
                 Keyword(Var) | Keyword(GlobalVar) |
 
                 // Possible statement:
@@ -604,7 +827,10 @@ enum Infix {
     Binary(ast::Binary),
     Field,
     Index,
+    Accessor(Accessor),
     Call,
+    Postfix(ast::Op),
+    Conditional,
 }
 
 impl Infix {
@@ -615,7 +841,11 @@ impl Infix {
         let op = match token {
             Token::Dot => Infix::Field,
             Token::OpenDelim(Delim::Bracket) => Infix::Index,
+            Token::OpenAccessor(accessor) => Infix::Accessor(accessor),
             Token::OpenDelim(Delim::Paren) => Infix::Call,
+            Token::PlusPlus => Infix::Postfix(Add),
+            Token::MinusMinus => Infix::Postfix(Subtract),
+            Token::Question => Infix::Conditional,
 
             _ => Infix::Binary(match token {
                 Token::Lt => Lt,
@@ -653,7 +883,7 @@ impl Infix {
         }
 
         let precedence = match op {
-            Infix::Field | Infix::Index | Infix::Call => 7,
+            Infix::Field | Infix::Index | Infix::Accessor(_) | Infix::Call | Infix::Postfix(_) => 7,
             Infix::Binary(op) => match op {
                 Op(Multiply) | Op(Divide) | Div | Mod => 6,
                 Op(Add) | Op(Subtract) => 5,
@@ -662,16 +892,45 @@ impl Infix {
                 Lt | Le | Eq | Ne | Ge | Gt => 2,
                 And | Or | Xor => 1,
             }
+            Infix::Conditional => 0,
         };
 
         Some((op, precedence))
     }
 }
 
+/// The value of a constant expression, such as an `enum` member's.
+fn constant(expr: &ast::Expr) -> Option<f64> {
+    use crate::front::ast::Binary::*;
+    use crate::front::ast::Op::*;
+
+    match *expr {
+        ast::Expr::Value(ast::Value::Real(value)) => Some(value),
+        ast::Expr::Unary((ast::Unary::Positive, _), box (ref expr, _)) => constant(expr),
+        ast::Expr::Unary((ast::Unary::Negate, _), box (ref expr, _)) => Some(-constant(expr)?),
+        ast::Expr::Binary((op, _), box (ref left, _), box (ref right, _)) => {
+            let (left, right) = (constant(left)?, constant(right)?);
+            let (a, b) = (left as i32, right as i32);
+            match op {
+                Op(Add) => Some(left + right),
+                Op(Subtract) => Some(left - right),
+                Op(Multiply) => Some(left * right),
+                Op(BitAnd) => Some((a & b) as f64),
+                Op(BitOr) => Some((a | b) as f64),
+                Op(BitXor) => Some((a ^ b) as f64),
+                ShiftLeft if (0..32).contains(&b) => Some((a << b) as f64),
+                ShiftRight if (0..32).contains(&b) => Some((a >> b) as f64),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::io;
-    use crate::{FunctionDisplay, ErrorPrinter};
+    use crate::{FunctionDisplay, ErrorPrinter, Severity, Dialect};
     use crate::symbol::Symbol;
     use crate::front::{Span, Lexer, Parser, Lines};
     use crate::front::ast::*;
@@ -752,4 +1011,55 @@ mod tests {
             span(0, 14)
         ));
     }
+
+    #[test]
+    fn gms() {
+        let lines = &mut Lines::default();
+        let source = b"enum e { a, b = e.a + 2 } e.b ? x++ : 1";
+        let (_, mut errors) = setup(lines, source);
+        let reader = Lexer::with_dialect(source, 0, Dialect::Gms);
+        let mut parser = Parser::new(reader, &mut errors);
+
+        let e = Symbol::intern(b"e");
+        let a = Symbol::intern(b"a");
+        let b = Symbol::intern(b"b");
+        let x = Symbol::intern(b"x");
+        assert_eq!(parser.parse_statement(), (
+            Stmt::Enum((e, span(5, 6)), vec![
+                ((a, span(9, 10)), 0.0),
+                ((b, span(12, 13)), 2.0),
+            ].into_boxed_slice()),
+            span(0, 25),
+        ));
+        assert_eq!(parser.parse_expression(0), (
+            Expr::Conditional(
+                Box::new((Expr::Value(Value::Real(2.0)), span(26, 29))),
+                Box::new((Expr::Postfix(
+                    (Op::Add, span(33, 35)),
+                    Box::new((Expr::Value(Value::Ident(x)), span(32, 33))),
+                ), span(32, 35))),
+                Box::new((Expr::Value(Value::Real(1.0)), span(38, 39))),
+            ),
+            span(26, 39)
+        ));
+    }
+
+    #[test]
+    fn gms_deprecated() {
+        let lines = &mut Lines::default();
+        let source = b"begin x := y <> z end";
+        let (_, mut errors) = setup(lines, source);
+        let reader = Lexer::with_dialect(source, 0, Dialect::Gms);
+        Parser::new(reader, &mut errors).parse_program();
+        assert_eq!(errors.count, 0);
+        let spans: Vec<_> = errors.diagnostics.iter()
+            .map(|&(severity, span, _)| (severity, span))
+            .collect();
+        let warning = |low, high| (Severity::Warning, span(low, high));
+        assert_eq!(spans, [warning(0, 5), warning(8, 10), warning(13, 15), warning(18, 21)]);
+
+        let (reader, mut errors) = setup(lines, source);
+        Parser::new(reader, &mut errors).parse_program();
+        assert_eq!(errors.count, 0);
+    }
 }
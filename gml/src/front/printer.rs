@@ -0,0 +1,768 @@
+use std::mem;
+
+use crate::symbol::Symbol;
+
+use crate::front::{ast, Span};
+
+/// Print a program as canonical GML.
+///
+/// Statements are printed one per line, with blocks indented by four spaces and simple statements
+/// ended by semicolons. Literals are printed as they appear in the source. Comments are kept: one
+/// on its own line stays before the statement that follows it, and any other is moved to the end
+/// of the line that holds the statement it was in.
+pub struct Printer<'s> {
+    source: &'s [u8],
+    /// The comments that have not been printed yet.
+    comments: &'s [Span],
+
+    output: Vec<u8>,
+    indent: usize,
+
+    /// The end of the last statement or comment printed, to preserve blank lines between them.
+    last: usize,
+    /// Whether nothing has been printed in the current block, to skip blank lines at its start.
+    first: bool,
+    /// The start of whatever follows the statement being printed. Comments after the statement
+    /// must come before it to stay on the statement's line.
+    next: usize,
+    /// Whether to print the next block with `begin` and `end`, as a program must if it starts
+    /// with a block followed by other statements.
+    begin: bool,
+}
+
+impl<'s> Printer<'s> {
+    pub fn new(source: &'s [u8], comments: &'s [Span]) -> Printer<'s> {
+        Printer {
+            source, comments,
+            output: vec![], indent: 0,
+            last: 0, first: true, next: usize::MAX, begin: false,
+        }
+    }
+
+    pub fn print_program(mut self, program: &(ast::Stmt, Span)) -> Vec<u8> {
+        match *program {
+            // Unless the program is a single block, print its statements at the top level.
+            (ast::Stmt::Block(ref stmts), span) if self.source.get(span.low) != Some(&b'{') => {
+                if let [(ast::Stmt::Block(_), _), _, ..] = stmts[..] {
+                    self.begin = true;
+                }
+                for (i, stmt) in stmts.iter().enumerate() {
+                    self.next = stmts.get(i + 1).map_or(usize::MAX, |&(_, span)| span.low);
+                    self.print_statement(stmt);
+                }
+            }
+            _ => self.print_statement(program),
+        }
+
+        self.flush(usize::MAX);
+        self.output
+    }
+
+    fn print_statement(&mut self, statement: &(ast::Stmt, Span)) {
+        let (ref stmt, span) = *statement;
+        self.flush(span.low);
+        self.start_line(span.low);
+
+        match *stmt {
+            ast::Stmt::Block(ref stmts) if !is_declaration(stmts, span) => {
+                self.print_block(stmts, false, span.low, span.high);
+                self.end_statement(span);
+            }
+
+            ast::Stmt::If(ref cond, ref true_branch, ref false_branch) => {
+                self.print_if(cond, true_branch, false_branch.as_deref(), span);
+            }
+
+            ast::Stmt::Repeat(ref expr, ref body) |
+            ast::Stmt::While(ref expr, ref body) |
+            ast::Stmt::With(ref expr, ref body) => {
+                let keyword: &[u8] = match *stmt {
+                    ast::Stmt::Repeat(..) => b"repeat ",
+                    ast::Stmt::While(..) => b"while ",
+                    _ => b"with ",
+                };
+                self.write(keyword);
+                self.print_condition(expr);
+                if self.print_body(body) {
+                    self.end_statement(span);
+                }
+            }
+
+            ast::Stmt::Do(ref body, ref cond) => {
+                self.write(b"do");
+                let (_, cond_span) = **cond;
+                let next = mem::replace(&mut self.next, cond_span.low);
+                let open = self.print_body(body);
+                self.next = next;
+                if open {
+                    self.write(b" ");
+                } else {
+                    self.write_indent();
+                }
+                self.write(b"until ");
+                self.print_condition(cond);
+                self.write(b";");
+                self.end_statement(span);
+            }
+
+            ast::Stmt::For(ref init, ref cond, ref next, ref body) => {
+                self.write(b"for (");
+                self.print_simple(init);
+                self.write(b"; ");
+                self.print_expr(cond);
+                self.write(b"; ");
+                self.print_simple(next);
+                self.write(b")");
+                if self.print_body(body) {
+                    self.end_statement(span);
+                }
+            }
+
+            ast::Stmt::Switch(ref expr, ref stmts) => {
+                self.write(b"switch ");
+                self.print_condition(expr);
+                self.write(b" ");
+                let (_, expr_span) = **expr;
+                self.print_block(stmts, true, expr_span.high, span.high);
+                self.end_statement(span);
+            }
+
+            ast::Stmt::Case(_) | ast::Stmt::Enum(..) => {
+                self.print_simple(statement);
+                self.end_statement(span);
+            }
+
+            _ => {
+                self.print_simple(statement);
+                self.write(b";");
+                self.end_statement(span);
+            }
+        }
+    }
+
+    /// Print a statement that fits on one line, without a trailing semicolon.
+    ///
+    /// Statements that do not fit on one line only appear here in the header of a `for` loop,
+    /// where they are printed as they appear in the source.
+    fn print_simple(&mut self, statement: &(ast::Stmt, Span)) {
+        let (ref stmt, span) = *statement;
+        match *stmt {
+            // A GMS `x++` or `--x` statement, parsed as `x += 1` or `x -= 1`.
+            ast::Stmt::Assign((Some(op), op_span), ref place, ref value) if value.1 == op_span => {
+                let (_, place_span) = **place;
+                if op_span.low < place_span.low {
+                    self.write(increment(op));
+                    self.print_operand(place, POSTFIX);
+                } else {
+                    self.print_operand(place, POSTFIX);
+                    self.write(increment(op));
+                }
+            }
+
+            ast::Stmt::Assign((op, _), ref place, ref value) => {
+                self.print_expr(place);
+                let op: &[u8] = match op {
+                    None => b" = ",
+                    Some(ast::Op::Add) => b" += ",
+                    Some(ast::Op::Subtract) => b" -= ",
+                    Some(ast::Op::Multiply) => b" *= ",
+                    Some(ast::Op::Divide) => b" /= ",
+                    Some(ast::Op::BitAnd) => b" &= ",
+                    Some(ast::Op::BitOr) => b" |= ",
+                    Some(ast::Op::BitXor) => b" ^= ",
+                };
+                self.write(op);
+                self.print_expr(value);
+            }
+
+            ast::Stmt::Invoke(ref call) => self.print_call(call),
+
+            ast::Stmt::Declare(declare, ref names) => self.print_declare(declare, names, &[]),
+            ast::Stmt::Block(ref stmts) => match stmts[0].0 {
+                ast::Stmt::Declare(declare, ref names) =>
+                    self.print_declare(declare, names, &stmts[1..]),
+                _ => unreachable!("block printed as a simple statement"),
+            },
+
+            ast::Stmt::Jump(jump) => {
+                let keyword: &[u8] = match jump {
+                    ast::Jump::Break => b"break",
+                    ast::Jump::Continue => b"continue",
+                    ast::Jump::Exit => b"exit",
+                };
+                self.write(keyword);
+            }
+
+            ast::Stmt::Return(ref expr) => {
+                self.write(b"return ");
+                self.print_expr(expr);
+            }
+
+            ast::Stmt::Case(Some(ref expr)) => {
+                self.write(b"case ");
+                self.print_expr(expr);
+                self.write(b":");
+            }
+            ast::Stmt::Case(None) => self.write(b"default:"),
+
+            _ => {
+                let source = self.source;
+                let text = &source[span.low..span.high];
+                let end = text.iter().rposition(|&b| !b"; \t\r\n".contains(&b));
+                let end = end.map_or(0, |end| end + 1);
+                self.write(&text[..end]);
+            }
+        }
+    }
+
+    /// Print a declaration, along with any GMS initializers from `assigns`.
+    fn print_declare(
+        &mut self, declare: ast::Declare, names: &[(Symbol, Span)], assigns: &[(ast::Stmt, Span)]
+    ) {
+        let keyword: &[u8] = match declare {
+            ast::Declare::Local => b"var ",
+            ast::Declare::Global => b"globalvar ",
+        };
+        self.write(keyword);
+        for (i, &(name, name_span)) in names.iter().enumerate() {
+            if i > 0 {
+                self.write(b", ");
+            }
+            self.write(&name[..]);
+
+            let value = assigns.iter().find_map(|assign| match *assign {
+                (ast::Stmt::Assign(_, ref place, ref value), _) if place.1 == name_span =>
+                    Some(value),
+                _ => None,
+            });
+            if let Some(value) = value {
+                self.write(b" = ");
+                self.print_expr(value);
+            }
+        }
+    }
+
+    fn print_if(
+        &mut self,
+        cond: &(ast::Expr, Span),
+        true_branch: &(ast::Stmt, Span),
+        false_branch: Option<&(ast::Stmt, Span)>,
+        span: Span,
+    ) {
+        self.write(b"if ");
+        self.print_condition(cond);
+        let next = match false_branch {
+            Some(&(_, false_span)) => mem::replace(&mut self.next, false_span.low),
+            None => self.next,
+        };
+        let mut open = self.print_body(true_branch);
+        self.next = next;
+
+        if let Some(false_branch) = false_branch {
+            if open {
+                self.write(b" else");
+            } else {
+                self.write_indent();
+                self.write(b"else");
+            }
+
+            open = match *false_branch {
+                (ast::Stmt::If(ref cond, ref true_branch, ref false_branch), span) => {
+                    self.write(b" ");
+                    return self.print_if(cond, true_branch, false_branch.as_deref(), span);
+                }
+                _ => self.print_body(false_branch),
+            };
+        }
+
+        if open {
+            self.end_statement(span);
+        }
+    }
+
+    /// Print the body of a compound statement, following its header.
+    ///
+    /// Returns whether the line with a block's closing brace is left open, to continue it with an
+    /// `else` or `until`, or end it with `end_statement`.
+    fn print_body(&mut self, body: &(ast::Stmt, Span)) -> bool {
+        match *body {
+            (ast::Stmt::Block(ref stmts), span) => {
+                self.write(b" ");
+                self.print_block(stmts, false, span.low, span.high);
+                true
+            }
+
+            (_, span) => {
+                self.end_line(span.low);
+                self.indent += 1;
+                self.first = true;
+                self.print_statement(body);
+                self.indent -= 1;
+                false
+            }
+        }
+    }
+
+    /// Print a block, leaving the line with its closing brace open.
+    ///
+    /// Comments on the same line as `open` are printed after the opening brace. In a `switch`
+    /// block, statements other than labels are indented further.
+    fn print_block(
+        &mut self, stmts: &[(ast::Stmt, Span)], switch: bool, open: usize, close: usize
+    ) {
+        let (open_brace, close_brace, empty): (&[u8], &[u8], &[u8]) =
+            if mem::replace(&mut self.begin, false) {
+                (b"begin", b"end", b"begin end")
+            } else {
+                (b"{", b"}", b"{}")
+            };
+
+        let comment = self.comments.first().filter(|comment| comment.low < close);
+        if stmts.is_empty() && comment.is_none() {
+            self.write(empty);
+            self.last = close;
+            return;
+        }
+
+        self.write(open_brace);
+        self.indent += 1;
+        self.first = true;
+        self.last = open;
+        let first = stmts.first().map_or(close, |&(_, span)| span.low);
+        self.end_line(usize::min(self.line_end(open), first));
+
+        let next = self.next;
+        for (i, stmt) in stmts.iter().enumerate() {
+            self.next = stmts.get(i + 1).map_or(close, |&(_, span)| span.low);
+            match *stmt {
+                (ast::Stmt::Case(_), _) => self.print_statement(stmt),
+                _ if switch => {
+                    self.indent += 1;
+                    self.print_statement(stmt);
+                    self.indent -= 1;
+                }
+                _ => self.print_statement(stmt),
+            }
+        }
+        self.next = next;
+
+        self.flush(close);
+        self.indent -= 1;
+        self.first = false;
+        self.write_indent();
+        self.write(close_brace);
+        self.last = close;
+    }
+
+    fn print_condition(&mut self, expr: &(ast::Expr, Span)) {
+        self.write(b"(");
+        self.print_expr(expr);
+        self.write(b")");
+    }
+
+    fn print_expr(&mut self, expression: &(ast::Expr, Span)) {
+        let (ref expr, span) = *expression;
+        match *expr {
+            ast::Expr::Error => {}
+
+            ast::Expr::Value(ast::Value::Ident(symbol)) => self.write(&symbol[..]),
+            ast::Expr::Value(_) => {
+                let source = self.source;
+                self.write(&source[span.low..span.high]);
+            }
+
+            ast::Expr::Unary((op, _), ref expr) => {
+                let sign = unary(op);
+                self.write(sign);
+
+                // Keep `-(-a)` from running together into `--a`.
+                let operand: &[u8] = match expr.0 {
+                    ast::Expr::Unary((op, _), _) => unary(op),
+                    ast::Expr::Prefix((op, _), _) => increment(op),
+                    _ => b"",
+                };
+                if matches!(op, ast::Unary::Positive | ast::Unary::Negate) &&
+                    operand.starts_with(sign)
+                {
+                    self.write(b"(");
+                    self.print_expr(expr);
+                    self.write(b")");
+                } else {
+                    self.print_operand(expr, UNARY);
+                }
+            }
+
+            ast::Expr::Binary((op, _), ref left, ref right) => {
+                let precedence = precedence(op);
+                self.print_operand(left, precedence);
+                self.write(b" ");
+                self.write(binary(op));
+                self.write(b" ");
+                self.print_operand(right, precedence + 1);
+            }
+
+            ast::Expr::Field(ref expr, (field, _)) => {
+                self.print_operand(expr, POSTFIX);
+                self.write(b".");
+                self.write(&field[..]);
+            }
+
+            ast::Expr::Index(ref expr, ref indices) => {
+                self.print_operand(expr, POSTFIX);
+                self.write(b"[");
+                self.print_list(indices);
+                self.write(b"]");
+            }
+
+            ast::Expr::Accessor(accessor, ref expr, ref indices) => {
+                self.print_operand(expr, POSTFIX);
+                self.write(match accessor {
+                    ast::Accessor::Array => b"[@ ",
+                    ast::Accessor::List => b"[| ",
+                    ast::Accessor::Map => b"[? ",
+                    ast::Accessor::Grid => b"[# ",
+                });
+                self.print_list(indices);
+                self.write(b"]");
+            }
+
+            ast::Expr::Prefix((op, _), ref expr) => {
+                self.write(increment(op));
+                self.print_operand(expr, POSTFIX);
+            }
+
+            ast::Expr::Postfix((op, _), ref expr) => {
+                self.print_operand(expr, POSTFIX);
+                self.write(increment(op));
+            }
+
+            ast::Expr::Conditional(ref cond, ref true_expr, ref false_expr) => {
+                self.print_operand(cond, CONDITIONAL + 1);
+                self.write(b" ? ");
+                self.print_expr(true_expr);
+                self.write(b" : ");
+                self.print_expr(false_expr);
+            }
+
+            ast::Expr::Call(ref call) => self.print_call(call),
+        }
+    }
+
+    /// Print an expression, in parentheses if it binds less tightly than `min_precedence`.
+    fn print_operand(&mut self, expression: &(ast::Expr, Span), min_precedence: usize) {
+        let precedence = match expression.0 {
+            ast::Expr::Binary((op, _), ..) => precedence(op),
+            ast::Expr::Unary(..) | ast::Expr::Prefix(..) => UNARY,
+            ast::Expr::Conditional(..) => CONDITIONAL,
+            _ => POSTFIX,
+        };
+        if precedence < min_precedence {
+            self.write(b"(");
+            self.print_expr(expression);
+            self.write(b")");
+        } else {
+            self.print_expr(expression);
+        }
+    }
+
+    fn print_call(&mut self, call: &ast::Call) {
+        let ast::Call((name, _), ref args) = *call;
+        self.write(&name[..]);
+        self.write(b"(");
+        self.print_list(args);
+        self.write(b")");
+    }
+
+    fn print_list(&mut self, exprs: &[(ast::Expr, Span)]) {
+        for (i, expr) in exprs.iter().enumerate() {
+            if i > 0 {
+                self.write(b", ");
+            }
+            self.print_expr(expr);
+        }
+    }
+
+    /// Print each comment before `before` on its own line.
+    fn flush(&mut self, before: usize) {
+        while let Some((&comment, rest)) = self.comments.split_first() {
+            if comment.low >= before {
+                break;
+            }
+            self.comments = rest;
+
+            self.start_line(comment.low);
+            self.write_comment(comment);
+            self.write(b"\n");
+            self.last = comment.high;
+        }
+    }
+
+    /// Finish the line, moving any comments before `before` to its end or after it.
+    fn end_line(&mut self, before: usize) {
+        match self.comments.split_first() {
+            Some((&comment, rest)) if comment.low < before => {
+                self.comments = rest;
+                self.write(b" ");
+                self.write_comment(comment);
+                self.last = usize::max(self.last, comment.high);
+            }
+            _ => {}
+        }
+        self.write(b"\n");
+        self.flush(before);
+    }
+
+    /// Finish a statement, along with any comments that follow it on the same line.
+    ///
+    /// The span of a `for` loop ends with its header, so this also accounts for anything printed
+    /// since.
+    fn end_statement(&mut self, span: Span) {
+        self.last = usize::max(self.last, span.high);
+        self.end_line(usize::min(self.line_end(self.last), self.next));
+    }
+
+    /// Start a line for a statement or comment, after a blank line if there was one in the
+    /// source.
+    fn start_line(&mut self, low: usize) {
+        let between = self.source.get(self.last..low).unwrap_or_default();
+        if !self.first && between.iter().filter(|&&b| b == b'\n').count() > 1 {
+            self.write(b"\n");
+        }
+        self.first = false;
+        self.write_indent();
+    }
+
+    fn write_indent(&mut self) {
+        for _ in 0..self.indent {
+            self.write(b"    ");
+        }
+    }
+
+    fn write_comment(&mut self, comment: Span) {
+        let source = self.source;
+        let text = &source[comment.low..comment.high];
+        let text = if text.starts_with(b"//") {
+            let end = text.iter().rposition(|b| !b.is_ascii_whitespace()).map_or(0, |i| i + 1);
+            &text[..end]
+        } else {
+            text
+        };
+        self.write(text);
+    }
+
+    fn write(&mut self, text: &[u8]) {
+        self.output.extend_from_slice(text);
+    }
+
+    /// The offset of the end of the line containing `offset`.
+    fn line_end(&self, offset: usize) -> usize {
+        let rest = self.source.get(offset..).unwrap_or_default();
+        rest.iter().position(|&b| b == b'\n').map_or(self.source.len(), |i| offset + i)
+    }
+}
+
+/// The precedence of GMS conditional expressions, which bind less tightly than any operator.
+const CONDITIONAL: usize = 0;
+/// The precedence of unary operators, which apply to terms.
+const UNARY: usize = 7;
+/// The precedence of terms, including field accesses, indexing, and calls.
+const POSTFIX: usize = 8;
+
+/// The precedence of a binary operator, as the parser assigns it.
+fn precedence(op: ast::Binary) -> usize {
+    use crate::front::ast::Binary::*;
+    use crate::front::ast::Op::*;
+
+    match op {
+        Op(Multiply) | Op(Divide) | Div | Mod => 6,
+        Op(Add) | Op(Subtract) => 5,
+        ShiftLeft | ShiftRight => 4,
+        Op(BitAnd) | Op(BitXor) | Op(BitOr) => 3,
+        Lt | Le | Eq | Ne | Ge | Gt => 2,
+        And | Or | Xor => 1,
+    }
+}
+
+/// Whether a block is a GMS declaration with initializers, which the parser represents as the
+/// declaration followed by its assignments, all with the declaration's span.
+fn is_declaration(stmts: &[(ast::Stmt, Span)], span: Span) -> bool {
+    match stmts.first() {
+        Some(&(ast::Stmt::Declare(..), declare_span)) => declare_span == span,
+        _ => false,
+    }
+}
+
+/// The spelling of a GMS increment or decrement operator.
+fn unary(op: ast::Unary) -> &'static [u8] {
+    match op {
+        ast::Unary::Positive => b"+",
+        ast::Unary::Negate => b"-",
+        ast::Unary::Invert => b"!",
+        ast::Unary::BitInvert => b"~",
+    }
+}
+
+fn increment(op: ast::Op) -> &'static [u8] {
+    match op {
+        ast::Op::Subtract => b"--",
+        _ => b"++",
+    }
+}
+
+fn binary(op: ast::Binary) -> &'static [u8] {
+    use crate::front::ast::Binary::*;
+    use crate::front::ast::Op::*;
+
+    match op {
+        Lt => b"<",
+        Le => b"<=",
+        Eq => b"==",
+        Ne => b"!=",
+        Ge => b">=",
+        Gt => b">",
+        And => b"&&",
+        Or => b"||",
+        Xor => b"^^",
+        Op(Add) => b"+",
+        Op(Subtract) => b"-",
+        Op(Multiply) => b"*",
+        Op(Divide) => b"/",
+        Op(BitAnd) => b"&",
+        Op(BitOr) => b"|",
+        Op(BitXor) => b"^",
+        Div => b"div",
+        Mod => b"mod",
+        ShiftLeft => b"<<",
+        ShiftRight => b">>",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use bstr::ByteSlice;
+    use crate::Dialect;
+    use crate::symbol::Symbol;
+
+    fn format(source: &[u8]) -> String {
+        format_dialect(source, Dialect::Gm8)
+    }
+
+    fn format_dialect(source: &[u8], dialect: Dialect) -> String {
+        let script = Symbol::intern(b"<test>");
+        let output = crate::format(script, source, dialect, io::stderr())
+            .expect("source has errors");
+        output.to_str().expect("output is not UTF-8").to_owned()
+    }
+
+    #[test]
+    fn statements() {
+        let source = b"\
+var i,total;total=0
+for(i=0;i<10;i+=1){if i mod 2==0 then total+=i else{total-=1;}}
+while total>0 do total-=1
+do{total+=1}until total>=3
+switch total{case 3:show_message('three');break
+default:exit}
+with(other)x=$FF
+";
+        let expected = "\
+var i, total;
+total = 0;
+for (i = 0; i < 10; i += 1) {
+    if (i mod 2 == 0)
+        total += i;
+    else {
+        total -= 1;
+    }
+}
+while (total > 0)
+    total -= 1;
+do {
+    total += 1;
+} until (total >= 3);
+switch (total) {
+    case 3:
+        show_message('three');
+        break;
+    default:
+        exit;
+}
+with (other)
+    x = $FF;
+";
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected.as_bytes()), expected);
+    }
+
+    #[test]
+    fn gms() {
+        let source = b"\
+#macro TEN 5+5
+#region setup
+enum color{red,green=color.red+3,blue}
+#endregion
+var c=color.blue*TEN;c[|0]++;x=c>1?c:--c;y=-(--c)
+";
+        let expected = "\
+#macro TEN 5+5
+#region setup
+enum color{red,green=color.red+3,blue}
+#endregion
+var c = color.blue * TEN;
+c[| 0]++;
+x = c > 1 ? c : --c;
+y = -(--c);
+";
+        assert_eq!(format_dialect(source, Dialect::Gms), expected);
+        assert_eq!(format_dialect(expected.as_bytes(), Dialect::Gms), expected);
+    }
+
+    #[test]
+    fn expressions() {
+        let source = b"x=(a+b)*-(c-d)-(e-f)+g.h[1,2]  ;y=!(a&&b)||(c||d);z=(-a).b;w=-(-a)- -b+(+(+c))";
+        let expected = "\
+x = (a + b) * -(c - d) - (e - f) + g.h[1, 2];
+y = !(a && b) || (c || d);
+z = (-a).b;
+w = -(-a) - -b + +(+c);
+";
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected.as_bytes()), expected);
+    }
+
+    #[test]
+    fn comments() {
+        let source = b"\
+{ // setup
+  /* the count */ count = 0
+
+
+  // loop
+  repeat (3) {
+    count += 1 // increment
+  }
+if count > 2 then show_message(count /* inline */)
+// trailing
+}
+// after
+";
+        let expected = "\
+{ // setup
+    /* the count */
+    count = 0;
+
+    // loop
+    repeat (3) {
+        count += 1; // increment
+    }
+    if (count > 2)
+        show_message(count); /* inline */
+    // trailing
+}
+// after
+";
+        assert_eq!(format(source), expected);
+        assert_eq!(format(expected.as_bytes()), expected);
+    }
+}
@@ -13,6 +13,8 @@ pub enum Token {
 
     OpenDelim(Delim),
     CloseDelim(Delim),
+    /// The opening bracket of a GMS accessor, closed by `CloseDelim(Delim::Bracket)`.
+    OpenAccessor(Accessor),
 
     Eq,
     ColonEq,
@@ -34,11 +36,14 @@ pub enum Token {
     Shr,
     Bang,
     Tilde,
+    PlusPlus,
+    MinusMinus,
 
     Dot,
     Comma,
     Semicolon,
     Colon,
+    Question,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
@@ -48,6 +53,14 @@ pub enum Delim {
     Brace,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Accessor {
+    Array,
+    List,
+    Map,
+    Grid,
+}
+
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum BinOp {
     Plus,
@@ -81,6 +94,12 @@ impl fmt::Display for Token {
                 Delim::Bracket => write!(fmt, "]")?,
                 Delim::Brace => write!(fmt, "}}")?,
             },
+            Token::OpenAccessor(accessor) => match accessor {
+                Accessor::Array => write!(fmt, "[@")?,
+                Accessor::List => write!(fmt, "[|")?,
+                Accessor::Map => write!(fmt, "[?")?,
+                Accessor::Grid => write!(fmt, "[#")?,
+            },
 
             Token::Eq => write!(fmt, "=")?,
             Token::ColonEq => write!(fmt, ":=")?,
@@ -120,11 +139,14 @@ impl fmt::Display for Token {
             Token::Shr => write!(fmt, ">>")?,
             Token::Bang => write!(fmt, "!")?,
             Token::Tilde => write!(fmt, "~")?,
+            Token::PlusPlus => write!(fmt, "++")?,
+            Token::MinusMinus => write!(fmt, "--")?,
 
             Token::Dot => write!(fmt, ".")?,
             Token::Comma => write!(fmt, ",")?,
             Token::Semicolon => write!(fmt, ";")?,
             Token::Colon => write!(fmt, ":")?,
+            Token::Question => write!(fmt, "?")?,
         }
 
         Ok(())
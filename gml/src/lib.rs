@@ -24,7 +24,7 @@ pub mod back;
 pub mod vm;
 
 /// The name of a single executable unit of GML or D&D actions.
-#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
 pub enum Function {
     Event { object_index: i32, event_type: u32, event_kind: i32 },
     Script { id: i32 },
@@ -34,38 +34,107 @@ pub enum Function {
     Instance { id: i32 },
 }
 
+impl vm::Serialize for Function {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        match *self {
+            Function::Event { object_index, event_type, event_kind } => {
+                out.write(&0u8)?;
+                out.write(&object_index)?;
+                out.write(&event_type)?;
+                out.write(&event_kind)
+            }
+            Function::Script { id } => {
+                out.write(&1u8)?;
+                out.write(&id)
+            }
+            Function::Room { id } => {
+                out.write(&2u8)?;
+                out.write(&id)
+            }
+            Function::Instance { id } => {
+                out.write(&3u8)?;
+                out.write(&id)
+            }
+        }
+    }
+}
+
+impl vm::Deserialize for Function {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        match input.read::<u8>()? {
+            0 => {
+                let object_index = input.read()?;
+                let event_type = input.read()?;
+                let event_kind = input.read()?;
+                Ok(Function::Event { object_index, event_type, event_kind })
+            }
+            1 => Ok(Function::Script { id: input.read()? }),
+            2 => Ok(Function::Room { id: input.read()? }),
+            3 => Ok(Function::Instance { id: input.read()? }),
+            _ => Err(vm::invalid_data()),
+        }
+    }
+}
+
 /// An entity defined by the runner.
+///
+/// Members are either per-instance variables, or values shared by the whole game.
 pub enum Item<W> {
     Native(vm::ApiFunction<W>, usize, bool),
-    Member(Option<vm::GetFunction<W>>, Option<vm::SetFunction<W>>),
+    Member(Option<vm::GetFunction<W>>, Option<vm::SetFunction<W>>, bool),
+}
+
+/// Settings that control how a project is compiled.
+#[derive(Copy, Clone, Debug)]
+pub struct Options {
+    /// Simplify code before generating bytecode. Turning this off keeps the bytecode close to the
+    /// source, which can make the disassembly easier to follow.
+    pub optimize: bool,
+    /// The version of GML to accept.
+    pub dialect: Dialect,
+    /// Warn about code that compiles but is likely a mistake.
+    pub lint: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self { Options { optimize: true, dialect: Dialect::default(), lint: false } }
+}
+
+/// A version of the GML language.
+///
+/// Game Maker: Studio extended GML's syntax, and gave arrays value semantics: an array can be
+/// held in a variable, passed to a script, or returned from one, and is copied when it is written
+/// through a variable that does not own it.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Dialect {
+    /// GML as of Game Maker 8.
+    Gm8,
+    /// GML as of Game Maker: Studio, with `++`, `?:`, data structure accessors, `#macro`,
+    /// `enum`, and `#region`.
+    Gms,
+}
+
+impl Default for Dialect {
+    fn default() -> Self { Dialect::Gm8 }
 }
 
 /// Build the GML and D&D in a Game Maker project.
 pub fn build<W, F: FnMut() -> E, E: io::Write + 'static>(
-    game: &project::Game, runner: &HashMap<Symbol, Item<W>>, mut errors: F
+    game: &project::Game, runner: &HashMap<Symbol, Item<W>>, errors: F
+) -> Result<(vm::Assets<W>, vm::Debug), u32> {
+    build_with_options(game, runner, Options::default(), errors)
+}
+
+/// Build the GML and D&D in a Game Maker project, with non-default `Options`.
+pub fn build_with_options<W, F: FnMut() -> E, E: io::Write + 'static>(
+    game: &project::Game, runner: &HashMap<Symbol, Item<W>>, options: Options, mut errors: F
 ) -> Result<(vm::Assets<W>, vm::Debug), u32> {
     let mut assets = vm::Assets::default();
-    let mut prototypes = HashMap::with_capacity(game.scripts.len() + runner.len());
     let mut debug = vm::Debug::default();
 
-    // Collect the prototypes of entities that may be referred to in code.
-    for (&name, item) in runner.iter() {
-        match *item {
-            Item::Native(api, arity, variadic) => {
-                assets.api.insert(name, api);
-                prototypes.insert(name, ssa::Prototype::Native { arity, variadic });
-            }
-            Item::Member(get, set) => {
-                if let Some(get) = get { assets.get.insert(name, get); }
-                if let Some(set) = set { assets.set.insert(name, set); }
-                prototypes.insert(name, ssa::Prototype::Member);
-            }
-        }
-    }
-    for (id, &project::Script { name, .. }) in game.scripts.iter().enumerate() {
-        let id = id as i32;
+    link(&mut assets, runner);
+    for &project::Script { name, .. } in game.scripts.iter() {
         let name = Symbol::intern(name);
-        prototypes.insert(name, ssa::Prototype::Script { id });
         debug.scripts.push(name);
     }
     for &project::Object { name, .. } in game.objects.iter() {
@@ -76,6 +145,15 @@ pub fn build<W, F: FnMut() -> E, E: io::Write + 'static>(
         let name = Symbol::intern(name);
         debug.rooms.push(name);
     }
+    let prototypes = prototypes(runner, &debug.scripts);
+    let definitions = match options.dialect {
+        Dialect::Gm8 => front::Definitions::default(),
+        Dialect::Gms => collect_definitions(game, &debug),
+    };
+    let lint = front::Resources {
+        objects: Some(debug.objects.len()),
+        arguments: game.scripts.iter().map(|script| front::arguments(script.body)).collect(),
+    };
 
     let mut total_errors = 0;
 
@@ -84,7 +162,8 @@ pub fn build<W, F: FnMut() -> E, E: io::Write + 'static>(
     for (id, (&script, &project::Script { body, .. })) in resources.enumerate() {
         let function = Function::Script { id: id as i32 };
         let name = FunctionDisplay::Script { script };
-        let (code, locations, errors) = compile_program(&prototypes, name, body, errors());
+        let (code, locations, errors) =
+            compile_program(&prototypes, &lint, options, &definitions, name, body, errors());
         assets.code.insert(function, code);
         debug.locations.insert(function, locations);
         total_errors += errors;
@@ -98,7 +177,8 @@ pub fn build<W, F: FnMut() -> E, E: io::Write + 'static>(
             let function = Function::Event { object_index, event_type, event_kind };
             let event_kind = EventDisplay::from_debug(&debug, event_type, event_kind);
             let name = FunctionDisplay::Event { object, event_type, event_kind };
-            let (code, locations, errors) = compile_event(&prototypes, name, actions, errors());
+            let (code, locations, errors) =
+                compile_event(&prototypes, &lint, options, &definitions, name, actions, errors());
             assets.code.insert(function, code);
             debug.locations.insert(function, locations);
             total_errors += errors;
@@ -113,7 +193,8 @@ pub fn build<W, F: FnMut() -> E, E: io::Write + 'static>(
         if code.len() > 0 {
             let function = Function::Room { id };
             let name = FunctionDisplay::Room { room };
-            let (code, locations, errors) = compile_program(&prototypes, name, code, errors());
+            let (code, locations, errors) =
+                compile_program(&prototypes, &lint, options, &definitions, name, code, errors());
             assets.code.insert(function, code);
             debug.locations.insert(function, locations);
             total_errors += errors;
@@ -124,7 +205,9 @@ pub fn build<W, F: FnMut() -> E, E: io::Write + 'static>(
             if code.len() > 0 {
                 let function = Function::Instance { id };
                 let name = FunctionDisplay::Instance { room, id };
-                let (code, locations, errors) = compile_program(&prototypes, name, code, errors());
+                let (code, locations, errors) = compile_program(
+                    &prototypes, &lint, options, &definitions, name, code, errors()
+                );
                 assets.code.insert(function, code);
                 debug.locations.insert(function, locations);
                 total_errors += errors;
@@ -141,16 +224,224 @@ pub fn build<W, F: FnMut() -> E, E: io::Write + 'static>(
     Ok((assets, debug))
 }
 
+/// Compile an expression to run alongside a game's code, such as from a debugger.
+///
+/// The expression may refer to the game's scripts, and to entities defined by the runner. It is
+/// compiled with the same `Options` as the game.
+pub fn compile_expression<W, E: io::Write>(
+    runner: &HashMap<Symbol, Item<W>>, debug: &vm::Debug, options: Options, expression: &[u8],
+    errors: E
+) -> Result<code::Function, u32> {
+    let prototypes = prototypes(runner, &debug.scripts);
+    let lines = Lines::from_code(expression);
+    let mut errors = ErrorPrinter::new(FunctionDisplay::Expression, &lines, errors);
+    let reader = Lexer::with_dialect(expression, 0, options.dialect);
+    let program = Parser::new(reader, &mut errors).parse_expression_program();
+    let mut program = front::Codegen::with_dialect(&prototypes, options.dialect, &mut errors)
+        .compile_program(&program);
+    if errors.count > 0 {
+        return Err(errors.count);
+    }
+    if options.optimize {
+        back::optimize(&mut program);
+    }
+    let (code, _) = back::Codegen::new(&prototypes).compile(&program);
+    Ok(code)
+}
+
+/// Format a script as canonical GML, keeping its comments, macros, and enum references.
+pub fn format<E: io::Write>(
+    script: Symbol, source: &[u8], dialect: Dialect, errors: E
+) -> Result<Vec<u8>, u32> {
+    let lines = Lines::from_code(source);
+    let mut errors = ErrorPrinter::new(FunctionDisplay::Script { script }, &lines, errors);
+    let reader = Lexer::with_dialect(source, 0, dialect).without_expansion();
+    let mut parser = Parser::new(reader, &mut errors);
+    let program = parser.parse_program();
+    let comments = parser.comments().to_vec();
+    if errors.count > 0 {
+        return Err(errors.count);
+    }
+    Ok(front::Printer::new(source, &comments).print_program(&program))
+}
+
+/// Collect the prototypes of entities that may be referred to in code.
+///
+/// Script prototypes refer to scripts by their index in `scripts`.
+pub fn prototypes<W>(runner: &HashMap<Symbol, Item<W>>, scripts: &[Symbol]) ->
+    HashMap<Symbol, ssa::Prototype>
+{
+    let mut prototypes = HashMap::with_capacity(scripts.len() + runner.len());
+    for (&name, item) in runner.iter() {
+        let prototype = match *item {
+            Item::Native(_, arity, variadic) => ssa::Prototype::Native { arity, variadic },
+            Item::Member(_, set, _) => ssa::Prototype::Member { settable: set.is_some() },
+        };
+        prototypes.insert(name, prototype);
+    }
+    for (id, &name) in scripts.iter().enumerate() {
+        prototypes.insert(name, ssa::Prototype::Script { id: id as i32 });
+    }
+    prototypes
+}
+
+/// The source code of a function, as its debug locations refer to it.
+pub fn source(game: &project::Game, function: Function) -> Option<Vec<u8>> {
+    match function {
+        Function::Event { object_index, event_type, event_kind } => {
+            let object = game.objects.get(object_index as usize)?;
+            let event = object.events.iter().find(|event| {
+                event.event_type == event_type && event.event_kind == event_kind
+            })?;
+            Some(Lines::source_from_actions(&event.actions))
+        }
+        Function::Script { id } => Some(game.scripts.get(id as usize)?.body.to_vec()),
+        Function::Room { id } => Some(game.rooms.get(id as usize)?.code.to_vec()),
+        Function::Instance { id } => game.rooms.iter()
+            .flat_map(|room| room.instances.iter())
+            .find(|instance| instance.id == id)
+            .map(|instance| instance.code.to_vec()),
+    }
+}
+
+/// Fill in the entities defined by the runner.
+fn link<W>(assets: &mut vm::Assets<W>, runner: &HashMap<Symbol, Item<W>>) {
+    for (&name, item) in runner.iter() {
+        match *item {
+            Item::Native(api, _, _) => { assets.api.insert(name, api); }
+            Item::Member(get, set, instance) => {
+                if let Some(get) = get { assets.get.insert(name, get); }
+                if let Some(set) = set { assets.set.insert(name, set); }
+                if instance { assets.members.insert(name); }
+            }
+        }
+    }
+}
+
+const MAGIC: &[u8; 4] = b"DJVC";
+const VERSION: u32 = 3;
+
+/// Save the output of `build`, so it can be run later without compiling it again.
+///
+/// Only the compiled code is saved. Entities defined by the runner are looked up by name when
+/// the code is loaded, so a bundle can be loaded by a newer runner with the same API.
+pub fn write_build<W, O: io::Write>(
+    assets: &vm::Assets<W>, debug: &vm::Debug, mut write: O
+) -> io::Result<()> {
+    write.write_all(MAGIC)?;
+    let mut out = vm::Serializer::new(write);
+    out.write(&VERSION)?;
+    out.write_sorted(&assets.code)?;
+    out.write(debug)
+}
+
+/// Load code saved with `write_build`, and link it with the entities defined by the runner.
+pub fn read_build<W, I: io::Read>(runner: &HashMap<Symbol, Item<W>>, mut read: I) ->
+    io::Result<(vm::Assets<W>, vm::Debug)>
+{
+    let mut magic = [0; 4];
+    read.read_exact(&mut magic)?;
+    let mut input = vm::Deserializer::new(read);
+    if &magic != MAGIC || input.read::<u32>()? != VERSION {
+        return Err(vm::invalid_data());
+    }
+    let code: HashMap<Function, code::Function> = input.read()?;
+    let debug: vm::Debug = input.read()?;
+
+    // Error messages need a name and source positions for every function.
+    if !code.keys().all(|&function| debug.describes(function)) {
+        return Err(vm::invalid_data());
+    }
+
+    // The interpreter trusts that calls refer to scripts and entities that exist.
+    let linked = code.values().flat_map(code::Function::callees).all(|callee| match callee {
+        code::Callee::Script(id) => code.contains_key(&Function::Script { id }),
+        code::Callee::Api(name) => matches!(runner.get(&name), Some(Item::Native(..))),
+        code::Callee::Get(name) => matches!(runner.get(&name), Some(Item::Member(Some(_), _, _))),
+        code::Callee::Set(name) => matches!(runner.get(&name), Some(Item::Member(_, Some(_), _))),
+    });
+    if !linked {
+        return Err(vm::invalid_data());
+    }
+
+    let mut assets = vm::Assets { code, ..vm::Assets::default() };
+    link(&mut assets, runner);
+    Ok((assets, debug))
+}
+
+/// Collect the GMS `#macro`s and `enum`s defined anywhere in a project, so all its code can use
+/// them regardless of the order it is compiled in.
+///
+/// Any errors are reported again when the code is compiled, so they are discarded here.
+fn collect_definitions(game: &project::Game, debug: &vm::Debug) -> front::Definitions {
+    let mut definitions = front::Definitions::default();
+
+    let resources = Iterator::zip(debug.scripts.iter(), game.scripts.iter());
+    for (&script, &project::Script { body, .. }) in resources {
+        let name = FunctionDisplay::Script { script };
+        definitions = define_program(definitions, name, body);
+    }
+
+    let resources = Iterator::zip(debug.objects.iter(), game.objects.iter());
+    for (&object, &project::Object { ref events, .. }) in resources {
+        for &project::Event { event_type, event_kind, ref actions } in events {
+            let event_kind = EventDisplay::from_debug(debug, event_type, event_kind);
+            let name = FunctionDisplay::Event { object, event_type, event_kind };
+            let lines = Lines::from_actions(actions);
+            let mut errors = ErrorPrinter::new(name, &lines, io::sink());
+            let mut parser = ActionParser::with_dialect(actions.iter(), Dialect::Gms, &mut errors)
+                .with_definitions(definitions);
+            parser.parse_event();
+            definitions = parser.definitions();
+        }
+    }
+
+    let resources = Iterator::zip(debug.rooms.iter(), game.rooms.iter());
+    for (&room, &project::Room { code, ref instances, .. }) in resources {
+        definitions = define_program(definitions, FunctionDisplay::Room { room }, code);
+        for &project::Instance { id, code, .. } in instances {
+            let name = FunctionDisplay::Instance { room, id };
+            definitions = define_program(definitions, name, code);
+        }
+    }
+
+    definitions
+}
+
+fn define_program(
+    definitions: front::Definitions, name: FunctionDisplay, code: &[u8]
+) -> front::Definitions {
+    let lines = Lines::from_code(code);
+    let mut errors = ErrorPrinter::new(name, &lines, io::sink());
+    let reader = Lexer::with_dialect(code, 0, Dialect::Gms);
+    let mut parser = Parser::with_definitions(reader, definitions, &mut errors);
+    parser.parse_program();
+    parser.definitions()
+}
+
 fn compile_program<E: io::Write + 'static>(
     prototypes: &HashMap<Symbol, ssa::Prototype>,
+    resources: &front::Resources,
+    options: Options,
+    definitions: &front::Definitions,
     name: FunctionDisplay,
     code: &[u8],
     errors: E,
 ) -> (code::Function, vm::Locations, u32) {
     let lines = Lines::from_code(code);
     let mut errors = ErrorPrinter::new(name, &lines, errors);
-    let program = Parser::new(Lexer::new(code, 0), &mut errors).parse_program();
-    let program = { front::Codegen::new(&prototypes, &mut errors).compile_program(&program) };
+    let reader = Lexer::with_dialect(code, 0, options.dialect);
+    let ast = Parser::with_definitions(reader, definitions.clone(), &mut errors).parse_program();
+    let mut program = front::Codegen::with_dialect(&prototypes, options.dialect, &mut errors)
+        .compile_program(&ast);
+    if errors.count == 0 {
+        if options.lint {
+            front::Linter::new(prototypes, resources, &mut errors).lint_program(&ast, &program);
+        }
+        if options.optimize {
+            back::optimize(&mut program);
+        }
+    }
     let (code, locations) = back::Codegen::new(prototypes).compile(&program);
     let count = errors.count;
     (code, vm::Locations { locations, lines }, count)
@@ -158,14 +449,28 @@ fn compile_program<E: io::Write + 'static>(
 
 fn compile_event<E: io::Write + 'static>(
     prototypes: &HashMap<Symbol, ssa::Prototype>,
+    resources: &front::Resources,
+    options: Options,
+    definitions: &front::Definitions,
     name: FunctionDisplay,
     actions: &[project::Action<'_>],
     errors: E,
 ) -> (code::Function, vm::Locations, u32) {
     let lines = Lines::from_actions(actions);
     let mut errors = ErrorPrinter::new(name, &lines, errors);
-    let program = ActionParser::new(actions.iter(), &mut errors).parse_event();
-    let program = front::Codegen::new(&prototypes, &mut errors).compile_event(&program);
+    let ast = ActionParser::with_dialect(actions.iter(), options.dialect, &mut errors)
+        .with_definitions(definitions.clone())
+        .parse_event();
+    let mut program = front::Codegen::with_dialect(&prototypes, options.dialect, &mut errors)
+        .compile_event(&ast);
+    if errors.count == 0 {
+        if options.lint {
+            front::Linter::new(prototypes, resources, &mut errors).lint_event(&ast, &program);
+        }
+        if options.optimize {
+            back::optimize(&mut program);
+        }
+    }
     let (code, locations) = back::Codegen::new(prototypes).compile(&program);
     let count = errors.count;
     (code, vm::Locations { locations, lines }, count)
@@ -175,14 +480,25 @@ pub struct ErrorPrinter<'a, W: ?Sized = dyn io::Write + 'a> {
     pub name: FunctionDisplay,
     pub lines: &'a Lines,
     pub count: u32,
+    /// Each diagnostic reported so far, for tools that present them other than as text.
+    pub diagnostics: Vec<(Severity, Span, String)>,
     pub write: W,
 }
 
+/// How serious a diagnostic is. Only errors cause compilation to fail.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
 pub enum FunctionDisplay {
     Event { object: Symbol, event_type: u32, event_kind: EventDisplay },
     Script { script: Symbol },
     Room { room: Symbol },
-    Instance { room: Symbol, id: i32 }
+    Instance { room: Symbol, id: i32 },
+    /// An expression compiled with `compile_expression`.
+    Expression,
 }
 
 #[derive(Copy, Clone)]
@@ -195,7 +511,7 @@ impl<'a> ErrorPrinter<'a> {
     pub fn new<W: io::Write>(name: FunctionDisplay, lines: &'a Lines, write: W) ->
         ErrorPrinter<'a, W>
     {
-        ErrorPrinter { name, lines, count: 0, write }
+        ErrorPrinter { name, lines, count: 0, diagnostics: Vec::default(), write }
     }
 
     pub fn from_debug<W: io::Write>(debug: &vm::Debug, function: Function, write: W) ->
@@ -207,10 +523,20 @@ impl<'a> ErrorPrinter<'a> {
     }
 
     pub fn error(&mut self, span: Span, message: fmt::Arguments<'_>) {
-        let _ = write!(self.write, "error in ");
+        self.report(Severity::Error, span, message);
+        self.count += 1;
+    }
+
+    /// Report a problem that does not prevent the code from compiling.
+    pub fn warning(&mut self, span: Span, message: fmt::Arguments<'_>) {
+        self.report(Severity::Warning, span, message);
+    }
+
+    fn report(&mut self, severity: Severity, span: Span, message: fmt::Arguments<'_>) {
+        let _ = write!(self.write, "{} in ", severity);
         Self::position(&mut self.write, &self.name, self.lines, span);
         let _ = writeln!(self.write, ": {}", message);
-        self.count += 1;
+        self.diagnostics.push((severity, span, message.to_string()));
     }
 
     pub fn stack_from_debug(&mut self, debug: &vm::Debug, stack: &[vm::ErrorFrame]) {
@@ -267,8 +593,12 @@ impl FunctionDisplay {
 }
 
 impl EventDisplay {
-    fn from_debug(_: &vm::Debug, event_type: u32, event_kind: i32) -> EventDisplay {
+    fn from_debug(debug: &vm::Debug, event_type: u32, event_kind: i32) -> EventDisplay {
         match event_type {
+            project::event_type::COLLISION => match debug.objects.get(event_kind as usize) {
+                Some(&object) => EventDisplay::Name(object),
+                None => EventDisplay::Id(event_kind),
+            }
             _ => EventDisplay::Id(event_kind),
         }
     }
@@ -291,6 +621,16 @@ impl fmt::Display for FunctionDisplay {
             FunctionDisplay::Room { room } => write!(f, "creation code of room {}", room),
             FunctionDisplay::Instance { room, id } =>
                 write!(f, "creation code for instance {} in room {}", id, room),
+            FunctionDisplay::Expression => write!(f, "expression"),
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
         }
     }
 }
@@ -298,11 +638,131 @@ impl fmt::Display for FunctionDisplay {
 fn display_event(
     object: Symbol, event_type: u32, event_kind: EventDisplay, f: &mut fmt::Formatter<'_>
 ) -> fmt::Result {
+    use project::{event_type, event_kind as kind};
+
     match (event_type, event_kind) {
-        (project::event_type::CREATE, _) => write!(f, "create event")?,
-        (project::event_type::DESTROY, _) => write!(f, "destroy event")?,
-        _ => write!(f, "unknown event")?,
+        (event_type::CREATE, _) => write!(f, "create event")?,
+        (event_type::DESTROY, _) => write!(f, "destroy event")?,
+        (event_type::ALARM, EventDisplay::Id(alarm)) => write!(f, "alarm {} event", alarm)?,
+        (event_type::STEP, EventDisplay::Id(kind::STEP_NORMAL)) => write!(f, "step event")?,
+        (event_type::STEP, EventDisplay::Id(kind::STEP_BEGIN)) => write!(f, "step begin event")?,
+        (event_type::STEP, EventDisplay::Id(kind::STEP_END)) => write!(f, "step end event")?,
+        (event_type::COLLISION, EventDisplay::Name(other)) =>
+            write!(f, "collision event with {}", other)?,
+        (event_type::COLLISION, EventDisplay::Id(other)) =>
+            write!(f, "collision event with object {}", other)?,
+        (event_type::KEYBOARD, EventDisplay::Id(key)) =>
+            write!(f, "keyboard event for {}", KeyDisplay(key))?,
+        (event_type::MOUSE, EventDisplay::Id(kind)) => display_mouse(kind, f)?,
+        (event_type::OTHER, EventDisplay::Id(kind)) => display_other(kind, f)?,
+        (event_type::DRAW, EventDisplay::Id(kind::DRAW_NORMAL)) => write!(f, "draw event")?,
+        (event_type::KEY_PRESS, EventDisplay::Id(key)) =>
+            write!(f, "key press event for {}", KeyDisplay(key))?,
+        (event_type::KEY_RELEASE, EventDisplay::Id(key)) =>
+            write!(f, "key release event for {}", KeyDisplay(key))?,
+        (event_type::TRIGGER, EventDisplay::Id(trigger)) =>
+            write!(f, "trigger {} event", trigger)?,
+        (_, EventDisplay::Id(kind)) => write!(f, "unknown event {} {}", event_type, kind)?,
+        (_, EventDisplay::Name(kind)) => write!(f, "unknown event {} {}", event_type, kind)?,
     };
     write!(f, " for object {}", object)?;
     Ok(())
 }
+
+fn display_mouse(kind: i32, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    use project::event_kind::*;
+
+    const BUTTONS: [&str; 12] = [
+        "left button", "right button", "middle button", "no button",
+        "left pressed", "right pressed", "middle pressed",
+        "left released", "right released", "middle released",
+        "mouse enter", "mouse leave",
+    ];
+    const DIRECTIONS: [&str; 4] = ["left", "right", "up", "down"];
+
+    match kind {
+        MOUSE_LEFT_BUTTON..=MOUSE_LEAVE => write!(f, "{} event", BUTTONS[kind as usize]),
+        JOYSTICK1_LEFT..=19 =>
+            write!(f, "joystick 1 {} event", DIRECTIONS[(kind - JOYSTICK1_LEFT) as usize]),
+        JOYSTICK1_BUTTON1..=28 =>
+            write!(f, "joystick 1 button {} event", kind - JOYSTICK1_BUTTON1 + 1),
+        JOYSTICK2_LEFT..=34 =>
+            write!(f, "joystick 2 {} event", DIRECTIONS[(kind - JOYSTICK2_LEFT) as usize]),
+        JOYSTICK2_BUTTON1..=43 =>
+            write!(f, "joystick 2 button {} event", kind - JOYSTICK2_BUTTON1 + 1),
+        GLOBAL_LEFT_BUTTON..=GLOBAL_MIDDLE_RELEASE => {
+            // There is no global counterpart to the no button event.
+            let button = match kind - GLOBAL_LEFT_BUTTON {
+                button @ 0..=2 => button,
+                button => button + 1,
+            };
+            write!(f, "global {} event", BUTTONS[button as usize])
+        }
+        MOUSE_WHEEL_UP => write!(f, "mouse wheel up event"),
+        MOUSE_WHEEL_DOWN => write!(f, "mouse wheel down event"),
+        _ => write!(f, "unknown mouse event {}", kind),
+    }
+}
+
+fn display_other(kind: i32, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    use project::event_kind::*;
+
+    const OTHER: [&str; 10] = [
+        "outside room", "intersect boundary", "game start", "game end", "room start", "room end",
+        "no more lives", "animation end", "end of path", "no more health",
+    ];
+
+    match kind {
+        OUTSIDE_ROOM..=NO_MORE_HEALTH => write!(f, "{} event", OTHER[kind as usize]),
+        _ if (USER0..USER0 + USER_COUNT).contains(&kind) =>
+            write!(f, "user defined {} event", kind - USER0),
+        CLOSE_BUTTON => write!(f, "close button event"),
+        _ if (OUTSIDE_VIEW0..OUTSIDE_VIEW0 + VIEW_COUNT).contains(&kind) =>
+            write!(f, "outside view {} event", kind - OUTSIDE_VIEW0),
+        _ if (BOUNDARY_VIEW0..BOUNDARY_VIEW0 + VIEW_COUNT).contains(&kind) =>
+            write!(f, "intersect boundary view {} event", kind - BOUNDARY_VIEW0),
+        _ => write!(f, "unknown other event {}", kind),
+    }
+}
+
+/// A virtual key code, named like the `vk_` constants.
+struct KeyDisplay(i32);
+
+impl fmt::Display for KeyDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.0 {
+            0 => "<no key>",
+            1 => "<any key>",
+            8 => "<backspace>",
+            9 => "<tab>",
+            13 => "<enter>",
+            16 => "<shift>",
+            17 => "<control>",
+            18 => "<alt>",
+            19 => "<pause>",
+            27 => "<escape>",
+            32 => "<space>",
+            33 => "<page up>",
+            34 => "<page down>",
+            35 => "<end>",
+            36 => "<home>",
+            37 => "<left>",
+            38 => "<up>",
+            39 => "<right>",
+            40 => "<down>",
+            44 => "<print screen>",
+            45 => "<insert>",
+            46 => "<delete>",
+            key @ 48..=57 | key @ 65..=90 => return write!(f, "<{}>", key as u8 as char),
+            key @ 96..=105 => return write!(f, "<numpad {}>", key - 96),
+            106 => "<numpad *>",
+            107 => "<numpad +>",
+            109 => "<numpad ->",
+            110 => "<numpad .>",
+            111 => "<numpad />",
+            key @ 112..=123 => return write!(f, "<F{}>", key - 111),
+            key => return write!(f, "key {}", key),
+        };
+        write!(f, "{}", name)
+    }
+}
@@ -36,6 +36,15 @@ impl Symbol {
     pub fn into_index(self) -> NonZeroUsize { self.index }
 
     pub fn from_index(index: NonZeroUsize) -> Symbol { Symbol { index, _marker: PhantomData } }
+
+    /// Look up a symbol by index, if it has been interned.
+    pub fn try_from_index(index: usize) -> Option<Symbol> {
+        let index = NonZeroUsize::new(index)?;
+        match Interner::with(|interner| index.get() < interner.indices.len()) {
+            true => Some(Symbol::from_index(index)),
+            false => None,
+        }
+    }
 }
 
 impl Default for Symbol {
@@ -64,6 +64,13 @@ impl<'a> ArrayRef<'a> {
         }
     }
 
+    pub fn len(self) -> usize {
+        // Safety: The shared reference into `*self.data` is discarded before returning.
+        unsafe { (*self.data.get()).len() }
+    }
+
+    pub fn is_empty(self) -> bool { self.len() == 0 }
+
     /// Construct a pointer to an element in a 1D array.
     pub(in crate::vm) fn get_raw(self, j: i32) -> Option<*const vm::Value> {
         // Safety: Shared references into `*self.data` are discarded before `self` is usable again.
@@ -135,6 +142,30 @@ impl<'a> ArrayRef<'a> {
         Array { data: Rc::clone(&data) }
     }
 
+    /// Whether any other owner holds a reference to this array.
+    pub fn is_shared(self) -> bool {
+        // Safety: See `clone`.
+        let data = unsafe { ManuallyDrop::new(Rc::from_raw(self.data)) };
+        Rc::strong_count(&data) > 1
+    }
+
+    /// Copy this array and each of its rows, leaving the elements themselves shared.
+    pub fn copy(self) -> Array {
+        // Safety: The shared references into `*self.data` and its rows are discarded before
+        // returning, and neither is mutated while they are live.
+        let vec = unsafe { &*self.data.get() };
+        let vec = vec.iter()
+            .map(|value| match value.borrow().decode() {
+                vm::Data::Array(row) => {
+                    let row = unsafe { &*row.data.get() };
+                    vm::Value::from(Array { data: Rc::new(UnsafeCell::new(row.clone())) })
+                }
+                _ => value.clone(),
+            })
+            .collect();
+        Array { data: Rc::new(UnsafeCell::new(vec)) }
+    }
+
     pub fn as_raw(self) -> *const Data { self.data }
 
     pub unsafe fn from_raw(ptr: *const Data) -> ArrayRef<'a> { ArrayRef { data: &*ptr } }
@@ -185,4 +216,24 @@ mod tests {
         assert_eq!(a.get_jagged(3, 6), None);
         assert_eq!(a.get_jagged(4, 0), None);
     }
+
+    #[test]
+    fn copy() {
+        let array = vm::Array::from_scalar(vm::Value::from(3.0));
+        let a = array.borrow();
+        assert!(!a.is_shared());
+
+        let shared = a.clone();
+        assert!(a.is_shared());
+
+        let copy = a.copy();
+        let b = copy.borrow();
+        assert!(!b.is_shared());
+        assert_eq!(b.set_jagged(0, 0, vm::Value::from(5.0)), Some(()));
+        assert_eq!(a.get_jagged(0, 0), Some(vm::Value::from(3.0)));
+        assert_eq!(b.get_jagged(0, 0), Some(vm::Value::from(5.0)));
+
+        drop(shared);
+        assert!(!a.is_shared());
+    }
 }
@@ -11,6 +11,7 @@ pub trait FnBind<'t, W> {
     unsafe fn call(self, cx: &mut W, thread: &'t mut Thread, args: Range<usize>) -> Result<Value>;
 }
 pub trait GetBind<W> {
+    const INSTANCE: bool;
     fn call(self, cx: &mut W, entity: Entity, i: usize) -> Value;
 }
 pub trait SetBind<'t, W> {
@@ -19,6 +20,7 @@ pub trait SetBind<'t, W> {
 
 pub fn arity<'t, B: FnBind<'t, W>, W>(_: &B) -> usize { B::ARITY }
 pub fn variadic<'t, B: FnBind<'t, W>, W>(_: &B) -> bool { B::VARIADIC }
+pub fn instance<B: GetBind<W>, W>(_: &B) -> bool { B::INSTANCE }
 
 pub trait Project<'r, R> { fn fields(&'r mut self) -> R; }
 
@@ -81,6 +83,8 @@ macro_rules! impl_get_bind { (($($r:ident)*) ($($e:ident)?) ($($i:ident)?)) => {
         W: for<'r> Project<'r, ($(&'r mut $r,)*)>,
         B: Into<Value>,
     {
+        const INSTANCE: bool = count!($($e)?) == 1;
+
         #[allow(nonstandard_style, unused, unreachable)]
         fn call(self, cx: &mut W, entity: Entity, index: usize) -> Value {
             let ($($r,)*) = cx.fields();
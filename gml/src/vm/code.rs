@@ -1,5 +1,10 @@
-use std::{u8, mem, fmt};
+use std::{u8, iter, mem, fmt, io};
+use std::cmp;
+use std::collections::HashSet;
 
+use bstr::ByteSlice;
+
+use crate::symbol::Symbol;
 use crate::vm;
 
 pub struct Function {
@@ -20,11 +25,12 @@ impl Function {
     }
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Locations {
     pub mappings: Vec<SourceMap>,
 }
 
+#[derive(Copy, Clone)]
 pub struct SourceMap {
     pub offset: u32,
     pub location: u32,
@@ -44,9 +50,16 @@ impl Locations {
 ///
 /// Fields use this structure, stored in little-endian order:
 /// | op: 8 | dst: 8 | a: 8 | b: 8 |
+///
+/// Operands that do not fit in a byte are extended by up to three `Op::Wide` prefixes, each of
+/// which holds the next most significant byte of every field. Jump targets are split across two
+/// fields, low half first.
 #[derive(Copy, Clone)]
 pub struct Inst(pub(crate) u32);
 
+/// The most `Op::Wide` prefixes an instruction may have, enough for 32-bit operands.
+pub const MAX_PREFIXES: usize = 3;
+
 impl Inst {
     pub fn decode(&self) -> (Op, usize, usize, usize) {
         let Inst(bits) = *self;
@@ -59,11 +72,22 @@ impl Inst {
     }
 }
 
+impl Inst {
+    /// Check that an instruction's opcode is valid, so that it can be decoded.
+    fn from_bits(bits: u32) -> Option<Inst> {
+        if (bits & 0xff) as u8 > Op::Wide as u8 {
+            return None;
+        }
+        Some(Inst(bits))
+    }
+}
+
 #[repr(u8)]
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum Op {
     Imm,
     Move,
+    Copy,
 
     Neg,
     Not,
@@ -112,6 +136,13 @@ pub enum Op {
     ToArray,
     ToScalar,
     ReleaseOwned,
+    Share,
+    ToUnique,
+    MarkOwned,
+    ReleaseOwnedTo,
+    ReleaseCall,
+    OwnLocal,
+    StoreOwned,
 
     LoadField,
     LoadFieldDefault,
@@ -130,52 +161,336 @@ pub enum Op {
 
     Jump,
     BranchFalse,
+
+    Wide,
+}
+
+impl Function {
+    /// Decode the instruction at `offset`, along with any `Op::Wide` prefixes it starts with.
+    ///
+    /// Returns the instruction with each operand at its full width and jump targets reassembled,
+    /// along with the offset of the next instruction.
+    pub fn decode(&self, offset: usize) -> ((Op, usize, usize, usize), usize) {
+        let mut fields = [0; 3];
+        let mut bits = 0;
+        let mut offset = offset;
+        loop {
+            let (op, a, b, c) = self.instructions[offset].decode();
+            offset += 1;
+            bits += 8;
+            fields = [fields[0] << 8 | a, fields[1] << 8 | b, fields[2] << 8 | c];
+            let [a, b, c] = fields;
+            let inst = match op {
+                Op::Wide => continue,
+                Op::Jump => (op, a | b << bits, 0, 0),
+                Op::BranchFalse => (op, a, b | c << bits, 0),
+                _ => (op, a, b, c),
+            };
+            return (inst, offset);
+        }
+    }
+
+    /// Check that every operand names a register, constant, or instruction of this function, and
+    /// that execution cannot run off its end. The interpreter trusts all of these.
+    fn validate(&self) -> bool {
+        let locals = self.locals as usize;
+        let constants = &self.constants[..];
+        let starts: HashSet<usize> = self.offsets().collect();
+        let reg = |r: usize| r < locals;
+        let name = |c: usize| {
+            matches!(constants.get(c).map(|c| c.borrow().decode()), Some(vm::Data::String(_)))
+        };
+        let target = |t: usize| starts.contains(&t);
+
+        // Immediates are borrowed without being reference counted, so they cannot be arrays.
+        let scalars = constants.iter()
+            .all(|c| matches!(c.borrow().decode(), vm::Data::Real(_) | vm::Data::String(_)));
+        if !scalars {
+            return false;
+        }
+
+        let operands = self.offsets().all(|offset| match self.decode(offset).0 {
+            (Op::Imm, t, c, _) => reg(t) && c < constants.len(),
+            (Op::Lookup, t, c, _) => reg(t) && name(c),
+            (Op::DeclareGlobal, c, _, _) => name(c),
+            (Op::Read, a, c, _) => reg(a) && name(c),
+            (Op::LoadField, t, e, c) | (Op::LoadFieldDefault, t, e, c) |
+            (Op::StoreField, t, e, c) => reg(t) && reg(e) && name(c),
+
+            (Op::ReleaseWith, _, _, _) | (Op::ReleaseOwned, _, _, _) | (Op::Ret, _, _, _) => true,
+            (Op::LoadScope, a, _, _) | (Op::StoreScope, a, _, _) |
+            (Op::MarkOwned, a, _, _) | (Op::OwnLocal, a, _, _) |
+            (Op::ReleaseOwnedTo, a, _, _) | (Op::ReleaseCall, a, _, _) |
+            (Op::ScopeError, a, _, _) => reg(a),
+            (Op::Move, t, a, _) | (Op::Copy, t, a, _) |
+            (Op::Neg, t, a, _) | (Op::Not, t, a, _) | (Op::BitNot, t, a, _) |
+            (Op::LoadPointer, t, a, _) | (Op::NextPointer, t, a, _) |
+            (Op::ExistsEntity, t, a, _) |
+            (Op::ToArray, t, a, _) | (Op::ToScalar, t, a, _) |
+            (Op::Share, t, a, _) | (Op::ToUnique, t, a, _) |
+            (Op::StoreOwned, t, a, _) => reg(t) && reg(a),
+
+            // Arguments and results are passed in registers starting at `base`.
+            (Op::Call, _, base, len) => base + len <= locals,
+            (Op::CallApi, c, base, len) => name(c) && base + cmp::max(len, 1) <= locals,
+            (Op::CallGet, c, base, _) => name(c) && base + 2 <= locals,
+            (Op::CallSet, c, base, _) => name(c) && base + 3 <= locals,
+
+            (Op::Jump, t, _, _) => target(t),
+            (Op::BranchFalse, a, t, _) => reg(a) && target(t),
+
+            (Op::Wide, _, _, _) => false,
+            (_, t, a, b) => reg(t) && reg(a) && reg(b),
+        });
+
+        let last = self.offsets().last().map(|offset| {
+            let (op, _, _, _) = self.decode(offset).0;
+            op
+        });
+        operands && matches!(last, Some(Op::Ret) | Some(Op::Jump))
+    }
+
+    /// The scripts and runner entities that this function calls, to be checked by the caller.
+    ///
+    /// Assumes the function has been validated.
+    pub fn callees(&self) -> impl Iterator<Item = Callee> + '_ {
+        let name = move |c: usize| match self.constants[c].borrow().decode() {
+            vm::Data::String(name) => name,
+            _ => unreachable!("expected a string"),
+        };
+        self.offsets().filter_map(move |offset| match self.decode(offset).0 {
+            (Op::Call, id, _, _) => Some(Callee::Script(id as i32)),
+            (Op::CallApi, c, _, _) => Some(Callee::Api(name(c))),
+            (Op::CallGet, c, _, _) => Some(Callee::Get(name(c))),
+            (Op::CallSet, c, _, _) => Some(Callee::Set(name(c))),
+            _ => None,
+        })
+    }
+
+    /// The offset of each instruction, skipping over prefixes.
+    fn offsets(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut offset = 0;
+        iter::from_fn(move || {
+            if offset >= self.instructions.len() {
+                return None;
+            }
+            let start = offset;
+            offset = self.decode(start).1;
+            Some(start)
+        })
+    }
+}
+
+/// Something outside a function that it calls.
+pub enum Callee {
+    Script(i32),
+    Api(Symbol),
+    Get(Symbol),
+    Set(Symbol),
 }
 
 impl fmt::Debug for Function {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_signature(f)?;
+        for offset in self.offsets() {
+            write!(f, "  ")?;
+            self.fmt_instruction(self.decode(offset).0, f)?;
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Function {
+    fn fmt_signature(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "(")?;
         for param in 0..self.params {
             write!(f, "%{:?}, ", param)?;
         }
-        writeln!(f, ")[{:?}]", self.locals)?;
-
-        for &inst in &self.instructions {
-            let (op, a, b, c) = inst.decode();
-            match op {
-                Op::Imm | Op::Lookup =>
-                    writeln!(f, "  %{:?} = {:?} {:?}", a, op, self.constants[b])?,
-                Op::Move => writeln!(f, "  %{:?} = %{:?}", a, b)?,
-                Op::Neg | Op::Not | Op::BitNot | Op::ToArray | Op::ToScalar |
-                Op::LoadPointer | Op::NextPointer | Op::ExistsEntity |
-                Op::ScopeError =>
-                    writeln!(f, "  %{:?} = {:?} %{:?}", a, op, b)?,
-                Op::DeclareGlobal => writeln!(f, "  {:?} {:?}", op, self.constants[a])?,
-                Op::LoadScope => writeln!(f, "  %{:?} = {:?} {:?}", a, op, b as i32)?,
-                Op::StoreScope => writeln!(f, "  {:?} %{:?}, {:?}", op, a, b as i32)?,
-                Op::With => writeln!(f, "  %{:?}, %{:?} = {:?} %{:?}", a, b, op, c)?,
-                Op::ReleaseWith | Op::ReleaseOwned | Op::Ret =>
-                    writeln!(f, "  {:?}", op)?,
-                Op::Read => writeln!(f, "  {:?} %{:?}, {:?}", op, a, self.constants[b])?,
-                Op::LoadField | Op::LoadFieldDefault =>
-                    writeln!(f, "  %{:?} = {:?} %{:?}.{:?}", a, op, b, self.constants[c])?,
-                Op::LoadRow | Op::LoadIndex | Op::StoreRow =>
-                    writeln!(f, "  %{:?} = {:?} %{:?}[%{:?}]", a, op, b, c)?,
-                Op::StoreField =>
-                    writeln!(f, "  {:?} %{:?}, %{:?}.{:?}", op, a, b, self.constants[c])?,
-                Op::StoreIndex => writeln!(f, "  {:?} %{:?}, %{:?}[%{:?}]", op, a, b, c)?,
-                Op::Call =>
-                    writeln!(f, "  %{:?} = {:?} {:?}(%{:?} +{:?})", b, op, a, b, c)?,
-                Op::CallApi | Op::CallGet =>
-                    writeln!(f, "  %{:?} = {:?} {:?}(%{:?} +{:?})", b, op, self.constants[a], b, c)?,
-                Op::CallSet =>
-                    writeln!(f, "  {:?} {:?}(%{:?} +{:?})", op, self.constants[a], b, c)?,
-                Op::Jump => writeln!(f, "  {:?} {:?}", op, a | (b << 8))?,
-                Op::BranchFalse => writeln!(f, "  {:?} %{:?}, {:?}", op, a, b | (c << 8))?,
-                _ => writeln!(f, "  %{:?} = {:?} %{:?}, %{:?}", a, op, b, c)?,
+        writeln!(f, ")[{:?}]", self.locals)
+    }
+
+    fn fmt_instruction(
+        &self, (op, a, b, c): (Op, usize, usize, usize), f: &mut fmt::Formatter<'_>
+    ) -> fmt::Result {
+        match op {
+            Op::Imm | Op::Lookup =>
+                write!(f, "%{:?} = {:?} {:?}", a, op, self.constants[b]),
+            Op::Move => write!(f, "%{:?} = %{:?}", a, b),
+            Op::Copy | Op::Neg | Op::Not | Op::BitNot | Op::ToArray | Op::ToScalar |
+            Op::Share | Op::ToUnique |
+            Op::LoadPointer | Op::NextPointer | Op::ExistsEntity |
+            Op::ScopeError =>
+                write!(f, "%{:?} = {:?} %{:?}", a, op, b),
+            Op::DeclareGlobal => write!(f, "{:?} {:?}", op, self.constants[a]),
+            Op::LoadScope => write!(f, "%{:?} = {:?} {:?}", a, op, b as i32),
+            Op::StoreScope => write!(f, "{:?} %{:?}, {:?}", op, a, b as i32),
+            Op::With => write!(f, "%{:?}, %{:?} = {:?} %{:?}", a, b, op, c),
+            Op::ReleaseWith | Op::ReleaseOwned | Op::Ret =>
+                write!(f, "{:?}", op),
+            Op::MarkOwned | Op::OwnLocal => write!(f, "%{:?} = {:?}", a, op),
+            Op::ReleaseOwnedTo | Op::ReleaseCall => write!(f, "{:?} %{:?}", op, a),
+            Op::StoreOwned => write!(f, "{:?} %{:?}, %{:?}", op, a, b),
+            Op::Read => write!(f, "{:?} %{:?}, {:?}", op, a, self.constants[b]),
+            Op::LoadField | Op::LoadFieldDefault =>
+                write!(f, "%{:?} = {:?} %{:?}.{:?}", a, op, b, self.constants[c]),
+            Op::LoadRow | Op::LoadIndex | Op::StoreRow =>
+                write!(f, "%{:?} = {:?} %{:?}[%{:?}]", a, op, b, c),
+            Op::StoreField =>
+                write!(f, "{:?} %{:?}, %{:?}.{:?}", op, a, b, self.constants[c]),
+            Op::StoreIndex => write!(f, "{:?} %{:?}, %{:?}[%{:?}]", op, a, b, c),
+            Op::Call =>
+                write!(f, "%{:?} = {:?} {:?}(%{:?} +{:?})", b, op, a, b, c),
+            Op::CallApi | Op::CallGet =>
+                write!(f, "%{:?} = {:?} {:?}(%{:?} +{:?})", b, op, self.constants[a], b, c),
+            Op::CallSet =>
+                write!(f, "{:?} {:?}(%{:?} +{:?})", op, self.constants[a], b, c),
+            Op::Jump => write!(f, "{:?} {:?}", op, a),
+            Op::BranchFalse => write!(f, "{:?} %{:?}, {:?}", op, a, b),
+            _ => write!(f, "%{:?} = {:?} %{:?}, %{:?}", a, op, b, c),
+        }
+    }
+}
+
+/// A listing of a function's instructions, interleaved with the source code they came from.
+///
+/// `source` must be the text that `locations` was computed from: a script's body, or the
+/// concatenated actions of an event as produced by `Lines::source_from_actions`.
+pub struct Disassembly<'a> {
+    pub function: &'a Function,
+    pub locations: &'a vm::Locations,
+    pub source: &'a [u8],
+}
+
+impl fmt::Display for Disassembly<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Disassembly { function, locations, source } = *self;
+        function.fmt_signature(f)?;
+
+        let mut mappings = locations.locations.mappings.iter().peekable();
+        let mut location = None;
+        let mut last_item = None;
+        for offset in function.offsets() {
+            while let Some(&&SourceMap { offset: start, location: next }) = mappings.peek() {
+                if start as usize > offset { break; }
+                location = Some(next as usize);
+                mappings.next();
+            }
+
+            // Print each action, argument, or line once, and columns alongside instructions.
+            let position = location.map(|location| locations.lines.get_position(location));
+            let column = position.as_ref().and_then(|position| position.column);
+            if let Some(mut position) = position {
+                position.column = None;
+                let item = (position.action, position.argument, position.line);
+                if last_item != Some(item) {
+                    let text = locations.lines.get_text(source, location.unwrap_or_default());
+                    writeln!(f, "  ; {}: {}", position, text.as_bstr())?;
+                    last_item = Some(item);
+                }
+            }
+
+            let instruction = Instruction(function, function.decode(offset).0).to_string();
+            match column {
+                Some(column) =>
+                    writeln!(f, "  {:4}: {:32} ; column {}", offset, instruction, column)?,
+                None => writeln!(f, "  {:4}: {}", offset, instruction)?,
             }
         }
 
         Ok(())
     }
 }
+
+struct Instruction<'a>(&'a Function, (Op, usize, usize, usize));
+
+impl fmt::Display for Instruction<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Instruction(function, inst) = *self;
+        function.fmt_instruction(inst, f)
+    }
+}
+
+impl vm::Serialize for Function {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        out.write(&self.params)?;
+        out.write(&self.locals)?;
+        out.write(&self.constants)?;
+        out.write(&self.instructions)
+    }
+}
+
+impl vm::Deserialize for Function {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        let params = input.read()?;
+        let locals = input.read()?;
+        let constants = input.read()?;
+        let instructions: Vec<Inst> = input.read()?;
+        if params > locals {
+            return Err(vm::invalid_data());
+        }
+        // `decode` relies on every run of prefixes being short and followed by an instruction.
+        let mut prefixes = 0;
+        for &inst in &instructions {
+            match inst.decode().0 {
+                Op::Wide => prefixes += 1,
+                _ => prefixes = 0,
+            }
+            if prefixes > MAX_PREFIXES {
+                return Err(vm::invalid_data());
+            }
+        }
+        if prefixes > 0 {
+            return Err(vm::invalid_data());
+        }
+        let function = Function { params, locals, constants, instructions };
+        if !function.validate() {
+            return Err(vm::invalid_data());
+        }
+        Ok(function)
+    }
+}
+
+impl vm::Serialize for Inst {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        let Inst(bits) = *self;
+        out.write(&bits)
+    }
+}
+
+impl vm::Deserialize for Inst {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        Inst::from_bits(input.read()?).ok_or_else(vm::invalid_data)
+    }
+}
+
+impl vm::Serialize for Locations {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        out.write(&self.mappings)
+    }
+}
+
+impl vm::Deserialize for Locations {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        let mappings: Vec<SourceMap> = input.read()?;
+        // `get_location` relies on binary search.
+        if !mappings.windows(2).all(|pair| pair[0].offset <= pair[1].offset) {
+            return Err(vm::invalid_data());
+        }
+        Ok(Locations { mappings })
+    }
+}
+
+impl vm::Serialize for SourceMap {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        out.write(&self.offset)?;
+        out.write(&self.location)
+    }
+}
+
+impl vm::Deserialize for SourceMap {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        Ok(SourceMap { offset: input.read()?, location: input.read()? })
+    }
+}
@@ -0,0 +1,247 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::symbol::Symbol;
+use crate::Function;
+use crate::front::Position;
+use crate::vm::{self, world, code, interpreter::{self, Erased, W}};
+
+/// A front-end that inspects a paused `Thread` and decides how it continues.
+pub trait Debugger {
+    fn pause(&mut self, pause: &mut Pause<'_>) -> Resume;
+}
+
+/// How a paused thread continues.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Resume {
+    /// Run until the next breakpoint.
+    Continue,
+    /// Pause at the next statement, including one in a called script.
+    StepIn,
+    /// Pause at the next statement in the current function or one of its callers.
+    StepOver,
+    /// Pause at the next statement in one of the current function's callers.
+    StepOut,
+}
+
+/// The debugging state attached to a `Thread`.
+pub struct Session {
+    pub(in crate::vm) debugger: Box<dyn Debugger>,
+    pub(in crate::vm) breakpoints: Breakpoints,
+    /// How to continue from the last pause, and the call depth it happened at.
+    pub(in crate::vm) resume: Resume,
+    pub(in crate::vm) depth: usize,
+}
+
+/// The statements of each function, and which of them the thread should pause at.
+#[derive(Default)]
+pub struct Breakpoints {
+    statements: HashMap<Function, Vec<Statement>>,
+    starts: HashSet<(Function, usize)>,
+    enabled: HashSet<(Function, usize)>,
+}
+
+/// The first instruction generated for an action, argument, or line.
+#[derive(Copy, Clone)]
+struct Statement {
+    instruction: usize,
+    action: Option<usize>,
+    line: Option<usize>,
+}
+
+/// The state of a thread at a pause, as seen by a `Debugger`.
+pub struct Pause<'a> {
+    pub breakpoints: &'a mut Breakpoints,
+    pub(in crate::vm) frames: Vec<(Function, usize)>,
+    pub(in crate::vm) registers: Vec<Slot>,
+    pub(in crate::vm) self_entity: vm::Entity,
+    pub(in crate::vm) other_entity: vm::Entity,
+    pub(in crate::vm) cx: &'a mut Erased,
+}
+
+/// The contents of a register.
+///
+/// Registers do not record their types, so anything that is not a real or a string is left as
+/// its raw bits. This includes arrays, entities, and registers that have not been written yet.
+#[derive(Debug)]
+pub enum Slot {
+    Value(vm::Value),
+    Raw(u64),
+}
+
+impl Session {
+    pub fn new(debug: &vm::Debug, debugger: Box<dyn Debugger>, resume: Resume) -> Session {
+        let breakpoints = Breakpoints::new(debug);
+        Session { debugger, breakpoints, resume, depth: 0 }
+    }
+
+    pub fn breakpoints(&mut self) -> &mut Breakpoints { &mut self.breakpoints }
+
+    /// Pause at the next statement the thread runs.
+    pub fn pause(&mut self) { self.resume = Resume::StepIn; }
+
+    pub(in crate::vm) fn should_pause(
+        &self, function: Function, instruction: usize, depth: usize
+    ) -> bool {
+        let Session { ref breakpoints, resume, depth: resume_depth, .. } = *self;
+        if breakpoints.enabled.contains(&(function, instruction)) {
+            return true;
+        }
+        let statement = match resume {
+            Resume::Continue => return false,
+            Resume::StepIn => true,
+            Resume::StepOver => depth <= resume_depth,
+            Resume::StepOut => depth < resume_depth,
+        };
+        statement && breakpoints.starts.contains(&(function, instruction))
+    }
+}
+
+impl Breakpoints {
+    pub fn new(debug: &vm::Debug) -> Breakpoints {
+        let mut statements = HashMap::with_capacity(debug.locations.len());
+        let mut starts = HashSet::default();
+        for (&function, locations) in debug.locations.iter() {
+            let list = Self::statements(locations);
+            starts.extend(list.iter().map(|statement| (function, statement.instruction)));
+            statements.insert(function, list);
+        }
+        Breakpoints { statements, starts, enabled: HashSet::default() }
+    }
+
+    /// Find where each action, argument, and line begins, by mapping instructions back to source
+    /// positions.
+    fn statements(locations: &vm::Locations) -> Vec<Statement> {
+        let mut statements: Vec<Statement> = Vec::default();
+        let mut last = None;
+        let mappings = &locations.locations.mappings;
+        for (i, map) in mappings.iter().enumerate() {
+            // Only the last of several mappings at the same offset has any instructions.
+            if let Some(next) = mappings.get(i + 1) {
+                if next.offset == map.offset { continue; }
+            }
+
+            let position = locations.lines.get_position(map.location as usize);
+            let key = (position.action, position.argument, position.line);
+            if last == Some(key) { continue; }
+            last = Some(key);
+
+            let instruction = map.offset as usize;
+            let Position { action, line, .. } = position;
+            statements.push(Statement { instruction, action, line });
+        }
+        statements
+    }
+
+    /// Pause at a line of a script or event, in any of its actions if `action` is `None`.
+    ///
+    /// Returns `false` if there is no code at that line.
+    pub fn insert(&mut self, function: Function, action: Option<usize>, line: usize) -> bool {
+        let starts: Vec<_> = self.find(function, action, line).collect();
+        self.enabled.extend(starts.iter().copied());
+        !starts.is_empty()
+    }
+
+    /// Stop pausing at a line. Returns `false` if there was no breakpoint there.
+    pub fn remove(&mut self, function: Function, action: Option<usize>, line: usize) -> bool {
+        let starts: Vec<_> = self.find(function, action, line).collect();
+        let mut removed = false;
+        for start in starts {
+            removed |= self.enabled.remove(&start);
+        }
+        removed
+    }
+
+    pub fn clear(&mut self) { self.enabled.clear(); }
+
+    /// The enabled breakpoints, as a function, action, and line.
+    pub fn iter(&self) -> impl Iterator<Item = (Function, Option<usize>, Option<usize>)> + '_ {
+        self.enabled.iter().filter_map(move |&(function, instruction)| {
+            let statements = self.statements.get(&function)?;
+            let statement = statements.iter().find(|s| s.instruction == instruction)?;
+            Some((function, statement.action, statement.line))
+        })
+    }
+
+    fn find(&self, function: Function, action: Option<usize>, line: usize) ->
+        impl Iterator<Item = (Function, usize)> + '_
+    {
+        self.statements.get(&function).into_iter()
+            .flat_map(|statements| statements.iter())
+            .filter(move |statement| statement.line == Some(line))
+            .filter(move |statement| action.is_none() || statement.action == action)
+            .map(move |statement| (function, statement.instruction))
+    }
+}
+
+impl Pause<'_> {
+    /// The function and instruction where the thread paused.
+    pub fn location(&self) -> (Function, usize) { self.frames[0] }
+
+    /// The paused function followed by its callers. Each caller's instruction is the one it will
+    /// resume at, as in `vm::ErrorFrame`.
+    pub fn frames(&self) -> &[(Function, usize)] { &self.frames }
+
+    /// The registers of the paused function, which hold its arguments and local variables.
+    pub fn registers(&self) -> &[Slot] { &self.registers }
+
+    pub fn self_entity(&self) -> vm::Entity { self.self_entity }
+
+    pub fn other_entity(&self) -> vm::Entity { self.other_entity }
+
+    pub fn world(&mut self) -> &vm::World {
+        let (world, _) = self.cx.fields();
+        world
+    }
+
+    /// Read a variable of an instance or of `global`, including those defined by the runner.
+    pub fn member(&mut self, entity: vm::Entity, name: Symbol) -> Option<vm::Value> {
+        let (world, assets) = self.cx.fields();
+        if let Some(value) = world.members.get(entity).and_then(|members| members.get(&name)) {
+            return Some(value.clone());
+        }
+
+        // Runner-defined variables belong to instances, which must still exist.
+        if entity == world::GLOBAL || !world.members.contains_key(entity) {
+            return None;
+        }
+        let get = *assets.get.get(&name)?;
+        let cx = unsafe { &mut *(&mut *self.cx as *mut Erased as *mut W) };
+        Some(get(cx, entity, 0))
+    }
+
+    /// All the variables of an instance or of `global`, sorted by name.
+    pub fn members(&mut self, entity: vm::Entity) -> Vec<(Symbol, vm::Value)> {
+        let (world, assets) = self.cx.fields();
+        let mut names: Vec<_> = match world.members.get(entity) {
+            Some(members) => members.keys().copied().collect(),
+            None => return Vec::default(),
+        };
+        if entity != world::GLOBAL {
+            names.extend(assets.members.iter().copied());
+        }
+        names.sort();
+        names.dedup();
+
+        names.into_iter()
+            .filter_map(|name| Some((name, self.member(entity, name)?)))
+            .collect()
+    }
+
+    /// Run code compiled with `gml::compile_expression`, as the paused `self` and `other`.
+    pub fn evaluate(&mut self, function: code::Function) -> vm::Result<vm::Value> {
+        let (_, assets) = self.cx.fields();
+        assets.code.insert(EXPRESSION, function);
+
+        // The paused thread's stack is still in use, so run on a fresh one.
+        let mut thread = vm::Thread::default();
+        let mut thread = thread.with_other(self.self_entity, self.other_entity);
+        let result = interpreter::execute_internal(&mut thread, self.cx, EXPRESSION, vec![]);
+
+        let (_, assets) = self.cx.fields();
+        assets.code.remove(&EXPRESSION);
+        result
+    }
+}
+
+/// Where `Pause::evaluate` keeps an expression while it runs. No script has a negative id.
+const EXPRESSION: Function = Function::Script { id: -1 };
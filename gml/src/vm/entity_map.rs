@@ -1,7 +1,9 @@
 use std::collections::VecDeque;
 use std::ops::{Index, IndexMut};
 use std::num::Wrapping;
-use std::u32;
+use std::{io, u32};
+
+use crate::vm::{Serialize, Deserialize, Serializer, Deserializer};
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
 pub struct Entity(u32);
@@ -80,8 +82,8 @@ impl EntityAllocator {
         self.free.push_back(index as u32);
     }
 
-    pub fn exists(&mut self, entity: Entity) -> bool {
-        self.generations[entity.index()] == entity.generation()
+    pub fn exists(&self, entity: Entity) -> bool {
+        self.generations.get(entity.index()) == Some(&entity.generation())
     }
 }
 
@@ -148,6 +150,12 @@ impl<T> EntityMap<T> {
         })
     }
 
+    pub fn iter(&self) -> impl Iterator<Item = (Entity, &T)> {
+        self.data.iter().enumerate().filter_map(|(index, entry)| {
+            entry.as_ref().map(|entry| (Entity::new(index, entry.generation), &entry.value))
+        })
+    }
+
     pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
         if entity.index() >= self.data.len() {
             return None;
@@ -177,3 +185,67 @@ impl<T> IndexMut<Entity> for EntityMap<T> {
         self.get_mut(entity).expect("no entry found for key")
     }
 }
+
+impl Serialize for Entity {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        let Entity(entity) = *self;
+        out.write(&entity)
+    }
+}
+
+impl Deserialize for Entity {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        Ok(Entity(input.read()?))
+    }
+}
+
+impl Serialize for EntityAllocator {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        out.write(&self.generations)?;
+        out.write(&self.free)
+    }
+}
+
+impl Deserialize for EntityAllocator {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        let generations = input.read()?;
+        let free = input.read()?;
+        Ok(EntityAllocator { generations, free })
+    }
+}
+
+impl<T: Serialize> Serialize for EntityMap<T> {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        out.write_len(self.data.len())?;
+        for entry in &self.data {
+            match *entry {
+                None => out.write(&false)?,
+                Some(Entry { generation, ref value }) => {
+                    out.write(&true)?;
+                    out.write(&generation)?;
+                    out.write(value)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: Deserialize> Deserialize for EntityMap<T> {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        let len = input.read_len()?;
+        let mut data = Vec::default();
+        for _ in 0..len {
+            let entry = match input.read()? {
+                false => None,
+                true => {
+                    let generation = input.read()?;
+                    let value = input.read()?;
+                    Some(Entry { generation, value })
+                }
+            };
+            data.push(entry);
+        }
+        Ok(EntityMap { data })
+    }
+}
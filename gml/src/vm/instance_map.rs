@@ -3,8 +3,10 @@ use std::collections::HashMap;
 use std::collections::hash_map::Entry;
 use std::ops::Index;
 use std::hash::Hash;
+use std::io;
 
 use crate::rc_vec::RcVec;
+use crate::vm::{Serialize, Deserialize, Serializer, Deserializer};
 
 /// A hash map that preserves insertion order in a copy-on-write array of values.
 ///
@@ -33,6 +35,9 @@ impl<K, V> InstanceMap<K, V> where K: Eq + Hash {
     pub fn is_empty(&self) -> bool { self.len() == 0 }
 
     pub fn contains_key(&self, key: K) -> bool { self.keys.contains_key(&key) }
+
+    /// The index of a key's value in `values`, which orders values by insertion.
+    pub fn position(&self, key: K) -> Option<usize> { self.keys.get(&key).copied() }
 }
 
 impl<K, V> InstanceMap<K, V> where K: Eq + Hash, V: Clone {
@@ -69,3 +74,21 @@ impl<K, V> Index<K> for InstanceMap<K, V> where K: Eq + Hash {
         &self.values[self.keys[&key]]
     }
 }
+
+impl<K, V> Serialize for InstanceMap<K, V> where K: Eq + Hash + Serialize, V: Serialize {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        out.write(&self.keys)?;
+        out.write(&self.values)
+    }
+}
+
+impl<K, V> Deserialize for InstanceMap<K, V> where K: Eq + Hash + Deserialize, V: Deserialize {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        let keys: HashMap<K, usize> = input.read()?;
+        let values: RcVec<V> = input.read()?;
+        if keys.len() != values.len() || keys.values().any(|&index| index >= values.len()) {
+            return Err(crate::vm::invalid_data());
+        }
+        Ok(InstanceMap { keys, values })
+    }
+}
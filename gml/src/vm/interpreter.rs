@@ -8,6 +8,7 @@ use crate::Function;
 use crate::vm::{self, world, code};
 use crate::vm::{World, Assets, Entity, Value, ValueRef, Data, Array, ArrayRef};
 use crate::vm::{to_i32, to_bool};
+use crate::vm::debugger::{Session, Pause, Slot};
 
 /// A single thread of GML execution.
 pub struct Thread {
@@ -18,13 +19,16 @@ pub struct Thread {
 
     self_entity: Entity,
     other_entity: Entity,
+
+    session: Option<Box<Session>>,
 }
 
 /// A 64-bit stack slot for the VM.
 #[repr(C)]
 union Register {
-    /// An uninitialized register.
-    uninit: (),
+    /// The raw contents of a register. New registers are zeroed, so that a debugger can inspect
+    /// them without knowing what they hold.
+    bits: u64,
 
     /// A language-level value, borrowed either from `thread.owned` or a scope.
     /// (Borrows from scopes must not live across operations that might drop the value.)
@@ -39,7 +43,7 @@ union Register {
 }
 
 impl Default for Register {
-    fn default() -> Self { Register { uninit: () } }
+    fn default() -> Self { Register { bits: 0 } }
 }
 
 pub type Result<T> = std::result::Result<T, Box<Error>>;
@@ -143,6 +147,8 @@ pub const LOCAL: i32 = -7;
 pub const PUSH_ARRAY: i32 = 0;
 /// Push any result onto the owned-value stack.
 pub const PUSH_ANY: i32 = 1;
+/// Write to a shared array rather than copying it.
+pub const IN_PLACE: i32 = 2;
 
 impl Default for Thread {
     fn default() -> Self {
@@ -154,13 +160,16 @@ impl Default for Thread {
 
             self_entity: Entity::NULL,
             other_entity: Entity::NULL,
+
+            session: None,
         }
     }
 }
 
 pub struct SelfGuard<'a> {
     thread: &'a mut Thread,
-    other: Entity,
+    self_entity: Entity,
+    other_entity: Entity,
 }
 
 impl ops::Deref for SelfGuard<'_> {
@@ -173,15 +182,53 @@ impl ops::DerefMut for SelfGuard<'_> {
 }
 
 impl Drop for SelfGuard<'_> {
-    fn drop(&mut self) { self.thread.self_entity = self.other; }
+    fn drop(&mut self) {
+        self.thread.self_entity = self.self_entity;
+        self.thread.other_entity = self.other_entity;
+    }
 }
 
 impl Thread {
     pub fn self_entity(&self) -> Entity { self.self_entity }
 
+    pub fn other_entity(&self) -> Entity { self.other_entity }
+
+    /// The number of values this thread keeps alive on behalf of the code it is running.
+    pub fn owned_len(&self) -> usize { self.owned.len() }
+
+    /// The function that made the API call currently running, if any.
+    pub fn caller(&self) -> Option<Function> {
+        self.calls.last().map(|&(function, _, _, _)| function)
+    }
+
+    /// The functions waiting on the API call currently running, innermost first.
+    pub fn callers(&self) -> impl Iterator<Item = Function> + '_ {
+        self.calls.iter().rev().map(|&(function, _, _, _)| function)
+    }
+
+    /// Attach a debugger, to pause this thread at breakpoints and while stepping.
+    pub fn attach(&mut self, session: Session) {
+        self.session = Some(Box::new(session));
+    }
+
+    pub fn detach(&mut self) -> Option<Session> {
+        self.session.take().map(|session| *session)
+    }
+
+    pub fn session(&mut self) -> Option<&mut Session> {
+        self.session.as_deref_mut()
+    }
+
     pub fn with(&mut self, entity: Entity) -> SelfGuard<'_> {
-        let other = mem::replace(&mut self.self_entity, entity);
-        SelfGuard { thread: self, other }
+        let other_entity = self.other_entity;
+        self.with_other(entity, other_entity)
+    }
+
+    /// Run code as `entity`, with `other` referring to `other_entity`.
+    pub fn with_other(&mut self, entity: Entity, other_entity: Entity) -> SelfGuard<'_> {
+        let self_entity = mem::replace(&mut self.self_entity, entity);
+        let other_entity = mem::replace(&mut self.other_entity, other_entity);
+        SelfGuard { thread: self, self_entity, other_entity }
     }
 
     /// Obtain the arguments to an API call.
@@ -209,12 +256,21 @@ fn get_string(value: ValueRef<'_>) -> Symbol {
     }
 }
 
+/// Read a position in `thread.owned`, from `MarkOwned` or `OwnLocal`.
+fn owned_index(value: ValueRef<'_>) -> usize {
+    match value.decode() {
+        Data::Real(index) => index as usize,
+        _ => unreachable!("expected an index"),
+    }
+}
+
 // Opaque type to erase the runner-side container for `vm::World` and `vm::Assets`.
-extern { type W; }
+extern { pub(in crate::vm) type W; }
 
-fn execute_internal(
-    thread: &mut Thread, cx: &mut dyn for<'r> vm::Project<'r, (&'r mut World, &'r mut Assets<W>)>,
-    function: Function, arguments: Vec<Value>
+pub(in crate::vm) type Erased = dyn for<'r> vm::Project<'r, (&'r mut World, &'r mut Assets<W>)>;
+
+pub(in crate::vm) fn execute_internal(
+    thread: &mut Thread, cx: &mut Erased, function: Function, arguments: Vec<Value>
 ) -> Result<Value> {
     let (mut world, mut assets) = cx.fields();
 
@@ -247,9 +303,47 @@ fn execute_internal(
     }
 
     let mut error = loop {
+        if let Some(ref session) = thread.session {
+            let depth = thread.calls.len();
+            if session.should_pause(function, instruction, depth) {
+                let registers = thread.stack[reg_base..][..code.locals as usize].iter()
+                    .map(|register| match Value::scalar_from_raw(unsafe { register.bits }) {
+                        Some(value) => Slot::Value(value),
+                        None => Slot::Raw(unsafe { register.bits }),
+                    })
+                    .collect();
+                let frames = iter::once((function, instruction))
+                    .chain(thread.calls.iter().rev().map(|&(function, instruction, _, _)| {
+                        (function, instruction)
+                    }))
+                    .collect();
+
+                let mut session = thread.session.take().unwrap();
+                let Session { ref mut debugger, ref mut breakpoints, .. } = *session;
+                let mut pause = Pause {
+                    breakpoints, frames, registers,
+                    self_entity: thread.self_entity,
+                    other_entity: thread.other_entity,
+                    cx: &mut *cx,
+                };
+                let resume = debugger.pause(&mut pause);
+                session.resume = resume;
+                session.depth = depth;
+                thread.session = Some(session);
+
+                // The debugger may have called into the runner through `cx`.
+                // Reload any invalidated borrows.
+                let (w, a) = cx.fields();
+                world = w;
+                assets = a;
+                code = &assets.code[&function];
+            }
+        }
+
         let registers = &mut thread.stack[reg_base..];
 
-        match code.instructions[instruction].decode() {
+        let (inst, next) = code.decode(instruction);
+        match inst {
             (code::Op::Imm, t, constant, _) => {
                 // Safety: Immediates must be reals or strings, which are never freed.
                 registers[t].value = unsafe { erase_ref(code.constants[constant].borrow()) };
@@ -260,6 +354,10 @@ fn execute_internal(
                 registers[t] = mem::take(&mut registers[s]);
             }
 
+            (code::Op::Copy, t, s, _) => {
+                registers[t] = Register { bits: unsafe { registers[s].bits } };
+            }
+
             (op @ code::Op::Neg, t, a, _) => {
                 let a = unsafe { registers[a].value };
                 registers[t].value = match a.decode() {
@@ -652,6 +750,67 @@ fn execute_internal(
                 thread.owned.pop();
             }
 
+            (code::Op::Share, t, a, _) => {
+                let a = unsafe { registers[a].value };
+                if let Data::Array(_) = a.decode() {
+                    thread.owned.push(a.clone());
+                }
+                registers[t].value = a;
+            }
+
+            (code::Op::ToUnique, t, a, p) => {
+                let a = unsafe { registers[a].value };
+                registers[t].value = match a.decode() {
+                    Data::Array(array) if p as i32 & IN_PLACE != 0 || !array.is_shared() => {
+                        if p as i32 & PUSH_ANY != 0 {
+                            thread.owned.push(a.clone());
+                        }
+                        a
+                    }
+                    data => {
+                        let array = match data {
+                            Data::Array(array) => array.copy(),
+                            _ => Array::default(),
+                        };
+                        let array = Value::from(array);
+                        let value = unsafe { erase_ref(array.borrow()) };
+                        thread.owned.push(array);
+                        value
+                    }
+                };
+            }
+
+            (code::Op::MarkOwned, t, _, _) => {
+                registers[t].value = ValueRef::from(thread.owned.len() as f64);
+            }
+
+            (code::Op::ReleaseOwnedTo, mark, _, _) => {
+                let mark = unsafe { registers[mark].value };
+                thread.owned.truncate(owned_index(mark));
+            }
+
+            (code::Op::ReleaseCall, mark, _, _) => {
+                let mark = unsafe { registers[mark].value };
+                let result = thread.owned.pop();
+                thread.owned.truncate(owned_index(mark));
+                if let Some(result) = result {
+                    if let Data::Array(_) = result.borrow().decode() {
+                        thread.owned.push(result);
+                    }
+                }
+            }
+
+            (code::Op::OwnLocal, t, _, _) => {
+                registers[t].value = ValueRef::from(thread.owned.len() as f64);
+                thread.owned.push(Value::default());
+            }
+
+            (code::Op::StoreOwned, s, slot, _) => {
+                let s = unsafe { registers[s].value };
+                let slot = unsafe { registers[slot].value };
+                thread.owned[owned_index(slot)] = s.clone();
+            }
+
             (code::Op::LoadField, t, entity, field) => {
                 let entity = unsafe { registers[entity].entity };
                 let field = get_string(code.constants[field].borrow());
@@ -739,7 +898,7 @@ fn execute_internal(
             }
 
             (code::Op::Call, callee, base, len) => {
-                thread.calls.push((function, instruction + 1, reg_base, thread.owned.len()));
+                thread.calls.push((function, next, reg_base, thread.owned.len()));
 
                 let id = callee as i32;
                 function = Function::Script { id };
@@ -788,13 +947,17 @@ fn execute_internal(
                 let api = assets.api[&symbol];
                 let reg_base = reg_base + base;
 
-                let array = unsafe {
+                // Make the caller visible to the API function via `Thread::caller`.
+                thread.calls.push((function, next, reg_base, thread.owned.len()));
+                let result = unsafe {
                     let cx = &mut *(cx as *mut _ as *mut _);
                     let arguments = reg_base..reg_base + len;
-                    match api(cx, thread, arguments) {
-                        Ok(value) => value,
-                        Err(error) => break error,
-                    }
+                    api(cx, thread, arguments)
+                };
+                thread.calls.pop();
+                let array = match result {
+                    Ok(value) => value,
+                    Err(error) => break error,
                 };
                 let value = unsafe { erase_ref(array.borrow()) };
                 thread.owned.push(array);
@@ -865,24 +1028,26 @@ fn execute_internal(
                 code = &assets.code[&function];
             }
 
-            (code::Op::Jump, t_low, t_high, _) => {
-                instruction = t_low | (t_high << 8);
+            (code::Op::Jump, target, _, _) => {
+                instruction = target;
                 continue;
             }
 
-            (op @ code::Op::BranchFalse, a, t_low, t_high) => {
+            (op @ code::Op::BranchFalse, a, target, _) => {
                 let a = unsafe { registers[a].value };
                 match a.decode() {
                     Data::Real(a) => if !to_bool(a) {
-                        instruction = t_low | (t_high << 8);
+                        instruction = target;
                         continue;
                     }
                     _ => break Error::type_unary(op, a.clone()),
                 }
             }
+
+            (code::Op::Wide, _, _, _) => unreachable!("prefixes are consumed by decode"),
         }
 
-        instruction += 1;
+        instruction = next;
     };
 
     error.frames.push(ErrorFrame { function, instruction });
@@ -1,17 +1,22 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::io;
 use std::ops::Range;
 
 use crate::symbol::Symbol;
 use crate::{Function, front::Lines};
 
 pub use crate::vm::interpreter::{Thread, Result, Error, ErrorFrame};
-pub use crate::vm::interpreter::{SELF, OTHER, ALL, NOONE, GLOBAL, LOCAL, PUSH_ARRAY, PUSH_ANY};
+pub use crate::vm::interpreter::{SELF, OTHER, ALL, NOONE, GLOBAL, LOCAL};
+pub use crate::vm::interpreter::{PUSH_ARRAY, PUSH_ANY, IN_PLACE};
 pub use crate::vm::world::World;
 pub use crate::vm::bind::{Bind, FnBind, GetBind, SetBind, Project};
 pub use crate::vm::entity_map::{Entity, EntityAllocator, EntityMap};
 pub use crate::vm::instance_map::InstanceMap;
 pub use crate::vm::value::{Value, ValueRef, Data, to_i32, to_u32, to_bool};
 pub use crate::vm::array::{Array, ArrayRef};
+pub use crate::vm::serialize::{Serialize, Deserialize, Serializer, Deserializer};
+pub use crate::vm::serialize::invalid_data;
+pub use crate::vm::debugger::{Debugger, Session, Breakpoints, Pause, Resume, Slot};
 
 pub mod code;
 pub mod world;
@@ -19,7 +24,8 @@ pub mod bind;
 mod entity_map;
 mod instance_map;
 mod interpreter;
-//mod serialize;
+mod serialize;
+mod debugger;
 mod value;
 mod array;
 mod debug;
@@ -29,9 +35,11 @@ pub struct Assets<W: ?Sized> {
     pub api: HashMap<Symbol, ApiFunction<W>>,
     pub get: HashMap<Symbol, GetFunction<W>>,
     pub set: HashMap<Symbol, SetFunction<W>>,
+    /// The members that belong to each instance, rather than to the game as a whole.
+    pub members: HashSet<Symbol>,
 }
 
-#[derive(Default)]
+#[derive(Default, Clone)]
 pub struct Debug {
     pub locations: HashMap<Function, Locations>,
     pub scripts: Vec<Symbol>,
@@ -40,6 +48,7 @@ pub struct Debug {
     pub instances: HashMap<i32, i32>,
 }
 
+#[derive(Clone)]
 pub struct Locations {
     pub locations: code::Locations,
     pub lines: Lines,
@@ -56,6 +65,55 @@ impl<W: ?Sized> Default for Assets<W> {
             api: HashMap::default(),
             get: HashMap::default(),
             set: HashMap::default(),
+            members: HashSet::default(),
         }
     }
 }
+
+impl Debug {
+    /// Whether this has everything needed to report errors in `function`.
+    pub fn describes(&self, function: Function) -> bool {
+        let named = match function {
+            Function::Event { object_index, .. } => self.objects.get(object_index as usize),
+            Function::Script { id } => self.scripts.get(id as usize),
+            Function::Room { id } => self.rooms.get(id as usize),
+            Function::Instance { id } => self.instances.get(&id)
+                .and_then(|&room| self.rooms.get(room as usize)),
+        };
+        named.is_some() && self.locations.contains_key(&function)
+    }
+}
+
+impl Serialize for Debug {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        out.write_sorted(&self.locations)?;
+        out.write(&self.scripts)?;
+        out.write(&self.objects)?;
+        out.write(&self.rooms)?;
+        out.write_sorted(&self.instances)
+    }
+}
+
+impl Deserialize for Debug {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        let locations = input.read()?;
+        let scripts = input.read()?;
+        let objects = input.read()?;
+        let rooms = input.read()?;
+        let instances = input.read()?;
+        Ok(Debug { locations, scripts, objects, rooms, instances })
+    }
+}
+
+impl Serialize for Locations {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        out.write(&self.locations)?;
+        out.write(&self.lines)
+    }
+}
+
+impl Deserialize for Locations {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        Ok(Locations { locations: input.read()?, lines: input.read()? })
+    }
+}
@@ -0,0 +1,449 @@
+use std::collections::{HashMap, HashSet, BTreeMap, VecDeque};
+use std::hash::Hash;
+use std::num::Wrapping;
+use std::io::{self, Read};
+
+use crate::rc_vec::RcVec;
+use crate::symbol::Symbol;
+use crate::vm::{self, array};
+
+/// A type that can be saved as part of a game.
+pub trait Serialize {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()>;
+}
+
+/// A type that can be restored from a saved game.
+pub trait Deserialize: Sized {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self>;
+}
+
+/// Writes a little-endian binary encoding of VM state.
+///
+/// Arrays are written once, and later references to the same array refer back to the first, so
+/// that sharing and cycles survive a round trip.
+pub struct Serializer<W> {
+    write: W,
+    arrays: HashMap<*const array::Data, u32>,
+}
+
+/// Reads the encoding written by a `Serializer`.
+pub struct Deserializer<R> {
+    read: R,
+    arrays: Vec<vm::Array>,
+}
+
+impl<W: io::Write> Serializer<W> {
+    pub fn new(write: W) -> Self {
+        Serializer { write, arrays: HashMap::default() }
+    }
+
+    pub fn into_inner(self) -> W { self.write }
+
+    pub fn write<T: Serialize + ?Sized>(&mut self, value: &T) -> io::Result<()> {
+        value.serialize(self)
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.write_len(bytes.len())?;
+        self.write.write_all(bytes)
+    }
+
+    pub fn write_len(&mut self, len: usize) -> io::Result<()> {
+        self.write(&(len as u32))
+    }
+
+    /// Write a `HashMap` with its entries sorted by key, so the output does not depend on the
+    /// order of iteration. It is read back like any other `HashMap`.
+    pub fn write_sorted<K, V>(&mut self, map: &HashMap<K, V>) -> io::Result<()> where
+        K: Serialize + Ord, V: Serialize
+    {
+        let mut entries: Vec<_> = map.iter().collect();
+        entries.sort_by_key(|&(key, _)| key);
+        self.write(&entries)
+    }
+}
+
+impl<R: io::Read> Deserializer<R> {
+    pub fn new(read: R) -> Self {
+        Deserializer { read, arrays: Vec::default() }
+    }
+
+    pub fn into_inner(self) -> R { self.read }
+
+    pub fn read<T: Deserialize>(&mut self) -> io::Result<T> {
+        T::deserialize(self)
+    }
+
+    pub fn read_bytes(&mut self) -> io::Result<Vec<u8>> {
+        let len = self.read_len()?;
+        let mut bytes = Vec::default();
+        (&mut self.read).take(len as u64).read_to_end(&mut bytes)?;
+        if bytes.len() != len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(bytes)
+    }
+
+    pub fn read_len(&mut self) -> io::Result<usize> {
+        Ok(self.read::<u32>()? as usize)
+    }
+}
+
+/// Construct the error for data that could not have been written by a `Serializer`.
+pub fn invalid_data() -> io::Error { io::ErrorKind::InvalidData.into() }
+
+macro_rules! impl_number {
+    ($($t:ty),*) => { $(
+        impl Serialize for $t {
+            fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+                out.write.write_all(&self.to_le_bytes())
+            }
+        }
+
+        impl Deserialize for $t {
+            fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+                let mut bytes = [0; std::mem::size_of::<$t>()];
+                input.read.read_exact(&mut bytes)?;
+                Ok(<$t>::from_le_bytes(bytes))
+            }
+        }
+    )* }
+}
+
+impl_number!(u8, u32, i32, u64, f32, f64);
+
+impl Serialize for usize {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        out.write(&(*self as u64))
+    }
+}
+
+impl Deserialize for usize {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        Ok(input.read::<u64>()? as usize)
+    }
+}
+
+impl Serialize for bool {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        out.write(&(*self as u8))
+    }
+}
+
+impl Deserialize for bool {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        match input.read::<u8>()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid_data()),
+        }
+    }
+}
+
+impl<T: Serialize> Serialize for Wrapping<T> {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        let Wrapping(ref value) = *self;
+        out.write(value)
+    }
+}
+
+impl<T: Deserialize> Deserialize for Wrapping<T> {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        Ok(Wrapping(input.read()?))
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        out.write_bytes(&self[..])
+    }
+}
+
+impl Deserialize for Symbol {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        Ok(Symbol::intern(&input.read_bytes()?))
+    }
+}
+
+impl<T: Serialize + ?Sized> Serialize for &T {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        (**self).serialize(out)
+    }
+}
+
+impl<T: Serialize> Serialize for Option<T> {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        match *self {
+            None => out.write(&false),
+            Some(ref value) => {
+                out.write(&true)?;
+                out.write(value)
+            }
+        }
+    }
+}
+
+impl<T: Deserialize> Deserialize for Option<T> {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        match input.read()? {
+            false => Ok(None),
+            true => Ok(Some(input.read()?)),
+        }
+    }
+}
+
+impl<A: Serialize, B: Serialize> Serialize for (A, B) {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        out.write(&self.0)?;
+        out.write(&self.1)
+    }
+}
+
+impl<A: Deserialize, B: Deserialize> Deserialize for (A, B) {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        Ok((input.read()?, input.read()?))
+    }
+}
+
+impl<T: Serialize> Serialize for [T] {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        out.write_len(self.len())?;
+        self.iter().try_for_each(|value| out.write(value))
+    }
+}
+
+impl<T: Serialize> Serialize for Vec<T> {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        out.write(&self[..])
+    }
+}
+
+impl<T: Deserialize> Deserialize for Vec<T> {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        let len = input.read_len()?;
+        // Do not trust `len` for the allocation, as it has not been validated.
+        let mut values = Vec::default();
+        for _ in 0..len {
+            values.push(input.read()?);
+        }
+        Ok(values)
+    }
+}
+
+impl<T: Deserialize> Deserialize for Box<[T]> {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        Ok(Vec::into_boxed_slice(input.read()?))
+    }
+}
+
+impl<T: Serialize> Serialize for VecDeque<T> {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        out.write_len(self.len())?;
+        self.iter().try_for_each(|value| out.write(value))
+    }
+}
+
+impl<T: Deserialize> Deserialize for VecDeque<T> {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        Ok(VecDeque::from(input.read::<Vec<T>>()?))
+    }
+}
+
+impl<T: Serialize> Serialize for RcVec<T> {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        out.write(&self[..])
+    }
+}
+
+impl<T: Deserialize> Deserialize for RcVec<T> {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        let values: Vec<T> = input.read()?;
+        let len = values.len();
+        Ok(RcVec::from_iter_with_capacity(values, len))
+    }
+}
+
+impl<K: Serialize, V: Serialize> Serialize for HashMap<K, V> {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        out.write_len(self.len())?;
+        self.iter().try_for_each(|(key, value)| {
+            out.write(key)?;
+            out.write(value)
+        })
+    }
+}
+
+impl<K: Deserialize + Eq + Hash, V: Deserialize> Deserialize for HashMap<K, V> {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        let entries: Vec<(K, V)> = input.read()?;
+        Ok(entries.into_iter().collect())
+    }
+}
+
+impl<T: Serialize> Serialize for HashSet<T> {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        out.write_len(self.len())?;
+        self.iter().try_for_each(|value| out.write(value))
+    }
+}
+
+impl<T: Deserialize + Eq + Hash> Deserialize for HashSet<T> {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        let values: Vec<T> = input.read()?;
+        Ok(values.into_iter().collect())
+    }
+}
+
+impl<K: Serialize, V: Serialize> Serialize for BTreeMap<K, V> {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        out.write_len(self.len())?;
+        self.iter().try_for_each(|(key, value)| {
+            out.write(key)?;
+            out.write(value)
+        })
+    }
+}
+
+impl<K: Deserialize + Ord, V: Deserialize> Deserialize for BTreeMap<K, V> {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        let entries: Vec<(K, V)> = input.read()?;
+        Ok(entries.into_iter().collect())
+    }
+}
+
+const REAL: u8 = 0;
+const STRING: u8 = 1;
+const ARRAY: u8 = 2;
+/// A reference to an array that was already written, by the order it was written in.
+const ARRAY_REF: u8 = 3;
+
+impl Serialize for vm::Value {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        match self.borrow().decode() {
+            vm::Data::Real(real) => {
+                out.write(&REAL)?;
+                out.write(&real)
+            }
+            vm::Data::String(string) => {
+                out.write(&STRING)?;
+                out.write(&string)
+            }
+            vm::Data::Array(array) => {
+                if let Some(&index) = out.arrays.get(&array.as_raw()) {
+                    out.write(&ARRAY_REF)?;
+                    return out.write(&index);
+                }
+                let index = out.arrays.len() as u32;
+                out.arrays.insert(array.as_raw(), index);
+
+                // Elements are cloned out one at a time, so no reference into the array is held
+                // while serializing them, which may visit it again.
+                let len = array.len();
+                out.write(&ARRAY)?;
+                out.write_len(len)?;
+                for i in 0..len {
+                    let value = array.get_flat(i as i32).unwrap_or_default();
+                    out.write(&value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Deserialize for vm::Value {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        match input.read::<u8>()? {
+            REAL => Ok(vm::Value::from(input.read::<f64>()?)),
+            STRING => Ok(vm::Value::from(input.read::<Symbol>()?)),
+            ARRAY => {
+                // Register the array before reading its elements, which may refer back to it.
+                let array = vm::Array::default();
+                input.arrays.push(array.clone());
+                let len = input.read_len()?;
+                for i in 0..len {
+                    let value = input.read()?;
+                    array.borrow().set_flat(i as i32, value).ok_or_else(invalid_data)?;
+                }
+                Ok(vm::Value::from(array))
+            }
+            ARRAY_REF => {
+                let index = input.read::<u32>()? as usize;
+                let array = input.arrays.get(index).ok_or_else(invalid_data)?;
+                Ok(vm::Value::from(array.clone()))
+            }
+            _ => Err(invalid_data()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::symbol::Symbol;
+    use crate::vm;
+    use super::*;
+
+    fn round_trip<T: Serialize + Deserialize>(value: &T) -> T {
+        let mut out = Serializer::new(Vec::default());
+        out.write(value).unwrap();
+        let data = out.into_inner();
+        let mut input = Deserializer::new(&data[..]);
+        let value = input.read().unwrap();
+        assert!(input.into_inner().is_empty());
+        value
+    }
+
+    #[test]
+    fn values() {
+        let values = vec![vm::Value::from(3.5), vm::Value::from(Symbol::intern(b"hello"))];
+        assert_eq!(round_trip(&values), values);
+
+        let array = vm::Value::from(vm::Array::from_scalar(vm::Value::from(1.0)));
+        match round_trip(&array).borrow().decode() {
+            vm::Data::Array(array) => {
+                assert_eq!(array.get_jagged(0, 0), Some(vm::Value::from(1.0)));
+            }
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn shared_arrays() {
+        let shared = vm::Array::default();
+        shared.borrow().set_flat(0, vm::Value::from(1.0));
+        let values = vec![vm::Value::from(shared.clone()), vm::Value::from(shared)];
+
+        let values = round_trip(&values);
+        let (a, b) = match (values[0].borrow().decode(), values[1].borrow().decode()) {
+            (vm::Data::Array(a), vm::Data::Array(b)) => (a, b),
+            _ => panic!(),
+        };
+        assert_eq!(a.as_raw(), b.as_raw());
+        a.set_flat(1, vm::Value::from(2.0));
+        assert_eq!(b.get_flat(1), Some(vm::Value::from(2.0)));
+    }
+
+    #[test]
+    fn cyclic_arrays() {
+        let array = vm::Array::default();
+        array.borrow().set_flat(0, vm::Value::from(array.clone()));
+        array.borrow().set_flat(1, vm::Value::from(5.0));
+        let value = vm::Value::from(array.clone());
+
+        let copy = round_trip(&value);
+        let copy = match copy.borrow().decode() {
+            vm::Data::Array(copy) => copy,
+            _ => panic!(),
+        };
+        assert_ne!(copy.as_raw(), array.borrow().as_raw());
+        assert_eq!(copy.get_flat(1), Some(vm::Value::from(5.0)));
+        match copy.get_flat(0).unwrap().borrow().decode() {
+            vm::Data::Array(inner) => assert_eq!(inner.as_raw(), copy.as_raw()),
+            _ => panic!(),
+        }
+
+        // Break the cycles so the arrays are freed.
+        array.borrow().set_flat(0, vm::Value::default());
+        copy.set_flat(0, vm::Value::default());
+    }
+}
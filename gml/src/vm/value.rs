@@ -103,6 +103,20 @@ impl Value {
     pub fn into_raw(self) -> u64 { let Value(value) = self; value }
 
     pub unsafe fn from_raw(raw: u64) -> Value { Value(raw) }
+
+    /// Reconstruct a real or string from bits of unknown origin.
+    pub fn scalar_from_raw(raw: u64) -> Option<Value> {
+        if raw <= 0xfff8_0000_0000_0000 {
+            return Some(Value(raw));
+        }
+
+        let tag = raw >> 48;
+        let payload = raw & ((1 << 48) - 1);
+        match tag {
+            0xfff8 => Some(Value::from(Symbol::try_from_index(payload as usize)?)),
+            _ => None,
+        }
+    }
 }
 
 impl fmt::Debug for Value {
@@ -1,8 +1,9 @@
 use std::collections::{HashMap, HashSet};
+use std::io;
 
 use crate::rc_vec::RcVec;
 use crate::symbol::Symbol;
-use crate::vm;
+use crate::vm::{self, Serialize, Deserialize, Serializer, Deserializer};
 
 pub struct World {
     pub entities: vm::EntityAllocator,
@@ -49,20 +50,52 @@ impl World {
         self.entities.destroy(entity);
     }
 
-    /// Add an entity to the instance lists.
-    pub fn add_entity(&mut self, entity: vm::Entity, object_index: i32, id: i32) {
-        self.objects.entry(object_index).or_default().push(entity);
+    /// Add an entity to the instance lists of its object and each of that object's ancestors.
+    pub fn add_entity<I>(&mut self, entity: vm::Entity, objects: I, id: i32) where
+        I: IntoIterator<Item = i32>
+    {
+        for object_index in objects {
+            self.objects.entry(object_index).or_default().push(entity);
+        }
         self.instances.insert(id, entity);
     }
 
     /// Remove an entity from the instance lists, but retain its entity.
-    pub fn remove_entity(&mut self, entity: vm::Entity, object_index: i32, id: i32) {
+    pub fn remove_entity<I>(&mut self, entity: vm::Entity, objects: I, id: i32) where
+        I: IntoIterator<Item = i32>
+    {
         self.instances.remove(id);
 
-        if let Some(object_instances) = self.objects.get_mut(&object_index) {
-            if let Some(position) = object_instances.iter().position(move |&e| e == entity) {
-                object_instances.remove(position);
+        for object_index in objects {
+            if let Some(object_instances) = self.objects.get_mut(&object_index) {
+                if let Some(position) = object_instances.iter().position(move |&e| e == entity) {
+                    object_instances.remove(position);
+                }
             }
         }
     }
 }
+
+impl Serialize for World {
+    fn serialize<W: io::Write>(&self, out: &mut Serializer<W>) -> io::Result<()> {
+        out.write(&self.entities)?;
+        out.write(&self.members)?;
+        out.write(&self.objects)?;
+        out.write(&self.instances)?;
+        out.write(&self.globals)
+    }
+}
+
+impl Deserialize for World {
+    fn deserialize<R: io::Read>(input: &mut Deserializer<R>) -> io::Result<Self> {
+        let entities = input.read()?;
+        let members: vm::EntityMap<_> = input.read()?;
+        let objects = input.read()?;
+        let instances = input.read()?;
+        let globals = input.read()?;
+        if !members.contains_key(GLOBAL) {
+            return Err(vm::invalid_data());
+        }
+        Ok(World { entities, members, objects, instances, globals })
+    }
+}
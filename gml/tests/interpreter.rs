@@ -3,7 +3,7 @@ use std::convert::TryFrom;
 use std::io;
 use std::ops::Range;
 
-use gml::{Function, Item, symbol::Symbol, vm};
+use gml::{Function, Item, symbol::Symbol, vm, vm::code::Disassembly};
 
 /// Read script arguments.
 #[test]
@@ -70,16 +70,24 @@ fn builtin() -> vm::Result<()> {
     let mut items = HashMap::new();
 
     let scalar = Symbol::intern(b"scalar");
-    items.insert(scalar, Item::Member(Some(Instance::get_scalar), Some(Instance::set_scalar)));
+    items.insert(scalar, Item::Member(
+        Some(Instance::get_scalar), Some(Instance::set_scalar), true
+    ));
 
     let array = Symbol::intern(b"array");
-    items.insert(array, Item::Member(Some(Instance::get_array), Some(Instance::set_array)));
+    items.insert(array, Item::Member(
+        Some(Instance::get_array), Some(Instance::set_array), true
+    ));
 
     let global_scalar = Symbol::intern(b"global_scalar");
-    items.insert(global_scalar, Item::Member(Some(World::get_global_scalar), Some(World::set_global_scalar)));
+    items.insert(global_scalar, Item::Member(
+        Some(World::get_global_scalar), Some(World::set_global_scalar), false
+    ));
 
     let global_array = Symbol::intern(b"global_array");
-    items.insert(global_array, Item::Member(Some(World::get_global_array), Some(World::set_global_array)));
+    items.insert(global_array, Item::Member(
+        Some(World::get_global_array), Some(World::set_global_array), false
+    ));
 
     let builtin = Function::Script { id: game.scripts.len() as i32 };
     game.scripts.push(project::Script { name: b"builtin", body: b"{
@@ -287,6 +295,41 @@ fn dead_undef() -> vm::Result<()> {
     Ok(())
 }
 
+/// Name events by their type and kind in diagnostics.
+#[test]
+fn event_names() {
+    use project::{event_type, event_kind};
+
+    let mut game = project::Game::default();
+    let items = HashMap::default();
+
+    let player = game.objects.len() as i32;
+    game.objects.push(project::Object { name: b"obj_player", ..project::Object::default() });
+    let wall = game.objects.len() as i32;
+    game.objects.push(project::Object { name: b"obj_wall", ..project::Object::default() });
+
+    let (_, debug): (vm::Assets<Context>, _) = gml::build(&game, &items, io::stderr)
+        .unwrap_or_else(|_| panic!());
+    let display = |event_type, event_kind| {
+        let function = Function::Event { object_index: player, event_type, event_kind };
+        gml::FunctionDisplay::from_debug(&debug, function).to_string()
+    };
+
+    assert_eq!(display(event_type::STEP, event_kind::STEP_END),
+        "step end event for object obj_player");
+    assert_eq!(display(event_type::COLLISION, wall),
+        "collision event with obj_wall for object obj_player");
+    assert_eq!(display(event_type::ALARM, 3), "alarm 3 event for object obj_player");
+    assert_eq!(display(event_type::KEY_PRESS, 37),
+        "key press event for <left> for object obj_player");
+    assert_eq!(display(event_type::MOUSE, event_kind::GLOBAL_LEFT_BUTTON + 3),
+        "global left pressed event for object obj_player");
+    assert_eq!(display(event_type::MOUSE, event_kind::GLOBAL_MIDDLE_RELEASE),
+        "global middle released event for object obj_player");
+    assert_eq!(display(event_type::OTHER, event_kind::USER0 + 2),
+        "user defined 2 event for object obj_player");
+}
+
 /// For loop working with locals.
 #[test]
 fn for_loop() -> vm::Result<()> {
@@ -314,6 +357,88 @@ fn for_loop() -> vm::Result<()> {
     Ok(())
 }
 
+/// Optimized code behaves the same as unoptimized code, but is shorter.
+#[test]
+fn optimize() -> vm::Result<()> {
+    let mut game = project::Game::default();
+    let mut items = HashMap::default();
+    items.insert(Symbol::intern(b"string"), Item::Native(World::native_string, 1, false));
+
+    let repeat = Function::Script { id: game.scripts.len() as i32 };
+    game.scripts.push(project::Script { name: b"repeat", body: b"{
+        var i, s, t;
+        s = \"\"
+        t = \"a\" + \"b\"
+        for (i = 0; i < argument0; i += 1) {
+            if 2 * 3 > 5 { s += t } else { s += \"c\" }
+        }
+        return s + string(argument0 << 1)
+    }" });
+
+    let mut sizes = vec![];
+    for &optimize in &[false, true] {
+        let options = gml::Options { optimize, ..gml::Options::default() };
+        let (code, _) = gml::build_with_options(&game, &items, options, io::stderr)
+            .unwrap_or_else(|_| panic!());
+        sizes.push(code.code[&repeat].instructions.len());
+        let assets = Assets { code };
+        let world = World::default();
+
+        let mut thread = vm::Thread::default();
+        let mut cx = Context { world, assets };
+
+        let arguments = vec![vm::Value::from(3)];
+        let result = vm::Value::from(Symbol::intern(b"ababab6"));
+        assert_eq!(thread.execute(&mut cx, repeat, arguments)?, result);
+    }
+    assert!(sizes[1] < sizes[0], "{:?}", sizes);
+
+    Ok(())
+}
+
+/// Functions with too many registers, constants, or instructions for a single byte operand.
+#[test]
+fn large_function() -> vm::Result<()> {
+    let mut game = project::Game::default();
+    let items = HashMap::default();
+
+    // Keep 300 locals live at once, each holding a distinct constant.
+    let mut body = String::from("var i, s;\n");
+    for i in 0..300 {
+        body.push_str(&format!("var a{i}; a{i} = {i} + argument0\n", i = i));
+    }
+    body.push_str("s = 0\n");
+    for i in 0..300 {
+        body.push_str(&format!("s += a{}\n", i));
+    }
+
+    // Jump back and forth over more than 65536 instructions.
+    body.push_str("for (i = 0; i < 2; i += 1) {\nif i == 1 {\n");
+    for _ in 0..70000 {
+        body.push_str("s += argument1\n");
+    }
+    body.push_str("}\n}\nreturn s\n");
+
+    let large = Function::Script { id: game.scripts.len() as i32 };
+    let body = Box::leak(body.into_bytes().into_boxed_slice());
+    game.scripts.push(project::Script { name: b"large", body });
+
+    let (code, _) = gml::build(&game, &items, io::stderr).unwrap_or_else(|_| panic!());
+    assert!(code.code[&large].locals > 256);
+    assert!(code.code[&large].constants.len() > 256);
+    assert!(code.code[&large].instructions.len() > 65536);
+    let assets = Assets { code };
+    let world = World::default();
+
+    let mut thread = vm::Thread::default();
+    let mut cx = Context { world, assets };
+
+    let arguments = vec![vm::Value::from(1), vm::Value::from(1)];
+    let result = (0..300).sum::<i32>() + 300 + 70000;
+    assert_eq!(thread.execute(&mut cx, large, arguments)?, vm::Value::from(result));
+    Ok(())
+}
+
 /// Control flow across a switch statement.
 #[test]
 fn switch() -> vm::Result<()> {
@@ -494,6 +619,290 @@ fn ffi() -> vm::Result<()> {
     Ok(())
 }
 
+/// Passing a local to a script or native function leaves it intact for later reads.
+#[test]
+fn reuse_arguments() -> vm::Result<()> {
+    let mut game = project::Game::default();
+    let mut items = HashMap::new();
+
+    let add = Symbol::intern(b"add");
+    items.insert(add, Item::Native(World::native_add, 2, false));
+
+    game.scripts.push(project::Script { name: b"id", body: b"return argument0" });
+
+    let script = Function::Script { id: game.scripts.len() as i32 };
+    game.scripts.push(project::Script { name: b"script", body: b"{
+        var s, i;
+        s = 5
+        i = 7
+        s += id(i)
+        return s * 100 + i
+    }" });
+
+    let native = Function::Script { id: game.scripts.len() as i32 };
+    game.scripts.push(project::Script { name: b"native", body: b"{
+        var a;
+        a = 3
+        return add(a, a) * 10 + a
+    }" });
+
+    let loop_ = Function::Script { id: game.scripts.len() as i32 };
+    game.scripts.push(project::Script { name: b"loop", body: b"{
+        var i, s;
+        i = 0
+        s = 0
+        while (i < 3) {
+            s += id(i)
+            i += 1
+        }
+        return s * 10 + i
+    }" });
+
+    for &optimize in &[false, true] {
+        let options = gml::Options { optimize, ..gml::Options::default() };
+        let (code, _) = gml::build_with_options(&game, &items, options, io::stderr)
+            .unwrap_or_else(|_| panic!());
+        let assets = Assets { code };
+        let world = World::default();
+
+        let mut thread = vm::Thread::default();
+        let mut cx = Context { world, assets };
+
+        assert_eq!(thread.execute(&mut cx, script, vec![])?, vm::Value::from(1207));
+        assert_eq!(thread.execute(&mut cx, native, vec![])?, vm::Value::from(63));
+        assert_eq!(thread.execute(&mut cx, loop_, vec![])?, vm::Value::from(33));
+    }
+
+    Ok(())
+}
+
+/// List bytecode alongside the source it came from.
+#[test]
+fn disassembly() {
+    use project::{event_type, event_kind};
+
+    let mut game = project::Game::default();
+    let mut items = HashMap::new();
+
+    let add = Symbol::intern(b"add");
+    items.insert(add, Item::Native(World::native_add, 2, false));
+
+    let call = Function::Script { id: game.scripts.len() as i32 };
+    game.scripts.push(project::Script { name: b"call", body: b"{
+        var a;
+        a = add(3, 5)
+        return a
+    }" });
+
+    let step = Function::Event {
+        object_index: game.objects.len() as i32,
+        event_type: event_type::STEP,
+        event_kind: event_kind::STEP_NORMAL,
+    };
+    game.objects.push(project::Object {
+        name: b"object",
+        events: vec![project::Event {
+            event_type: event_type::STEP,
+            event_kind: event_kind::STEP_NORMAL,
+            actions: vec![project::Action {
+                library: 1,
+                action: 603,
+                action_kind: project::action_kind::CODE,
+                has_target: true,
+                parameters_used: 1,
+                parameters: vec![project::argument_type::STRING],
+                target: vm::SELF,
+                arguments: vec![b"x = 1\ny = add(x, 2)"],
+                ..project::Action::default()
+            }],
+        }],
+        ..project::Object::default()
+    });
+
+    let (code, debug): (vm::Assets<Context>, _) = gml::build(&game, &items, io::stderr)
+        .unwrap_or_else(|_| panic!());
+    let disassemble = |function| {
+        let source = gml::source(&game, function).unwrap();
+        let locations = &debug.locations[&function];
+        let function = &code.code[&function];
+        Disassembly { function, locations, source: &source }.to_string()
+    };
+
+    let listing = disassemble(call);
+    assert!(listing.contains("; line 3: a = add(3, 5)\n"), "{}", listing);
+    assert!(listing.contains("= CallApi add("), "{}", listing);
+    assert!(listing.contains("; line 4: return a\n"), "{}", listing);
+    assert!(listing.contains("; column 9\n"), "{}", listing);
+
+    let listing = disassemble(step);
+    assert_eq!(listing.matches("; action 1, line 1: x = 1\n").count(), 1, "{}", listing);
+    assert_eq!(listing.matches("; action 1, line 2: y = add(x, 2)\n").count(), 1, "{}", listing);
+    assert!(listing.contains("StoreField %1, %0.y"), "{}", listing);
+}
+
+/// Pause at breakpoints, step through scripts, and inspect variables.
+#[test]
+fn debugger() -> vm::Result<()> {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut game = project::Game::default();
+    let items = HashMap::default();
+
+    let outer = Function::Script { id: game.scripts.len() as i32 };
+    game.scripts.push(project::Script { name: b"outer", body: b"{
+        var a;
+        a = 3
+        b = inner(a)
+        return a + b
+    }" });
+
+    let inner = Function::Script { id: game.scripts.len() as i32 };
+    game.scripts.push(project::Script { name: b"inner", body: b"{
+        return argument0 * 2
+    }" });
+
+    /// The function, line, call depth, and value of `b` at each pause.
+    type Pauses = Vec<(Function, Option<usize>, usize, Option<vm::Value>)>;
+
+    struct Script {
+        debug: vm::Debug,
+        resumes: Vec<vm::Resume>,
+        pauses: Rc<RefCell<Pauses>>,
+    }
+
+    impl vm::Debugger for Script {
+        fn pause(&mut self, pause: &mut vm::Pause<'_>) -> vm::Resume {
+            let (function, instruction) = pause.location();
+            let locations = &self.debug.locations[&function];
+            let location = locations.locations.get_location(instruction as u32);
+            let line = locations.lines.get_position(location as usize).line;
+            let b = pause.member(pause.self_entity(), Symbol::intern(b"b"));
+            let depth = pause.frames().len();
+            self.pauses.borrow_mut().push((function, line, depth, b));
+
+            if let (Function::Script { id: 0 }, Some(5)) = (function, line) {
+                let a = vm::Value::from(3);
+                let a = pause.registers().iter().any(|slot| match slot {
+                    vm::Slot::Value(value) => *value == a,
+                    _ => false,
+                });
+                assert!(a);
+            }
+
+            self.resumes.remove(0)
+        }
+    }
+
+    let options = gml::Options { optimize: false, ..gml::Options::default() };
+    let (code, debug) = gml::build_with_options(&game, &items, options, io::stderr)
+        .unwrap_or_else(|_| panic!());
+    let assets = Assets { code };
+    let mut world = World::default();
+    let (_, entity) = world.create_instance();
+
+    let pauses = Rc::default();
+    let resumes = vec![
+        vm::Resume::StepIn, vm::Resume::StepIn, vm::Resume::StepOut, vm::Resume::StepOver,
+    ];
+    let script = Script { debug: debug.clone(), resumes, pauses: Rc::clone(&pauses) };
+    let mut session = vm::Session::new(&debug, Box::new(script), vm::Resume::Continue);
+    assert!(session.breakpoints().insert(outer, None, 3));
+    assert!(!session.breakpoints().insert(outer, None, 20));
+
+    let mut thread = vm::Thread::default();
+    thread.attach(session);
+    let mut cx = Context { world, assets };
+
+    assert_eq!(thread.with(entity).execute(&mut cx, outer, vec![])?, vm::Value::from(9));
+    assert_eq!(&pauses.borrow()[..], &[
+        (outer, Some(3), 1, None),
+        (outer, Some(4), 1, None),
+        (inner, Some(1), 2, None),
+        (outer, Some(5), 1, Some(vm::Value::from(6))),
+    ]);
+    Ok(())
+}
+
+/// Save compiled code and run it without the compiler.
+#[test]
+fn precompiled() -> vm::Result<()> {
+    let mut game = project::Game::default();
+    let mut items = HashMap::new();
+
+    let add = Symbol::intern(b"add");
+    items.insert(add, Item::Native(World::native_add, 2, false));
+
+    let greet = Function::Script { id: game.scripts.len() as i32 };
+    game.scripts.push(project::Script { name: b"greet", body: b"{
+        return 'hello ' + string(add(argument0, 1))
+    }" });
+    items.insert(Symbol::intern(b"string"), Item::Native(World::native_string, 1, false));
+
+    let (code, debug) = gml::build(&game, &items, io::stderr).unwrap_or_else(|_| panic!());
+    let mut data = Vec::default();
+    gml::write_build(&code, &debug, &mut data).unwrap();
+
+    // The output does not depend on hash map iteration order.
+    let (code, debug) = gml::build(&game, &items, io::stderr).unwrap_or_else(|_| panic!());
+    let mut again = Vec::default();
+    gml::write_build(&code, &debug, &mut again).unwrap();
+    assert_eq!(data, again);
+
+    let (code, debug) = gml::read_build(&items, &data[..]).unwrap();
+    assert_eq!(gml::FunctionDisplay::from_debug(&debug, greet).to_string(), "script greet");
+    let assets = Assets { code };
+    let world = World::default();
+
+    let mut thread = vm::Thread::default();
+    let mut cx = Context { world, assets };
+
+    let arguments = vec![vm::Value::from(2)];
+    let hello = vm::Value::from(Symbol::intern(b"hello 3"));
+    assert_eq!(thread.execute(&mut cx, greet, arguments)?, hello);
+
+    // Truncated and corrupted bundles are rejected.
+    assert!(gml::read_build(&items, &data[..data.len() - 1]).is_err());
+    data[0] = b'X';
+    assert!(gml::read_build(&items, &data[..]).is_err());
+
+    // So are bundles whose operands refer to registers or constants that do not exist.
+    let (mut code, debug) = gml::build(&game, &items, io::stderr).unwrap_or_else(|_| panic!());
+    code.code.get_mut(&greet).unwrap().locals -= 1;
+    let mut data = Vec::default();
+    gml::write_build(&code, &debug, &mut data).unwrap();
+    assert!(gml::read_build(&items, &data[..]).is_err());
+
+    let (mut code, debug) = gml::build(&game, &items, io::stderr).unwrap_or_else(|_| panic!());
+    code.code.get_mut(&greet).unwrap().constants.pop();
+    let mut data = Vec::default();
+    gml::write_build(&code, &debug, &mut data).unwrap();
+    assert!(gml::read_build(&items, &data[..]).is_err());
+
+    // And bundles that call into a runner without the functions they need.
+    let (code, debug) = gml::build(&game, &items, io::stderr).unwrap_or_else(|_| panic!());
+    let mut data = Vec::default();
+    gml::write_build(&code, &debug, &mut data).unwrap();
+    items.remove(&add);
+    assert!(gml::read_build(&items, &data[..]).is_err());
+
+    // Or that write to members the runner only lets them read.
+    items.insert(add, Item::Native(World::native_add, 2, false));
+    let scalar = Symbol::intern(b"scalar");
+    items.insert(scalar, Item::Member(
+        Some(Instance::get_scalar), Some(Instance::set_scalar), true
+    ));
+    game.scripts.push(project::Script { name: b"reset", body: b"scalar = 0" });
+    let (code, debug) = gml::build(&game, &items, io::stderr).unwrap_or_else(|_| panic!());
+    let mut data = Vec::default();
+    gml::write_build(&code, &debug, &mut data).unwrap();
+    assert!(gml::read_build(&items, &data[..]).is_ok());
+    items.insert(scalar, Item::Member(Some(Instance::get_scalar), None, true));
+    assert!(gml::read_build(&items, &data[..]).is_err());
+
+    Ok(())
+}
+
 #[test]
 fn reentrant() -> vm::Result<()> {
     let mut game = project::Game::default();
@@ -526,6 +935,194 @@ fn reentrant() -> vm::Result<()> {
     Ok(())
 }
 
+/// Game Maker: Studio syntax, and arrays with value semantics.
+#[test]
+fn gms() -> vm::Result<()> {
+    let mut game = project::Game::default();
+    let items = HashMap::default();
+
+    let entry = Function::Script { id: game.scripts.len() as i32 };
+    game.scripts.push(project::Script { name: b"entry", body: b"{
+        #macro TEN 5 + 5
+        enum color { red, green = 3, blue }
+
+        #region arrays
+        var i = 0, s = 0;
+        a[2] = color.blue
+        b = a
+        b[0] = 1
+        var c = a;
+        c[@ 1] = 2
+        s += a[0] + a[1] * 10 + b[0] * 100 + a[2] * 1000
+        #endregion
+
+        var d = make();
+        modify(d)
+        s += d[1] * 10000
+
+        while (i < 3) { s += i++ }
+        s += --i * TEN
+        return s > 4000 ? s : -1
+    }" });
+
+    game.scripts.push(project::Script { name: b"make", body: b"{
+        var r;
+        r[1] = 7
+        return r
+    }" });
+
+    game.scripts.push(project::Script { name: b"modify", body: b"{
+        argument0[1] = 9
+    }" });
+
+    let scalar = Function::Script { id: game.scripts.len() as i32 };
+    game.scripts.push(project::Script { name: b"scalar", body: b"{
+        var x = 5;
+        return x[0]
+    }" });
+
+    let options = gml::Options { dialect: gml::Dialect::Gms, ..gml::Options::default() };
+    let (code, _) = gml::build_with_options(&game, &items, options, io::stderr)
+        .unwrap_or_else(|_| panic!());
+    let assets = Assets { code };
+    let mut world = World::default();
+
+    let (_, entity) = world.create_instance();
+
+    let mut thread = vm::Thread::default();
+    let mut cx = Context { world, assets };
+
+    let result = thread.with(entity).execute(&mut cx, entry, vec![])?;
+    assert_eq!(result, vm::Value::from(74138.0));
+    assert!(thread.execute(&mut cx, scalar, vec![]).is_err());
+    Ok(())
+}
+
+/// GMS loops release the temporary arrays they keep alive, rather than accumulating them.
+#[test]
+fn gms_owned() -> vm::Result<()> {
+    let mut game = project::Game::default();
+    let mut items = HashMap::new();
+
+    let owned = Symbol::intern(b"owned");
+    items.insert(owned, Item::Native(World::native_owned, 0, false));
+
+    let array_length_1d = Symbol::intern(b"array_length_1d");
+    items.insert(array_length_1d, Item::Native(World::native_array_length, 1, false));
+
+    let entry = Function::Script { id: game.scripts.len() as i32 };
+    game.scripts.push(project::Script { name: b"entry", body: b"{
+        var low, high = 0, b = 0, c;
+        a[99] = 0
+        low = owned()
+        for (var i = 0; i < array_length_1d(a); i++) {
+            a[i] = i
+            b = a
+            b[0] = -1
+            c = make()
+            c[1] = a
+            if owned() > high { high = owned() }
+        }
+        return a[99] * 10000 + (high - low) * 1000 + a[0] + b[0]
+    }" });
+
+    game.scripts.push(project::Script { name: b"make", body: b"{
+        var r;
+        r[1] = 7
+        return r
+    }" });
+
+    let options = gml::Options { dialect: gml::Dialect::Gms, ..gml::Options::default() };
+    let (code, _) = gml::build_with_options(&game, &items, options, io::stderr)
+        .unwrap_or_else(|_| panic!());
+    let assets = Assets { code };
+    let mut world = World::default();
+
+    let (_, entity) = world.create_instance();
+
+    let mut thread = vm::Thread::default();
+    let mut cx = Context { world, assets };
+
+    let result = thread.with(entity).execute(&mut cx, entry, vec![])?;
+    assert_eq!(result, vm::Value::from(990000.0 + 3000.0 - 1.0));
+    assert_eq!(thread.owned_len(), 0);
+    Ok(())
+}
+
+/// Passing a GMS local, scalar or array, to a script or native function leaves it intact.
+#[test]
+fn gms_reuse_arguments() -> vm::Result<()> {
+    let mut game = project::Game::default();
+    let mut items = HashMap::new();
+
+    let array_length_1d = Symbol::intern(b"array_length_1d");
+    items.insert(array_length_1d, Item::Native(World::native_array_length, 1, false));
+
+    game.scripts.push(project::Script { name: b"id", body: b"return argument0" });
+
+    let entry = Function::Script { id: game.scripts.len() as i32 };
+    game.scripts.push(project::Script { name: b"entry", body: b"{
+        var a, i = 0, s = 0;
+        a[2] = 5
+        var b = id(a);
+        while (i < array_length_1d(a)) {
+            s += id(i) + a[i]
+            i++
+        }
+        return s * 100 + i * 10 + b[2]
+    }" });
+
+    for &optimize in &[false, true] {
+        let dialect = gml::Dialect::Gms;
+        let options = gml::Options { optimize, dialect, ..gml::Options::default() };
+        let (code, _) = gml::build_with_options(&game, &items, options, io::stderr)
+            .unwrap_or_else(|_| panic!());
+        let assets = Assets { code };
+        let world = World::default();
+
+        let mut thread = vm::Thread::default();
+        let mut cx = Context { world, assets };
+
+        assert_eq!(thread.execute(&mut cx, entry, vec![])?, vm::Value::from(835));
+    }
+
+    Ok(())
+}
+
+/// GMS macros and enums are visible from every script, no matter where they are defined.
+#[test]
+fn gms_definitions() -> vm::Result<()> {
+    let mut game = project::Game::default();
+    let items = HashMap::default();
+
+    let entry = Function::Script { id: game.scripts.len() as i32 };
+    game.scripts.push(project::Script { name: b"entry", body: b"{
+        #macro SIZE (WIDTH * 2)
+        return SIZE + direction.down * 100 + layer.top
+    }" });
+
+    game.scripts.push(project::Script { name: b"config", body: b"{
+        #macro WIDTH 3
+        enum direction { up, down }
+        enum layer { top = direction.down + WIDTH }
+    }" });
+
+    let options = gml::Options { dialect: gml::Dialect::Gms, ..gml::Options::default() };
+    let (code, _) = gml::build_with_options(&game, &items, options, io::stderr)
+        .unwrap_or_else(|_| panic!());
+    let assets = Assets { code };
+    let mut world = World::default();
+
+    let (_, entity) = world.create_instance();
+
+    let mut thread = vm::Thread::default();
+    let mut cx = Context { world, assets };
+
+    let result = thread.with(entity).execute(&mut cx, entry, vec![])?;
+    assert_eq!(result, vm::Value::from(6.0 + 100.0 + 4.0));
+    Ok(())
+}
+
 struct Context {
     world: World,
     assets: Assets,
@@ -594,6 +1191,40 @@ impl World {
         Ok(vm::Value::from(value + 8))
     }
 
+    fn native_string(
+        _: &mut Context, thread: &mut vm::Thread, arguments: Range<usize>
+    ) -> vm::Result<vm::Value> {
+        let arguments = unsafe { thread.arguments(arguments) };
+        let value = match arguments[0].borrow().decode() {
+            vm::Data::Real(a) => vm::Value::from(Symbol::intern(a.to_string().as_bytes())),
+            _ => arguments[0].clone(),
+        };
+
+        Ok(value)
+    }
+
+    fn native_owned(
+        _: &mut Context, thread: &mut vm::Thread, _arguments: Range<usize>
+    ) -> vm::Result<vm::Value> {
+        Ok(vm::Value::from(thread.owned_len() as f64))
+    }
+
+    fn native_array_length(
+        _: &mut Context, thread: &mut vm::Thread, arguments: Range<usize>
+    ) -> vm::Result<vm::Value> {
+        let arguments = unsafe { thread.arguments(arguments) };
+        // Arrays are stored as rows, so a 1D array is the first row.
+        let row = match arguments[0].borrow().decode() {
+            vm::Data::Array(array) => array.get_flat(0),
+            _ => None,
+        };
+        let len = match row.as_ref().map(|row| row.borrow().decode()) {
+            Some(vm::Data::Array(row)) => row.len(),
+            _ => 0,
+        };
+        Ok(vm::Value::from(len as f64))
+    }
+
     fn native_create_instance(
         cx: &mut Context, _thread: &mut vm::Thread, _arguments: Range<usize>
     ) -> vm::Result<vm::Value> {
@@ -607,7 +1238,7 @@ impl World {
         self.next_id += 1;
 
         let entity = self.world.create_entity();
-        self.world.add_entity(entity, 0, id);
+        self.world.add_entity(entity, Some(0), id);
         (id, entity)
     }
 
@@ -66,7 +66,13 @@ pub fn run(source: &str) {
     let mut world = World::from_assets(&assets, debug);
     world.show.error = |state, error| state.show_vm_error_write(error, HostOut);
     world.show.write = Box::new(HostOut);
-    runner::run(&mut runner::Context { world, assets });
+
+    // Run only the room's creation code, as a frame loop would block the browser.
+    let mut thread = gml::vm::Thread::default();
+    let mut cx = runner::Context { world, assets };
+    if let Err(error) = runner::start(&mut cx, &mut thread) {
+        cx.world.show.show_vm_error(&*error);
+    }
 }
 
 impl io::Write for HostOut {
@@ -0,0 +1,394 @@
+//! Compiled Game Maker 8.0 and 8.1 executables.
+//!
+//! The game data is appended to the runner executable. In 8.0 it starts at a fixed offset, while
+//! in 8.1 its header must be searched for and everything after it is masked with an XOR stream.
+//! Both versions then scramble the resources with a byte swap table, before laying them out much
+//! like a project file.
+
+use typed_arena::Arena;
+
+use crate::read::{Reader, Error};
+use crate::gmk::{self, Format, MAGIC};
+use crate::Game;
+
+const GM80_HEADER: usize = 2_000_000;
+const GM81_HEADER: usize = 3_800_004;
+
+/// Read an executable, keeping its decrypted and decompressed data alive in `arena`.
+///
+/// The executable is decrypted in place, so it is taken by value.
+pub fn read_exe(exe: Vec<u8>, arena: &Arena<Vec<u8>>) -> Result<Game<'_>, Error> {
+    let data = arena.alloc(exe);
+
+    let start = match find_gm80(data)? {
+        Some(start) => start,
+        None => match find_gm81(data) {
+            Some(header) => decrypt_xor(data, header)?,
+            None => return Err(Error::Magic),
+        },
+    };
+
+    let mut read = Reader::new(data);
+    read.seek(start);
+    read.skip_zlib()?; // settings
+    let _dll_name = read.read_string()?;
+    let _dll = read.read_string()?;
+    let start = read.position();
+
+    let start = decrypt_swap(data, start)?;
+
+    let data: &Vec<u8> = data;
+    let mut read = Reader::new(data);
+    read.seek(start);
+    let garbage = read.read_u32()? as usize;
+    read.skip(garbage * 4)?;
+    let _pro = read.read_bool()?;
+    let _game_id = read.read_u32()?;
+    let _guid = read.read_bytes(16)?;
+    skip_extensions(&mut read)?;
+
+    let mut game = Game::default();
+    gmk::read_resources(&mut read, arena, Format::Executable, &mut game)?;
+    Ok(game)
+}
+
+/// Find the end of the 8.0 header, if there is one.
+fn find_gm80(data: &[u8]) -> Result<Option<usize>, Error> {
+    let mut read = Reader::new(data);
+    read.seek(GM80_HEADER);
+    match read.read_u32() {
+        Ok(MAGIC) => {}
+        _ => return Ok(None),
+    }
+    match read.read_u32()? {
+        800 => Ok(Some(read.position())),
+        version => Err(Error::Version(version)),
+    }
+}
+
+/// Find the end of the 8.1 header, if there is one.
+fn find_gm81(data: &[u8]) -> Option<usize> {
+    let header = (GM81_HEADER..).step_by(4).take(1024).find(|&offset| {
+        let mut read = Reader::new(data);
+        read.seek(offset);
+        match (read.read_u32(), read.read_u32()) {
+            (Ok(a), Ok(b)) => a & 0xFF00FF00 == 0xF7000000 && b & 0x00FF00FF == 0x00140067,
+            _ => false,
+        }
+    })?;
+    Some(header + 8)
+}
+
+/// Remove the 8.1 XOR mask, returning the start of the unmasked data.
+///
+/// The mask is generated from two seeds: one stored directly, and one derived from the CRC of a
+/// key string.
+fn decrypt_xor(data: &mut [u8], pos: usize) -> Result<usize, Error> {
+    let mut read = Reader::new(data);
+    read.seek(pos);
+    let key = format!("_MJD{}#RWK", read.read_u32()?);
+    let mut seed1 = read.read_u32()?;
+
+    // The key is hashed as UTF-16.
+    let key: Vec<u8> = key.bytes().flat_map(|byte| vec![byte, 0]).collect();
+    let mut seed2 = crc32(&key);
+
+    let start = read.position() + (seed2 & 0xFF) as usize + 10;
+    let masked = data.get_mut(start..).ok_or(Error::UnexpectedEof)?;
+    for word in masked.chunks_exact_mut(4) {
+        seed1 = (seed1 & 0xFFFF) * 0x9069 + (seed1 >> 16);
+        seed2 = (seed2 & 0xFFFF) * 0x4650 + (seed2 >> 16);
+        let mask = (seed1 << 16) + (seed2 & 0xFFFF);
+
+        let value = u32::from_le_bytes([word[0], word[1], word[2], word[3]]) ^ mask;
+        word.copy_from_slice(&value.to_le_bytes());
+    }
+
+    Ok(start)
+}
+
+/// CRC-32, without the final inversion.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { crc >> 1 ^ 0xEDB88320 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+/// Remove the swap table encryption, returning the start of the decrypted data.
+///
+/// The swap table is stored between two runs of garbage, just before the encrypted data.
+fn decrypt_swap(data: &mut [u8], pos: usize) -> Result<usize, Error> {
+    let mut read = Reader::new(data);
+    read.seek(pos);
+    let garbage1 = read.read_u32()? as usize;
+    let garbage2 = read.read_u32()? as usize;
+    read.skip(garbage1 * 4)?;
+    let mut table = [0; 256];
+    table.copy_from_slice(read.read_bytes(256)?);
+    read.skip(garbage2 * 4)?;
+    let len = read.read_u32()? as usize;
+    let start = read.position();
+    read.skip(len)?;
+
+    let mut reverse = [0; 256];
+    for (i, &byte) in table.iter().enumerate() {
+        reverse[byte as usize] = i as u8;
+    }
+
+    // Each byte is substituted based on its encrypted predecessor and its offset...
+    let data = &mut data[start..start + len];
+    for i in (1..len).rev() {
+        data[i] = reverse[data[i] as usize].wrapping_sub(data[i - 1].wrapping_add(i as u8));
+    }
+
+    // ... after being swapped with an earlier byte.
+    for i in (0..len).rev() {
+        let j = i.saturating_sub(table[(start + i) & 0xFF] as usize);
+        data.swap(i, j);
+    }
+
+    Ok(start)
+}
+
+fn skip_extensions(read: &mut Reader<'_>) -> Result<(), Error> {
+    let _version = read.read_u32()?;
+    for _ in 0..read.read_u32()? {
+        let _version = read.read_u32()?;
+        let _name = read.read_string()?;
+        let _folder = read.read_string()?;
+
+        for _ in 0..read.read_u32()? {
+            let _version = read.read_u32()?;
+            let _name = read.read_string()?;
+            let _kind = read.read_u32()?;
+            let _initialization = read.read_string()?;
+            let _finalization = read.read_string()?;
+
+            for _ in 0..read.read_u32()? {
+                let _version = read.read_u32()?;
+                let _name = read.read_string()?;
+                let _external_name = read.read_string()?;
+                let _convention = read.read_u32()?;
+                let _id = read.read_u32()?;
+                let _arity = read.read_u32()?;
+                let _parameters = read.read_bytes(17 * 4)?;
+                let _return_type = read.read_u32()?;
+            }
+
+            for _ in 0..read.read_u32()? {
+                let _version = read.read_u32()?;
+                let _name = read.read_string()?;
+                let _value = read.read_string()?;
+            }
+        }
+
+        let _contents = read.read_string()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gmk::swap_table;
+    use crate::gmk::tests::{u32, string, zlib, empty_section};
+    use super::*;
+
+    fn encrypt_swap(exe: &mut Vec<u8>, data: &[u8]) {
+        let table = swap_table(1234);
+        u32(exe, 1);
+        u32(exe, 2);
+        exe.extend_from_slice(&[0xAA; 4]);
+        exe.extend_from_slice(&table);
+        exe.extend_from_slice(&[0xBB; 8]);
+        u32(exe, data.len() as u32);
+
+        let start = exe.len();
+        exe.extend_from_slice(data);
+        let data = &mut exe[start..];
+        for i in 0..data.len() {
+            let j = i.saturating_sub(table[(start + i) & 0xFF] as usize);
+            data.swap(i, j);
+        }
+        for i in 1..data.len() {
+            let byte = data[i].wrapping_add(data[i - 1]).wrapping_add(i as u8);
+            data[i] = table[byte as usize];
+        }
+    }
+
+    fn game_data(exe: &mut Vec<u8>) {
+        zlib(exe, b"settings");
+        string(exe, b"D3DX8.dll");
+        string(exe, b"dll");
+
+        let mut data = Vec::default();
+        u32(&mut data, 1);
+        u32(&mut data, 0);
+        u32(&mut data, 0);
+        u32(&mut data, 0);
+        data.extend_from_slice(&[0; 16]);
+        u32(&mut data, 700);
+        u32(&mut data, 0);
+        empty_section(&mut data); // triggers
+        empty_section(&mut data); // constants
+        empty_section(&mut data); // sounds
+
+        u32(&mut data, 800);
+        u32(&mut data, 1);
+        let mut sprite = Vec::default();
+        u32(&mut sprite, 1);
+        string(&mut sprite, b"spr_main");
+        u32(&mut sprite, 800);
+        u32(&mut sprite, 1);
+        u32(&mut sprite, 2);
+        u32(&mut sprite, 1);
+        u32(&mut sprite, 800);
+        u32(&mut sprite, 2);
+        u32(&mut sprite, 1);
+        string(&mut sprite, &[0, 0, 255, 255, 255, 0, 0, 255]);
+        u32(&mut sprite, 0);
+        for &field in &[800, 2, 1, 0, 1, 0, 0] {
+            u32(&mut sprite, field);
+        }
+        u32(&mut sprite, 1);
+        u32(&mut sprite, 1);
+        zlib(&mut data, &sprite);
+
+        empty_section(&mut data); // backgrounds
+        empty_section(&mut data); // paths
+
+        u32(&mut data, 800);
+        u32(&mut data, 1);
+        let mut script = Vec::default();
+        u32(&mut script, 1);
+        string(&mut script, b"scr_main");
+        u32(&mut script, 800);
+        string(&mut script, b"return 3");
+        zlib(&mut data, &script);
+
+        empty_section(&mut data); // fonts
+        empty_section(&mut data); // timelines
+        empty_section(&mut data); // objects
+        empty_section(&mut data); // rooms
+        u32(&mut data, 100000);
+        u32(&mut data, 10000000);
+
+        encrypt_swap(exe, &data);
+    }
+
+    fn check_game(game: &Game<'_>) {
+        assert_eq!(game.sprites.len(), 1);
+        let sprite = &game.sprites[0];
+        assert_eq!(sprite.name, b"spr_main");
+        assert_eq!((sprite.origin_x, sprite.origin_y), (1, 2));
+        assert_eq!(sprite.frames.len(), 1);
+        assert_eq!(sprite.frames[0].data, [0, 0, 255, 255, 255, 0, 0, 255]);
+        assert_eq!((sprite.bbox_left, sprite.bbox_right), (0, 1));
+        assert_eq!(sprite.masks.len(), 1);
+        assert_eq!((sprite.masks[0].width, sprite.masks[0].height), (2, 1));
+        assert_eq!(sprite.masks[0].data, [1, 0, 0, 0, 1, 0, 0, 0]);
+
+        assert_eq!(game.scripts.len(), 1);
+        assert_eq!(game.scripts[0].name, b"scr_main");
+        assert_eq!(game.scripts[0].body, b"return 3");
+        assert_eq!(game.last_instance, 100000);
+    }
+
+    #[test]
+    fn gm80() -> Result<(), Error> {
+        let mut exe = vec![0; GM80_HEADER];
+        u32(&mut exe, MAGIC);
+        u32(&mut exe, 800);
+        game_data(&mut exe);
+
+        let arena = Arena::new();
+        check_game(&read_exe(exe, &arena)?);
+        Ok(())
+    }
+
+    /// Append an 8.1 header and game data, masked as Game Maker does.
+    fn gm81_data(exe: &mut Vec<u8>) -> Result<(), Error> {
+        let header = exe.len();
+        u32(exe, 0xF7120034);
+        u32(exe, 0x56148967);
+        u32(exe, 42);
+        u32(exe, 0xDEADBEEF);
+
+        let key: Vec<u8> = b"_MJD42#RWK".iter().flat_map(|&byte| vec![byte, 0]).collect();
+        let gap = (crc32(&key) & 0xFF) as usize + 10;
+        exe.resize(exe.len() + gap, 0);
+
+        let start = exe.len();
+        game_data(exe);
+        assert_eq!(decrypt_xor(exe, header + 8)?, start);
+        Ok(())
+    }
+
+    /// Stand in for the runner that precedes the game data, with a DOS header and arbitrary code.
+    fn runner(len: usize) -> Vec<u8> {
+        let mut exe = b"MZ".to_vec();
+        let mut state = 0x2545F491u32;
+        while exe.len() < len {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            exe.push(state as u8);
+        }
+        exe
+    }
+
+    #[test]
+    fn gm81() -> Result<(), Error> {
+        let mut exe = vec![0; GM81_HEADER + 64];
+        gm81_data(&mut exe)?;
+
+        let arena = Arena::new();
+        check_game(&read_exe(exe, &arena)?);
+        Ok(())
+    }
+
+    /// Find the game data past a runner that is not blank, as in a real executable. An 8.1
+    /// runner also extends past where 8.0 puts its header.
+    #[test]
+    fn runner_layout() -> Result<(), Error> {
+        let arena = Arena::new();
+
+        let mut exe = runner(GM80_HEADER);
+        u32(&mut exe, MAGIC);
+        u32(&mut exe, 800);
+        game_data(&mut exe);
+        check_game(&read_exe(exe, &arena)?);
+
+        let mut exe = runner(GM81_HEADER + 4 * 300);
+        gm81_data(&mut exe)?;
+        check_game(&read_exe(exe, &arena)?);
+
+        Ok(())
+    }
+
+    /// Read the game data of an 8.0 executable from a checked-in fixture, which was encrypted
+    /// apart from `encrypt_swap` and has runs of garbage of the sizes Game Maker writes.
+    #[test]
+    fn gm80_fixture() -> Result<(), Error> {
+        let mut exe = runner(GM80_HEADER);
+        exe.extend_from_slice(include_bytes!("../fixtures/gm80.bin"));
+
+        let arena = Arena::new();
+        let game = read_exe(exe, &arena)?;
+        assert_eq!(game.scripts.len(), 2);
+        assert_eq!(game.scripts[1].name, b"scr_fixture");
+        assert_eq!(game.scripts[1].body, b"return argument0 * 8");
+        assert_eq!(game.last_instance, 100000);
+        Ok(())
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(!crc32(b"123456789"), 0xCBF43926);
+    }
+}
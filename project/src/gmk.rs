@@ -0,0 +1,651 @@
+//! Game Maker 8.0 and 8.1 project files (.gmk and .gm81).
+//!
+//! After a short header, a project is a sequence of resource sections. Each resource is stored in
+//! its own zlib-compressed block, so resource types the runner does not use can be skipped without
+//! decompressing them. In 8.1, everything after the header is also encrypted with a swap table.
+
+use typed_arena::Arena;
+
+use crate::read::{Reader, Error};
+use crate::{Game, Sprite, Frame, Mask, Script, Object, Event, Action, Room, Instance};
+
+pub(crate) const MAGIC: u32 = 1234321;
+
+/// The container a resource is stored in.
+///
+/// Executables use the same resource layout as projects, minus some fields used only by the
+/// editor.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) enum Format {
+    Project,
+    Executable,
+}
+
+/// Read a project file, keeping its decompressed sections alive in `arena`.
+pub fn read_gmk<'a>(data: &'a [u8], arena: &'a Arena<Vec<u8>>) -> Result<Game<'a>, Error> {
+    let mut read = Reader::new(data);
+    if read.read_u32()? != MAGIC {
+        return Err(Error::Magic);
+    }
+    match read.read_u32()? {
+        800 => {}
+        810 => read = decrypt(data, &mut read, arena)?,
+        version => return Err(Error::Version(version)),
+    }
+    let _game_id = read.read_u32()?;
+    let _guid = read.read_bytes(16)?;
+
+    // Settings:
+    let _version = read.read_u32()?;
+    read.skip_zlib()?;
+
+    let mut game = Game::default();
+    read_resources(&mut read, arena, Format::Project, &mut game)?;
+    Ok(game)
+}
+
+/// Remove the 8.1 encryption, returning a reader over the decrypted remainder of the file.
+///
+/// A seed is stored between two runs of garbage. Every byte after the one that follows it is
+/// substituted through the swap table built from that seed, and offset by its position.
+fn decrypt<'a>(
+    data: &'a [u8], read: &mut Reader<'a>, arena: &'a Arena<Vec<u8>>
+) -> Result<Reader<'a>, Error> {
+    let garbage1 = read.read_u32()? as usize;
+    let garbage2 = read.read_u32()? as usize;
+    read.skip(garbage1 * 4)?;
+    let seed = read.read_u32()?;
+    read.skip(garbage2 * 4)?;
+    let start = read.position();
+
+    let mut reverse = [0; 256];
+    for (i, &byte) in swap_table(seed).iter().enumerate() {
+        reverse[byte as usize] = i as u8;
+    }
+
+    let data = arena.alloc(data.to_vec());
+    for (i, byte) in data.iter_mut().enumerate().skip(start + 1) {
+        *byte = reverse[*byte as usize].wrapping_sub(i as u8);
+    }
+
+    let data: &Vec<u8> = data;
+    let mut read = Reader::new(data);
+    read.seek(start);
+    Ok(read)
+}
+
+/// Build a swap table from a seed, the same way as Game Maker.
+pub(crate) fn swap_table(seed: u32) -> [u8; 256] {
+    let a = 6 + seed % 250;
+    let b = seed / 250;
+    let mut table = [0; 256];
+    for (i, byte) in table.iter_mut().enumerate() {
+        *byte = i as u8;
+    }
+    for i in 1..10001 {
+        let j = 1 + ((i * a + b) % 254) as usize;
+        table.swap(j, j + 1);
+    }
+    table
+}
+
+/// Read the resource sections, starting with triggers, shared by projects and executables.
+pub(crate) fn read_resources<'a>(
+    read: &mut Reader<'a>, arena: &'a Arena<Vec<u8>>, format: Format, game: &mut Game<'a>
+) -> Result<(), Error> {
+    // Triggers:
+    let _version = read.read_u32()?;
+    for _ in 0..read.read_u32()? {
+        read.skip_zlib()?;
+    }
+    if format == Format::Project {
+        let _last_changed = read.read_bytes(8)?;
+    }
+
+    // Constants:
+    let _version = read.read_u32()?;
+    for _ in 0..read.read_u32()? {
+        let _name = read.read_string()?;
+        let _value = read.read_string()?;
+    }
+    if format == Format::Project {
+        let _last_changed = read.read_bytes(8)?;
+    }
+
+    skip_resources(read)?; // sounds
+
+    let _version = read.read_u32()?;
+    for _ in 0..read.read_u32()? {
+        let mut read = read.read_zlib(arena)?;
+        let sprite = match read.read_bool()? {
+            true => read_sprite(&mut read, arena, format)?,
+            false => Sprite::default(),
+        };
+        game.sprites.push(sprite);
+    }
+
+    skip_resources(read)?; // backgrounds
+    skip_resources(read)?; // paths
+
+    let _version = read.read_u32()?;
+    for _ in 0..read.read_u32()? {
+        let mut read = read.read_zlib(arena)?;
+        let script = match read.read_bool()? {
+            true => read_script(&mut read, format)?,
+            false => Script::default(),
+        };
+        game.scripts.push(script);
+    }
+
+    skip_resources(read)?; // fonts
+    skip_resources(read)?; // timelines
+
+    let _version = read.read_u32()?;
+    for _ in 0..read.read_u32()? {
+        let mut read = read.read_zlib(arena)?;
+        let object = match read.read_bool()? {
+            true => read_object(&mut read, format)?,
+            false => Object::default(),
+        };
+        game.objects.push(object);
+    }
+
+    let _version = read.read_u32()?;
+    for _ in 0..read.read_u32()? {
+        let mut read = read.read_zlib(arena)?;
+        let room = match read.read_bool()? {
+            true => read_room(&mut read, format)?,
+            false => Room::default(),
+        };
+        game.rooms.push(room);
+    }
+
+    game.last_instance = read.read_i32()?;
+    let _last_tile = read.read_i32()?;
+
+    Ok(())
+}
+
+fn skip_resources(read: &mut Reader<'_>) -> Result<(), Error> {
+    let _version = read.read_u32()?;
+    for _ in 0..read.read_u32()? {
+        read.skip_zlib()?;
+    }
+    Ok(())
+}
+
+fn read_sprite<'a>(read: &mut Reader<'a>, arena: &'a Arena<Vec<u8>>, format: Format) ->
+    Result<Sprite<'a>, Error>
+{
+    let name = read.read_string()?;
+    if format == Format::Project {
+        let _last_changed = read.read_bytes(8)?;
+    }
+    let _version = read.read_u32()?;
+    let origin_x = read.read_i32()?;
+    let origin_y = read.read_i32()?;
+
+    let mut frames = Vec::default();
+    for _ in 0..read.read_u32()? {
+        let _version = read.read_u32()?;
+        let width = read.read_u32()?;
+        let height = read.read_u32()?;
+        let len = width as usize * height as usize * 4;
+        let data = match format {
+            // Projects compress each frame, and leave out the data for empty frames.
+            Format::Project if len == 0 => &[][..],
+            Format::Project => read.read_zlib(arena)?.read_bytes(len)?,
+            Format::Executable => read.read_string()?,
+        };
+        frames.push(Frame { width, height, data });
+    }
+
+    let mut sprite = Sprite { name, origin_x, origin_y, frames, ..Sprite::default() };
+    match format {
+        Format::Project => {
+            // TODO: build masks for the disk and diamond shapes, rather than using rectangles.
+            let shape = read.read_u32()?;
+            sprite.precise = shape == 0;
+            sprite.alpha_tolerance = read.read_u32()?;
+            sprite.separate_masks = read.read_bool()?;
+            let _bbox_mode = read.read_u32()?;
+            sprite.bbox_left = read.read_i32()?;
+            sprite.bbox_right = read.read_i32()?;
+            sprite.bbox_bottom = read.read_i32()?;
+            sprite.bbox_top = read.read_i32()?;
+        }
+
+        // Executables store precomputed collision masks instead of the settings used to build
+        // them. Take the bounding box from the first mask.
+        Format::Executable if !sprite.frames.is_empty() => {
+            sprite.separate_masks = read.read_bool()?;
+            let masks = match sprite.separate_masks {
+                true => sprite.frames.len(),
+                false => 1,
+            };
+            for i in 0..masks {
+                let _version = read.read_u32()?;
+                let width = read.read_u32()?;
+                let height = read.read_u32()?;
+                let left = read.read_i32()?;
+                let right = read.read_i32()?;
+                let bottom = read.read_i32()?;
+                let top = read.read_i32()?;
+                let data = read.read_bytes(width as usize * height as usize * 4)?;
+                sprite.masks.push(Mask { width, height, data });
+                if i == 0 {
+                    sprite.bbox_left = left;
+                    sprite.bbox_right = right;
+                    sprite.bbox_bottom = bottom;
+                    sprite.bbox_top = top;
+                }
+            }
+        }
+        Format::Executable => {}
+    }
+
+    Ok(sprite)
+}
+
+fn read_script<'a>(read: &mut Reader<'a>, format: Format) -> Result<Script<'a>, Error> {
+    let name = read.read_string()?;
+    if format == Format::Project {
+        let _last_changed = read.read_bytes(8)?;
+    }
+    let _version = read.read_u32()?;
+    let body = read.read_string()?;
+    Ok(Script { name, body })
+}
+
+fn read_object<'a>(read: &mut Reader<'a>, format: Format) -> Result<Object<'a>, Error> {
+    let name = read.read_string()?;
+    if format == Format::Project {
+        let _last_changed = read.read_bytes(8)?;
+    }
+    let _version = read.read_u32()?;
+    let sprite = read.read_i32()?;
+    let solid = read.read_bool()?;
+    let visible = read.read_bool()?;
+    let depth = read.read_i32()?;
+    let persistent = read.read_bool()?;
+    let parent = read.read_i32()?;
+    let mask = read.read_i32()?;
+
+    let mut events = Vec::default();
+    let last_event_type = read.read_u32()?;
+    for event_type in 0..=last_event_type {
+        loop {
+            let event_kind = read.read_i32()?;
+            if event_kind == -1 {
+                break;
+            }
+            let actions = read_actions(read)?;
+            events.push(Event { event_type, event_kind, actions });
+        }
+    }
+
+    Ok(Object { name, sprite, solid, visible, depth, persistent, parent, mask, events })
+}
+
+/// Read a list of D&D actions, shared by object events and timeline moments.
+fn read_actions<'a>(read: &mut Reader<'a>) -> Result<Vec<Action<'a>>, Error> {
+    let _version = read.read_u32()?;
+    let mut actions = Vec::default();
+    for _ in 0..read.read_u32()? {
+        let _version = read.read_u32()?;
+        let library = read.read_u32()?;
+        let action = read.read_u32()?;
+        let action_kind = read.read_u32()?;
+        let has_relative = read.read_bool()?;
+        let is_question = read.read_bool()?;
+        let has_target = read.read_bool()?;
+        let action_type = read.read_u32()?;
+        let name = read.read_string()?;
+        let code = read.read_string()?;
+
+        let parameters_used = read.read_u32()?;
+        let mut parameters = Vec::default();
+        for _ in 0..read.read_u32()? {
+            parameters.push(read.read_u32()?);
+        }
+
+        let target = read.read_i32()?;
+        let relative = read.read_bool()?;
+
+        let mut arguments = Vec::default();
+        for _ in 0..read.read_u32()? {
+            arguments.push(read.read_string()?);
+        }
+
+        let negate = read.read_bool()?;
+
+        actions.push(Action {
+            library, action, action_kind, has_relative, is_question, has_target, action_type,
+            name, code, parameters_used, parameters, target, relative, arguments, negate,
+        });
+    }
+    Ok(actions)
+}
+
+fn read_room<'a>(read: &mut Reader<'a>, format: Format) -> Result<Room<'a>, Error> {
+    let name = read.read_string()?;
+    if format == Format::Project {
+        let _last_changed = read.read_bytes(8)?;
+    }
+    let _version = read.read_u32()?;
+    let _caption = read.read_string()?;
+    let width = read.read_u32()?;
+    let height = read.read_u32()?;
+    if format == Format::Project {
+        let _snap_x = read.read_u32()?;
+        let _snap_y = read.read_u32()?;
+        let _isometric = read.read_bool()?;
+    }
+    let _speed = read.read_u32()?;
+    let _persistent = read.read_bool()?;
+    let background_color = read.read_u32()?;
+    let draw_background_color = read.read_bool()?;
+    let code = read.read_string()?;
+
+    // Backgrounds and views have a fixed size.
+    for _ in 0..read.read_u32()? {
+        read.skip(10 * 4)?;
+    }
+    let _enable_views = read.read_bool()?;
+    for _ in 0..read.read_u32()? {
+        read.skip(14 * 4)?;
+    }
+
+    let mut instances = Vec::default();
+    for _ in 0..read.read_u32()? {
+        let x = read.read_i32()?;
+        let y = read.read_i32()?;
+        let object_index = read.read_i32()?;
+        let id = read.read_i32()?;
+        let code = read.read_string()?;
+        if format == Format::Project {
+            let _locked = read.read_bool()?;
+        }
+        instances.push(Instance { x, y, object_index, id, code });
+    }
+
+    Ok(Room { name, width, height, background_color, draw_background_color, code, instances })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::io::Write;
+    use flate2::{Compression, write::ZlibEncoder};
+    use super::*;
+
+    pub(crate) fn u32(buf: &mut Vec<u8>, value: u32) {
+        buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub(crate) fn string(buf: &mut Vec<u8>, value: &[u8]) {
+        u32(buf, value.len() as u32);
+        buf.extend_from_slice(value);
+    }
+
+    pub(crate) fn zlib(buf: &mut Vec<u8>, value: &[u8]) {
+        let mut encoder = ZlibEncoder::new(Vec::default(), Compression::default());
+        encoder.write_all(value).unwrap();
+        string(buf, &encoder.finish().unwrap());
+    }
+
+    pub(crate) fn empty_section(buf: &mut Vec<u8>) {
+        u32(buf, 800);
+        u32(buf, 0);
+    }
+
+    #[test]
+    fn project() -> Result<(), Error> {
+        let mut file = Vec::default();
+        u32(&mut file, MAGIC);
+        u32(&mut file, 800);
+        u32(&mut file, 0);
+        file.extend_from_slice(&[0; 16]);
+
+        u32(&mut file, 800);
+        zlib(&mut file, b"settings");
+        empty_section(&mut file); // triggers
+        file.extend_from_slice(&[0; 8]);
+        empty_section(&mut file); // constants
+        file.extend_from_slice(&[0; 8]);
+        empty_section(&mut file); // sounds
+
+        u32(&mut file, 800);
+        u32(&mut file, 1);
+        let mut sprite = Vec::default();
+        u32(&mut sprite, 1);
+        string(&mut sprite, b"spr_main");
+        sprite.extend_from_slice(&[0; 8]);
+        u32(&mut sprite, 800);
+        u32(&mut sprite, 1);
+        u32(&mut sprite, 2);
+        u32(&mut sprite, 1);
+        u32(&mut sprite, 800);
+        u32(&mut sprite, 2);
+        u32(&mut sprite, 1);
+        zlib(&mut sprite, &[0, 0, 255, 255, 255, 0, 0, 255]);
+        for &field in &[0, 0, 1, 0, 0, 1, 0, 0] {
+            u32(&mut sprite, field);
+        }
+        zlib(&mut file, &sprite);
+
+        empty_section(&mut file); // backgrounds
+        empty_section(&mut file); // paths
+
+        u32(&mut file, 800);
+        u32(&mut file, 2);
+        zlib(&mut file, &[0; 4]);
+        let mut script = Vec::default();
+        u32(&mut script, 1);
+        string(&mut script, b"scr_main");
+        script.extend_from_slice(&[0; 8]);
+        u32(&mut script, 800);
+        string(&mut script, b"return 3");
+        zlib(&mut file, &script);
+
+        empty_section(&mut file); // fonts
+        empty_section(&mut file); // timelines
+
+        u32(&mut file, 800);
+        u32(&mut file, 1);
+        let mut object = Vec::default();
+        u32(&mut object, 1);
+        string(&mut object, b"obj_main");
+        object.extend_from_slice(&[0; 8]);
+        u32(&mut object, 430);
+        for &field in &[0, 1, 1, 0, 1, -100, 0] {
+            u32(&mut object, field as u32);
+        }
+        u32(&mut object, 11);
+        for event_type in 0..=11 {
+            if event_type == crate::event_type::STEP {
+                u32(&mut object, crate::event_kind::STEP_BEGIN as u32);
+                u32(&mut object, 400);
+                u32(&mut object, 1);
+                u32(&mut object, 440);
+                u32(&mut object, 1);
+                u32(&mut object, 603);
+                u32(&mut object, crate::action_kind::CODE);
+                u32(&mut object, 0);
+                u32(&mut object, 0);
+                u32(&mut object, 1);
+                u32(&mut object, crate::action_type::CODE);
+                string(&mut object, b"");
+                string(&mut object, b"");
+                u32(&mut object, 1);
+                u32(&mut object, 1);
+                u32(&mut object, crate::argument_type::STRING);
+                u32(&mut object, -1i32 as u32);
+                u32(&mut object, 0);
+                u32(&mut object, 1);
+                string(&mut object, b"x += 1");
+                u32(&mut object, 0);
+            }
+            u32(&mut object, -1i32 as u32);
+        }
+        zlib(&mut file, &object);
+
+        u32(&mut file, 800);
+        u32(&mut file, 1);
+        let mut room = Vec::default();
+        u32(&mut room, 1);
+        string(&mut room, b"rm_main");
+        room.extend_from_slice(&[0; 8]);
+        u32(&mut room, 541);
+        string(&mut room, b"");
+        for &field in &[640, 480, 16, 16, 0, 30, 0, 0, 1] {
+            u32(&mut room, field);
+        }
+        string(&mut room, b"show_debug_message(1)");
+        u32(&mut room, 8);
+        room.extend_from_slice(&[0; 8 * 10 * 4]);
+        u32(&mut room, 0);
+        u32(&mut room, 8);
+        room.extend_from_slice(&[0; 8 * 14 * 4]);
+        u32(&mut room, 1);
+        for &field in &[32, 64, 0, 100001] {
+            u32(&mut room, field);
+        }
+        string(&mut room, b"y = 3");
+        u32(&mut room, 0);
+        zlib(&mut file, &room);
+
+        u32(&mut file, 100001);
+        u32(&mut file, 10000000);
+
+        let arena = Arena::new();
+        let game = read_gmk(&file, &arena)?;
+
+        assert_eq!(game.sprites.len(), 1);
+        let sprite = &game.sprites[0];
+        assert_eq!(sprite.name, b"spr_main");
+        assert_eq!((sprite.origin_x, sprite.origin_y), (1, 2));
+        assert_eq!(sprite.frames.len(), 1);
+        assert_eq!((sprite.frames[0].width, sprite.frames[0].height), (2, 1));
+        assert_eq!(sprite.frames[0].data, [0, 0, 255, 255, 255, 0, 0, 255]);
+        assert_eq!((sprite.bbox_left, sprite.bbox_right), (0, 1));
+        assert_eq!((sprite.bbox_top, sprite.bbox_bottom), (0, 0));
+        assert!(sprite.precise);
+        assert!(sprite.separate_masks);
+
+        assert_eq!(game.scripts.len(), 2);
+        assert_eq!(game.scripts[0].name, b"");
+        assert_eq!(game.scripts[1].name, b"scr_main");
+        assert_eq!(game.scripts[1].body, b"return 3");
+
+        assert_eq!(game.objects.len(), 1);
+        assert_eq!(game.objects[0].name, b"obj_main");
+        assert_eq!(game.objects[0].sprite, 0);
+        assert!(game.objects[0].solid);
+        assert!(game.objects[0].visible);
+        assert_eq!(game.objects[0].mask, 0);
+        assert!(game.objects[0].persistent);
+        assert_eq!(game.objects[0].parent, -100);
+        assert_eq!(game.objects[0].events.len(), 1);
+        let event = &game.objects[0].events[0];
+        assert_eq!(event.event_type, crate::event_type::STEP);
+        assert_eq!(event.event_kind, crate::event_kind::STEP_BEGIN);
+        assert_eq!(event.actions.len(), 1);
+        assert_eq!(event.actions[0].action, 603);
+        assert_eq!(event.actions[0].target, -1);
+        assert_eq!(event.actions[0].arguments, [b"x += 1"]);
+
+        assert_eq!(game.rooms.len(), 1);
+        assert_eq!(game.rooms[0].name, b"rm_main");
+        assert_eq!((game.rooms[0].width, game.rooms[0].height), (640, 480));
+        assert!(game.rooms[0].draw_background_color);
+        assert_eq!(game.rooms[0].code, b"show_debug_message(1)");
+        assert_eq!(game.rooms[0].instances.len(), 1);
+        let instance = &game.rooms[0].instances[0];
+        assert_eq!((instance.x, instance.y, instance.object_index), (32, 64, 0));
+        assert_eq!((instance.id, instance.code), (100001, &b"y = 3"[..]));
+
+        assert_eq!(game.last_instance, 100001);
+
+        Ok(())
+    }
+
+    #[test]
+    fn truncated_actions() {
+        let mut file = Vec::default();
+        u32(&mut file, 400);
+        u32(&mut file, u32::MAX);
+
+        let mut read = Reader::new(&file);
+        match read_actions(&mut read) {
+            Err(Error::UnexpectedEof) => {}
+            _ => panic!("expected the end of the file"),
+        }
+    }
+
+    #[test]
+    fn encrypted() -> Result<(), Error> {
+        let mut data = Vec::default();
+        u32(&mut data, 0xAB);
+        data.extend_from_slice(&[0; 16]);
+        u32(&mut data, 800);
+        zlib(&mut data, b"settings");
+        empty_section(&mut data); // triggers
+        data.extend_from_slice(&[0; 8]);
+        empty_section(&mut data); // constants
+        data.extend_from_slice(&[0; 8]);
+        for _ in 0..4 {
+            empty_section(&mut data); // sounds, sprites, backgrounds, paths
+        }
+        u32(&mut data, 800);
+        u32(&mut data, 1);
+        let mut script = Vec::default();
+        u32(&mut script, 1);
+        string(&mut script, b"scr_main");
+        script.extend_from_slice(&[0; 8]);
+        u32(&mut script, 800);
+        string(&mut script, b"return 3");
+        zlib(&mut data, &script);
+        for _ in 0..4 {
+            empty_section(&mut data); // fonts, timelines, objects, rooms
+        }
+        u32(&mut data, 100000);
+        u32(&mut data, 10000000);
+
+        let mut file = Vec::default();
+        u32(&mut file, MAGIC);
+        u32(&mut file, 810);
+        u32(&mut file, 2);
+        u32(&mut file, 1);
+        file.extend_from_slice(&[0xAA; 8]);
+        u32(&mut file, 1234);
+        file.extend_from_slice(&[0xBB; 4]);
+        let table = swap_table(1234);
+        let start = file.len();
+        file.extend_from_slice(&data);
+        for (i, byte) in file.iter_mut().enumerate().skip(start + 1) {
+            *byte = table[byte.wrapping_add(i as u8) as usize];
+        }
+
+        let arena = Arena::new();
+        let game = read_gmk(&file, &arena)?;
+        assert_eq!(game.scripts.len(), 1);
+        assert_eq!(game.scripts[0].name, b"scr_main");
+        assert_eq!(game.scripts[0].body, b"return 3");
+        assert_eq!(game.last_instance, 100000);
+
+        Ok(())
+    }
+
+    #[test]
+    fn version() {
+        let mut file = Vec::default();
+        u32(&mut file, MAGIC);
+        u32(&mut file, 530);
+
+        let arena = Arena::new();
+        match read_gmk(&file, &arena) {
+            Err(Error::Version(530)) => {}
+            _ => panic!("expected a version error"),
+        }
+    }
+}
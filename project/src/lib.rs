@@ -1,4 +1,14 @@
+pub use crate::gmk::read_gmk;
+pub use crate::exe::read_exe;
+pub use crate::read::Error;
+pub use typed_arena::Arena;
+
+mod read;
+mod gmk;
+mod exe;
+
 pub struct Game<'a> {
+    pub sprites: Vec<Sprite<'a>>,
     pub scripts: Vec<Script<'a>>,
     pub objects: Vec<Object<'a>>,
     pub rooms: Vec<Room<'a>>,
@@ -6,19 +16,79 @@ pub struct Game<'a> {
     pub last_instance: i32,
 }
 
+#[derive(Default)]
+pub struct Sprite<'a> {
+    pub name: &'a [u8],
+    pub origin_x: i32,
+    pub origin_y: i32,
+    pub frames: Vec<Frame<'a>>,
+    pub bbox_left: i32,
+    pub bbox_right: i32,
+    pub bbox_top: i32,
+    pub bbox_bottom: i32,
+    /// Whether collisions check individual pixels rather than just the bounding box.
+    pub precise: bool,
+    /// Whether each frame has its own mask, rather than sharing one covering all frames.
+    pub separate_masks: bool,
+    /// The greatest alpha value treated as transparent in a precise mask.
+    pub alpha_tolerance: u32,
+    /// Collision masks, which only executables store. Projects build them from the settings above.
+    pub masks: Vec<Mask<'a>>,
+}
+
+#[derive(Default)]
+pub struct Frame<'a> {
+    pub width: u32,
+    pub height: u32,
+    /// Pixels in BGRA order, row by row.
+    pub data: &'a [u8],
+}
+
+#[derive(Default)]
+pub struct Mask<'a> {
+    pub width: u32,
+    pub height: u32,
+    /// One little-endian `u32` per pixel, row by row, which is nonzero where the mask is solid.
+    pub data: &'a [u8],
+}
+
 #[derive(Default)]
 pub struct Script<'a> {
     pub name: &'a [u8],
     pub body: &'a [u8],
 }
 
-#[derive(Default)]
 pub struct Object<'a> {
     pub name: &'a [u8],
+    /// The index of the default sprite, or a negative value for none.
+    pub sprite: i32,
+    pub solid: bool,
+    pub visible: bool,
+    pub depth: i32,
     pub persistent: bool,
+    /// The index of the parent object, or a negative value for none.
+    pub parent: i32,
+    /// The index of the sprite used for collisions, or a negative value to use `sprite`.
+    pub mask: i32,
     pub events: Vec<Event<'a>>,
 }
 
+impl Default for Object<'_> {
+    fn default() -> Self {
+        Object {
+            name: b"",
+            sprite: -1,
+            solid: false,
+            visible: true,
+            depth: 0,
+            persistent: false,
+            parent: -1,
+            mask: -1,
+            events: Vec::default(),
+        }
+    }
+}
+
 #[derive(Default)]
 pub struct Event<'a> {
     pub event_type: u32,
@@ -29,6 +99,77 @@ pub struct Event<'a> {
 pub mod event_type {
     pub const CREATE: u32 = 0;
     pub const DESTROY: u32 = 1;
+    /// The event kind is the alarm index.
+    pub const ALARM: u32 = 2;
+    pub const STEP: u32 = 3;
+    /// The event kind is the index of the other object.
+    pub const COLLISION: u32 = 4;
+    /// The event kind is a virtual key code.
+    pub const KEYBOARD: u32 = 5;
+    pub const MOUSE: u32 = 6;
+    pub const OTHER: u32 = 7;
+    pub const DRAW: u32 = 8;
+    /// The event kind is a virtual key code.
+    pub const KEY_PRESS: u32 = 9;
+    /// The event kind is a virtual key code.
+    pub const KEY_RELEASE: u32 = 10;
+    /// The event kind is the trigger index.
+    pub const TRIGGER: u32 = 11;
+}
+
+pub mod event_kind {
+    pub const STEP_NORMAL: i32 = 0;
+    pub const STEP_BEGIN: i32 = 1;
+    pub const STEP_END: i32 = 2;
+
+    pub const MOUSE_LEFT_BUTTON: i32 = 0;
+    pub const MOUSE_RIGHT_BUTTON: i32 = 1;
+    pub const MOUSE_MIDDLE_BUTTON: i32 = 2;
+    pub const MOUSE_NO_BUTTON: i32 = 3;
+    pub const MOUSE_LEFT_PRESS: i32 = 4;
+    pub const MOUSE_RIGHT_PRESS: i32 = 5;
+    pub const MOUSE_MIDDLE_PRESS: i32 = 6;
+    pub const MOUSE_LEFT_RELEASE: i32 = 7;
+    pub const MOUSE_RIGHT_RELEASE: i32 = 8;
+    pub const MOUSE_MIDDLE_RELEASE: i32 = 9;
+    pub const MOUSE_ENTER: i32 = 10;
+    pub const MOUSE_LEAVE: i32 = 11;
+    /// Joystick 1 left, right, up, and down.
+    pub const JOYSTICK1_LEFT: i32 = 16;
+    /// Joystick 1 buttons 1 through 8.
+    pub const JOYSTICK1_BUTTON1: i32 = 21;
+    /// Joystick 2 left, right, up, and down.
+    pub const JOYSTICK2_LEFT: i32 = 31;
+    /// Joystick 2 buttons 1 through 8.
+    pub const JOYSTICK2_BUTTON1: i32 = 36;
+    /// The global mouse events, in the same order as the button, press, and release events,
+    /// without a no button event.
+    pub const GLOBAL_LEFT_BUTTON: i32 = 50;
+    pub const GLOBAL_MIDDLE_RELEASE: i32 = 58;
+    pub const MOUSE_WHEEL_UP: i32 = 60;
+    pub const MOUSE_WHEEL_DOWN: i32 = 61;
+
+    pub const OUTSIDE_ROOM: i32 = 0;
+    pub const INTERSECT_BOUNDARY: i32 = 1;
+    pub const GAME_START: i32 = 2;
+    pub const GAME_END: i32 = 3;
+    pub const ROOM_START: i32 = 4;
+    pub const ROOM_END: i32 = 5;
+    pub const NO_MORE_LIVES: i32 = 6;
+    pub const ANIMATION_END: i32 = 7;
+    pub const END_OF_PATH: i32 = 8;
+    pub const NO_MORE_HEALTH: i32 = 9;
+    /// User defined events 0 through 15.
+    pub const USER0: i32 = 10;
+    pub const USER_COUNT: i32 = 16;
+    pub const CLOSE_BUTTON: i32 = 30;
+    /// Outside view 0 through 7.
+    pub const OUTSIDE_VIEW0: i32 = 40;
+    /// Intersect boundary of view 0 through 7.
+    pub const BOUNDARY_VIEW0: i32 = 50;
+    pub const VIEW_COUNT: i32 = 8;
+
+    pub const DRAW_NORMAL: i32 = 0;
 }
 
 #[derive(Default)]
@@ -93,6 +234,10 @@ pub mod argument_type {
 pub struct Room<'a> {
     pub name: &'a [u8],
 
+    pub width: u32,
+    pub height: u32,
+    pub background_color: u32,
+    pub draw_background_color: bool,
     pub code: &'a [u8],
 
     pub instances: Vec<Instance<'a>>,
@@ -110,6 +255,7 @@ pub struct Instance<'a> {
 impl<'a> Default for Game<'a> {
     fn default() -> Game<'a> {
         Game {
+            sprites: Vec::default(),
             scripts: Vec::default(),
             objects: Vec::default(),
             rooms: Vec::default(),
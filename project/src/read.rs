@@ -0,0 +1,91 @@
+use std::{error, fmt, io::Read};
+
+use flate2::read::ZlibDecoder;
+use typed_arena::Arena;
+
+/// A cursor over the little-endian binary formats used by Game Maker.
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The file does not start with a known signature.
+    Magic,
+    /// The file, or one of its sections, uses an unsupported format version.
+    Version(u32),
+    /// The file ended in the middle of a section.
+    UnexpectedEof,
+    /// A compressed section could not be decompressed.
+    Zlib,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self { Reader { data, pos: 0 } }
+
+    pub fn position(&self) -> usize { self.pos }
+
+    pub fn seek(&mut self, pos: usize) { self.pos = pos; }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let end = self.pos.checked_add(len).ok_or(Error::UnexpectedEof)?;
+        let bytes = self.data.get(self.pos..end).ok_or(Error::UnexpectedEof)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    pub fn skip(&mut self, len: usize) -> Result<(), Error> {
+        self.read_bytes(len)?;
+        Ok(())
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, Error> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, Error> {
+        Ok(self.read_u32()? as i32)
+    }
+
+    /// Read a boolean, stored as a 32-bit integer.
+    pub fn read_bool(&mut self) -> Result<bool, Error> {
+        Ok(self.read_u32()? != 0)
+    }
+
+    /// Read a string, stored as a 32-bit length followed by its bytes.
+    pub fn read_string(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.read_u32()? as usize;
+        self.read_bytes(len)
+    }
+
+    /// Read a zlib-compressed block, stored as a 32-bit length followed by its compressed bytes.
+    ///
+    /// The decompressed bytes are kept alive in `arena`, so they can be borrowed by the `Game`.
+    pub fn read_zlib(&mut self, arena: &'a Arena<Vec<u8>>) -> Result<Reader<'a>, Error> {
+        let data = self.read_string()?;
+        let mut buf = Vec::default();
+        ZlibDecoder::new(data).read_to_end(&mut buf).map_err(|_| Error::Zlib)?;
+        Ok(Reader::new(arena.alloc(buf)))
+    }
+
+    /// Skip a zlib-compressed block without decompressing it.
+    pub fn skip_zlib(&mut self) -> Result<(), Error> {
+        self.read_string()?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::Magic => write!(f, "not a Game Maker file"),
+            Error::Version(version) => write!(f, "unsupported version {}", version),
+            Error::UnexpectedEof => write!(f, "unexpected end of file"),
+            Error::Zlib => write!(f, "corrupt compressed data"),
+        }
+    }
+}
+
+impl error::Error for Error {}
@@ -0,0 +1,586 @@
+//! A Debug Adapter Protocol server, for debugging games from an editor.
+//!
+//! Each script, event, and piece of creation code is presented to the client as a source with a
+//! `sourceReference`, whose text is its code with one line per line of `vm::Locations`.
+
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::fmt;
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value as Json};
+use gml::{symbol::Symbol, vm, Function, FunctionDisplay};
+
+/// A compiled game, along with the source code its debug locations refer to.
+pub struct Program {
+    pub assets: runner::Assets,
+    pub debug: vm::Debug,
+    pub sources: HashMap<Function, Vec<u8>>,
+    /// How the game was compiled, which expressions are compiled to match.
+    pub options: gml::Options,
+}
+
+/// The requests that need a paused game.
+const PAUSED: &[&str] = &[
+    "next", "stepIn", "stepOut", "stackTrace", "scopes", "variables", "evaluate",
+];
+
+/// The id of the only thread a game runs on.
+const THREAD: u64 = 1;
+
+/// The variable references of the scopes of a paused frame.
+const LOCALS: u64 = 1;
+const SELF: u64 = 2;
+const GLOBAL: u64 = 3;
+
+/// Serve a single debugging session, until the client disconnects or stops sending requests.
+///
+/// Games are loaded by `load`, from the arguments of the client's `launch` request.
+pub fn serve<L>(requests: mpsc::Receiver<Json>, output: mpsc::Sender<Json>, mut load: L) where
+    L: FnMut(&Json) -> Result<Program, String>
+{
+    let connection = Connection { requests, output, seq: Cell::new(1) };
+
+    let (program, stop_on_entry) = loop {
+        let request = match connection.requests.recv() {
+            Ok(request) => request,
+            Err(_) => return,
+        };
+        let arguments = &request["arguments"];
+        match command(&request) {
+            "initialize" => connection.respond(&request, Ok(capabilities())),
+            "launch" => match load(arguments) {
+                Ok(program) => {
+                    connection.respond(&request, Ok(Json::Null));
+                    let stop_on_entry = arguments["stopOnEntry"].as_bool().unwrap_or(false);
+                    break (program, stop_on_entry);
+                }
+                Err(message) => connection.respond(&request, Err(message)),
+            },
+            "disconnect" => {
+                connection.respond(&request, Ok(Json::Null));
+                return;
+            }
+            _ => connection.respond(&request, Err(String::from("no game has been launched"))),
+        }
+    };
+
+    let Program { assets, debug, sources, options } = program;
+    let adapter = Rc::new(Adapter::new(connection, &debug, &sources, options));
+    let resume = if stop_on_entry {
+        adapter.reason.set("entry");
+        vm::Resume::StepIn
+    } else {
+        vm::Resume::Continue
+    };
+    let debugger = Box::new(Debugger(Rc::clone(&adapter)));
+    let mut thread = vm::Thread::default();
+    thread.attach(vm::Session::new(&debug, debugger, resume));
+
+    // Let the client set up breakpoints before the game starts.
+    adapter.connection.event("initialized", Json::Null);
+    loop {
+        let request = match adapter.connection.requests.recv() {
+            Ok(request) => request,
+            Err(_) => return,
+        };
+        if command(&request) == "configurationDone" {
+            adapter.connection.respond(&request, Ok(Json::Null));
+            break;
+        }
+        let session = thread.session().expect("debugger is attached");
+        adapter.handle(&request, Target::Running(session));
+        if adapter.disconnected.get() {
+            return;
+        }
+    }
+
+    let world = runner::World::from_assets(&assets, debug);
+    let mut cx = runner::Context { world, assets };
+    cx.world.show.write = Box::new(Output { adapter: Rc::clone(&adapter), line: Vec::default() });
+    let result = run(&adapter, &mut cx, &mut thread);
+    if adapter.disconnected.get() {
+        return;
+    }
+
+    let exit_code = match result {
+        Ok(()) => 0,
+        Err(error) => {
+            let mut message = Vec::default();
+            cx.world.show.show_vm_error_write(&error, &mut message);
+            let output = String::from_utf8_lossy(&message);
+            adapter.connection.event("output", json!({ "category": "stderr", "output": output }));
+            1
+        }
+    };
+    adapter.connection.event("exited", json!({ "exitCode": exit_code }));
+    adapter.connection.event("terminated", Json::Null);
+
+    while !adapter.disconnected.get() {
+        let request = match adapter.connection.requests.recv() {
+            Ok(request) => request,
+            Err(_) => return,
+        };
+        adapter.handle(&request, Target::Exited);
+    }
+}
+
+/// Run the game as `runner::run_thread` does, handling requests between frames.
+fn run(adapter: &Adapter, cx: &mut runner::Context, thread: &mut vm::Thread) -> vm::Result<()> {
+    let frame_time = Duration::from_secs(1) / 30;
+
+    runner::start(cx, thread)?;
+    let mut next_frame = Instant::now();
+    while !cx.world.room.end && !adapter.disconnected.get() {
+        loop {
+            let request = match adapter.connection.requests.try_recv() {
+                Ok(request) => request,
+                Err(mpsc::TryRecvError::Empty) => break,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    adapter.disconnected.set(true);
+                    return Ok(());
+                }
+            };
+            let session = thread.session().expect("debugger is attached");
+            adapter.handle(&request, Target::Running(session));
+        }
+
+        runner::frame(cx, thread)?;
+
+        next_frame += frame_time;
+        if let Some(delay) = next_frame.checked_duration_since(Instant::now()) {
+            thread::sleep(delay);
+        }
+    }
+    Ok(())
+}
+
+struct Connection {
+    requests: mpsc::Receiver<Json>,
+    output: mpsc::Sender<Json>,
+    seq: Cell<u64>,
+}
+
+impl Connection {
+    fn send(&self, mut message: Json) {
+        message["seq"] = json!(self.seq.get());
+        self.seq.set(self.seq.get() + 1);
+        let _ = self.output.send(message);
+    }
+
+    fn respond(&self, request: &Json, result: Result<Json, String>) {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+        });
+        match result {
+            Ok(body) => {
+                response["success"] = json!(true);
+                if !body.is_null() {
+                    response["body"] = body;
+                }
+            }
+            Err(message) => {
+                response["success"] = json!(false);
+                response["message"] = json!(message);
+            }
+        }
+        self.send(response);
+    }
+
+    fn event(&self, event: &str, body: Json) {
+        let mut message = json!({ "type": "event", "event": event });
+        if !body.is_null() {
+            message["body"] = body;
+        }
+        self.send(message);
+    }
+}
+
+fn command(request: &Json) -> &str { request["command"].as_str().unwrap_or("") }
+
+fn capabilities() -> Json {
+    json!({ "supportsConfigurationDoneRequest": true })
+}
+
+/// The state of a debugging session, shared between the server and its `vm::Debugger`.
+struct Adapter {
+    connection: Connection,
+    debug: vm::Debug,
+    items: HashMap<Symbol, gml::Item<runner::Context>>,
+    options: gml::Options,
+    /// Each function with code, sorted. A function's source reference is its index plus one.
+    sources: Vec<Source>,
+    /// The lines of each function with verified breakpoints.
+    breakpoints: RefCell<HashMap<Function, Vec<usize>>>,
+    /// Why the thread will next pause, unless it reaches a breakpoint.
+    reason: Cell<&'static str>,
+    disconnected: Cell<bool>,
+}
+
+struct Source {
+    function: Function,
+    name: String,
+    text: String,
+}
+
+/// What a request can act on, depending on the state of the game.
+enum Target<'a, 'p> {
+    Running(&'a mut vm::Session),
+    Paused(&'a mut vm::Pause<'p>),
+    Exited,
+}
+
+impl Adapter {
+    fn new(
+        connection: Connection, debug: &vm::Debug, sources: &HashMap<Function, Vec<u8>>,
+        options: gml::Options,
+    ) -> Adapter {
+        let mut items = HashMap::default();
+        runner::World::register(&mut items);
+
+        let mut functions: Vec<_> = debug.locations.keys().copied().collect();
+        functions.sort();
+        let sources = functions.into_iter()
+            .map(|function| {
+                let name = FunctionDisplay::from_debug(debug, function).to_string();
+                let source = sources.get(&function).map_or(&[][..], |source| &source[..]);
+                let text = Self::text(&debug.locations[&function], source);
+                Source { function, name, text }
+            })
+            .collect();
+
+        Adapter {
+            connection,
+            debug: debug.clone(),
+            items,
+            options,
+            sources,
+            breakpoints: RefCell::default(),
+            reason: Cell::new("step"),
+            disconnected: Cell::new(false),
+        }
+    }
+
+    /// Lay out a function's code with one line per line of its locations.
+    ///
+    /// For scripts, this is just the script. For events, it is the lines of each code action.
+    fn text(locations: &vm::Locations, source: &[u8]) -> String {
+        let lines = &locations.lines;
+        let mut starts: Vec<_> = Iterator::chain(
+            lines.actions.iter().chain(lines.arguments.iter()).map(|&(start, _)| start),
+            lines.lines.iter().copied(),
+        ).collect();
+        starts.sort();
+
+        let mut text = String::default();
+        for &start in lines.lines.iter() {
+            let end = starts.iter().copied().find(|&end| end > start).unwrap_or(source.len());
+            let line = source.get(start..end).unwrap_or_default();
+            let line = match line.iter().position(|&b| b == b'\n') {
+                Some(newline) => &line[..newline],
+                None => line,
+            };
+            let line = if line.ends_with(b"\r") { &line[..line.len() - 1] } else { line };
+            text.push_str(&String::from_utf8_lossy(line));
+            text.push('\n');
+        }
+        text
+    }
+
+    fn handle(&self, request: &Json, target: Target<'_, '_>) -> Option<vm::Resume> {
+        let arguments = &request["arguments"];
+        let mut resume = None;
+        let result = match (command(request), target) {
+            ("initialize", _) => Ok(capabilities()),
+            ("threads", _) => Ok(json!({ "threads": [{ "id": THREAD, "name": "main" }] })),
+            ("source", _) => self.source(arguments),
+
+            ("setBreakpoints", Target::Running(session)) =>
+                self.set_breakpoints(arguments, Some(session.breakpoints())),
+            ("setBreakpoints", Target::Paused(pause)) =>
+                self.set_breakpoints(arguments, Some(&mut *pause.breakpoints)),
+            ("setBreakpoints", Target::Exited) => self.set_breakpoints(arguments, None),
+            ("setExceptionBreakpoints", _) => Ok(Json::Null),
+
+            ("pause", Target::Running(session)) => {
+                session.pause();
+                self.reason.set("pause");
+                Ok(Json::Null)
+            }
+            ("continue", Target::Paused(_)) => {
+                resume = Some(vm::Resume::Continue);
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            ("next", Target::Paused(_)) => {
+                resume = Some(vm::Resume::StepOver);
+                Ok(Json::Null)
+            }
+            ("stepIn", Target::Paused(_)) => {
+                resume = Some(vm::Resume::StepIn);
+                Ok(Json::Null)
+            }
+            ("stepOut", Target::Paused(_)) => {
+                resume = Some(vm::Resume::StepOut);
+                Ok(Json::Null)
+            }
+
+            ("stackTrace", Target::Paused(pause)) => self.stack_trace(pause),
+            ("scopes", Target::Paused(_)) => Self::scopes(arguments),
+            ("variables", Target::Paused(pause)) => Self::variables(pause, arguments),
+            ("evaluate", Target::Paused(pause)) => self.evaluate(pause, arguments),
+
+            ("disconnect", _) => {
+                self.disconnected.set(true);
+                resume = Some(vm::Resume::Continue);
+                Ok(Json::Null)
+            }
+
+            // The game is already paused, or already running.
+            ("pause", _) | ("continue", _) => Ok(Json::Null),
+            (command, _) if PAUSED.contains(&command) =>
+                Err(format!("cannot {} while the game is not paused", command)),
+            (command, _) => Err(format!("unsupported request `{}`", command)),
+        };
+        self.connection.respond(request, result);
+        resume
+    }
+
+    fn source(&self, arguments: &Json) -> Result<Json, String> {
+        let source = self.find_source(arguments)?;
+        Ok(json!({ "content": source.text }))
+    }
+
+    /// Find a source by its reference or by its name, as given to the client.
+    fn find_source(&self, source: &Json) -> Result<&Source, String> {
+        let reference = source["sourceReference"].as_u64().unwrap_or(0) as usize;
+        let name = source["name"].as_str();
+        let source = match reference.checked_sub(1) {
+            Some(index) => self.sources.get(index),
+            None => self.sources.iter().find(|source| Some(&source.name[..]) == name),
+        };
+        source.ok_or_else(|| String::from("unknown source"))
+    }
+
+    fn source_json(&self, function: Function) -> Json {
+        match self.sources.binary_search_by_key(&function, |source| source.function) {
+            Ok(index) => json!({ "name": self.sources[index].name, "sourceReference": index + 1 }),
+            Err(_) => Json::Null,
+        }
+    }
+
+    /// The line and column of an instruction, in the text of its function's source.
+    fn position(&self, function: Function, instruction: usize) -> (usize, usize) {
+        let locations = &self.debug.locations[&function];
+        let location = locations.locations.get_location(instruction as u32) as usize;
+        let lines = &locations.lines.lines;
+        match lines.binary_search(&location) {
+            Ok(line) => (line + 1, 1),
+            Err(0) => (0, 0),
+            Err(line) => (line, 1 + location - lines[line - 1]),
+        }
+    }
+
+    /// Find the action and line of a line in the text of a function's source.
+    fn statement(&self, function: Function, line: usize) -> Option<(Option<usize>, usize)> {
+        let lines = &self.debug.locations.get(&function)?.lines;
+        let start = *lines.lines.get(line.checked_sub(1)?)?;
+        let position = lines.get_position(start);
+        Some((position.action, position.line?))
+    }
+
+    /// Replace the breakpoints in a source.
+    fn set_breakpoints(
+        &self, arguments: &Json, breakpoints: Option<&mut vm::Breakpoints>
+    ) -> Result<Json, String> {
+        let source = self.find_source(&arguments["source"])?;
+        let function = source.function;
+        let lines: Vec<_> = match arguments["breakpoints"].as_array() {
+            Some(list) => list.iter().filter_map(|point| point["line"].as_u64()).collect(),
+            None => Vec::default(),
+        };
+
+        let mut enabled = self.breakpoints.borrow_mut();
+        let old = enabled.remove(&function).unwrap_or_default();
+        let mut verified = Vec::default();
+        if let Some(breakpoints) = breakpoints {
+            for line in old {
+                if let Some((action, line)) = self.statement(function, line) {
+                    breakpoints.remove(function, action, line);
+                }
+            }
+            for &line in &lines {
+                let line = line as usize;
+                if let Some((action, statement)) = self.statement(function, line) {
+                    if breakpoints.insert(function, action, statement) {
+                        verified.push(line);
+                    }
+                }
+            }
+        }
+
+        let list: Vec<_> = lines.iter()
+            .map(|&line| json!({
+                "verified": verified.contains(&(line as usize)),
+                "line": line,
+                "source": self.source_json(function),
+            }))
+            .collect();
+        enabled.insert(function, verified);
+        Ok(json!({ "breakpoints": list }))
+    }
+
+    fn stack_trace(&self, pause: &vm::Pause<'_>) -> Result<Json, String> {
+        let frames: Vec<_> = pause.frames().iter().enumerate()
+            .map(|(index, &(function, instruction))| {
+                let (line, column) = self.position(function, instruction);
+                json!({
+                    "id": index + 1,
+                    "name": FunctionDisplay::from_debug(&self.debug, function).to_string(),
+                    "source": self.source_json(function),
+                    "line": line,
+                    "column": column,
+                })
+            })
+            .collect();
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    /// Only the innermost frame's registers are available, so other frames have no locals.
+    fn scopes(arguments: &Json) -> Result<Json, String> {
+        let mut scopes = Vec::default();
+        if arguments["frameId"].as_u64() == Some(1) {
+            scopes.push(("Locals", LOCALS));
+        }
+        scopes.push(("Self", SELF));
+        scopes.push(("Global", GLOBAL));
+        let scopes: Vec<_> = scopes.into_iter()
+            .map(|(name, reference)| json!({
+                "name": name,
+                "variablesReference": reference,
+                "expensive": false,
+            }))
+            .collect();
+        Ok(json!({ "scopes": scopes }))
+    }
+
+    fn variables(pause: &mut vm::Pause<'_>, arguments: &Json) -> Result<Json, String> {
+        let variables: Vec<_> = match arguments["variablesReference"].as_u64() {
+            Some(LOCALS) => pause.registers().iter().enumerate()
+                .map(|(index, slot)| {
+                    let name = format!("%{}", index);
+                    match *slot {
+                        vm::Slot::Value(ref value) => variable(&name, value),
+                        vm::Slot::Raw(bits) => json!({
+                            "name": name,
+                            "value": format!("<{:#018x}>", bits),
+                            "variablesReference": 0,
+                        }),
+                    }
+                })
+                .collect(),
+            Some(SELF) => {
+                let entity = pause.self_entity();
+                pause.members(entity).iter()
+                    .map(|(name, value)| variable(name, value))
+                    .collect()
+            }
+            Some(GLOBAL) => pause.members(vm::world::GLOBAL).iter()
+                .map(|(name, value)| variable(name, value))
+                .collect(),
+            _ => return Err(String::from("unknown variable reference")),
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    fn evaluate(&self, pause: &mut vm::Pause<'_>, arguments: &Json) -> Result<Json, String> {
+        let expression = arguments["expression"].as_str().unwrap_or("");
+        let mut errors = Vec::default();
+        let function = gml::compile_expression(
+            &self.items, &self.debug, self.options, expression.as_bytes(), &mut errors
+        ).map_err(|_| String::from_utf8_lossy(&errors).trim_end().to_string())?;
+        let value = pause.evaluate(function).map_err(|error| error.kind.to_string())?;
+        let (result, kind) = display(&value);
+        Ok(json!({ "result": result, "type": kind, "variablesReference": 0 }))
+    }
+}
+
+fn variable(name: &dyn fmt::Display, value: &vm::Value) -> Json {
+    let (value, kind) = display(value);
+    json!({ "name": name.to_string(), "value": value, "type": kind, "variablesReference": 0 })
+}
+
+/// Format a value as it would be written in GML, along with the name of its type.
+fn display(value: &vm::Value) -> (String, &'static str) {
+    match value.borrow().decode() {
+        vm::Data::Real(_) => (format!("{:?}", value), "real"),
+        vm::Data::String(string) if string.contains(&b'\'') =>
+            (format!("\"{}\"", string), "string"),
+        vm::Data::String(string) => (format!("'{}'", string), "string"),
+        vm::Data::Array(_) => (format!("{:?}", value), "array"),
+    }
+}
+
+struct Debugger(Rc<Adapter>);
+
+impl vm::Debugger for Debugger {
+    fn pause(&mut self, pause: &mut vm::Pause<'_>) -> vm::Resume {
+        let Debugger(ref adapter) = *self;
+        if adapter.disconnected.get() {
+            return vm::Resume::Continue;
+        }
+
+        let (function, instruction) = pause.location();
+        let (line, _) = adapter.position(function, instruction);
+        let breakpoint = match adapter.breakpoints.borrow().get(&function) {
+            Some(lines) => lines.contains(&line),
+            None => false,
+        };
+        let reason = if breakpoint { "breakpoint" } else { adapter.reason.get() };
+        adapter.connection.event("stopped", json!({
+            "reason": reason,
+            "threadId": THREAD,
+            "allThreadsStopped": true,
+        }));
+
+        loop {
+            let request = match adapter.connection.requests.recv() {
+                Ok(request) => request,
+                Err(_) => {
+                    adapter.disconnected.set(true);
+                    return vm::Resume::Continue;
+                }
+            };
+            if let Some(resume) = adapter.handle(&request, Target::Paused(&mut *pause)) {
+                adapter.reason.set("step");
+                return resume;
+            }
+        }
+    }
+}
+
+/// Forwards the game's output to the client, a line at a time.
+struct Output {
+    adapter: Rc<Adapter>,
+    line: Vec<u8>,
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        if let Some(newline) = self.line.iter().rposition(|&b| b == b'\n') {
+            let lines: Vec<_> = self.line.drain(..=newline).collect();
+            let output = String::from_utf8_lossy(&lines);
+            let body = json!({ "category": "stdout", "output": output });
+            self.adapter.connection.event("output", body);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
@@ -0,0 +1,83 @@
+use std::cell::RefCell;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::{fs, io, thread};
+
+use serde_json::Value as Json;
+
+fn main() {
+    let (send_request, requests) = mpsc::channel();
+    let (output, receive_output) = mpsc::channel();
+
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+        while let Ok(Some(request)) = protocol::read_message(&mut stdin) {
+            if send_request.send(request).is_err() {
+                break;
+            }
+        }
+    });
+    let writer = thread::spawn(move || {
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        for message in receive_output {
+            if protocol::write_message(&mut stdout, &message).is_err() {
+                break;
+            }
+        }
+    });
+
+    dap::serve(requests, output, load);
+    let _ = writer.join();
+}
+
+/// Load a game from the `launch` request's `program`, and optionally its precompiled `code`.
+fn load(arguments: &Json) -> Result<dap::Program, String> {
+    let path = arguments["program"].as_str().ok_or("missing `program`")?;
+    let path = Path::new(path);
+    let data = fs::read(path)
+        .map_err(|error| format!("could not read {}: {}", path.display(), error))?;
+    let arena = project::Arena::new();
+    // Executables start with a DOS header, whatever their extension.
+    let game = match data.starts_with(b"MZ") {
+        true => project::read_exe(data, &arena),
+        false => project::read_gmk(&data, &arena),
+    };
+    let game = game.map_err(|error| format!("could not load {}: {}", path.display(), error))?;
+
+    // The optimizer folds away variables, which would leave nothing to inspect.
+    let options = gml::Options { optimize: false, ..gml::Options::default() };
+    let (assets, debug) = match arguments["code"].as_str() {
+        Some(code) => {
+            let code = Path::new(code);
+            fs::File::open(code)
+                .and_then(|file| runner::load_build(&game, io::BufReader::new(file)))
+                .map_err(|error| format!("could not load {}: {}", code.display(), error))?
+        }
+        None => {
+            let errors = Errors::default();
+            runner::build_with_options(&game, options, || errors.clone())
+                .map_err(|_| String::from_utf8_lossy(&errors.0.borrow()).into_owned())?
+        }
+    };
+
+    let sources = debug.locations.keys()
+        .filter_map(|&function| Some((function, gml::source(&game, function)?)))
+        .collect();
+    Ok(dap::Program { assets, debug, sources, options })
+}
+
+/// Collects compile errors, to report them in the response to `launch`.
+#[derive(Clone, Default)]
+struct Errors(Rc<RefCell<Vec<u8>>>);
+
+impl io::Write for Errors {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
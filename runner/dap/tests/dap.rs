@@ -0,0 +1,195 @@
+use std::collections::VecDeque;
+use std::sync::mpsc;
+use std::time::Duration;
+use std::{io, thread};
+
+use serde_json::{json, Value as Json};
+use gml::vm;
+
+/// A scripted client, which checks each response as it arrives.
+struct Client {
+    seq: u64,
+    requests: mpsc::Sender<Json>,
+    output: mpsc::Receiver<Json>,
+    events: VecDeque<Json>,
+}
+
+impl Client {
+    fn receive(&mut self) -> Json {
+        self.output.recv_timeout(Duration::from_secs(10)).expect("no message from the server")
+    }
+
+    fn request(&mut self, command: &str, arguments: Json) -> Json {
+        let seq = self.seq;
+        self.seq += 1;
+        let request = json!({
+            "seq": seq,
+            "type": "request",
+            "command": command,
+            "arguments": arguments,
+        });
+        self.requests.send(request).unwrap();
+
+        loop {
+            let message = self.receive();
+            match message["type"].as_str() {
+                Some("response") if message["request_seq"] == json!(seq) => return message,
+                Some("event") => self.events.push_back(message),
+                _ => panic!("unexpected message {}", message),
+            }
+        }
+    }
+
+    /// Send a request that should succeed, and return the body of its response.
+    fn expect(&mut self, command: &str, arguments: Json) -> Json {
+        let response = self.request(command, arguments);
+        assert_eq!(response["success"], json!(true), "{} failed: {}", command, response);
+        response["body"].clone()
+    }
+
+    fn event(&mut self, event: &str) -> Json {
+        loop {
+            let message = match self.events.pop_front() {
+                Some(message) => message,
+                None => self.receive(),
+            };
+            if message["event"] == json!(event) {
+                return message["body"].clone();
+            }
+        }
+    }
+}
+
+fn code_event(event_type: u32, code: &[u8]) -> project::Event<'_> {
+    project::Event {
+        event_type,
+        event_kind: 0,
+        actions: vec![
+            project::Action {
+                library: 1,
+                action: 603,
+                action_kind: project::action_kind::CODE,
+                has_target: true,
+                parameters_used: 1,
+                parameters: vec![project::argument_type::STRING],
+                target: vm::SELF,
+                arguments: vec![code],
+                ..project::Action::default()
+            },
+        ],
+    }
+}
+
+fn load(_: &Json) -> Result<dap::Program, String> {
+    let mut game = project::Game::default();
+    game.scripts.push(project::Script {
+        name: b"double",
+        body: b"show_debug_message('doubling', argument0)\nreturn argument0 * 2",
+    });
+    game.objects.push(project::Object {
+        name: b"obj",
+        events: vec![
+            code_event(project::event_type::CREATE, b"a = 1\nb = double(a)\nc = b + 1\ngame_end()"),
+        ],
+        ..project::Object::default()
+    });
+    game.last_instance += 1;
+    game.rooms.push(project::Room {
+        instances: vec![
+            project::Instance { x: 0, y: 0, object_index: 0, id: game.last_instance, code: b"" },
+        ],
+        ..project::Room::default()
+    });
+
+    let (assets, debug) = runner::build(&game, io::stderr).map_err(|_| "build failed")?;
+    let sources = debug.locations.keys()
+        .filter_map(|&function| Some((function, gml::source(&game, function)?)))
+        .collect();
+    Ok(dap::Program { assets, debug, sources, options: gml::Options::default() })
+}
+
+/// Drive a session through breakpoints, stepping, inspection, and evaluation.
+#[test]
+fn session() {
+    let (requests, server_requests) = mpsc::channel();
+    let (server_output, output) = mpsc::channel();
+    let client = thread::spawn(move || {
+        let mut client = Client { seq: 1, requests, output, events: VecDeque::default() };
+
+        let capabilities = client.expect("initialize", json!({ "adapterID": "dejavu" }));
+        assert_eq!(capabilities["supportsConfigurationDoneRequest"], json!(true));
+        client.expect("launch", json!({ "program": "game.gmk" }));
+        client.event("initialized");
+
+        let create = json!({ "name": "create event for object obj" });
+        let body = client.expect("setBreakpoints", json!({
+            "source": create,
+            "breakpoints": [{ "line": 2 }, { "line": 20 }],
+        }));
+        let verified: Vec<_> = body["breakpoints"].as_array().unwrap().iter()
+            .map(|breakpoint| breakpoint["verified"].clone())
+            .collect();
+        assert_eq!(verified, [json!(true), json!(false)]);
+        client.expect("configurationDone", json!({}));
+
+        // Stop at the breakpoint, before `double` is called.
+        assert_eq!(client.event("stopped")["reason"], json!("breakpoint"));
+        let frames = client.expect("stackTrace", json!({ "threadId": 1 }))["stackFrames"].clone();
+        assert_eq!(frames[0]["name"], json!("create event for object obj"));
+        assert_eq!(frames[0]["line"], json!(2));
+
+        let scopes = client.expect("scopes", json!({ "frameId": 1 }))["scopes"].clone();
+        let names: Vec<_> = scopes.as_array().unwrap().iter().map(|scope| &scope["name"]).collect();
+        assert_eq!(names, [&json!("Locals"), &json!("Self"), &json!("Global")]);
+        let reference = scopes[1]["variablesReference"].clone();
+        let body = client.expect("variables", json!({ "variablesReference": reference }));
+        let variables = body["variables"].as_array().unwrap();
+        let a = variables.iter()
+            .find(|variable| variable["name"] == json!("a"))
+            .expect("missing variable a");
+        assert_eq!(a["value"], json!("1.0"));
+
+        // The runner's variables are listed only if they belong to the instance.
+        let listed = |name| variables.iter().any(|variable| variable["name"] == json!(name));
+        assert!(listed("x") && listed("image_index"));
+        assert!(!listed("vk_left") && !listed("c_red") && !listed("current_time"));
+
+        // Evaluate expressions, including ones that call scripts and the runner.
+        let body = client.expect("evaluate", json!({ "expression": "a + 10", "frameId": 1 }));
+        assert_eq!(body["result"], json!("11.0"));
+        let body = client.expect("evaluate", json!({ "expression": "double(4)", "frameId": 1 }));
+        assert_eq!(body["result"], json!("8.0"));
+        assert_eq!(client.event("output")["output"], json!("doubling 4.0 \n"));
+        let response = client.request("evaluate", json!({ "expression": "a +", "frameId": 1 }));
+        assert_eq!(response["success"], json!(false));
+        assert!(response["message"].as_str().unwrap().starts_with("error in expression"));
+
+        // Step into `double`, and back out to the next line of the event.
+        client.expect("stepIn", json!({ "threadId": 1 }));
+        assert_eq!(client.event("stopped")["reason"], json!("step"));
+        let frames = client.expect("stackTrace", json!({ "threadId": 1 }))["stackFrames"].clone();
+        assert_eq!(frames.as_array().unwrap().len(), 2);
+        assert_eq!(frames[0]["name"], json!("script double"));
+        assert_eq!(frames[0]["line"], json!(1));
+        assert_eq!(frames[1]["name"], json!("create event for object obj"));
+
+        let reference = frames[0]["source"]["sourceReference"].clone();
+        let body = client.expect("source", json!({ "sourceReference": reference }));
+        let text = "show_debug_message('doubling', argument0)\nreturn argument0 * 2\n";
+        assert_eq!(body["content"], json!(text));
+
+        client.expect("stepOut", json!({ "threadId": 1 }));
+        assert_eq!(client.event("output")["output"], json!("doubling 1.0 \n"));
+        client.event("stopped");
+        let frames = client.expect("stackTrace", json!({ "threadId": 1 }))["stackFrames"].clone();
+        assert_eq!(frames[0]["line"], json!(3));
+
+        client.expect("continue", json!({ "threadId": 1 }));
+        assert_eq!(client.event("exited")["exitCode"], json!(0));
+        client.event("terminated");
+        client.expect("disconnect", json!({}));
+    });
+
+    dap::serve(server_requests, server_output, load);
+    client.join().unwrap();
+}
@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::process;
+
+use gml::symbol::Symbol;
+use gml::{vm, Function, FunctionDisplay};
+
+const HELP: &str = "\
+commands:
+    c, continue         run until the next breakpoint
+    s, step             run to the next line, stepping into scripts
+    n, next             run to the next line, stepping over scripts
+    o, out              run to the next line in the calling script or event
+    bt, backtrace       show the scripts and events being run
+    r, registers        show the registers of the current script or event
+    p, print <var>      show a variable, as `name`, `self.name`, `other.name`, or `global.name`
+    vars [<scope>]      show the variables of `self` (the default), `other`, or `global`
+    b, break [<loc>]    set a breakpoint, or list them
+    d, delete <loc>     remove a breakpoint
+    q, quit             stop the game
+
+<loc> is `<script>:<line>` or `<object>:<event>[:<kind>]:<line>`, where <event> is one of
+create, destroy, alarm, step, collision, keyboard, mouse, other, draw, keypress, keyrelease, or
+trigger, and <kind> defaults to 0.";
+
+/// A debugger front-end that reads commands from standard input.
+pub struct Console {
+    debug: vm::Debug,
+    sources: HashMap<Function, Vec<u8>>,
+}
+
+impl Console {
+    pub fn new(game: &project::Game, debug: vm::Debug) -> Console {
+        let sources = debug.locations.keys()
+            .filter_map(|&function| Some((function, gml::source(game, function)?)))
+            .collect();
+        Console { debug, sources }
+    }
+
+    /// Find the script or event and line named by a breakpoint location.
+    pub fn parse_location(&self, location: &str) -> Result<(Function, usize), String> {
+        let parts: Vec<_> = location.split(':').collect();
+        let (line, parts) = match parts.split_last() {
+            Some((line, parts)) => (line, parts),
+            None => return Err(format!("invalid location `{}`", location)),
+        };
+        let line = line.parse().map_err(|_| format!("invalid line `{}`", line))?;
+
+        let function = match *parts {
+            [script] => {
+                let id = Self::find(&self.debug.scripts, script)
+                    .ok_or_else(|| format!("unknown script `{}`", script))?;
+                Function::Script { id }
+            }
+            [object, event_type] | [object, event_type, _] => {
+                let object_index = Self::find(&self.debug.objects, object)
+                    .ok_or_else(|| format!("unknown object `{}`", object))?;
+                let event_type = Self::event_type(event_type)
+                    .ok_or_else(|| format!("unknown event `{}`", event_type))?;
+                let event_kind = match parts.get(2) {
+                    Some(kind) => kind.parse().map_err(|_| format!("invalid kind `{}`", kind))?,
+                    None => 0,
+                };
+                Function::Event { object_index, event_type, event_kind }
+            }
+            _ => return Err(format!("invalid location `{}`", location)),
+        };
+        Ok((function, line))
+    }
+
+    fn find(names: &[Symbol], name: &str) -> Option<i32> {
+        let index = names.iter().position(|&symbol| &symbol[..] == name.as_bytes())?;
+        Some(index as i32)
+    }
+
+    fn event_type(name: &str) -> Option<u32> {
+        use project::event_type::*;
+
+        let event_type = match name {
+            "create" => CREATE,
+            "destroy" => DESTROY,
+            "alarm" => ALARM,
+            "step" => STEP,
+            "collision" => COLLISION,
+            "keyboard" => KEYBOARD,
+            "mouse" => MOUSE,
+            "other" => OTHER,
+            "draw" => DRAW,
+            "keypress" => KEY_PRESS,
+            "keyrelease" => KEY_RELEASE,
+            "trigger" => TRIGGER,
+            _ => return None,
+        };
+        Some(event_type)
+    }
+
+    /// Describe an instruction by its script or event and the source it came from.
+    fn describe(&self, function: Function, instruction: usize) -> String {
+        let name = FunctionDisplay::from_debug(&self.debug, function);
+        let locations = &self.debug.locations[&function];
+        let location = locations.locations.get_location(instruction as u32) as usize;
+        let position = locations.lines.get_position(location);
+        let source = self.sources.get(&function).map_or(&[][..], |source| &source[..]);
+        let text = locations.lines.get_text(source, location);
+        format!("{}, {}: {}", name, position, String::from_utf8_lossy(text))
+    }
+
+    fn print(&self, pause: &mut vm::Pause<'_>, variable: &str) {
+        let (entity, name) = match variable.find('.') {
+            Some(dot) => match Self::scope(pause, &variable[..dot]) {
+                Some(entity) => (entity, &variable[dot + 1..]),
+                None => return println!("unknown scope `{}`", &variable[..dot]),
+            },
+            None => {
+                let global = pause.world().globals.contains(&Symbol::intern(variable.as_bytes()));
+                let entity = if global { vm::world::GLOBAL } else { pause.self_entity() };
+                (entity, variable)
+            }
+        };
+        match pause.member(entity, Symbol::intern(name.as_bytes())) {
+            Some(value) => println!("{} = {:?}", variable, value),
+            None => println!("unknown variable `{}`", variable),
+        }
+    }
+
+    fn vars(&self, pause: &mut vm::Pause<'_>, scope: &str) {
+        let entity = match Self::scope(pause, scope) {
+            Some(entity) => entity,
+            None => return println!("unknown scope `{}`", scope),
+        };
+        let members = match pause.world().members.get(entity) {
+            Some(members) => members,
+            None => return println!("`{}` does not exist", scope),
+        };
+        let mut members: Vec<_> = members.iter().collect();
+        members.sort_by_key(|&(&name, _)| name);
+        for (name, value) in members {
+            println!("  {} = {:?}", name, value);
+        }
+    }
+
+    fn scope(pause: &vm::Pause<'_>, scope: &str) -> Option<vm::Entity> {
+        match scope {
+            "self" => Some(pause.self_entity()),
+            "other" => Some(pause.other_entity()),
+            "global" => Some(vm::world::GLOBAL),
+            _ => None,
+        }
+    }
+
+    fn breakpoints(&self, breakpoints: &vm::Breakpoints) {
+        let mut list: Vec<_> = breakpoints.iter().collect();
+        list.sort();
+        list.dedup();
+        for (function, action, line) in list {
+            let name = FunctionDisplay::from_debug(&self.debug, function);
+            match (action, line) {
+                (Some(action), Some(line)) => {
+                    println!("  {}, action {}, line {}", name, action, line)
+                }
+                (None, Some(line)) => println!("  {}, line {}", name, line),
+                (_, None) => println!("  {}", name),
+            }
+        }
+    }
+}
+
+impl vm::Debugger for Console {
+    fn pause(&mut self, pause: &mut vm::Pause<'_>) -> vm::Resume {
+        let (function, instruction) = pause.location();
+        println!("paused in {}", self.describe(function, instruction));
+
+        let stdin = io::stdin();
+        loop {
+            print!("(debug) ");
+            let _ = io::stdout().flush();
+            let mut line = String::new();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) | Err(_) => {
+                    // Without any more input, let the game run to completion.
+                    pause.breakpoints.clear();
+                    return vm::Resume::Continue;
+                }
+                Ok(_) => {}
+            }
+
+            let mut words = line.split_whitespace();
+            let command = words.next().unwrap_or("");
+            let argument = words.next();
+            match (command, argument) {
+                ("", _) => {}
+                ("c", _) | ("continue", _) => return vm::Resume::Continue,
+                ("s", _) | ("step", _) => return vm::Resume::StepIn,
+                ("n", _) | ("next", _) => return vm::Resume::StepOver,
+                ("o", _) | ("out", _) => return vm::Resume::StepOut,
+                ("bt", _) | ("backtrace", _) => {
+                    for &(function, instruction) in pause.frames() {
+                        println!("  {}", self.describe(function, instruction));
+                    }
+                }
+                ("r", _) | ("registers", _) => {
+                    for (i, slot) in pause.registers().iter().enumerate() {
+                        match *slot {
+                            vm::Slot::Value(ref value) => println!("  %{} = {:?}", i, value),
+                            vm::Slot::Raw(bits) => println!("  %{} = <{:#018x}>", i, bits),
+                        }
+                    }
+                }
+                ("p", Some(variable)) | ("print", Some(variable)) => self.print(pause, variable),
+                ("vars", scope) => self.vars(pause, scope.unwrap_or("self")),
+                ("b", None) | ("break", None) => self.breakpoints(pause.breakpoints),
+                ("b", Some(location)) | ("break", Some(location)) => {
+                    let (function, line) = match self.parse_location(location) {
+                        Ok(location) => location,
+                        Err(message) => { println!("{}", message); continue; }
+                    };
+                    if !pause.breakpoints.insert(function, None, line) {
+                        println!("no code at `{}`", location);
+                    }
+                }
+                ("d", Some(location)) | ("delete", Some(location)) => {
+                    let (function, line) = match self.parse_location(location) {
+                        Ok(location) => location,
+                        Err(message) => { println!("{}", message); continue; }
+                    };
+                    if !pause.breakpoints.remove(function, None, line) {
+                        println!("no breakpoint at `{}`", location);
+                    }
+                }
+                ("q", _) | ("quit", _) => process::exit(0),
+                ("h", _) | ("help", _) => println!("{}", HELP),
+                _ => println!("unknown command `{}`; type `help` for a list", line.trim()),
+            }
+        }
+    }
+}
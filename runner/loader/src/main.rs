@@ -1,140 +1,478 @@
-use std::io;
+use std::ffi::OsString;
+use std::path::Path;
+use std::io::Write;
+use std::{env, fs, io, process};
+
+use gml::{symbol::Symbol, vm, vm::code::Disassembly, FunctionDisplay};
+
+mod debugger;
+
+const USAGE: &str = "\
+usage: loader <command> <game> [<options>]
+       loader fmt <file> [--check] [--gms]
+
+commands:
+    run      run a game
+    render   run a game without a window and save its last frame as a PNG
+    debug    run a game, pausing at breakpoints to step through and inspect its code
+    check    compile a game and report any errors and warnings
+    dump     compile a game and print its bytecode alongside its source
+    compile  compile a game and save its bytecode, to run later without compiling
+    fmt      format a file of GML scripts in place
+
+run options:
+    --record <path>    record the run's inputs and events to a replay file
+    --replay <path>    play back a replay file, checking that the run matches it
+    --code <path>      run bytecode saved by `compile` instead of compiling the game
+
+render options:
+    --frames <count>   number of frames to run (default 1)
+    --output <path>    where to save the frame (default frame.png)
+    --code <path>      run bytecode saved by `compile` instead of compiling the game
+
+debug options:
+    --break <loc>      pause at `<script>:<line>` or `<object>:<event>[:<kind>]:<line>`
+                       (may be repeated; without any, pauses at the first line to run)
+    --code <path>      run bytecode saved by `compile` instead of compiling the game
+
+compile options:
+    --output <path>    where to save the bytecode (default code.bin)
+
+fmt options:
+    --check            report whether the file is formatted, without changing it
+    --gms              parse the file as Game Maker: Studio GML rather than GM8 GML
+
+options for commands that compile a game:
+    --no-optimize      generate bytecode without simplifying it first
+    --gms              compile code as Game Maker: Studio GML rather than GM8 GML
+
+<game> may be a project file (.gmk, .gm81) or an executable (.exe). <file> may hold one script,
+or several that each start with a `#define <name>` line.";
 
 fn main() {
-    let mut game = project::Game::default();
-
-    let main = game.scripts.len() as i32;
-    let main = format!("{}", main);
-    game.scripts.push(project::Script { name: b"main", body: br#"{
-        show_debug_message("hello world")
-
-        var list;
-        list = ds_list_create()
-        ds_list_add(list, 3, "foo")
-        ds_list_add(list, 5)
-        show_debug_message("list[| 0] =", ds_list_find_value(list, 0))
-        show_debug_message("list[| 1] =", ds_list_find_value(list, 1))
-        show_debug_message("list[| 2] =", ds_list_find_value(list, 2))
-        ds_list_destroy(list)
-
-        var map, key1, key2;
-        map = ds_map_create()
-        ds_map_add(map, 3, "foo")
-        ds_map_add(map, "abc", "bar")
-        key1 = ds_map_find_first(map)
-        key2 = ds_map_find_next(map, key1)
-        show_debug_message("map[?", key1, "] =", ds_map_find_value(map, key1))
-        show_debug_message("map[?", key2, "] =", ds_map_find_value(map, key2))
-        ds_map_destroy(map)
-
-        var grid;
-        grid = ds_grid_create(2, 2)
-        ds_grid_set(grid, 0, 0, 1)
-        ds_grid_set(grid, 1, 0, 2)
-        ds_grid_set(grid, 0, 1, 3)
-        ds_grid_set(grid, 1, 1, 4)
-        show_debug_message("grid[# 0, 0] =", ds_grid_get(grid, 0, 0))
-        show_debug_message("grid[# 1, 0] =", ds_grid_get(grid, 1, 0))
-        show_debug_message("grid[# 0, 1] =", ds_grid_get(grid, 0, 1))
-        show_debug_message("grid[# 1, 1] =", ds_grid_get(grid, 1, 1))
-        ds_grid_destroy(grid)
-
-        repeat (2) {
-            show_debug_message()
-            show_debug_message("object_index =", object_index)
-            show_debug_message("id =", id)
-            persistent = true
-            show_debug_message("persistent =", persistent)
-
-            for (i = 0; i < instance_count; i += 1) {
-                if instance_exists(instance_id[i]) {
-                    show_debug_message(instance_id[i], "=>", instance_id[i].object_index)
-                }
+    let args: Vec<_> = env::args_os().skip(1).collect();
+    let (command, path, options) = match &args[..] {
+        [command, path, options @ ..] => (command.to_string_lossy(), Path::new(path), options),
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if command == "fmt" {
+        exit(fmt(path, options));
+    }
+    let command: fn(&project::Game, gml::Options, &[OsString]) -> Result<i32, String> =
+        match &command[..] {
+            "run" => run,
+            "render" => render,
+            "debug" => debug,
+            "check" => check,
+            "dump" => dump,
+            "compile" => compile,
+            _ => {
+                eprintln!("error: unknown command `{}`\n\n{}", command, USAGE);
+                process::exit(2);
             }
+        };
 
-            show_debug_message("instance_find(1, 0) =>", instance_find(1, 0))
-            show_debug_message("instance_exists(0) =>", instance_exists(0))
-            show_debug_message("instance_exists(id) =>", instance_exists(id))
-            show_debug_message("instance_exists(100002) =>", instance_exists(100002))
-            show_debug_message("instance_exists(2) =>", instance_exists(2))
-            show_debug_message("instance_number(1) =>", instance_number(1))
-
-            instance_destroy()
-        }
-    }"# });
-
-    let first_obj = game.objects.len() as i32;
-    game.objects.push(project::Object {
-        name: b"first_obj",
-        persistent: false,
-        events: vec![
-            project::Event {
-                event_type: project::event_type::CREATE,
-                event_kind: 0,
-                actions: vec![
-                    project::Action {
-                        library: 1,
-                        action: 601,
-                        action_kind: project::action_kind::NORMAL,
-                        has_target: true,
-                        action_type: project::action_type::FUNCTION,
-                        name: b"action_execute_script",
-                        parameters_used: 6,
-                        parameters: vec![
-                            project::argument_type::SCRIPT,
-                            project::argument_type::EXPR,
-                            project::argument_type::EXPR,
-                            project::argument_type::EXPR,
-                            project::argument_type::EXPR,
-                            project::argument_type::EXPR,
-                        ],
-                        target: gml::vm::SELF,
-                        arguments: vec![
-                            main.as_bytes(),
-                            b"0", b"0", b"0", b"0", b"0",
-                        ],
-                        ..project::Action::default()
-                    },
-                ],
-            },
-        ],
-    });
+    // Compiler options may appear anywhere among a command's own options.
+    let mut compiler = gml::Options::default();
+    let mut command_options = Vec::with_capacity(options.len());
+    for option in options {
+        match &option.to_string_lossy()[..] {
+            "--no-optimize" => compiler.optimize = false,
+            "--gms" => compiler.dialect = gml::Dialect::Gms,
+            _ => command_options.push(option.clone()),
+        }
+    }
 
-    let second_obj = game.objects.len() as i32;
-    game.objects.push(project::Object {
-        name: b"second_obj",
-        persistent: false,
-        events: vec![],
-    });
+    let data = match fs::read(path) {
+        Ok(data) => data,
+        Err(error) => {
+            eprintln!("error: could not read {}: {}", path.display(), error);
+            process::exit(1);
+        }
+    };
+    let arena = project::Arena::new();
+    // Executables start with a DOS header, whatever their extension.
+    let game = match data.starts_with(b"MZ") {
+        true => project::read_exe(data, &arena),
+        false => project::read_gmk(&data, &arena),
+    };
+    let game = match game {
+        Ok(game) => game,
+        Err(error) => {
+            eprintln!("error: could not load {}: {}", path.display(), error);
+            process::exit(1);
+        }
+    };
 
-    let _first_rm = game.rooms.len() as i32;
+    exit(command(&game, compiler, &command_options));
+}
 
-    game.last_instance += 1;
-    let first_id = game.last_instance;
+fn exit(result: Result<i32, String>) -> ! {
+    match result {
+        Ok(code) => process::exit(code),
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            process::exit(2);
+        }
+    }
+}
 
-    game.last_instance += 1;
-    let second_id = game.last_instance;
+fn no_options(options: &[OsString]) -> Result<(), String> {
+    match options.first() {
+        Some(option) => Err(format!("unexpected option `{}`", option.to_string_lossy())),
+        None => Ok(()),
+    }
+}
 
-    game.rooms.push(project::Room {
-        name: b"first_rm",
-        code: b"",
-        instances: vec![
-            project::Instance { x: 0, y: 0, object_index: first_obj, id: first_id, code: b"" },
-            project::Instance { x: 0, y: 0, object_index: second_obj, id: second_id, code: b"" },
-        ],
-    });
+fn build(game: &project::Game, compiler: gml::Options) ->
+    Result<(runner::Assets, vm::Debug), i32>
+{
+    runner::build_with_options(game, compiler, io::stderr).map_err(abort)
+}
+
+fn abort(errors: u32) -> i32 {
+    if errors > 1 {
+        eprintln!("aborting due to {} previous errors", errors);
+    } else {
+        eprintln!("aborting due to previous error");
+    }
+    1
+}
+
+/// Load bytecode saved by `compile`, or compile the game if there is none.
+fn load(game: &project::Game, compiler: gml::Options, code: Option<OsString>) ->
+    Result<(runner::Assets, vm::Debug), i32>
+{
+    let code = match code {
+        Some(code) => code,
+        None => return build(game, compiler),
+    };
+    let code = Path::new(&code);
+    fs::File::open(code)
+        .and_then(|file| runner::load_build(game, io::BufReader::new(file)))
+        .map_err(|error| {
+            eprintln!("error: could not load {}: {}", code.display(), error);
+            1
+        })
+}
+
+fn run(game: &project::Game, compiler: gml::Options, options: &[OsString]) ->
+    Result<i32, String>
+{
+    let mut record = None;
+    let mut replay = None;
+    let mut code = None;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let option = option.to_string_lossy();
+        let value = options.next().ok_or_else(|| format!("missing value for `{}`", option))?;
+        match &option[..] {
+            "--record" => record = Some(value.clone()),
+            "--replay" => replay = Some(value.clone()),
+            "--code" => code = Some(value.clone()),
+            _ => return Err(format!("unexpected option `{}`", option)),
+        }
+    }
+    if record.is_some() && replay.is_some() {
+        return Err(String::from("cannot both record and play back a replay"));
+    }
 
-    let (assets, debug) = match runner::build(&game, io::stderr) {
+    let (assets, debug) = match load(game, compiler, code) {
         Ok(assets) => assets,
-        Err(errors) => {
-            if errors > 1 {
-                eprintln!("aborting due to {} previous errors", errors);
-            } else {
-                eprintln!("aborting due to previous error");
+        Err(code) => return Ok(code),
+    };
+    let world = runner::World::from_assets(&assets, debug);
+    let mut cx = runner::Context { world, assets };
+
+    if let Some(input) = replay {
+        let input = Path::new(&input);
+        let replay = match fs::File::open(input)
+            .and_then(|file| runner::replay::Replay::read(io::BufReader::new(file)))
+        {
+            Ok(replay) => replay,
+            Err(error) => {
+                eprintln!("error: could not read {}: {}", input.display(), error);
+                return Ok(1);
+            }
+        };
+        let frames = replay.frames();
+        cx.world.replay = runner::replay::State::play(replay);
+        if let Err(error) = runner::run_frames(&mut cx, frames) {
+            cx.world.show.show_vm_error(&error);
+            return Ok(1);
+        }
+        if let Some(entry) = cx.world.replay.remaining().first() {
+            eprintln!("error: replay diverged: expected {:?}, found the end", entry);
+            return Ok(1);
+        }
+        return Ok(0);
+    }
+
+    if record.is_some() {
+        cx.world.replay = runner::replay::State::record();
+    }
+    let result = runner::run(&mut cx);
+    if let Err(ref error) = result {
+        cx.world.show.show_vm_error(error);
+    }
+
+    // Keep the recording of a failed run, as that is when it is most useful.
+    if let Some(output) = record {
+        let output = Path::new(&output);
+        let replay = cx.world.replay.recording().expect("recording was started");
+        let result = fs::File::create(output).and_then(|file| {
+            let mut write = io::BufWriter::new(file);
+            replay.write(&mut write)?;
+            write.flush()
+        });
+        if let Err(error) = result {
+            eprintln!("error: could not write {}: {}", output.display(), error);
+            return Ok(1);
+        }
+    }
+    Ok(if result.is_ok() { 0 } else { 1 })
+}
+
+fn render(game: &project::Game, compiler: gml::Options, options: &[OsString]) ->
+    Result<i32, String>
+{
+    let mut frames = 1;
+    let mut output = OsString::from("frame.png");
+    let mut code = None;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let option = option.to_string_lossy();
+        let value = options.next().ok_or_else(|| format!("missing value for `{}`", option))?;
+        match &option[..] {
+            "--frames" => {
+                let value = value.to_string_lossy();
+                frames = value.parse().map_err(|_| format!("invalid frame count `{}`", value))?;
             }
-            return;
+            "--output" => output = value.clone(),
+            "--code" => code = Some(value.clone()),
+            _ => return Err(format!("unexpected option `{}`", option)),
         }
+    }
+
+    let (assets, debug) = match load(game, compiler, code) {
+        Ok(assets) => assets,
+        Err(code) => return Ok(code),
+    };
+    let world = runner::World::from_assets(&assets, debug);
+    let mut cx = runner::Context { world, assets };
+    if let Err(error) = runner::run_frames(&mut cx, frames) {
+        cx.world.show.show_vm_error(&error);
+        return Ok(1);
+    }
+
+    let output = Path::new(&output);
+    let result = fs::File::create(output)
+        .and_then(|file| cx.world.draw.canvas.write_png(io::BufWriter::new(file)));
+    if let Err(error) = result {
+        eprintln!("error: could not write {}: {}", output.display(), error);
+        return Ok(1);
+    }
+    Ok(0)
+}
+
+fn debug(game: &project::Game, mut compiler: gml::Options, options: &[OsString]) ->
+    Result<i32, String>
+{
+    // The optimizer folds away variables, which would leave nothing to inspect.
+    compiler.optimize = false;
+
+    let mut breaks = Vec::default();
+    let mut code = None;
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let option = option.to_string_lossy();
+        let value = options.next().ok_or_else(|| format!("missing value for `{}`", option))?;
+        match &option[..] {
+            "--break" => breaks.push(value.to_string_lossy().into_owned()),
+            "--code" => code = Some(value.clone()),
+            _ => return Err(format!("unexpected option `{}`", option)),
+        }
+    }
+
+    let (assets, debug) = match load(game, compiler, code) {
+        Ok(assets) => assets,
+        Err(code) => return Ok(code),
     };
+    let console = debugger::Console::new(game, debug.clone());
+    let locations = breaks.iter()
+        .map(|location| console.parse_location(location))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let resume = if breaks.is_empty() { vm::Resume::StepIn } else { vm::Resume::Continue };
+    let mut session = vm::Session::new(&debug, Box::new(console), resume);
+    for (location, &(function, line)) in breaks.iter().zip(&locations) {
+        if !session.breakpoints().insert(function, None, line) {
+            return Err(format!("no code at `{}`", location));
+        }
+    }
+
     let world = runner::World::from_assets(&assets, debug);
-    runner::run(&mut runner::Context { world, assets });
+    let mut cx = runner::Context { world, assets };
+    let mut thread = vm::Thread::default();
+    thread.attach(session);
+    println!("type `help` for a list of commands");
+    if let Err(error) = runner::run_thread(&mut cx, &mut thread) {
+        cx.world.show.show_vm_error(&error);
+        return Ok(1);
+    }
+    Ok(0)
+}
+
+fn check(game: &project::Game, compiler: gml::Options, options: &[OsString]) ->
+    Result<i32, String>
+{
+    no_options(options)?;
+    let compiler = gml::Options { lint: true, ..compiler };
+    match build(game, compiler) {
+        Ok(_) => Ok(0),
+        Err(code) => Ok(code),
+    }
+}
+
+fn dump(game: &project::Game, compiler: gml::Options, options: &[OsString]) ->
+    Result<i32, String>
+{
+    no_options(options)?;
+    let (assets, debug) = match build(game, compiler) {
+        Ok(assets) => assets,
+        Err(code) => return Ok(code),
+    };
+
+    let mut functions: Vec<_> = assets.code.code.iter().collect();
+    functions.sort_by_key(|&(&function, _)| function);
+    for (&function, code) in functions {
+        let locations = &debug.locations[&function];
+        let source = gml::source(game, function).unwrap_or_default();
+        println!("{}:", FunctionDisplay::from_debug(&debug, function));
+        println!("{}", Disassembly { function: code, locations, source: &source });
+    }
+    Ok(0)
+}
+
+fn compile(game: &project::Game, compiler: gml::Options, options: &[OsString]) ->
+    Result<i32, String>
+{
+    let mut output = OsString::from("code.bin");
+    let mut options = options.iter();
+    while let Some(option) = options.next() {
+        let option = option.to_string_lossy();
+        let value = options.next().ok_or_else(|| format!("missing value for `{}`", option))?;
+        match &option[..] {
+            "--output" => output = value.clone(),
+            _ => return Err(format!("unexpected option `{}`", option)),
+        }
+    }
+
+    let (assets, debug) = match build(game, compiler) {
+        Ok(assets) => assets,
+        Err(code) => return Ok(code),
+    };
+
+    let output = Path::new(&output);
+    let result = fs::File::create(output).and_then(|file| {
+        let mut write = io::BufWriter::new(file);
+        gml::write_build(&assets.code, &debug, &mut write)?;
+        write.flush()
+    });
+    if let Err(error) = result {
+        eprintln!("error: could not write {}: {}", output.display(), error);
+        return Ok(1);
+    }
+    Ok(0)
+}
+
+fn fmt(path: &Path, options: &[OsString]) -> Result<i32, String> {
+    let mut check = false;
+    let mut dialect = gml::Dialect::Gm8;
+    for option in options {
+        match &option.to_string_lossy()[..] {
+            "--check" => check = true,
+            "--gms" => dialect = gml::Dialect::Gms,
+            option => return Err(format!("unexpected option `{}`", option)),
+        }
+    }
+
+    let source = match fs::read(path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("error: could not read {}: {}", path.display(), error);
+            return Ok(1);
+        }
+    };
+
+    // Format each script on its own, keeping the `#define` lines between them.
+    let mut output = Vec::with_capacity(source.len());
+    let mut errors = 0;
+    for (define, script) in scripts(&source) {
+        let name = match define {
+            Some(name) => {
+                output.extend_from_slice(b"#define ");
+                output.extend_from_slice(name);
+                output.push(b'\n');
+                Symbol::intern(name)
+            }
+            None => {
+                let name = path.file_stem().unwrap_or_default().to_string_lossy();
+                Symbol::intern(name.as_bytes())
+            }
+        };
+        match gml::format(name, script, dialect, io::stderr()) {
+            Ok(script) => output.extend_from_slice(&script),
+            Err(count) => errors += count,
+        }
+    }
+    if errors > 0 {
+        return Ok(abort(errors));
+    }
+
+    if output == source {
+        return Ok(0);
+    }
+    if check {
+        eprintln!("{} is not formatted", path.display());
+        return Ok(1);
+    }
+    if let Err(error) = fs::write(path, &output) {
+        eprintln!("error: could not write {}: {}", path.display(), error);
+        return Ok(1);
+    }
+    Ok(0)
+}
+
+/// Split a file into scripts, each named by the `#define` line before it.
+///
+/// Any code before the first `#define` line is a script without a name.
+fn scripts(source: &[u8]) -> Vec<(Option<&[u8]>, &[u8])> {
+    let mut scripts = vec![];
+    let mut define = None;
+    let mut start = 0;
+    let mut line = 0;
+    while line < source.len() {
+        let end = source[line..].iter().position(|&b| b == b'\n')
+            .map_or(source.len(), |newline| line + newline + 1);
+        if source[line..end].starts_with(b"#define") {
+            let name = &source[line + b"#define".len()..end];
+            if define.is_some() || !source[start..line].iter().all(u8::is_ascii_whitespace) {
+                scripts.push((define, &source[start..line]));
+            }
+            let name = name.iter().position(|b| !b.is_ascii_whitespace())
+                .map_or(&[][..], |low| &name[low..]);
+            let high = name.iter().position(u8::is_ascii_whitespace).unwrap_or(name.len());
+            define = Some(&name[..high]);
+            start = end;
+        }
+        line = end;
+    }
+    if define.is_some() || !source[start..].iter().all(u8::is_ascii_whitespace) {
+        scripts.push((define, &source[start..]));
+    }
+    scripts
 }
@@ -0,0 +1,655 @@
+//! A Language Server Protocol server, for editing GML outside of Game Maker.
+//!
+//! The workspace is a directory of `.gml` files. A file directly inside `objects/<object>/` holds
+//! one of that object's events. Any other file holds scripts: one for each `#define <name>` line,
+//! as Game Maker exports them, or a single script named after the file if it has no such lines.
+//!
+//! The code is GM8 GML unless the client's `initializationOptions` set `"dialect": "gms"`.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Write};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::{fs, mem};
+
+use serde_json::{json, Value as Json};
+use gml::symbol::Symbol;
+use gml::front::{self, token::{Token, Delim}, Lexer, Lines, Parser};
+use gml::back::ssa;
+use gml::{ErrorPrinter, FunctionDisplay, Severity};
+
+/// Serve a single client, until it sends `exit` or stops sending messages.
+pub fn serve<R: BufRead, W: Write>(mut input: R, mut output: W) -> io::Result<()> {
+    let mut server = Server::new();
+    while let Some(message) = protocol::read_message(&mut input)? {
+        let method = match message["method"].as_str() {
+            Some(method) => method,
+            // Responses to requests from the server; there are none.
+            None => continue,
+        };
+        let params = &message["params"];
+
+        if let Some(id) = message.get("id") {
+            let response = match server.request(method, params) {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err((code, message)) => json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": code, "message": message },
+                }),
+            };
+            protocol::write_message(&mut output, &response)?;
+            continue;
+        }
+
+        match method {
+            "exit" => break,
+            "initialized" => {}
+            "textDocument/didOpen" => {
+                let document = &params["textDocument"];
+                let uri = document["uri"].as_str().unwrap_or_default();
+                let text = document["text"].as_str().unwrap_or_default();
+                server.update(uri, Some(Vec::from(text)));
+            }
+            "textDocument/didChange" => {
+                // With full synchronization, the last change holds the whole text.
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                let changes = params["contentChanges"].as_array();
+                let text = changes.and_then(|changes| changes.last()?["text"].as_str());
+                server.update(uri, text.map(Vec::from));
+            }
+            "textDocument/didClose" => {
+                let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+                server.close(uri);
+            }
+            _ => continue,
+        }
+
+        for notification in server.diagnostics() {
+            protocol::write_message(&mut output, &notification)?;
+        }
+    }
+    Ok(())
+}
+
+/// The JSON-RPC error code for requests the server does not support.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// LSP `CompletionItemKind`s.
+const FUNCTION: u64 = 3;
+const CLASS: u64 = 7;
+const PROPERTY: u64 = 10;
+
+struct Server {
+    items: HashMap<Symbol, gml::Item<runner::Context>>,
+    documents: BTreeMap<String, Document>,
+    /// The documents whose diagnostics have been published, to clear them once they are removed.
+    published: BTreeSet<String>,
+    /// The documents whose diagnostics must be checked again.
+    stale: BTreeSet<String>,
+
+    /// Each script in the workspace, indexed by its id in `prototypes`.
+    scripts: Vec<(Symbol, Location)>,
+    objects: HashMap<Symbol, Location>,
+    prototypes: HashMap<Symbol, ssa::Prototype>,
+    /// Object indices depend on the project file, so only scripts are known here.
+    resources: front::Resources,
+
+    dialect: gml::Dialect,
+    /// The macros and enums defined by all documents, in GMS.
+    definitions: front::Definitions,
+}
+
+struct Document {
+    text: Vec<u8>,
+    contents: Contents,
+}
+
+enum Contents {
+    Event { object: Symbol, file: String },
+    Scripts(Vec<Script>),
+}
+
+/// A script within a document.
+struct Script {
+    name: Symbol,
+    /// The offset of the script's name, or of the start of its document.
+    definition: usize,
+    start: usize,
+    end: usize,
+}
+
+#[derive(Clone)]
+struct Location {
+    uri: String,
+    offset: usize,
+}
+
+impl Server {
+    fn new() -> Server {
+        let mut items = HashMap::default();
+        runner::World::register(&mut items);
+        Server {
+            items,
+            documents: BTreeMap::default(),
+            published: BTreeSet::default(),
+            stale: BTreeSet::default(),
+            scripts: Vec::default(),
+            objects: HashMap::default(),
+            prototypes: HashMap::default(),
+            resources: front::Resources::default(),
+            dialect: gml::Dialect::default(),
+            definitions: front::Definitions::default(),
+        }
+    }
+
+    fn request(&mut self, method: &str, params: &Json) -> Result<Json, (i64, String)> {
+        match method {
+            "initialize" => {
+                if params["initializationOptions"]["dialect"] == json!("gms") {
+                    self.dialect = gml::Dialect::Gms;
+                }
+                let root = params["rootUri"].as_str().and_then(path_from_uri)
+                    .or_else(|| params["rootPath"].as_str().map(PathBuf::from));
+                if let Some(root) = root {
+                    self.scan(&root);
+                }
+                self.index();
+                self.stale = self.documents.keys().cloned().collect();
+                Ok(json!({
+                    "capabilities": {
+                        "textDocumentSync": 1,
+                        "definitionProvider": true,
+                        "completionProvider": {},
+                        "signatureHelpProvider": { "triggerCharacters": ["(", ","] },
+                    },
+                    "serverInfo": { "name": "dejavu" },
+                }))
+            }
+            "shutdown" => Ok(Json::Null),
+            "textDocument/definition" => Ok(self.definition(params)),
+            "textDocument/completion" => Ok(self.completion()),
+            "textDocument/signatureHelp" => Ok(self.signature_help(params)),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method `{}`", method))),
+        }
+    }
+
+    /// Add each `.gml` file under `dir` to the workspace.
+    fn scan(&mut self, dir: &Path) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+        for entry in entries.filter_map(Result::ok) {
+            // Do not follow links to directories, which may lead back up the tree.
+            let file_type = match entry.file_type() {
+                Ok(file_type) => file_type,
+                Err(_) => continue,
+            };
+            let path = entry.path();
+            if file_type.is_dir() {
+                self.scan(&path);
+            } else if path.extension() == Some(OsStr::new("gml")) {
+                if let Ok(text) = fs::read(&path) {
+                    let uri = uri_from_path(&path);
+                    let document = Document::new(&uri, text);
+                    self.documents.insert(uri, document);
+                }
+            }
+        }
+    }
+
+    /// Replace the text of an open document.
+    fn update(&mut self, uri: &str, text: Option<Vec<u8>>) {
+        let text = match text {
+            Some(text) => text,
+            None => return,
+        };
+        self.documents.insert(String::from(uri), Document::new(uri, text));
+        self.stale.insert(String::from(uri));
+        self.index();
+    }
+
+    /// Return a closed document to the file system's version, if it has one.
+    fn close(&mut self, uri: &str) {
+        match path_from_uri(uri).and_then(|path| fs::read(path).ok()) {
+            Some(text) => { self.documents.insert(String::from(uri), Document::new(uri, text)); }
+            None => { self.documents.remove(uri); }
+        }
+        self.stale.insert(String::from(uri));
+        self.index();
+    }
+
+    /// Collect the scripts and objects defined by all documents.
+    ///
+    /// When this changes the scripts or definitions that code is checked against, every document
+    /// becomes stale.
+    fn index(&mut self) {
+        let old_scripts = mem::take(&mut self.scripts);
+        let old_arguments = mem::take(&mut self.resources.arguments);
+
+        self.objects.clear();
+        for (uri, document) in self.documents.iter() {
+            match document.contents {
+                Contents::Event { object, ref file } => {
+                    let location = Location { uri: uri.clone(), offset: 0 };
+                    if file == "Create_0" {
+                        self.objects.insert(object, location);
+                    } else {
+                        self.objects.entry(object).or_insert(location);
+                    }
+                }
+                Contents::Scripts(ref scripts) => {
+                    for script in scripts {
+                        let location = Location { uri: uri.clone(), offset: script.definition };
+                        self.scripts.push((script.name, location));
+                        let source = &document.text[script.start..script.end];
+                        self.resources.arguments.push(front::arguments(source));
+                    }
+                }
+            }
+        }
+
+        let scripts: Vec<_> = self.scripts.iter().map(|&(name, _)| name).collect();
+        self.prototypes = gml::prototypes(&self.items, &scripts);
+
+        // Macros and enums are global, so collect them before any document is compiled.
+        let mut definitions = front::Definitions::default();
+        if self.dialect == gml::Dialect::Gms {
+            for document in self.documents.values() {
+                let text = &document.text[..];
+                let lines = Lines::from_code(text);
+                let mut define = |name, start, end| {
+                    let name = FunctionDisplay::Script { script: name };
+                    let mut errors = ErrorPrinter::new(name, &lines, io::sink());
+                    let reader = Lexer::with_dialect(&text[start..end], start, self.dialect);
+                    let defined = mem::take(&mut definitions);
+                    let mut parser = Parser::with_definitions(reader, defined, &mut errors);
+                    parser.parse_program();
+                    definitions = parser.definitions();
+                };
+                match document.contents {
+                    Contents::Event { object, .. } => define(object, 0, text.len()),
+                    Contents::Scripts(ref scripts) => for script in scripts {
+                        define(script.name, script.start, script.end);
+                    }
+                }
+            }
+        }
+        let changed = !old_scripts.iter().map(|&(name, _)| name).eq(scripts) ||
+            self.resources.arguments != old_arguments ||
+            self.definitions != definitions;
+        if changed {
+            self.stale.extend(self.documents.keys().cloned());
+        }
+        self.definitions = definitions;
+    }
+
+    /// Build `textDocument/publishDiagnostics` notifications for every stale document.
+    fn diagnostics(&mut self) -> Vec<Json> {
+        let mut notifications = Vec::default();
+        for uri in mem::take(&mut self.stale) {
+            let document = match self.documents.get(&uri) {
+                Some(document) => document,
+                None => {
+                    if self.published.remove(&uri) {
+                        notifications.push(publish(&uri, Vec::default()));
+                    }
+                    continue;
+                }
+            };
+            let text = &document.text[..];
+            let lines = Lines::from_code(text);
+            let mut diagnostics = Vec::default();
+            let mut compile = |name, start, end| {
+                let name = FunctionDisplay::Script { script: name };
+                let mut errors = ErrorPrinter::new(name, &lines, io::sink());
+                let reader = Lexer::with_dialect(&text[start..end], start, self.dialect);
+                let definitions = self.definitions.clone();
+                let program = Parser::with_definitions(reader, definitions, &mut errors)
+                    .parse_program();
+                let dialect = self.dialect;
+                let function = front::Codegen::with_dialect(&self.prototypes, dialect, &mut errors)
+                    .compile_program(&program);
+                if errors.count == 0 {
+                    front::Linter::new(&self.prototypes, &self.resources, &mut errors)
+                        .lint_program(&program, &function);
+                }
+                for (severity, span, message) in errors.diagnostics {
+                    let severity = match severity {
+                        Severity::Error => 1,
+                        Severity::Warning => 2,
+                    };
+                    diagnostics.push(json!({
+                        "range": range(text, span.low, span.high),
+                        "severity": severity,
+                        "source": "gml",
+                        "message": message,
+                    }));
+                }
+            };
+            match document.contents {
+                Contents::Event { object, .. } => compile(object, 0, text.len()),
+                Contents::Scripts(ref scripts) => for script in scripts {
+                    compile(script.name, script.start, script.end);
+                }
+            }
+
+            notifications.push(publish(&uri, diagnostics));
+            self.published.insert(uri);
+        }
+
+        notifications
+    }
+
+    /// Find the script or object named by the identifier at a position.
+    fn definition(&self, params: &Json) -> Json {
+        let (text, offset) = match self.position(params) {
+            Some(position) => position,
+            None => return Json::Null,
+        };
+        let name = match identifier(text, offset, self.dialect) {
+            Some(name) => name,
+            None => return Json::Null,
+        };
+        let location = match self.prototypes.get(&name) {
+            Some(&ssa::Prototype::Script { id }) => &self.scripts[id as usize].1,
+            _ => match self.objects.get(&name) {
+                Some(location) => location,
+                None => return Json::Null,
+            },
+        };
+
+        let text = &self.documents[&location.uri].text;
+        let end = location.offset + if location.offset > 0 { name.len() } else { 0 };
+        json!({ "uri": location.uri, "range": range(text, location.offset, end) })
+    }
+
+    /// Offer every function, builtin variable, script, and object.
+    fn completion(&self) -> Json {
+        let mut items = Vec::default();
+        for (&name, item) in self.items.iter() {
+            let (kind, detail) = match *item {
+                gml::Item::Native(_, arity, variadic) => {
+                    (FUNCTION, signature(name, arity, variadic).0)
+                }
+                gml::Item::Member(..) => (PROPERTY, String::from("builtin variable")),
+            };
+            items.push((name, kind, detail));
+        }
+        for &(name, _) in self.scripts.iter() {
+            items.push((name, FUNCTION, String::from("script")));
+        }
+        for &name in self.objects.keys() {
+            items.push((name, CLASS, String::from("object")));
+        }
+        items.sort_by_key(|&(name, _, _)| name);
+        items.dedup_by_key(|&mut (name, _, _)| name);
+
+        let items: Vec<_> = items.into_iter()
+            .map(|(name, kind, detail)| json!({
+                "label": name.to_string(),
+                "kind": kind,
+                "detail": detail,
+            }))
+            .collect();
+        json!({ "isIncomplete": false, "items": items })
+    }
+
+    /// Describe the arguments of the function call around a position.
+    fn signature_help(&self, params: &Json) -> Json {
+        let (text, offset) = match self.position(params) {
+            Some(position) => position,
+            None => return Json::Null,
+        };
+        let (name, argument) = match call(text, offset, self.dialect) {
+            Some(call) => call,
+            None => return Json::Null,
+        };
+        let (arity, variadic) = match self.prototypes.get(&name) {
+            Some(&ssa::Prototype::Native { arity, variadic }) => (arity, variadic),
+            _ => return Json::Null,
+        };
+
+        let (label, parameters) = signature(name, arity, variadic);
+        let active = if variadic { argument.min(arity) } else { argument };
+        let parameters: Vec<_> = parameters.into_iter()
+            .map(|parameter| json!({ "label": parameter }))
+            .collect();
+        json!({
+            "signatures": [{ "label": label, "parameters": parameters }],
+            "activeSignature": 0,
+            "activeParameter": active,
+        })
+    }
+
+    /// Find the text and byte offset of a request's `textDocument` and `position`.
+    fn position(&self, params: &Json) -> Option<(&[u8], usize)> {
+        let uri = params["textDocument"]["uri"].as_str()?;
+        let text = &self.documents.get(uri)?.text[..];
+        Some((text, offset(text, &params["position"])))
+    }
+}
+
+impl Document {
+    fn new(uri: &str, text: Vec<u8>) -> Document {
+        let contents = Contents::new(uri, &text);
+        Document { text, contents }
+    }
+}
+
+impl Contents {
+    fn new(uri: &str, text: &[u8]) -> Contents {
+        let path = path_from_uri(uri).map_or_else(
+            || String::from(uri),
+            |path| path.to_string_lossy().into_owned(),
+        );
+        let mut components = path.rsplit(&['/', '\\'][..]);
+        let file = components.next().unwrap_or_default();
+        let stem = match file.rfind('.') {
+            Some(dot) => &file[..dot],
+            None => file,
+        };
+        if let (Some(object), Some("objects")) = (components.next(), components.next()) {
+            let object = Symbol::intern(object.as_bytes());
+            return Contents::Event { object, file: String::from(stem) };
+        }
+
+        // Find each `#define` line, and the start of the script that follows it.
+        let mut defines = Vec::default();
+        let mut start = 0;
+        while start < text.len() {
+            let end = text[start..].iter().position(|&b| b == b'\n')
+                .map_or(text.len(), |newline| start + newline);
+            let line = &text[start..end];
+            if line.starts_with(b"#define") {
+                let rest = &line[b"#define".len()..];
+                let space = rest.iter().take_while(|b| b.is_ascii_whitespace()).count();
+                let name = rest[space..].iter().take_while(|b| !b.is_ascii_whitespace()).count();
+                let definition = start + b"#define".len() + space;
+                let name = Symbol::intern(&text[definition..definition + name]);
+                defines.push((name, definition, start, usize::min(end + 1, text.len())));
+            }
+            start = end + 1;
+        }
+
+        if defines.is_empty() {
+            let name = Symbol::intern(stem.as_bytes());
+            let script = Script { name, definition: 0, start: 0, end: text.len() };
+            return Contents::Scripts(vec![script]);
+        }
+        let scripts = defines.iter().enumerate()
+            .map(|(i, &(name, definition, _, start))| {
+                let end = defines.get(i + 1).map_or(text.len(), |&(_, _, line, _)| line);
+                Script { name, definition, start, end }
+            })
+            .collect();
+        Contents::Scripts(scripts)
+    }
+}
+
+fn publish(uri: &str, diagnostics: Vec<Json>) -> Json {
+    json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/publishDiagnostics",
+        "params": { "uri": uri, "diagnostics": diagnostics },
+    })
+}
+
+/// The label and parameter labels of a native function's signature.
+fn signature(name: Symbol, arity: usize, variadic: bool) -> (String, Vec<String>) {
+    let mut parameters: Vec<_> = (0..arity).map(|i| format!("argument{}", i)).collect();
+    if variadic {
+        parameters.push(String::from("..."));
+    }
+    (format!("{}({})", name, parameters.join(", ")), parameters)
+}
+
+/// Find the identifier that contains or ends at a byte offset.
+fn identifier(text: &[u8], offset: usize, dialect: gml::Dialect) -> Option<Symbol> {
+    let mut lexer = Lexer::with_dialect(text, 0, dialect);
+    loop {
+        match lexer.read_token() {
+            (Token::Eof, _) => return None,
+            (_, span) if span.low > offset => return None,
+            (Token::Ident(name), span) if offset <= span.high => return Some(name),
+            _ => {}
+        }
+    }
+}
+
+/// Find the innermost call to a named function that is open at a byte offset, and the index of
+/// the argument the offset is in.
+fn call(text: &[u8], offset: usize, dialect: gml::Dialect) -> Option<(Symbol, usize)> {
+    let mut lexer = Lexer::with_dialect(text, 0, dialect);
+    let mut calls: Vec<(Option<Symbol>, usize)> = Vec::default();
+    let mut previous = None;
+    loop {
+        let (token, span) = lexer.read_token();
+        if token == Token::Eof || span.low >= offset {
+            break;
+        }
+        match token {
+            Token::OpenDelim(Delim::Paren) => {
+                let name = match previous {
+                    Some(Token::Ident(name)) => Some(name),
+                    _ => None,
+                };
+                calls.push((name, 0));
+            }
+            Token::CloseDelim(Delim::Paren) => { calls.pop(); }
+            Token::Comma => if let Some((_, argument)) = calls.last_mut() {
+                *argument += 1;
+            }
+            _ => {}
+        }
+        previous = Some(token);
+    }
+    calls.into_iter().rev().find_map(|(name, argument)| Some((name?, argument)))
+}
+
+/// Convert a byte offset to an LSP range, whose characters are counted in UTF-16 code units.
+fn range(text: &[u8], start: usize, end: usize) -> Json {
+    json!({ "start": position(text, start), "end": position(text, end) })
+}
+
+fn position(text: &[u8], offset: usize) -> Json {
+    let offset = usize::min(offset, text.len());
+    let start = text[..offset].iter().rposition(|&b| b == b'\n').map_or(0, |newline| newline + 1);
+    let line = text[..start].iter().filter(|&&b| b == b'\n').count();
+    let mut character = 0;
+    let mut i = start;
+    while i < offset {
+        let (len, units) = char_len(&text[i..offset]);
+        character += units;
+        i += len;
+    }
+    json!({ "line": line, "character": character })
+}
+
+/// Convert an LSP position to a byte offset.
+fn offset(text: &[u8], position: &Json) -> usize {
+    let line = position["line"].as_u64().unwrap_or(0) as usize;
+    let character = position["character"].as_u64().unwrap_or(0) as usize;
+
+    let start = match line {
+        0 => 0,
+        line => match text.iter().enumerate().filter(|&(_, &b)| b == b'\n').nth(line - 1) {
+            Some((newline, _)) => newline + 1,
+            None => return text.len(),
+        },
+    };
+    let end = text[start..].iter().position(|&b| b == b'\n').map_or(text.len(), |end| start + end);
+
+    let mut units = 0;
+    let mut i = start;
+    while i < end && units < character {
+        let (len, char_units) = char_len(&text[i..end]);
+        units += char_units;
+        i += len;
+    }
+    i
+}
+
+/// The length in bytes and in UTF-16 code units of the character that starts `bytes`.
+///
+/// Invalid UTF-8 counts as one character per replacement made by `String::from_utf8_lossy`,
+/// which is also how editors display it.
+fn char_len(bytes: &[u8]) -> (usize, usize) {
+    let bytes = &bytes[..usize::min(bytes.len(), 4)];
+    let len = match std::str::from_utf8(bytes) {
+        Ok(_) => bytes.len(),
+        Err(error) if error.valid_up_to() > 0 => error.valid_up_to(),
+        Err(error) => return (error.error_len().unwrap_or(bytes.len()), 1),
+    };
+    let c = std::str::from_utf8(&bytes[..len]).ok().and_then(|valid| valid.chars().next());
+    c.map_or((1, 1), |c| (c.len_utf8(), c.len_utf16()))
+}
+
+fn uri_from_path(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for &b in path.to_string_lossy().as_bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'.' | b'_' | b'~' => {
+                uri.push(b as char)
+            }
+            _ => { let _ = write!(uri, "%{:02X}", b); }
+        }
+    }
+    uri
+}
+
+fn path_from_uri(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut i = 0;
+    while i < path.len() {
+        if path[i] == b'%' {
+            let hex = std::str::from_utf8(path.get(i + 1..i + 3)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            bytes.push(path[i]);
+            i += 1;
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::{offset, position};
+
+    /// Count characters in UTF-16 code units, over text that is not all valid UTF-8.
+    #[test]
+    fn positions() {
+        let text = b"a\n\xE2\x82b\xF0\x9F\x98\x80c\n";
+        assert_eq!(position(text, 4), json!({ "line": 1, "character": 1 }));
+        assert_eq!(position(text, 9), json!({ "line": 1, "character": 4 }));
+        assert_eq!(offset(text, &json!({ "line": 1, "character": 1 })), 4);
+        assert_eq!(offset(text, &json!({ "line": 1, "character": 4 })), 9);
+        assert_eq!(offset(text, &json!({ "line": 1, "character": 9 })), 10);
+    }
+}
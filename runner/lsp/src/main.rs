@@ -0,0 +1,10 @@
+use std::{io, process};
+
+fn main() {
+    let stdin = io::stdin();
+    let stdout = io::stdout();
+    if let Err(error) = lsp::serve(stdin.lock(), stdout.lock()) {
+        eprintln!("error: {}", error);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::HashMap;
+use std::{env, fs, io, process};
+
+use serde_json::{json, Value as Json};
+
+const SCRIPTS: &str = "file:///game/scripts.gml";
+const CREATE: &str = "file:///game/objects/obj/Create_0.gml";
+
+/// Run a session through diagnostics, definitions, completion, and signature help.
+#[test]
+fn session() {
+    let scripts = "\
+#define double
+return argument0 * 2
+#define caller
+return string_copy('abc', double(2), 1) + missing(1)
+#define triple
+return double(argument0, 1) * 3 / 2
+";
+    let create = "\
+x = double(1)
+instance_create(0, 0, obj)
+y = caller(
+";
+
+    let requests = [
+        json!({ "id": 1, "method": "initialize", "params": { "rootUri": null } }),
+        json!({ "method": "initialized", "params": {} }),
+        json!({ "method": "textDocument/didOpen", "params": {
+            "textDocument": { "uri": SCRIPTS, "languageId": "gml", "version": 1, "text": scripts },
+        } }),
+        json!({ "method": "textDocument/didOpen", "params": {
+            "textDocument": { "uri": CREATE, "languageId": "gml", "version": 1, "text": create },
+        } }),
+        json!({ "id": 2, "method": "textDocument/definition", "params": {
+            "textDocument": { "uri": CREATE },
+            "position": { "line": 0, "character": 6 },
+        } }),
+        json!({ "id": 3, "method": "textDocument/definition", "params": {
+            "textDocument": { "uri": CREATE },
+            "position": { "line": 1, "character": 23 },
+        } }),
+        json!({ "id": 4, "method": "textDocument/completion", "params": {
+            "textDocument": { "uri": CREATE },
+            "position": { "line": 0, "character": 0 },
+        } }),
+        json!({ "id": 5, "method": "textDocument/signatureHelp", "params": {
+            "textDocument": { "uri": SCRIPTS },
+            "position": { "line": 3, "character": 37 },
+        } }),
+        json!({ "id": 6, "method": "textDocument/hover", "params": {} }),
+        json!({ "id": 7, "method": "shutdown" }),
+        json!({ "method": "exit" }),
+    ];
+    let (responses, diagnostics, _) = serve(&requests);
+
+    let capabilities = &responses[&1]["result"]["capabilities"];
+    assert_eq!(capabilities["definitionProvider"], json!(true));
+
+    // Unknown scripts and syntax errors are reported where they occur, along with warnings.
+    let errors = diagnostics[SCRIPTS].as_array().unwrap();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0]["message"], json!("unknown function or script: missing"));
+    assert_eq!(errors[0]["range"]["start"], json!({ "line": 3, "character": 42 }));
+    assert_eq!(errors[0]["severity"], json!(1));
+    let message = "script `double` uses 1 argument, but is called with 2";
+    assert_eq!(errors[1]["message"], json!(message));
+    assert_eq!(errors[1]["range"]["start"], json!({ "line": 5, "character": 7 }));
+    assert_eq!(errors[1]["severity"], json!(2));
+    let errors = diagnostics[CREATE].as_array().unwrap();
+    assert!(!errors.is_empty());
+    assert_eq!(errors[0]["range"]["start"]["line"], json!(3));
+
+    // Scripts are defined by their `#define` line, and objects by their create event.
+    let location = &responses[&2]["result"];
+    assert_eq!(location["uri"], json!(SCRIPTS));
+    assert_eq!(location["range"]["start"], json!({ "line": 0, "character": 8 }));
+    assert_eq!(location["range"]["end"], json!({ "line": 0, "character": 14 }));
+    let location = &responses[&3]["result"];
+    assert_eq!(location["uri"], json!(CREATE));
+
+    let items = responses[&4]["result"]["items"].as_array().unwrap();
+    let item = |label: &str| items.iter()
+        .find(|item| item["label"] == json!(label))
+        .unwrap_or_else(|| panic!("missing completion {}", label));
+    let string_copy = "string_copy(argument0, argument1, argument2)";
+    assert_eq!(item("string_copy")["detail"], json!(string_copy));
+    assert_eq!(item("double")["detail"], json!("script"));
+    assert_eq!(item("obj")["kind"], json!(7));
+    assert_eq!(item("x")["kind"], json!(10));
+
+    // The third argument to `string_copy`, after the nested call to `double`.
+    let help = &responses[&5]["result"];
+    assert_eq!(help["signatures"][0]["label"], json!(string_copy));
+    assert_eq!(help["activeParameter"], json!(2));
+
+    assert_eq!(responses[&6]["error"]["code"], json!(-32601));
+    assert_eq!(responses[&7]["result"], Json::Null);
+}
+
+/// Diagnose GMS code, with macros and enums defined in another document.
+#[test]
+fn gms() {
+    let scripts = "\
+#define setup
+#macro SIZE 4
+enum shape { square = SIZE, circle }
+#define area
+var a = shape.circle * SIZE;
+a++;
+return a <> 0
+";
+    let create = "x = area() + SIZE + shape.square\n";
+
+    let requests = [
+        json!({ "id": 1, "method": "initialize", "params": {
+            "rootUri": null,
+            "initializationOptions": { "dialect": "gms" },
+        } }),
+        json!({ "method": "initialized", "params": {} }),
+        json!({ "method": "textDocument/didOpen", "params": {
+            "textDocument": { "uri": SCRIPTS, "languageId": "gml", "version": 1, "text": scripts },
+        } }),
+        json!({ "method": "textDocument/didOpen", "params": {
+            "textDocument": { "uri": CREATE, "languageId": "gml", "version": 1, "text": create },
+        } }),
+        json!({ "id": 2, "method": "shutdown" }),
+        json!({ "method": "exit" }),
+    ];
+    let (_, diagnostics, _) = serve(&requests);
+
+    // Only the GM8 spelling of `!=` is reported, as a warning.
+    let errors = diagnostics[SCRIPTS].as_array().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0]["range"]["start"], json!({ "line": 6, "character": 9 }));
+    assert_eq!(errors[0]["severity"], json!(2));
+    assert_eq!(diagnostics[CREATE], json!([]));
+}
+
+/// Scan a workspace on disk, without following a link that leads back up the tree.
+#[cfg(unix)]
+#[test]
+fn workspace() {
+    let root = env::temp_dir().join(format!("lsp_workspace_{}", process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    fs::write(root.join("scripts.gml"), "#define broken\nreturn 1 +\n").unwrap();
+    std::os::unix::fs::symlink(&root, root.join("loop")).unwrap();
+
+    let requests = [
+        json!({ "id": 1, "method": "initialize", "params": {
+            "rootUri": null,
+            "rootPath": root.to_str().unwrap(),
+        } }),
+        json!({ "method": "initialized", "params": {} }),
+        json!({ "id": 2, "method": "shutdown" }),
+        json!({ "method": "exit" }),
+    ];
+    let (_, diagnostics, _) = serve(&requests);
+    fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(diagnostics.len(), 1);
+    let (uri, errors) = diagnostics.iter().next().unwrap();
+    assert!(uri.ends_with("/scripts.gml"));
+    assert_eq!(errors.as_array().unwrap().len(), 1);
+}
+
+/// Check only the changed document again, unless it changes the scripts others can call.
+#[test]
+fn stale() {
+    let change = |uri, text| json!({ "method": "textDocument/didChange", "params": {
+        "textDocument": { "uri": uri, "version": 2 },
+        "contentChanges": [{ "text": text }],
+    } });
+    let requests = [
+        json!({ "id": 1, "method": "initialize", "params": { "rootUri": null } }),
+        json!({ "method": "textDocument/didOpen", "params": {
+            "textDocument": { "uri": SCRIPTS, "languageId": "gml", "version": 1, "text": "x = 1" },
+        } }),
+        json!({ "method": "textDocument/didOpen", "params": {
+            "textDocument": { "uri": CREATE, "languageId": "gml", "version": 1, "text": "x = 1" },
+        } }),
+        change(CREATE, "x = double(1)\n"),
+        change(SCRIPTS, "#define double\nreturn argument0 * 2\n"),
+        json!({ "id": 2, "method": "shutdown" }),
+        json!({ "method": "exit" }),
+    ];
+    let (_, diagnostics, published) = serve(&requests);
+
+    assert_eq!(published[SCRIPTS], 2);
+    assert_eq!(published[CREATE], 3);
+    assert_eq!(diagnostics[CREATE], json!([]));
+}
+
+/// Send a session's requests to a server, and collect its responses by id, the latest
+/// diagnostics for each document, and how many times each document's were published.
+fn serve(requests: &[Json]) -> (HashMap<u64, Json>, HashMap<String, Json>, HashMap<String, u32>) {
+    let mut input = Vec::default();
+    for request in requests.iter() {
+        let mut request = request.clone();
+        request["jsonrpc"] = json!("2.0");
+        protocol::write_message(&mut input, &request).unwrap();
+    }
+
+    let mut output = Vec::default();
+    lsp::serve(&input[..], &mut output).unwrap();
+
+    let mut read = io::Cursor::new(output);
+    let mut responses = HashMap::new();
+    let mut diagnostics = HashMap::new();
+    let mut published = HashMap::new();
+    while let Some(message) = protocol::read_message(&mut read).unwrap() {
+        match message["method"].as_str() {
+            Some("textDocument/publishDiagnostics") => {
+                let params = &message["params"];
+                let uri = String::from(params["uri"].as_str().unwrap());
+                *published.entry(uri.clone()).or_insert(0) += 1;
+                diagnostics.insert(uri, params["diagnostics"].clone());
+            }
+            _ => { responses.insert(message["id"].as_u64().unwrap(), message); }
+        }
+    }
+    (responses, diagnostics, published)
+}
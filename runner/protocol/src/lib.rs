@@ -0,0 +1,69 @@
+//! The base protocol shared by the Language Server and Debug Adapter Protocols: JSON messages,
+//! each framed by a `Content-Length` header.
+
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value as Json;
+
+/// The longest message accepted, so a bad header cannot make the reader allocate without bound.
+pub const MAX_LENGTH: usize = 64 << 20;
+
+/// Read one message, framed by a `Content-Length` header. Returns `None` at the end of the input.
+pub fn read_message<R: BufRead>(read: &mut R) -> io::Result<Option<Json>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if read.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = Some(value.trim().parse().map_err(|_| invalid_data("invalid length"))?);
+        }
+    }
+
+    let length = length.ok_or_else(|| invalid_data("missing Content-Length"))?;
+    if length > MAX_LENGTH {
+        return Err(invalid_data("message too long"));
+    }
+    let mut content = vec![0; length];
+    read.read_exact(&mut content)?;
+    let message = serde_json::from_slice(&content).map_err(|_| invalid_data("invalid JSON"))?;
+    Ok(Some(message))
+}
+
+/// Write one message, framed by a `Content-Length` header.
+pub fn write_message<W: Write>(write: &mut W, message: &Json) -> io::Result<()> {
+    let content = message.to_string();
+    write!(write, "Content-Length: {}\r\n\r\n{}", content.len(), content)?;
+    write.flush()
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use super::*;
+
+    #[test]
+    fn framing() -> io::Result<()> {
+        let message = json!({ "seq": 1, "command": "initialize" });
+        let mut input = Vec::default();
+        write_message(&mut input, &message)?;
+        let mut read = &input[..];
+        assert_eq!(read_message(&mut read)?, Some(message));
+        assert_eq!(read_message(&mut read)?, None);
+
+        let header = format!("Content-Length: {}\r\n\r\n", MAX_LENGTH + 1);
+        let error = read_message(&mut header.as_bytes()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,98 @@
+use std::io;
+
+use gml::{self, vm};
+use crate::{Context, instance, replay};
+
+#[derive(Default)]
+pub struct State {
+    pub instances: vm::EntityMap<Instance>,
+}
+
+pub struct Instance {
+    pub alarms: [i32; ALARMS],
+}
+
+pub const ALARMS: usize = 12;
+
+impl Default for Instance {
+    fn default() -> Self {
+        Instance { alarms: [-1; ALARMS] }
+    }
+}
+
+#[gml::bind]
+impl State {
+    #[gml::get(alarm)]
+    pub fn get_alarm(&self, entity: vm::Entity, i: usize) -> i32 {
+        self.instances[entity].alarms.get(i).map_or(-1, |&alarm| alarm)
+    }
+    #[gml::set(alarm)]
+    pub fn set_alarm(&mut self, entity: vm::Entity, i: usize, value: i32) {
+        if let Some(alarm) = self.instances[entity].alarms.get_mut(i) {
+            *alarm = value;
+        }
+    }
+
+    /// Count down each instance's alarms, running the alarm event for any that reach zero.
+    ///
+    /// As in GM8, alarms only count down for objects that define the corresponding event.
+    pub fn step(cx: &mut Context, thread: &mut vm::Thread) -> vm::Result<()> {
+        use project::event_type;
+
+        for alarm in 0..ALARMS {
+            let event_kind = alarm as i32;
+            let alarm_event = |cx: &mut Context, thread: &mut vm::Thread, entity, event| {
+                let time = &mut cx.world.alarm.instances[entity].alarms[alarm];
+                if *time <= 0 {
+                    return Ok(());
+                }
+                *time -= 1;
+                if *time > 0 {
+                    return Ok(());
+                }
+
+                replay::State::execute(cx, &mut thread.with(entity), event)?;
+
+                // The event may have set the alarm again.
+                let time = &mut cx.world.alarm.instances[entity].alarms[alarm];
+                if *time == 0 {
+                    *time = -1;
+                }
+                Ok(())
+            };
+            instance::State::dispatch(cx, thread, event_type::ALARM, event_kind, alarm_event)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl vm::Serialize for State {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        out.write(&self.instances)
+    }
+}
+
+impl vm::Deserialize for State {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        Ok(State { instances: input.read()? })
+    }
+}
+
+impl vm::Serialize for Instance {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        out.write(&self.alarms[..])
+    }
+}
+
+impl vm::Deserialize for Instance {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        let alarms: Vec<i32> = input.read()?;
+        let mut instance = Instance::default();
+        if alarms.len() != instance.alarms.len() {
+            return Err(vm::invalid_data());
+        }
+        instance.alarms.copy_from_slice(&alarms);
+        Ok(instance)
+    }
+}
@@ -0,0 +1,593 @@
+use std::{cmp, io};
+
+use gml::{self, vm};
+use crate::{Context, Assets, Mask, instance, replay, sprite};
+
+#[derive(Default)]
+pub struct State {
+    pub instances: vm::EntityMap<Instance>,
+}
+
+pub struct Instance {
+    pub solid: bool,
+    pub mask_index: i32,
+}
+
+/// An instance's collision shape, placed in the room.
+pub struct Collider<'a> {
+    /// The bounding box, in pixels, inclusive on all sides.
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+
+    x: f32,
+    y: f32,
+    origin_x: f32,
+    origin_y: f32,
+    xscale: f32,
+    yscale: f32,
+    sin: f32,
+    cos: f32,
+    /// The mask to test individual pixels against, or none to use only the bounding box.
+    mask: Option<&'a Mask>,
+}
+
+impl<'a> Collider<'a> {
+    /// Place the mask of an instance's sprite at a position, taking into account its scale and
+    /// rotation. Instances without a sprite or mask have no collider.
+    pub fn new(
+        assets: &'a Assets, image: &sprite::Instance, mask_index: i32, x: f32, y: f32,
+        precise: bool
+    ) -> Option<Self> {
+        let sprite_index = if mask_index >= 0 { mask_index } else { image.sprite_index };
+        let sprite = assets.sprite(sprite_index)?;
+
+        let (origin_x, origin_y) = (sprite.origin_x as f32, sprite.origin_y as f32);
+        let (xscale, yscale) = (image.image_xscale, image.image_yscale);
+        let (sin, cos) = image.image_angle.to_radians().sin_cos();
+        let transform = |sx: i32, sy: i32| {
+            let lx = (sx as f32 - origin_x) * xscale;
+            let ly = (sy as f32 - origin_y) * yscale;
+            (x + lx * cos + ly * sin, y - lx * sin + ly * cos)
+        };
+        let corners = [
+            transform(sprite.bbox_left, sprite.bbox_top),
+            transform(sprite.bbox_right + 1, sprite.bbox_top),
+            transform(sprite.bbox_left, sprite.bbox_bottom + 1),
+            transform(sprite.bbox_right + 1, sprite.bbox_bottom + 1),
+        ];
+        let left = corners.iter().map(|&(x, _)| x).fold(f32::INFINITY, f32::min);
+        let top = corners.iter().map(|&(_, y)| y).fold(f32::INFINITY, f32::min);
+        let right = corners.iter().map(|&(x, _)| x).fold(f32::NEG_INFINITY, f32::max);
+        let bottom = corners.iter().map(|&(_, y)| y).fold(f32::NEG_INFINITY, f32::max);
+
+        let mask = match sprite.masks.len() {
+            _ if !precise => None,
+            0 => None,
+            1 => sprite.masks.first(),
+            len => sprite.masks.get((image.image_index.floor() as usize) % len),
+        };
+
+        Some(Collider {
+            left: left.round() as i32,
+            top: top.round() as i32,
+            right: right.round() as i32 - 1,
+            bottom: bottom.round() as i32 - 1,
+            x, y, origin_x, origin_y, xscale, yscale, sin, cos,
+            mask,
+        })
+    }
+
+    /// The bounding box, as a region to search for other colliders in.
+    fn bounds(&self) -> (f32, f32, f32, f32) {
+        (self.left as f32, self.top as f32, self.right as f32, self.bottom as f32)
+    }
+
+    /// Test whether the collider covers a pixel.
+    pub fn contains(&self, px: i32, py: i32) -> bool {
+        if px < self.left || self.right < px || py < self.top || self.bottom < py {
+            return false;
+        }
+        let mask = match self.mask {
+            Some(mask) => mask,
+            None => return true,
+        };
+
+        // Map the center of the pixel back into the sprite.
+        let (dx, dy) = (px as f32 + 0.5 - self.x, py as f32 + 0.5 - self.y);
+        let lx = dx * self.cos - dy * self.sin;
+        let ly = dx * self.sin + dy * self.cos;
+        let mx = (lx / self.xscale + self.origin_x).floor();
+        let my = (ly / self.yscale + self.origin_y).floor();
+        if !(0.0 <= mx && mx < mask.width as f32 && 0.0 <= my && my < mask.height as f32) {
+            return false;
+        }
+        mask.data[my as usize * mask.width as usize + mx as usize]
+    }
+
+    /// Test whether the collider covers any pixel of a rectangle, given inclusive bounds.
+    pub fn intersects_rectangle(&self, x1: i32, y1: i32, x2: i32, y2: i32) -> bool {
+        let (left, right) = (cmp::max(self.left, x1), cmp::min(self.right, x2));
+        let (top, bottom) = (cmp::max(self.top, y1), cmp::min(self.bottom, y2));
+        if left > right || top > bottom {
+            return false;
+        }
+        if self.mask.is_none() {
+            return true;
+        }
+        (top..=bottom).any(|py| (left..=right).any(|px| self.contains(px, py)))
+    }
+
+    pub fn intersects(&self, other: &Collider<'_>) -> bool {
+        let (left, right) = (cmp::max(self.left, other.left), cmp::min(self.right, other.right));
+        let (top, bottom) = (cmp::max(self.top, other.top), cmp::min(self.bottom, other.bottom));
+        if left > right || top > bottom {
+            return false;
+        }
+        if self.mask.is_none() && other.mask.is_none() {
+            return true;
+        }
+        (top..=bottom).any(|py| {
+            (left..=right).any(|px| self.contains(px, py) && other.contains(px, py))
+        })
+    }
+
+    pub fn intersects_circle(&self, x: f32, y: f32, r: f32) -> bool {
+        let inside = |px: i32, py: i32| {
+            let (dx, dy) = (px as f32 - x, py as f32 - y);
+            dx * dx + dy * dy <= r * r
+        };
+        if self.mask.is_none() {
+            // Test the point of the bounding box nearest to the center.
+            let px = cmp::min(cmp::max(x.round() as i32, self.left), self.right);
+            let py = cmp::min(cmp::max(y.round() as i32, self.top), self.bottom);
+            return inside(px, py);
+        }
+
+        let (left, right) = ((x - r).floor() as i32, (x + r).ceil() as i32);
+        let (top, bottom) = ((y - r).floor() as i32, (y + r).ceil() as i32);
+        let (left, right) = (cmp::max(self.left, left), cmp::min(self.right, right));
+        let (top, bottom) = (cmp::max(self.top, top), cmp::min(self.bottom, bottom));
+        (top..=bottom).any(|py| {
+            (left..=right).any(|px| inside(px, py) && self.contains(px, py))
+        })
+    }
+
+    pub fn intersects_line(&self, x1: f32, y1: f32, x2: f32, y2: f32) -> bool {
+        let (left, right) = (f32::min(x1, x2).floor() as i32, f32::max(x1, x2).ceil() as i32);
+        let (top, bottom) = (f32::min(y1, y2).floor() as i32, f32::max(y1, y2).ceil() as i32);
+        if right < self.left || self.right < left || bottom < self.top || self.bottom < top {
+            return false;
+        }
+
+        // Visit one point per pixel along the line's major axis, skipping the points that cannot
+        // round to a pixel within the bounding box.
+        let (x1, y1, x2, y2) = (x1 as f64, y1 as f64, x2 as f64, y2 as f64);
+        let steps = f64::max((x2 - x1).abs(), (y2 - y1).abs()).ceil() as i64;
+        let (mut low, mut high) = (0.0, 1.0);
+        let axes = [(x1, x2, self.left, self.right), (y1, y2, self.top, self.bottom)];
+        for &(start, end, min, max) in &axes {
+            let delta = end - start;
+            if delta == 0.0 {
+                continue;
+            }
+            let a = (min as f64 - 0.5 - start) / delta;
+            let b = (max as f64 + 0.5 - start) / delta;
+            low = f64::max(low, f64::min(a, b));
+            high = f64::min(high, f64::max(a, b));
+        }
+        let first = cmp::max((low * steps as f64).floor() as i64 - 1, 0);
+        let last = cmp::min((high * steps as f64).ceil() as i64 + 1, steps);
+        (first..=last).any(|i| {
+            let t = if steps == 0 { 0.0 } else { i as f64 / steps as f64 };
+            let (x, y) = (x1 + (x2 - x1) * t, y1 + (y2 - y1) * t);
+            self.contains(x.round() as i32, y.round() as i32)
+        })
+    }
+}
+
+#[gml::bind]
+impl State {
+    #[gml::get(solid)]
+    pub fn get_solid(&self, entity: vm::Entity) -> bool { self.instances[entity].solid }
+    #[gml::set(solid)]
+    pub fn set_solid(&mut self, entity: vm::Entity, value: bool) {
+        self.instances[entity].solid = value;
+    }
+
+    #[gml::get(mask_index)]
+    pub fn get_mask_index(&self, entity: vm::Entity) -> i32 { self.instances[entity].mask_index }
+    #[gml::set(mask_index)]
+    pub fn set_mask_index(&mut self, entity: vm::Entity, value: i32) {
+        self.instances[entity].mask_index = value;
+    }
+
+    #[gml::get(bbox_left)]
+    pub fn get_bbox_left(cx: &Context, entity: vm::Entity) -> i32 {
+        let motion = &cx.world.motion.instances[entity];
+        Self::collider(cx, entity, true).map_or(motion.x.round() as i32, |c| c.left)
+    }
+
+    #[gml::get(bbox_right)]
+    pub fn get_bbox_right(cx: &Context, entity: vm::Entity) -> i32 {
+        let motion = &cx.world.motion.instances[entity];
+        Self::collider(cx, entity, true).map_or(motion.x.round() as i32, |c| c.right)
+    }
+
+    #[gml::get(bbox_top)]
+    pub fn get_bbox_top(cx: &Context, entity: vm::Entity) -> i32 {
+        let motion = &cx.world.motion.instances[entity];
+        Self::collider(cx, entity, true).map_or(motion.y.round() as i32, |c| c.top)
+    }
+
+    #[gml::get(bbox_bottom)]
+    pub fn get_bbox_bottom(cx: &Context, entity: vm::Entity) -> i32 {
+        let motion = &cx.world.motion.instances[entity];
+        Self::collider(cx, entity, true).map_or(motion.y.round() as i32, |c| c.bottom)
+    }
+
+    #[gml::api]
+    pub fn distance_to_point(cx: &mut Context, entity: vm::Entity, x: f32, y: f32) -> f32 {
+        let (left, top, right, bottom) = Self::bbox(cx, entity);
+        let dx = f32::max(f32::max(left - x, x - right), 0.0);
+        let dy = f32::max(f32::max(top - y, y - bottom), 0.0);
+        dx.hypot(dy)
+    }
+
+    /// The distance between the bounding boxes of an instance and the nearest instance of `obj`,
+    /// or 1000000 if there is none.
+    #[gml::api]
+    pub fn distance_to_object(cx: &mut Context, entity: vm::Entity, obj: i32) -> f32 {
+        let world = &cx.world.world;
+        let entities = if obj == vm::ALL {
+            &world.instances.values()[..]
+        } else if obj < 100000 {
+            world.objects.get(&obj).map_or(&[][..], |entities| &entities[..])
+        } else if world.instances.contains_key(obj) {
+            std::slice::from_ref(&world.instances[obj])
+        } else {
+            &[]
+        };
+
+        let (left, top, right, bottom) = Self::bbox(cx, entity);
+        let distance = |other| {
+            let (other_left, other_top, other_right, other_bottom) = Self::bbox(cx, other);
+            let dx = f32::max(f32::max(left - other_right, other_left - right), 0.0);
+            let dy = f32::max(f32::max(top - other_bottom, other_top - bottom), 0.0);
+            dx.hypot(dy)
+        };
+        if entities.len() <= instance::LINEAR_SEARCH_LIMIT {
+            return entities.iter()
+                .filter(|&&other| other != entity)
+                .map(|&other| distance(other))
+                .fold(1000000.0, f32::min);
+        }
+
+        // The instance nearest to the center bounds the distance to the nearest bounding box, and
+        // so how far away to look for others.
+        let grid = &cx.world.motion.grid;
+        let key = |other| {
+            if other == entity {
+                return None;
+            }
+            Self::member(cx, obj, other)
+        };
+        let (x, y) = ((left + right) / 2.0, (top + bottom) / 2.0);
+        let nearest = match grid.nearest(x, y, key) {
+            Some(nearest) => nearest,
+            None => return 1000000.0,
+        };
+        let mut best = f32::min(distance(nearest), 1000000.0);
+        let reach = Self::reach(cx) + best;
+        grid.rectangle(left - reach, top - reach, right + reach, bottom + reach, |other| {
+            if key(other).is_some() {
+                best = f32::min(best, distance(other));
+            }
+        });
+        best
+    }
+
+    #[gml::api]
+    pub fn place_meeting(cx: &mut Context, entity: vm::Entity, x: f32, y: f32, obj: i32) -> bool {
+        Self::instance_place(cx, entity, x, y, obj) != vm::NOONE
+    }
+
+    #[gml::api]
+    pub fn place_free(cx: &mut Context, entity: vm::Entity, x: f32, y: f32) -> bool {
+        let collider = match Self::collider_at(cx, entity, x, y) {
+            Some(collider) => collider,
+            None => return true,
+        };
+        let collision = &cx.world.collision;
+        Self::find(cx, vm::ALL, Some(entity), true, collider.bounds(), |other, c| {
+            collision.instances[other].solid && collider.intersects(c)
+        }) == vm::NOONE
+    }
+
+    #[gml::api]
+    pub fn place_empty(cx: &mut Context, entity: vm::Entity, x: f32, y: f32) -> bool {
+        Self::instance_place(cx, entity, x, y, vm::ALL) == vm::NOONE
+    }
+
+    #[gml::api]
+    pub fn instance_place(cx: &mut Context, entity: vm::Entity, x: f32, y: f32, obj: i32) -> i32 {
+        let collider = match Self::collider_at(cx, entity, x, y) {
+            Some(collider) => collider,
+            None => return vm::NOONE,
+        };
+        Self::find(cx, obj, Some(entity), true, collider.bounds(), |_, c| collider.intersects(c))
+    }
+
+    #[gml::api]
+    pub fn position_meeting(cx: &mut Context, x: f32, y: f32, obj: i32) -> bool {
+        let (px, py) = (x.floor() as i32, y.floor() as i32);
+        let point = (px as f32, py as f32, px as f32, py as f32);
+        Self::find(cx, obj, None, true, point, |_, c| c.contains(px, py)) != vm::NOONE
+    }
+
+    #[gml::api]
+    pub fn collision_point(
+        cx: &mut Context, entity: vm::Entity, x: f32, y: f32, obj: i32, prec: bool, notme: bool
+    ) -> i32 {
+        let except = if notme { Some(entity) } else { None };
+        let (px, py) = (x.floor() as i32, y.floor() as i32);
+        let point = (px as f32, py as f32, px as f32, py as f32);
+        Self::find(cx, obj, except, prec, point, |_, c| c.contains(px, py))
+    }
+
+    #[gml::api]
+    #[allow(clippy::too_many_arguments)]
+    pub fn collision_rectangle(
+        cx: &mut Context, entity: vm::Entity,
+        x1: f32, y1: f32, x2: f32, y2: f32, obj: i32, prec: bool, notme: bool
+    ) -> i32 {
+        let except = if notme { Some(entity) } else { None };
+        let (x1, x2) = (f32::min(x1, x2).round() as i32, f32::max(x1, x2).round() as i32);
+        let (y1, y2) = (f32::min(y1, y2).round() as i32, f32::max(y1, y2).round() as i32);
+        let region = (x1 as f32, y1 as f32, x2 as f32, y2 as f32);
+        Self::find(cx, obj, except, prec, region, |_, c| c.intersects_rectangle(x1, y1, x2, y2))
+    }
+
+    #[gml::api]
+    #[allow(clippy::too_many_arguments)]
+    pub fn collision_circle(
+        cx: &mut Context, entity: vm::Entity,
+        xc: f32, yc: f32, radius: f32, obj: i32, prec: bool, notme: bool
+    ) -> i32 {
+        let except = if notme { Some(entity) } else { None };
+        let region = (xc - radius, yc - radius, xc + radius, yc + radius);
+        Self::find(cx, obj, except, prec, region, |_, c| c.intersects_circle(xc, yc, radius))
+    }
+
+    #[gml::api]
+    #[allow(clippy::too_many_arguments)]
+    pub fn collision_line(
+        cx: &mut Context, entity: vm::Entity,
+        x1: f32, y1: f32, x2: f32, y2: f32, obj: i32, prec: bool, notme: bool
+    ) -> i32 {
+        let except = if notme { Some(entity) } else { None };
+        let region = (f32::min(x1, x2), f32::min(y1, y2), f32::max(x1, x2), f32::max(y1, y2));
+        Self::find(cx, obj, except, prec, region, |_, c| c.intersects_line(x1, y1, x2, y2))
+    }
+
+    /// An instance's bounding box, or its position if it has no collider.
+    fn bbox(cx: &Context, entity: vm::Entity) -> (f32, f32, f32, f32) {
+        match Self::collider(cx, entity, false) {
+            Some(c) => (c.left as f32, c.top as f32, c.right as f32, c.bottom as f32),
+            None => {
+                let motion = &cx.world.motion.instances[entity];
+                (motion.x, motion.y, motion.x, motion.y)
+            }
+        }
+    }
+
+    /// An instance's collider at its current position.
+    pub fn collider(cx: &Context, entity: vm::Entity, precise: bool) -> Option<Collider<'_>> {
+        let Context { world, assets } = cx;
+        let motion = &world.motion.instances[entity];
+        let image = &world.sprite.instances[entity];
+        let mask_index = world.collision.instances[entity].mask_index;
+        Collider::new(assets, image, mask_index, motion.x, motion.y, precise)
+    }
+
+    /// An instance's collider, moved to a different position.
+    fn collider_at(cx: &Context, entity: vm::Entity, x: f32, y: f32) -> Option<Collider<'_>> {
+        let Context { world, assets } = cx;
+        let image = &world.sprite.instances[entity];
+        let mask_index = world.collision.instances[entity].mask_index;
+        Collider::new(assets, image, mask_index, x, y, true)
+    }
+
+    /// Find the first instance of `obj`, other than `except`, whose collider satisfies `f`.
+    ///
+    /// `f` must reject colliders whose bounding boxes miss `region`, which lets objects with many
+    /// instances be searched through the motion grid. Returns the instance's id, or `noone` if
+    /// there is none.
+    fn find<F>(
+        cx: &Context, obj: i32, except: Option<vm::Entity>, precise: bool,
+        region: (f32, f32, f32, f32), mut f: F
+    ) -> i32 where F: FnMut(vm::Entity, &Collider<'_>) -> bool {
+        let world = &cx.world.world;
+        let instance = &cx.world.instance;
+        let entities = if obj == vm::ALL {
+            &world.instances.values()[..]
+        } else if obj < 100000 {
+            match world.objects.get(&obj) {
+                Some(entities) => &entities[..],
+                None => return vm::NOONE,
+            }
+        } else if world.instances.contains_key(obj) {
+            std::slice::from_ref(&world.instances[obj])
+        } else {
+            return vm::NOONE;
+        };
+
+        let near;
+        let entities = if entities.len() <= instance::LINEAR_SEARCH_LIMIT {
+            entities
+        } else {
+            near = Self::near(cx, obj, region);
+            &near[..]
+        };
+        for &entity in entities {
+            if Some(entity) == except {
+                continue;
+            }
+            let collider = match Self::collider(cx, entity, precise) {
+                Some(collider) => collider,
+                None => continue,
+            };
+            if f(entity, &collider) {
+                return instance.instances[entity].id;
+            }
+        }
+        vm::NOONE
+    }
+
+    /// Find the instances of `obj` whose bounding boxes may touch a region, through the motion
+    /// grid. They are returned in the same order as the instance lists.
+    fn near(cx: &Context, obj: i32, region: (f32, f32, f32, f32)) -> Vec<vm::Entity> {
+        let (left, top, right, bottom) = region;
+        let reach = Self::reach(cx);
+        let mut near = Vec::default();
+        let grid = &cx.world.motion.grid;
+        grid.rectangle(left - reach, top - reach, right + reach, bottom + reach, |entity| {
+            if let Some(id) = Self::member(cx, obj, entity) {
+                near.push((cx.world.world.instances.position(id), entity));
+            }
+        });
+        near.sort_unstable_by_key(|&(position, _)| position);
+        near.into_iter().map(|(_, entity)| entity).collect()
+    }
+
+    /// The id of an instance in the grid, if it is still in the world and an instance of `obj`.
+    fn member(cx: &Context, obj: i32, entity: vm::Entity) -> Option<i32> {
+        let Context { world, assets } = cx;
+        // The grid also holds instances that are destroyed but not yet freed.
+        let inst = world.instance.instances.get(entity)?;
+        if !world.world.instances.contains_key(inst.id) {
+            return None;
+        }
+        if obj != vm::ALL && !assets.ancestors(inst.object_index).any(|o| o == obj) {
+            return None;
+        }
+        Some(inst.id)
+    }
+
+    /// The furthest any instance's bounding box can reach from its position.
+    fn reach(cx: &Context) -> f32 {
+        let Context { world, assets } = cx;
+        // Rotation keeps this distance, and rounding to whole pixels may add one more.
+        assets.sprite_reach * world.sprite.max_scale() + 1.0
+    }
+
+    /// The instances of `obj` whose colliders intersect an instance's, in the same order as the
+    /// instance lists, or `None` if the instance has no collider.
+    fn colliding(cx: &Context, entity: vm::Entity, obj: i32) -> Option<Vec<vm::Entity>> {
+        let collider = Self::collider(cx, entity, true)?;
+        let entities = cx.world.world.objects.get(&obj).map_or(&[][..], |entities| &entities[..]);
+        let near;
+        let entities = if entities.len() <= instance::LINEAR_SEARCH_LIMIT {
+            entities
+        } else {
+            near = Self::near(cx, obj, collider.bounds());
+            &near[..]
+        };
+        let others = entities.iter()
+            .cloned()
+            .filter(|&other| other != entity)
+            .filter(|&other| match Self::collider(cx, other, true) {
+                Some(other) => collider.intersects(&other),
+                None => false,
+            })
+            .collect();
+        Some(others)
+    }
+
+    /// Run collision events for each pair of colliding instances.
+    ///
+    /// When either instance is solid, both are moved back to their previous positions before
+    /// the event runs.
+    pub fn step(cx: &mut Context, thread: &mut vm::Thread) -> vm::Result<()> {
+        use project::event_type;
+
+        // Collision events are keyed by the other object, which is not known up front.
+        let Context { assets, .. } = cx;
+        let mut kinds: Vec<_> = assets.code.code.keys()
+            .filter_map(|&function| match function {
+                gml::Function::Event { event_type: event_type::COLLISION, event_kind, .. } =>
+                    Some(event_kind),
+                _ => None,
+            })
+            .collect();
+        kinds.sort();
+        kinds.dedup();
+
+        for kind in kinds {
+            let collision = |cx: &mut Context, thread: &mut vm::Thread, entity, event| {
+                // Skip instances destroyed by earlier collision events.
+                let alive = |cx: &Context, entity| {
+                    let crate::World { world, instance, .. } = &cx.world;
+                    world.instances.contains_key(instance.instances[entity].id)
+                };
+                if !alive(cx, entity) {
+                    return Ok(());
+                }
+
+                let others = match Self::colliding(cx, entity, kind) {
+                    Some(others) => others,
+                    None => return Ok(()),
+                };
+                for other in others {
+                    if !alive(cx, entity) {
+                        break;
+                    }
+                    if !alive(cx, other) {
+                        continue;
+                    }
+
+                    let crate::World { collision, motion, .. } = &mut cx.world;
+                    if collision.instances[entity].solid || collision.instances[other].solid {
+                        for &entity in &[entity, other] {
+                            let instance = &motion.instances[entity];
+                            let (x, y) = (instance.xprevious, instance.yprevious);
+                            motion.set_position(entity, x, y);
+                        }
+                    }
+
+                    replay::State::execute(cx, &mut thread.with_other(entity, other), event)?;
+                }
+                Ok(())
+            };
+            instance::State::dispatch(cx, thread, event_type::COLLISION, kind, collision)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl vm::Serialize for State {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        out.write(&self.instances)
+    }
+}
+
+impl vm::Deserialize for State {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        Ok(State { instances: input.read()? })
+    }
+}
+
+impl vm::Serialize for Instance {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        out.write(&self.solid)?;
+        out.write(&self.mask_index)
+    }
+}
+
+impl vm::Deserialize for Instance {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        Ok(Instance { solid: input.read()?, mask_index: input.read()? })
+    }
+}
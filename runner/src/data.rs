@@ -1,4 +1,4 @@
-use std::{mem, cmp, fmt, error};
+use std::{mem, cmp, fmt, error, io};
 use std::collections::{hash_map, HashMap};
 use std::collections::{btree_map, BTreeMap};
 
@@ -19,6 +19,9 @@ pub struct State {
 
 type List = Vec<vm::Value>;
 
+/// How far past its end a single write may grow a list, so a stray index can't exhaust memory.
+const LIST_GROWTH: usize = 32000;
+
 type Map = BTreeMap<MapKey, vm::Value>;
 
 #[derive(Clone, Eq, PartialEq)]
@@ -79,6 +82,8 @@ pub enum Error {
     Resource(Type, i32),
     /// The key already exists in a map.
     KeyExists(vm::Value),
+    /// The position is too far past the end of a list.
+    Position(i32),
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
@@ -107,6 +112,9 @@ impl fmt::Display for Error {
             Error::KeyExists(ref key) => {
                 write!(f, "an entry with key {:?} already exists in the map", key)?;
             }
+            Error::Position(pos) => {
+                write!(f, "position {} is too far past the end of the list", pos)?;
+            }
         }
         Ok(())
     }
@@ -114,6 +122,65 @@ impl fmt::Display for Error {
 
 impl error::Error for Error {}
 
+impl vm::Serialize for State {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        out.write(&self.lists)?;
+        out.write(&self.next_list)?;
+        out.write(&self.maps)?;
+        out.write(&self.next_map)?;
+        out.write(&self.grids)?;
+        out.write(&self.next_grid)
+    }
+}
+
+impl vm::Deserialize for State {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        Ok(State {
+            lists: input.read()?,
+            next_list: input.read()?,
+            maps: input.read()?,
+            next_map: input.read()?,
+            grids: input.read()?,
+            next_grid: input.read()?,
+        })
+    }
+}
+
+impl vm::Serialize for MapKey {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        let MapKey(ref key) = *self;
+        out.write(key)
+    }
+}
+
+impl vm::Deserialize for MapKey {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        Ok(MapKey(input.read()?))
+    }
+}
+
+impl vm::Serialize for Grid {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        out.write(&self.width)?;
+        out.write(&self.data[..])
+    }
+}
+
+impl vm::Deserialize for Grid {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        let width = input.read()?;
+        let data: Box<[vm::Value]> = input.read()?;
+        let valid = match data.len().checked_rem(width) {
+            Some(rem) => rem == 0,
+            None => data.is_empty(),
+        };
+        if !valid {
+            return Err(vm::invalid_data());
+        }
+        Ok(Grid { data, width })
+    }
+}
+
 #[gml::bind]
 impl State {
     // ds_list
@@ -214,6 +281,27 @@ impl State {
         Ok(())
     }
 
+    /// Write an element, growing the list with zeros if necessary. This backs the GMS `[| ]`
+    /// accessor.
+    ///
+    /// Writing more than `LIST_GROWTH` elements past the end is an error.
+    #[gml::api]
+    pub fn ds_list_set(&mut self, id: i32, pos: i32, val: vm::ValueRef) -> vm::Result<()> {
+        let list = self.lists.get_mut(&id).ok_or(Error::Resource(Type::List, id))?;
+        if pos < 0 {
+            return Ok(());
+        }
+        let index = pos as usize;
+        if list.len() <= index {
+            if index - list.len() >= LIST_GROWTH {
+                Err(Error::Position(pos))?;
+            }
+            list.resize_with(index + 1, vm::Value::default);
+        }
+        list[index] = val.clone();
+        Ok(())
+    }
+
     // ds_map
 
     #[gml::api]
@@ -282,6 +370,14 @@ impl State {
         Ok(())
     }
 
+    /// Add or replace an entry. This backs the GMS `[? ]` accessor.
+    #[gml::api]
+    pub fn ds_map_set(&mut self, id: i32, key: vm::ValueRef, val: vm::ValueRef) -> vm::Result<()> {
+        let map = self.maps.get_mut(&id).ok_or(Error::Resource(Type::Map, id))?;
+        map.insert(MapKey(key.clone()), val.clone());
+        Ok(())
+    }
+
     #[gml::api]
     pub fn ds_map_delete(&mut self, id: i32, key: vm::ValueRef) -> vm::Result<()> {
         let map = self.maps.get_mut(&id).ok_or(Error::Resource(Type::Map, id))?;
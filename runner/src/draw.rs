@@ -0,0 +1,158 @@
+use std::cmp::Ordering;
+
+use gml::{self, vm};
+use crate::{Context, raster::Canvas, replay, string};
+
+pub struct State {
+    pub color: u32,
+    pub alpha: f32,
+    pub canvas: Canvas,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State { color: 0x000000, alpha: 1.0, canvas: Canvas::new(0, 0) }
+    }
+}
+
+#[gml::bind]
+impl State {
+    #[gml::get(c_aqua)]
+    pub fn get_c_aqua() -> u32 { 0xFFFF00 }
+    #[gml::get(c_black)]
+    pub fn get_c_black() -> u32 { 0x000000 }
+    #[gml::get(c_blue)]
+    pub fn get_c_blue() -> u32 { 0xFF0000 }
+    #[gml::get(c_dkgray)]
+    pub fn get_c_dkgray() -> u32 { 0x404040 }
+    #[gml::get(c_fuchsia)]
+    pub fn get_c_fuchsia() -> u32 { 0xFF00FF }
+    #[gml::get(c_gray)]
+    pub fn get_c_gray() -> u32 { 0x808080 }
+    #[gml::get(c_green)]
+    pub fn get_c_green() -> u32 { 0x008000 }
+    #[gml::get(c_lime)]
+    pub fn get_c_lime() -> u32 { 0x00FF00 }
+    #[gml::get(c_ltgray)]
+    pub fn get_c_ltgray() -> u32 { 0xC0C0C0 }
+    #[gml::get(c_maroon)]
+    pub fn get_c_maroon() -> u32 { 0x000080 }
+    #[gml::get(c_navy)]
+    pub fn get_c_navy() -> u32 { 0x800000 }
+    #[gml::get(c_olive)]
+    pub fn get_c_olive() -> u32 { 0x008080 }
+    #[gml::get(c_orange)]
+    pub fn get_c_orange() -> u32 { 0x40A0FF }
+    #[gml::get(c_purple)]
+    pub fn get_c_purple() -> u32 { 0x800080 }
+    #[gml::get(c_red)]
+    pub fn get_c_red() -> u32 { 0x0000FF }
+    #[gml::get(c_silver)]
+    pub fn get_c_silver() -> u32 { 0xC0C0C0 }
+    #[gml::get(c_teal)]
+    pub fn get_c_teal() -> u32 { 0x808000 }
+    #[gml::get(c_white)]
+    pub fn get_c_white() -> u32 { 0xFFFFFF }
+    #[gml::get(c_yellow)]
+    pub fn get_c_yellow() -> u32 { 0x00FFFF }
+
+    #[gml::api]
+    pub fn make_color_rgb(red: u32, green: u32, blue: u32) -> u32 {
+        (red & 0xFF) | (green & 0xFF) << 8 | (blue & 0xFF) << 16
+    }
+
+    #[gml::api]
+    pub fn draw_set_color(&mut self, col: u32) { self.color = col & 0xFFFFFF; }
+
+    #[gml::api]
+    pub fn draw_get_color(&mut self) -> u32 { self.color }
+
+    #[gml::api]
+    pub fn draw_set_alpha(&mut self, alpha: f32) { self.alpha = alpha; }
+
+    #[gml::api]
+    pub fn draw_get_alpha(&mut self) -> f32 { self.alpha }
+
+    #[gml::api]
+    pub fn draw_clear(&mut self, col: u32) { self.canvas.clear(col); }
+
+    #[gml::api]
+    pub fn draw_point(&mut self, x: f32, y: f32) {
+        let State { color, alpha, ref mut canvas } = *self;
+        canvas.point(x.round() as i32, y.round() as i32, color, alpha);
+    }
+
+    #[gml::api]
+    pub fn draw_line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32) {
+        let State { color, alpha, ref mut canvas } = *self;
+        let (x1, y1) = (x1.round() as i32, y1.round() as i32);
+        let (x2, y2) = (x2.round() as i32, y2.round() as i32);
+        canvas.line(x1, y1, x2, y2, color, alpha);
+    }
+
+    #[gml::api]
+    pub fn draw_rectangle(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, outline: bool) {
+        let State { color, alpha, ref mut canvas } = *self;
+        let (x1, y1) = (x1.round() as i32, y1.round() as i32);
+        let (x2, y2) = (x2.round() as i32, y2.round() as i32);
+        if outline {
+            canvas.rectangle(x1, y1, x2, y2, color, alpha);
+        } else {
+            canvas.fill_rectangle(x1, y1, x2, y2, color, alpha);
+        }
+    }
+
+    #[gml::api]
+    pub fn draw_circle(&mut self, x: f32, y: f32, r: f32, outline: bool) {
+        let State { color, alpha, ref mut canvas } = *self;
+        canvas.circle(x, y, r, outline, color, alpha);
+    }
+
+    #[gml::api]
+    pub fn draw_text(&mut self, x: f32, y: f32, string: vm::ValueRef) {
+        let State { color, alpha, ref mut canvas } = *self;
+        let string = string::State::string(string);
+        canvas.text(x.round() as i32, y.round() as i32, &string[..], color, alpha);
+    }
+
+    /// Clear the canvas to the room's background, and run each visible instance's draw event.
+    ///
+    /// Instances draw from the greatest depth to the least, and otherwise in creation order.
+    pub fn draw(cx: &mut Context, thread: &mut vm::Thread) -> vm::Result<()> {
+        use project::{event_type, event_kind};
+
+        let Context { world, assets } = cx;
+        let room = &assets.rooms[world.room.room as usize];
+        let canvas = &mut world.draw.canvas;
+        if (canvas.width, canvas.height) != (room.width, room.height) {
+            *canvas = Canvas::new(room.width, room.height);
+        }
+        if room.draw_background_color {
+            canvas.clear(room.background_color);
+        }
+
+        let instance = &world.instance;
+        let mut entities = world.world.instances.values().to_vec();
+        entities.sort_by(|&a, &b| {
+            let (a, b) = (instance.instances[a].depth, instance.instances[b].depth);
+            b.partial_cmp(&a).unwrap_or(Ordering::Equal)
+        });
+
+        for entity in entities {
+            // Skip instances destroyed or hidden by earlier draw events.
+            let Context { world, assets } = cx;
+            let crate::World { world, instance, .. } = world;
+            let inst = &instance.instances[entity];
+            if !world.instances.contains_key(inst.id) || !inst.visible {
+                continue;
+            }
+
+            let draw = event_kind::DRAW_NORMAL;
+            if let Some(event) = assets.find_event(inst.object_index, event_type::DRAW, draw) {
+                replay::State::execute(cx, &mut thread.with(entity), event)?;
+            }
+        }
+
+        Ok(())
+    }
+}
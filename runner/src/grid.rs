@@ -0,0 +1,382 @@
+use std::collections::HashMap;
+use std::{cmp, f32};
+
+use gml::vm;
+
+/// A uniform grid of instance positions, for finding instances near a point or in a region
+/// without visiting every instance.
+#[derive(Default)]
+pub struct Grid {
+    cells: HashMap<Cell, Vec<vm::Entity>>,
+    entries: vm::EntityMap<Entry>,
+}
+
+type Cell = (i32, i32);
+
+struct Entry {
+    cell: Cell,
+    x: f32,
+    y: f32,
+}
+
+/// The width and height of a cell, in pixels.
+pub const CELL_SIZE: f32 = 64.0;
+
+fn cell(x: f32, y: f32) -> Cell {
+    ((x / CELL_SIZE).floor() as i32, (y / CELL_SIZE).floor() as i32)
+}
+
+/// The bounds of the occupied cells, inclusive on all sides.
+///
+/// Cells are widened to `i64`, so that distances between them cannot overflow.
+struct Extent {
+    left: i64,
+    top: i64,
+    right: i64,
+    bottom: i64,
+}
+
+impl Grid {
+    pub fn insert(&mut self, entity: vm::Entity, x: f32, y: f32) {
+        let cell = cell(x, y);
+        self.cells.entry(cell).or_default().push(entity);
+        self.entries.insert(entity, Entry { cell, x, y });
+    }
+
+    pub fn remove(&mut self, entity: vm::Entity) {
+        if let Some(Entry { cell, .. }) = self.entries.remove(entity) {
+            self.remove_from_cell(entity, cell);
+        }
+    }
+
+    /// Record a new position for an entity.
+    pub fn update(&mut self, entity: vm::Entity, x: f32, y: f32) {
+        let entry = match self.entries.get_mut(entity) {
+            Some(entry) => entry,
+            None => return,
+        };
+        entry.x = x;
+        entry.y = y;
+
+        let (old, new) = (entry.cell, cell(x, y));
+        if old != new {
+            entry.cell = new;
+            self.remove_from_cell(entity, old);
+            self.cells.entry(new).or_default().push(entity);
+        }
+    }
+
+    fn remove_from_cell(&mut self, entity: vm::Entity, cell: Cell) {
+        let entities = match self.cells.get_mut(&cell) {
+            Some(entities) => entities,
+            None => return,
+        };
+        // Preserve order within the cell, so queries do not depend on removal history.
+        if let Some(i) = entities.iter().position(|&e| e == entity) {
+            entities.remove(i);
+        }
+        if entities.is_empty() {
+            self.cells.remove(&cell);
+        }
+    }
+
+    fn extent(&self) -> Option<Extent> {
+        let mut cells = self.cells.keys();
+        let &(x, y) = cells.next()?;
+        let (x, y) = (x as i64, y as i64);
+        let extent = Extent { left: x, top: y, right: x, bottom: y };
+        Some(cells.fold(extent, |extent, &(x, y)| Extent {
+            left: cmp::min(extent.left, x as i64),
+            top: cmp::min(extent.top, y as i64),
+            right: cmp::max(extent.right, x as i64),
+            bottom: cmp::max(extent.bottom, y as i64),
+        }))
+    }
+
+    /// Call `f` for each entity positioned in a rectangle, inclusive on all sides.
+    pub fn rectangle<F>(&self, x1: f32, y1: f32, x2: f32, y2: f32, mut f: F) where
+        F: FnMut(vm::Entity)
+    {
+        let (left, right) = (f32::min(x1, x2), f32::max(x1, x2));
+        let (top, bottom) = (f32::min(y1, y2), f32::max(y1, y2));
+        let inside = |entry: &Entry| {
+            left <= entry.x && entry.x <= right && top <= entry.y && entry.y <= bottom
+        };
+
+        let extent = match self.extent() {
+            Some(extent) => extent,
+            None => return,
+        };
+        let (cell_left, cell_top) = cell(left, top);
+        let (cell_right, cell_bottom) = cell(right, bottom);
+        let (cell_left, cell_right) =
+            (cmp::max(cell_left as i64, extent.left), cmp::min(cell_right as i64, extent.right));
+        let (cell_top, cell_bottom) =
+            (cmp::max(cell_top as i64, extent.top), cmp::min(cell_bottom as i64, extent.bottom));
+        if cell_left > cell_right || cell_top > cell_bottom {
+            return;
+        }
+
+        // A sparse grid may span far more cells than are occupied, so when the rectangle covers
+        // more cells than that, visit just the occupied ones, in the same order.
+        let area = (cell_right - cell_left + 1).saturating_mul(cell_bottom - cell_top + 1);
+        let cells: Vec<Cell> = if area > self.cells.len() as i64 {
+            let mut cells: Vec<_> = self.cells.keys()
+                .filter(|&&(cx, cy)| {
+                    let (cx, cy) = (cx as i64, cy as i64);
+                    cell_left <= cx && cx <= cell_right && cell_top <= cy && cy <= cell_bottom
+                })
+                .map(|&(cx, cy)| (cy, cx))
+                .collect();
+            cells.sort_unstable();
+            cells.into_iter().map(|(cy, cx)| (cx, cy)).collect()
+        } else {
+            (cell_top..=cell_bottom)
+                .flat_map(|cy| (cell_left..=cell_right).map(move |cx| (cx as i32, cy as i32)))
+                .collect()
+        };
+        for cell in cells {
+            for &entity in self.cells.get(&cell).into_iter().flatten() {
+                if inside(&self.entries[entity]) {
+                    f(entity);
+                }
+            }
+        }
+    }
+
+    /// Call `f` for each entity positioned in a circle, including its edge.
+    pub fn circle<F>(&self, x: f32, y: f32, r: f32, mut f: F) where F: FnMut(vm::Entity) {
+        self.rectangle(x - r, y - r, x + r, y + r, |entity| {
+            let entry = &self.entries[entity];
+            if (entry.x - x).hypot(entry.y - y) <= r {
+                f(entity);
+            }
+        });
+    }
+
+    /// Find the entity nearest to a point, among those for which `key` returns a value.
+    ///
+    /// Ties go to the entity with the least key.
+    pub fn nearest<F>(&self, x: f32, y: f32, mut key: F) -> Option<vm::Entity> where
+        F: FnMut(vm::Entity) -> Option<i32>
+    {
+        let extent = self.extent()?;
+        let (cx, cy) = cell(x, y);
+        let center = (cx as i64, cy as i64);
+
+        let mut best = None;
+        let mut consider = |best: &mut Option<(f32, i32, vm::Entity)>, entity, entry: &Entry| {
+            let key = match key(entity) {
+                Some(key) => key,
+                None => return,
+            };
+            let distance = (entry.x - x).hypot(entry.y - y);
+            match *best {
+                Some((best, best_key, _)) if (best, best_key) <= (distance, key) => {}
+                _ => *best = Some((distance, key, entity)),
+            }
+        };
+
+        // Every entity in ring `r` around the center is at least `r - 1` cells away.
+        let mut budget = self.cells.len();
+        for r in extent.min_ring(center)..=extent.max_ring(center) {
+            if let Some((distance, _, _)) = best {
+                if distance < (r - 1) as f32 * CELL_SIZE {
+                    break;
+                }
+            }
+            let complete = self.ring(&extent, center, r, &mut budget, |entity, entry| {
+                consider(&mut best, entity, entry)
+            });
+            if !complete {
+                self.all(|entity, entry| consider(&mut best, entity, entry));
+                break;
+            }
+        }
+        best.map(|(_, _, entity)| entity)
+    }
+
+    /// Find the entity furthest from a point, among those for which `key` returns a value.
+    ///
+    /// Ties go to the entity with the least key.
+    pub fn furthest<F>(&self, x: f32, y: f32, mut key: F) -> Option<vm::Entity> where
+        F: FnMut(vm::Entity) -> Option<i32>
+    {
+        let extent = self.extent()?;
+        let (cx, cy) = cell(x, y);
+        let center = (cx as i64, cy as i64);
+
+        let mut best = None;
+        let mut consider = |best: &mut Option<(f32, i32, vm::Entity)>, entity, entry: &Entry| {
+            let key = match key(entity) {
+                Some(key) => key,
+                None => return,
+            };
+            let distance = (entry.x - x).hypot(entry.y - y);
+            match *best {
+                Some((best, best_key, _))
+                    if best > distance || (best == distance && best_key <= key) => {}
+                _ => *best = Some((distance, key, entity)),
+            }
+        };
+
+        // Every entity in ring `r` around the center is within `r + 1` cells on each axis.
+        let mut budget = self.cells.len();
+        for r in (extent.min_ring(center)..=extent.max_ring(center)).rev() {
+            if let Some((distance, _, _)) = best {
+                if distance > (r + 1) as f32 * CELL_SIZE * f32::consts::SQRT_2 {
+                    break;
+                }
+            }
+            let complete = self.ring(&extent, center, r, &mut budget, |entity, entry| {
+                consider(&mut best, entity, entry)
+            });
+            if !complete {
+                self.all(|entity, entry| consider(&mut best, entity, entry));
+                break;
+            }
+        }
+        best.map(|(_, _, entity)| entity)
+    }
+
+    /// Visit the occupied cells at a Chebyshev distance of `r` from `center`.
+    ///
+    /// Each cell of the ring within the extent spends one unit of `budget`. If that runs out,
+    /// the ring is abandoned and this returns false, as a sparse grid can span far more cells
+    /// than it would take to visit every entity directly.
+    fn ring<F>(&self, extent: &Extent, center: (i64, i64), r: i64, budget: &mut usize, mut f: F)
+        -> bool where F: FnMut(vm::Entity, &Entry)
+    {
+        let (cx, cy) = center;
+        let mut visit = |x: i64, y: i64| {
+            if x < extent.left || extent.right < x || y < extent.top || extent.bottom < y {
+                return true;
+            }
+            if *budget == 0 {
+                return false;
+            }
+            *budget -= 1;
+            for &entity in self.cells.get(&(x as i32, y as i32)).into_iter().flatten() {
+                f(entity, &self.entries[entity]);
+            }
+            true
+        };
+        if r == 0 {
+            return visit(cx, cy);
+        }
+
+        // Only scan the sides that cross the extent, so that every step spends the budget.
+        let row = |y| extent.top <= y && y <= extent.bottom;
+        let column = |x| extent.left <= x && x <= extent.right;
+
+        let (left, right) = (cmp::max(cx - r, extent.left), cmp::min(cx + r, extent.right));
+        if row(cy - r) || row(cy + r) {
+            for x in left..=right {
+                if !visit(x, cy - r) || !visit(x, cy + r) {
+                    return false;
+                }
+            }
+        }
+        let (top, bottom) = (cmp::max(cy - r + 1, extent.top), cmp::min(cy + r - 1, extent.bottom));
+        if column(cx - r) || column(cx + r) {
+            for y in top..=bottom {
+                if !visit(cx - r, y) || !visit(cx + r, y) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Visit every entity, one occupied cell at a time.
+    fn all<F>(&self, mut f: F) where F: FnMut(vm::Entity, &Entry) {
+        for &entity in self.cells.values().flatten() {
+            f(entity, &self.entries[entity]);
+        }
+    }
+}
+
+impl Extent {
+    /// The Chebyshev distance from `center` to the nearest occupied cell's ring.
+    fn min_ring(&self, (x, y): (i64, i64)) -> i64 {
+        let dx = cmp::max(cmp::max(self.left - x, x - self.right), 0);
+        let dy = cmp::max(cmp::max(self.top - y, y - self.bottom), 0);
+        cmp::max(dx, dy)
+    }
+
+    /// The Chebyshev distance from `center` to the furthest occupied cell's ring.
+    fn max_ring(&self, (x, y): (i64, i64)) -> i64 {
+        let dx = cmp::max((x - self.left).abs(), (self.right - x).abs());
+        let dy = cmp::max((y - self.top).abs(), (self.bottom - y).abs());
+        cmp::max(dx, dy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries() {
+        let mut entities = vm::EntityAllocator::default();
+        let mut grid = Grid::default();
+        let positions = [(10.0, 10.0), (100.0, 20.0), (-300.0, 500.0), (130.0, 30.0)];
+        let ids: Vec<_> = positions.iter()
+            .map(|&(x, y)| {
+                let entity = entities.create();
+                grid.insert(entity, x, y);
+                entity
+            })
+            .collect();
+        let key = |entity| ids.iter().position(|&e| e == entity).map(|i| i as i32);
+
+        assert_eq!(grid.nearest(120.0, 25.0, key), Some(ids[3]));
+        assert_eq!(grid.nearest(0.0, 0.0, key), Some(ids[0]));
+        assert_eq!(grid.furthest(0.0, 0.0, key), Some(ids[2]));
+        assert_eq!(grid.nearest(0.0, 0.0, |entity| key(entity).filter(|&i| i != 0)), Some(ids[1]));
+
+        grid.update(ids[3], 5000.0, 5000.0);
+        assert_eq!(grid.nearest(120.0, 25.0, key), Some(ids[1]));
+        assert_eq!(grid.furthest(0.0, 0.0, key), Some(ids[3]));
+
+        let mut found = Vec::default();
+        grid.rectangle(0.0, 0.0, 100.0, 100.0, |entity| found.push(entity));
+        assert_eq!(found, [ids[0], ids[1]]);
+
+        let mut found = Vec::default();
+        grid.circle(0.0, 0.0, 50.0, |entity| found.push(entity));
+        assert_eq!(found, [ids[0]]);
+
+        grid.remove(ids[0]);
+        assert_eq!(grid.nearest(0.0, 0.0, key), Some(ids[1]));
+    }
+
+    #[test]
+    fn far() {
+        let mut entities = vm::EntityAllocator::default();
+        let mut grid = Grid::default();
+        let positions = [(-1e12, -1e12), (1e12, 1e12), (0.0, 0.0), (1e12, -1e12)];
+        let ids: Vec<_> = positions.iter()
+            .map(|&(x, y)| {
+                let entity = entities.create();
+                grid.insert(entity, x, y);
+                entity
+            })
+            .collect();
+        let key = |entity| ids.iter().position(|&e| e == entity).map(|i| i as i32);
+
+        assert_eq!(grid.nearest(1e12, 1e12, key), Some(ids[1]));
+        assert_eq!(grid.nearest(10.0, -10.0, key), Some(ids[2]));
+        assert_eq!(grid.nearest(-3e12, 0.0, key), Some(ids[0]));
+        assert_eq!(grid.furthest(0.0, 0.0, key), Some(ids[0]));
+        assert_eq!(grid.furthest(1e12, 1e12, key), Some(ids[0]));
+
+        let mut found = Vec::default();
+        grid.rectangle(-1e12, -1e12, 1e12, 0.0, |entity| found.push(entity));
+        assert_eq!(found, [ids[0], ids[3], ids[2]]);
+
+        let mut found = Vec::default();
+        grid.circle(1e12, 1e12, 10.0, |entity| found.push(entity));
+        assert_eq!(found, [ids[1]]);
+    }
+}
@@ -0,0 +1,623 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::{io, mem};
+
+use gml::symbol::Symbol;
+use gml::{self, vm};
+use crate::{Context, instance, collision, replay};
+
+/// Keyboard and mouse state, fed by the embedder between frames.
+///
+/// Input is queued until the start of the next frame. Presses and releases are then visible for
+/// the whole frame, and are cleared at the end of it.
+pub struct State {
+    keys: [Key; 256],
+    buttons: [Key; 4],
+    wheel_up: bool,
+    wheel_down: bool,
+
+    pub mouse_x: f32,
+    pub mouse_y: f32,
+    pub keyboard_key: u32,
+    pub keyboard_lastkey: u32,
+    pub keyboard_lastchar: Vec<u8>,
+    pub keyboard_string: Vec<u8>,
+    pub mouse_button: i32,
+    pub mouse_lastbutton: i32,
+
+    events: Vec<Event>,
+    /// The ids of the instances that were under the mouse as of the last mouse enter and leave
+    /// events.
+    hover: HashSet<i32>,
+}
+
+#[derive(Copy, Clone, Default)]
+struct Key {
+    held: bool,
+    pressed: bool,
+    released: bool,
+}
+
+pub const VK_NOKEY: u32 = 0;
+pub const VK_ANYKEY: u32 = 1;
+pub const VK_BACKSPACE: u32 = 8;
+
+pub const MB_ANY: i32 = -1;
+pub const MB_NONE: i32 = 0;
+pub const MB_LEFT: i32 = 1;
+pub const MB_RIGHT: i32 = 2;
+pub const MB_MIDDLE: i32 = 3;
+
+/// The longest `keyboard_string` that GM8 keeps.
+const KEYBOARD_STRING_LIMIT: usize = 1024;
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            keys: [Key::default(); 256],
+            buttons: [Key::default(); 4],
+            wheel_up: false,
+            wheel_down: false,
+
+            mouse_x: 0.0,
+            mouse_y: 0.0,
+            keyboard_key: VK_NOKEY,
+            keyboard_lastkey: VK_NOKEY,
+            keyboard_lastchar: Vec::default(),
+            keyboard_string: Vec::default(),
+            mouse_button: MB_NONE,
+            mouse_lastbutton: MB_NONE,
+
+            events: Vec::default(),
+            hover: HashSet::default(),
+        }
+    }
+}
+
+/// Input delivered by the embedder, which takes effect at the start of the next frame.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Event {
+    /// A key went down. Keys are Windows virtual key codes, as used by the `vk_` constants.
+    /// `vk_nokey` and `vk_anykey` are not keys, and are ignored.
+    KeyPress(u32),
+    KeyRelease(u32),
+    /// A key produced a character, which is appended to `keyboard_string`.
+    Char(char),
+    /// The mouse moved, to a position in room coordinates.
+    MouseMove(f32, f32),
+    /// A mouse button went down. Buttons are numbered like the `mb_` constants.
+    MousePress(i32),
+    MouseRelease(i32),
+    /// The mouse wheel turned, away from the user for positive values.
+    MouseWheel(i32),
+}
+
+/// The embedder's interface, for delivering input between frames.
+impl State {
+    pub fn push(&mut self, event: Event) { self.events.push(event); }
+
+    pub fn key_press(&mut self, key: u32) { self.push(Event::KeyPress(key)); }
+
+    pub fn key_release(&mut self, key: u32) { self.push(Event::KeyRelease(key)); }
+
+    pub fn char_input(&mut self, c: char) { self.push(Event::Char(c)); }
+
+    pub fn mouse_move(&mut self, x: f32, y: f32) { self.push(Event::MouseMove(x, y)); }
+
+    pub fn mouse_press(&mut self, button: i32) { self.push(Event::MousePress(button)); }
+
+    pub fn mouse_release(&mut self, button: i32) { self.push(Event::MouseRelease(button)); }
+
+    pub fn mouse_wheel(&mut self, delta: i32) { self.push(Event::MouseWheel(delta)); }
+
+    /// Take the events delivered since the last frame, without applying them.
+    pub fn take_events(&mut self) -> Vec<Event> { mem::take(&mut self.events) }
+
+    /// Update the keyboard and mouse state for the start of a frame.
+    pub fn apply(&mut self, events: &[Event]) {
+        for &event in events {
+            match event {
+                Event::KeyPress(key) => self.press_key(key),
+                Event::KeyRelease(key) => self.release_key(key),
+                Event::Char(c) => self.type_char(c),
+                Event::MouseMove(x, y) => self.move_mouse(x, y),
+                Event::MousePress(button) => self.press_button(button),
+                Event::MouseRelease(button) => self.release_button(button),
+                Event::MouseWheel(delta) => self.turn_wheel(delta),
+            }
+        }
+    }
+
+    fn press_key(&mut self, key: u32) {
+        let state = match self.keys.get_mut(key as usize) {
+            Some(state) if !matches!(key, VK_NOKEY | VK_ANYKEY) => state,
+            _ => return,
+        };
+        if !state.held {
+            state.pressed = true;
+        }
+        state.held = true;
+        self.keyboard_key = key;
+        self.keyboard_lastkey = key;
+
+        if key == VK_BACKSPACE {
+            self.keyboard_string.pop();
+        }
+    }
+
+    fn release_key(&mut self, key: u32) {
+        let state = match self.keys.get_mut(key as usize) {
+            Some(state) if !matches!(key, VK_NOKEY | VK_ANYKEY) => state,
+            _ => return,
+        };
+        if state.held {
+            state.released = true;
+        }
+        state.held = false;
+        if self.keyboard_key == key {
+            self.keyboard_key = VK_NOKEY;
+        }
+    }
+
+    fn type_char(&mut self, c: char) {
+        let mut buf = [0; 4];
+        let c = c.encode_utf8(&mut buf).as_bytes();
+        self.keyboard_lastchar = c.to_vec();
+        if self.keyboard_string.len() + c.len() <= KEYBOARD_STRING_LIMIT {
+            self.keyboard_string.extend_from_slice(c);
+        }
+    }
+
+    fn move_mouse(&mut self, x: f32, y: f32) {
+        self.mouse_x = x;
+        self.mouse_y = y;
+    }
+
+    fn press_button(&mut self, button: i32) {
+        let state = match self.button_mut(button) {
+            Some(state) => state,
+            None => return,
+        };
+        if !state.held {
+            state.pressed = true;
+        }
+        state.held = true;
+        self.mouse_button = button;
+        self.mouse_lastbutton = button;
+    }
+
+    fn release_button(&mut self, button: i32) {
+        let state = match self.button_mut(button) {
+            Some(state) => state,
+            None => return,
+        };
+        if state.held {
+            state.released = true;
+        }
+        state.held = false;
+        if self.mouse_button == button {
+            self.mouse_button = MB_NONE;
+        }
+    }
+
+    fn turn_wheel(&mut self, delta: i32) {
+        if delta > 0 {
+            self.wheel_up = true;
+        } else if delta < 0 {
+            self.wheel_down = true;
+        }
+    }
+
+    /// Forget this frame's presses and releases.
+    pub fn clear_pressed(&mut self) {
+        for key in self.keys.iter_mut().chain(self.buttons.iter_mut()) {
+            key.pressed = false;
+            key.released = false;
+        }
+        self.wheel_up = false;
+        self.wheel_down = false;
+    }
+
+    fn button_mut(&mut self, button: i32) -> Option<&mut Key> {
+        if button < MB_LEFT {
+            return None;
+        }
+        self.buttons.get_mut(button as usize)
+    }
+
+    /// Test a key, treating `vk_anykey` and `vk_nokey` as any key and no key at all.
+    fn check_key(&self, key: u32, test: fn(&Key) -> bool) -> bool {
+        match key {
+            VK_NOKEY => !self.keys.iter().any(test),
+            VK_ANYKEY => self.keys.iter().any(test),
+            key => match self.keys.get(key as usize) {
+                Some(key) => test(key),
+                None => false,
+            },
+        }
+    }
+
+    /// Test a button, treating `mb_any` and `mb_none` as any button and no button at all.
+    fn check_button(&self, button: i32, test: fn(&Key) -> bool) -> bool {
+        match button {
+            MB_NONE => !self.buttons.iter().any(test),
+            MB_ANY => self.buttons.iter().any(test),
+            button if button < 0 => false,
+            button => match self.buttons.get(button as usize) {
+                Some(button) => test(button),
+                None => false,
+            },
+        }
+    }
+}
+
+#[gml::bind]
+impl State {
+    #[gml::get(vk_nokey)]
+    pub fn get_vk_nokey() -> u32 { VK_NOKEY }
+    #[gml::get(vk_anykey)]
+    pub fn get_vk_anykey() -> u32 { VK_ANYKEY }
+    #[gml::get(vk_backspace)]
+    pub fn get_vk_backspace() -> u32 { VK_BACKSPACE }
+    #[gml::get(vk_tab)]
+    pub fn get_vk_tab() -> u32 { 9 }
+    #[gml::get(vk_enter)]
+    pub fn get_vk_enter() -> u32 { 13 }
+    #[gml::get(vk_return)]
+    pub fn get_vk_return() -> u32 { 13 }
+    #[gml::get(vk_shift)]
+    pub fn get_vk_shift() -> u32 { 16 }
+    #[gml::get(vk_control)]
+    pub fn get_vk_control() -> u32 { 17 }
+    #[gml::get(vk_alt)]
+    pub fn get_vk_alt() -> u32 { 18 }
+    #[gml::get(vk_pause)]
+    pub fn get_vk_pause() -> u32 { 19 }
+    #[gml::get(vk_escape)]
+    pub fn get_vk_escape() -> u32 { 27 }
+    #[gml::get(vk_space)]
+    pub fn get_vk_space() -> u32 { 32 }
+    #[gml::get(vk_pageup)]
+    pub fn get_vk_pageup() -> u32 { 33 }
+    #[gml::get(vk_pagedown)]
+    pub fn get_vk_pagedown() -> u32 { 34 }
+    #[gml::get(vk_end)]
+    pub fn get_vk_end() -> u32 { 35 }
+    #[gml::get(vk_home)]
+    pub fn get_vk_home() -> u32 { 36 }
+    #[gml::get(vk_left)]
+    pub fn get_vk_left() -> u32 { 37 }
+    #[gml::get(vk_up)]
+    pub fn get_vk_up() -> u32 { 38 }
+    #[gml::get(vk_right)]
+    pub fn get_vk_right() -> u32 { 39 }
+    #[gml::get(vk_down)]
+    pub fn get_vk_down() -> u32 { 40 }
+    #[gml::get(vk_printscreen)]
+    pub fn get_vk_printscreen() -> u32 { 44 }
+    #[gml::get(vk_insert)]
+    pub fn get_vk_insert() -> u32 { 45 }
+    #[gml::get(vk_delete)]
+    pub fn get_vk_delete() -> u32 { 46 }
+    #[gml::get(vk_numpad0)]
+    pub fn get_vk_numpad0() -> u32 { 96 }
+    #[gml::get(vk_numpad1)]
+    pub fn get_vk_numpad1() -> u32 { 97 }
+    #[gml::get(vk_numpad2)]
+    pub fn get_vk_numpad2() -> u32 { 98 }
+    #[gml::get(vk_numpad3)]
+    pub fn get_vk_numpad3() -> u32 { 99 }
+    #[gml::get(vk_numpad4)]
+    pub fn get_vk_numpad4() -> u32 { 100 }
+    #[gml::get(vk_numpad5)]
+    pub fn get_vk_numpad5() -> u32 { 101 }
+    #[gml::get(vk_numpad6)]
+    pub fn get_vk_numpad6() -> u32 { 102 }
+    #[gml::get(vk_numpad7)]
+    pub fn get_vk_numpad7() -> u32 { 103 }
+    #[gml::get(vk_numpad8)]
+    pub fn get_vk_numpad8() -> u32 { 104 }
+    #[gml::get(vk_numpad9)]
+    pub fn get_vk_numpad9() -> u32 { 105 }
+    #[gml::get(vk_multiply)]
+    pub fn get_vk_multiply() -> u32 { 106 }
+    #[gml::get(vk_add)]
+    pub fn get_vk_add() -> u32 { 107 }
+    #[gml::get(vk_subtract)]
+    pub fn get_vk_subtract() -> u32 { 109 }
+    #[gml::get(vk_decimal)]
+    pub fn get_vk_decimal() -> u32 { 110 }
+    #[gml::get(vk_divide)]
+    pub fn get_vk_divide() -> u32 { 111 }
+    #[gml::get(vk_f1)]
+    pub fn get_vk_f1() -> u32 { 112 }
+    #[gml::get(vk_f2)]
+    pub fn get_vk_f2() -> u32 { 113 }
+    #[gml::get(vk_f3)]
+    pub fn get_vk_f3() -> u32 { 114 }
+    #[gml::get(vk_f4)]
+    pub fn get_vk_f4() -> u32 { 115 }
+    #[gml::get(vk_f5)]
+    pub fn get_vk_f5() -> u32 { 116 }
+    #[gml::get(vk_f6)]
+    pub fn get_vk_f6() -> u32 { 117 }
+    #[gml::get(vk_f7)]
+    pub fn get_vk_f7() -> u32 { 118 }
+    #[gml::get(vk_f8)]
+    pub fn get_vk_f8() -> u32 { 119 }
+    #[gml::get(vk_f9)]
+    pub fn get_vk_f9() -> u32 { 120 }
+    #[gml::get(vk_f10)]
+    pub fn get_vk_f10() -> u32 { 121 }
+    #[gml::get(vk_f11)]
+    pub fn get_vk_f11() -> u32 { 122 }
+    #[gml::get(vk_f12)]
+    pub fn get_vk_f12() -> u32 { 123 }
+    #[gml::get(vk_lshift)]
+    pub fn get_vk_lshift() -> u32 { 160 }
+    #[gml::get(vk_rshift)]
+    pub fn get_vk_rshift() -> u32 { 161 }
+    #[gml::get(vk_lcontrol)]
+    pub fn get_vk_lcontrol() -> u32 { 162 }
+    #[gml::get(vk_rcontrol)]
+    pub fn get_vk_rcontrol() -> u32 { 163 }
+    #[gml::get(vk_lalt)]
+    pub fn get_vk_lalt() -> u32 { 164 }
+    #[gml::get(vk_ralt)]
+    pub fn get_vk_ralt() -> u32 { 165 }
+
+    #[gml::get(mb_any)]
+    pub fn get_mb_any() -> i32 { MB_ANY }
+    #[gml::get(mb_none)]
+    pub fn get_mb_none() -> i32 { MB_NONE }
+    #[gml::get(mb_left)]
+    pub fn get_mb_left() -> i32 { MB_LEFT }
+    #[gml::get(mb_right)]
+    pub fn get_mb_right() -> i32 { MB_RIGHT }
+    #[gml::get(mb_middle)]
+    pub fn get_mb_middle() -> i32 { MB_MIDDLE }
+
+    #[gml::get(keyboard_key)]
+    pub fn get_keyboard_key(&self) -> u32 { self.keyboard_key }
+    #[gml::set(keyboard_key)]
+    pub fn set_keyboard_key(&mut self, value: u32) { self.keyboard_key = value; }
+
+    #[gml::get(keyboard_lastkey)]
+    pub fn get_keyboard_lastkey(&self) -> u32 { self.keyboard_lastkey }
+    #[gml::set(keyboard_lastkey)]
+    pub fn set_keyboard_lastkey(&mut self, value: u32) { self.keyboard_lastkey = value; }
+
+    #[gml::get(keyboard_lastchar)]
+    pub fn get_keyboard_lastchar(&self) -> Symbol { Symbol::intern(&self.keyboard_lastchar) }
+    #[gml::set(keyboard_lastchar)]
+    pub fn set_keyboard_lastchar(&mut self, value: Symbol) {
+        self.keyboard_lastchar = value[..].to_vec();
+    }
+
+    #[gml::get(keyboard_string)]
+    pub fn get_keyboard_string(&self) -> Symbol { Symbol::intern(&self.keyboard_string) }
+    #[gml::set(keyboard_string)]
+    pub fn set_keyboard_string(&mut self, value: Symbol) {
+        self.keyboard_string = value[..].to_vec();
+    }
+
+    #[gml::get(mouse_x)]
+    pub fn get_mouse_x(&self) -> f32 { self.mouse_x }
+
+    #[gml::get(mouse_y)]
+    pub fn get_mouse_y(&self) -> f32 { self.mouse_y }
+
+    #[gml::get(mouse_button)]
+    pub fn get_mouse_button(&self) -> i32 { self.mouse_button }
+    #[gml::set(mouse_button)]
+    pub fn set_mouse_button(&mut self, value: i32) { self.mouse_button = value; }
+
+    #[gml::get(mouse_lastbutton)]
+    pub fn get_mouse_lastbutton(&self) -> i32 { self.mouse_lastbutton }
+    #[gml::set(mouse_lastbutton)]
+    pub fn set_mouse_lastbutton(&mut self, value: i32) { self.mouse_lastbutton = value; }
+
+    #[gml::api]
+    pub fn keyboard_check(&mut self, key: u32) -> bool { self.check_key(key, |key| key.held) }
+
+    #[gml::api]
+    pub fn keyboard_check_pressed(&mut self, key: u32) -> bool {
+        self.check_key(key, |key| key.pressed)
+    }
+
+    #[gml::api]
+    pub fn keyboard_check_released(&mut self, key: u32) -> bool {
+        self.check_key(key, |key| key.released)
+    }
+
+    #[gml::api]
+    pub fn keyboard_check_direct(&mut self, key: u32) -> bool {
+        self.check_key(key, |key| key.held)
+    }
+
+    #[gml::api]
+    pub fn keyboard_clear(&mut self, key: u32) {
+        if let Some(key) = self.keys.get_mut(key as usize) {
+            *key = Key::default();
+        }
+    }
+
+    #[gml::api]
+    pub fn mouse_check_button(&mut self, numb: i32) -> bool {
+        self.check_button(numb, |button| button.held)
+    }
+
+    #[gml::api]
+    pub fn mouse_check_button_pressed(&mut self, numb: i32) -> bool {
+        self.check_button(numb, |button| button.pressed)
+    }
+
+    #[gml::api]
+    pub fn mouse_check_button_released(&mut self, numb: i32) -> bool {
+        self.check_button(numb, |button| button.released)
+    }
+
+    #[gml::api]
+    pub fn mouse_clear(&mut self, button: i32) {
+        if let Some(button) = self.button_mut(button) {
+            *button = Key::default();
+        }
+        self.mouse_button = MB_NONE;
+    }
+
+    #[gml::api]
+    pub fn io_clear(&mut self) {
+        self.keys = [Key::default(); 256];
+        self.buttons = [Key::default(); 4];
+        self.wheel_up = false;
+        self.wheel_down = false;
+        self.keyboard_key = VK_NOKEY;
+        self.mouse_button = MB_NONE;
+    }
+
+    /// Run the keyboard, key press, key release, and mouse events for this frame's input.
+    pub fn step(cx: &mut Context, thread: &mut vm::Thread) -> vm::Result<()> {
+        use project::{event_type, event_kind};
+
+        let key_events = [
+            (event_type::KEYBOARD, (|key| key.held) as fn(&Key) -> bool),
+            (event_type::KEY_PRESS, |key| key.pressed),
+            (event_type::KEY_RELEASE, |key| key.released),
+        ];
+        for &(event_type, test) in &key_events {
+            let input = &cx.world.input;
+            let keys: Vec<_> = (0..input.keys.len() as i32)
+                .filter(|&key| test(&input.keys[key as usize]))
+                .collect();
+            for &key in &keys {
+                instance::State::event(cx, thread, event_type, key)?;
+            }
+            let any = if keys.is_empty() { VK_NOKEY } else { VK_ANYKEY };
+            instance::State::event(cx, thread, event_type, any as i32)?;
+        }
+
+        // The global events skip over the local no button event.
+        let global = event_kind::GLOBAL_LEFT_BUTTON;
+        let button_events = [
+            (event_kind::MOUSE_LEFT_BUTTON, global, (|button| button.held) as fn(&Key) -> bool),
+            (event_kind::MOUSE_LEFT_PRESS, global + 3, |button| button.pressed),
+            (event_kind::MOUSE_LEFT_RELEASE, global + 6, |button| button.released),
+        ];
+        for &(local, global, test) in &button_events {
+            for button in MB_LEFT..=MB_MIDDLE {
+                if !test(&cx.world.input.buttons[button as usize]) {
+                    continue;
+                }
+                let offset = button - MB_LEFT;
+                Self::mouse_event(cx, thread, local + offset)?;
+                instance::State::event(cx, thread, event_type::MOUSE, global + offset)?;
+            }
+        }
+        if !cx.world.input.buttons.iter().any(|button| button.held) {
+            Self::mouse_event(cx, thread, event_kind::MOUSE_NO_BUTTON)?;
+        }
+
+        // Only instances with an enter or leave event are tracked, so the set is rebuilt here.
+        let previous = mem::take(&mut cx.world.input.hover);
+        let hover_events = [(event_kind::MOUSE_ENTER, true), (event_kind::MOUSE_LEAVE, false)];
+        for &(kind, enter) in &hover_events {
+            let hover_event = |cx: &mut Context, thread: &mut vm::Thread, entity, event| {
+                let id = cx.world.instance.instances[entity].id;
+                let hover = Self::hover(cx, entity);
+                if hover {
+                    cx.world.input.hover.insert(id);
+                }
+                if hover == enter && previous.contains(&id) != enter {
+                    replay::State::execute(cx, &mut thread.with(entity), event)?;
+                }
+                Ok(())
+            };
+            instance::State::dispatch(cx, thread, event_type::MOUSE, kind, hover_event)?;
+        }
+
+        if cx.world.input.wheel_up {
+            instance::State::event(cx, thread, event_type::MOUSE, event_kind::MOUSE_WHEEL_UP)?;
+        }
+        if cx.world.input.wheel_down {
+            instance::State::event(cx, thread, event_type::MOUSE, event_kind::MOUSE_WHEEL_DOWN)?;
+        }
+
+        Ok(())
+    }
+
+    /// Run a mouse event for the instances under the mouse.
+    fn mouse_event(cx: &mut Context, thread: &mut vm::Thread, kind: i32) -> vm::Result<()> {
+        use project::event_type;
+
+        instance::State::dispatch(cx, thread, event_type::MOUSE, kind, |cx, thread, entity, event| {
+            if Self::hover(cx, entity) {
+                replay::State::execute(cx, &mut thread.with(entity), event)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Whether the mouse is over an instance's mask.
+    fn hover(cx: &mut Context, entity: vm::Entity) -> bool {
+        let input = &cx.world.input;
+        let (x, y) = (input.mouse_x.floor() as i32, input.mouse_y.floor() as i32);
+        match collision::State::collider(cx, entity, true) {
+            Some(collider) => collider.contains(x, y),
+            None => false,
+        }
+    }
+}
+
+impl vm::Serialize for Event {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        match *self {
+            Event::KeyPress(key) => {
+                out.write(&0u8)?;
+                out.write(&key)
+            }
+            Event::KeyRelease(key) => {
+                out.write(&1u8)?;
+                out.write(&key)
+            }
+            Event::Char(c) => {
+                out.write(&2u8)?;
+                out.write(&(c as u32))
+            }
+            Event::MouseMove(x, y) => {
+                out.write(&3u8)?;
+                out.write(&x)?;
+                out.write(&y)
+            }
+            Event::MousePress(button) => {
+                out.write(&4u8)?;
+                out.write(&button)
+            }
+            Event::MouseRelease(button) => {
+                out.write(&5u8)?;
+                out.write(&button)
+            }
+            Event::MouseWheel(delta) => {
+                out.write(&6u8)?;
+                out.write(&delta)
+            }
+        }
+    }
+}
+
+impl vm::Deserialize for Event {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        match input.read::<u8>()? {
+            0 => Ok(Event::KeyPress(input.read()?)),
+            1 => Ok(Event::KeyRelease(input.read()?)),
+            2 => {
+                let c = char::try_from(input.read::<u32>()?).map_err(|_| vm::invalid_data())?;
+                Ok(Event::Char(c))
+            }
+            3 => Ok(Event::MouseMove(input.read()?, input.read()?)),
+            4 => Ok(Event::MousePress(input.read()?)),
+            5 => Ok(Event::MouseRelease(input.read()?)),
+            6 => Ok(Event::MouseWheel(input.read()?)),
+            _ => Err(vm::invalid_data()),
+        }
+    }
+}
@@ -1,5 +1,8 @@
+use std::cmp::Ordering;
+use std::io;
+
 use gml::{self, vm};
-use crate::{Context, motion};
+use crate::{Context, motion, alarm, sprite, collision, replay};
 
 pub struct State {
     pub next_id: i32,
@@ -10,9 +13,16 @@ pub struct State {
 pub struct Instance {
     pub object_index: i32,
     pub id: i32,
+    pub visible: bool,
+    pub depth: f32,
     pub persistent: bool,
 }
 
+enum Search { Nearest, Furthest }
+
+/// The number of candidates above which searches for instances use the grid.
+pub const LINEAR_SEARCH_LIMIT: usize = 32;
+
 impl Default for State {
     fn default() -> Self {
         State {
@@ -33,6 +43,20 @@ impl State {
     #[gml::get(id)]
     pub fn get_id(&self, entity: vm::Entity) -> i32 { self.instances[entity].id }
 
+    #[gml::get(visible)]
+    pub fn get_visible(&self, entity: vm::Entity) -> bool { self.instances[entity].visible }
+    #[gml::set(visible)]
+    pub fn set_visible(&mut self, entity: vm::Entity, value: bool) {
+        self.instances[entity].visible = value;
+    }
+
+    #[gml::get(depth)]
+    pub fn get_depth(&self, entity: vm::Entity) -> f32 { self.instances[entity].depth }
+    #[gml::set(depth)]
+    pub fn set_depth(&mut self, entity: vm::Entity, value: f32) {
+        self.instances[entity].depth = value;
+    }
+
     #[gml::get(persistent)]
     pub fn get_persistent(&self, entity: vm::Entity) -> bool {
         self.instances[entity].persistent
@@ -96,6 +120,74 @@ impl State {
         }
     }
 
+    #[gml::api]
+    pub fn instance_nearest(cx: &mut Context, x: f32, y: f32, obj: i32) -> i32 {
+        Self::instance_search(cx, x, y, obj, Search::Nearest)
+    }
+
+    #[gml::api]
+    pub fn instance_furthest(cx: &mut Context, x: f32, y: f32, obj: i32) -> i32 {
+        Self::instance_search(cx, x, y, obj, Search::Furthest)
+    }
+
+    /// Find the instance of `obj` nearest to or furthest from a point, returning its id.
+    ///
+    /// Ties go to the instance created first. Objects with only a few instances are scanned
+    /// directly, and the rest are searched through the motion grid.
+    fn instance_search(cx: &Context, x: f32, y: f32, obj: i32, search: Search) -> i32 {
+        let Context { world, assets } = cx;
+        let crate::World { world, instance, motion, .. } = world;
+
+        let entities = if obj == vm::ALL {
+            world.instances.values()
+        } else if obj < 100000 {
+            match world.objects.get(&obj) {
+                Some(entities) => entities,
+                None => return vm::NOONE,
+            }
+        } else if world.instances.contains_key(obj) {
+            return obj;
+        } else {
+            return vm::NOONE;
+        };
+
+        let entity = if entities.len() <= LINEAR_SEARCH_LIMIT {
+            let distance = |entity| {
+                let motion = &motion.instances[entity];
+                (motion.x - x).hypot(motion.y - y)
+            };
+            let candidates = entities.iter()
+                .map(|&entity| (distance(entity), instance.instances[entity].id, entity));
+            let best = match search {
+                Search::Nearest => candidates.min_by(|(a, a_id, _), (b, b_id, _)| {
+                    a.partial_cmp(b).unwrap_or(Ordering::Equal).then(a_id.cmp(b_id))
+                }),
+                Search::Furthest => candidates.min_by(|(a, a_id, _), (b, b_id, _)| {
+                    b.partial_cmp(a).unwrap_or(Ordering::Equal).then(a_id.cmp(b_id))
+                }),
+            };
+            best.map(|(_, _, entity)| entity)
+        } else {
+            // The grid also holds instances that are destroyed but not yet freed.
+            let key = |entity| {
+                let inst = instance.instances.get(entity)?;
+                if !world.instances.contains_key(inst.id) {
+                    return None;
+                }
+                if obj != vm::ALL && !assets.ancestors(inst.object_index).any(|o| o == obj) {
+                    return None;
+                }
+                Some(inst.id)
+            };
+            match search {
+                Search::Nearest => motion.grid.nearest(x, y, key),
+                Search::Furthest => motion.grid.furthest(x, y, key),
+            }
+        };
+
+        entity.map_or(vm::NOONE, |entity| instance.instances[entity].id)
+    }
+
     #[gml::api]
     pub fn instance_create(
         cx: &mut Context, thread: &mut vm::Thread,
@@ -109,9 +201,8 @@ impl State {
 
         let Context { assets, .. } = cx;
         let event_type = project::event_type::CREATE;
-        let create = gml::Function::Event { event_type, event_kind: 0, object_index };
-        if assets.code.code.contains_key(&create) {
-            thread.with(entity).execute(cx, create, vec![])?;
+        if let Some(create) = assets.find_event(object_index, event_type, 0) {
+            replay::State::execute(cx, &mut thread.with(entity), create)?;
         }
 
         Ok(id)
@@ -122,19 +213,67 @@ impl State {
     {
         let Context { world, assets } = cx;
 
-        let persistent = assets.objects[object_index as usize].persistent;
+        let crate::Object { sprite, solid, visible, depth, persistent, mask, .. } =
+            assets.objects[object_index as usize];
 
-        let crate::World { world, instance, motion, .. } = world;
+        let crate::World { world, instance, motion, alarm, sprite: image, collision, .. } = world;
         let entity = world.create_entity();
-        world.add_entity(entity, object_index, id);
-        let inst = Instance { object_index, id, persistent };
+        world.add_entity(entity, assets.ancestors(object_index), id);
+        let inst = Instance { object_index, id, visible, depth: depth as f32, persistent };
         instance.instances.insert(entity, inst);
-        let instance = motion::Instance::from_pos(x, y);
-        motion.instances.insert(entity, instance);
+        motion.insert(entity, motion::Instance::from_pos(x, y));
+        alarm.instances.insert(entity, alarm::Instance::default());
+        image.insert(entity, sprite::Instance::from_sprite(sprite));
+        collision.instances.insert(entity, collision::Instance { solid, mask_index: mask });
 
         entity
     }
 
+    /// Run an event for every instance whose object defines or inherits it, in object order.
+    pub fn event(cx: &mut Context, thread: &mut vm::Thread, event_type: u32, event_kind: i32) ->
+        vm::Result<()>
+    {
+        Self::dispatch(cx, thread, event_type, event_kind, |cx, thread, entity, event| {
+            replay::State::execute(cx, &mut thread.with(entity), event)?;
+            Ok(())
+        })
+    }
+
+    /// Call `f` for every instance whose object defines or inherits an event, in object order.
+    pub fn dispatch<F>(
+        cx: &mut Context, thread: &mut vm::Thread, event_type: u32, event_kind: i32, mut f: F
+    ) -> vm::Result<()> where
+        F: FnMut(&mut Context, &mut vm::Thread, vm::Entity, gml::Function) -> vm::Result<()>
+    {
+        let Context { assets, .. } = cx;
+        for object_index in 0..assets.objects.len() as i32 {
+            let Context { world, assets } = cx;
+            let event = match assets.find_event(object_index, event_type, event_kind) {
+                Some(event) => event,
+                None => continue,
+            };
+            let entities = match world.world.objects.get(&object_index) {
+                Some(entities) => entities.clone(),
+                None => continue,
+            };
+
+            for &entity in entities.iter() {
+                // Skip instances of descendants, which run their own object's handler,
+                // and instances destroyed earlier in this pass.
+                let Context { world, .. } = cx;
+                let crate::World { world, instance, .. } = world;
+                let inst = &instance.instances[entity];
+                if inst.object_index != object_index || !world.instances.contains_key(inst.id) {
+                    continue;
+                }
+
+                f(cx, thread, entity, event)?;
+            }
+        }
+
+        Ok(())
+    }
+
     #[gml::api]
     pub fn instance_destroy(cx: &mut Context, thread: &mut vm::Thread, entity: vm::Entity) ->
         vm::Result<()>
@@ -146,23 +285,50 @@ impl State {
             Some(instance) => instance,
             None => return Ok(()),
         };
-        let destroy = gml::Function::Event { event_type, event_kind: 0, object_index };
-        if assets.code.code.contains_key(&destroy) {
-            thread.with(entity).execute(cx, destroy, vec![])?;
+        if let Some(destroy) = assets.find_event(object_index, event_type, 0) {
+            replay::State::execute(cx, &mut thread.with(entity), destroy)?;
         }
 
-        let Context { world, .. } = cx;
+        let Context { world, assets } = cx;
         let crate::World { world, instance, .. } = world;
-        world.remove_entity(entity, object_index, id);
+        world.remove_entity(entity, assets.ancestors(object_index), id);
         instance.destroyed.push(entity);
 
         Ok(())
     }
 
-    pub fn free_destroyed(&mut self, world: &mut vm::World, motion: &mut motion::State) {
-        for entity in self.destroyed.drain(..) {
-            motion.instances.remove(entity);
-            self.instances.remove(entity);
+    /// Run the parent's handler for the event currently running, even from within a script.
+    #[gml::api]
+    pub fn event_inherited(cx: &mut Context, thread: &mut vm::Thread) -> vm::Result<()> {
+        let caller = thread.callers()
+            .find(|function| !matches!(function, gml::Function::Script { .. }));
+        let (object_index, event_type, event_kind) = match caller {
+            Some(gml::Function::Event { object_index, event_type, event_kind }) =>
+                (object_index, event_type, event_kind),
+            _ => return Ok(()),
+        };
+
+        let Context { assets, .. } = cx;
+        let parent = match assets.objects.get(object_index as usize) {
+            Some(&crate::Object { parent, .. }) if parent >= 0 => parent,
+            _ => return Ok(()),
+        };
+        if let Some(event) = assets.find_event(parent, event_type, event_kind) {
+            thread.execute(cx, event, vec![])?;
+        }
+
+        Ok(())
+    }
+
+    /// Release the components of instances destroyed since the last call.
+    pub fn free_destroyed(world: &mut crate::World) {
+        let crate::World { world, instance, motion, alarm, sprite, collision, .. } = world;
+        for entity in instance.destroyed.drain(..) {
+            motion.remove(entity);
+            alarm.instances.remove(entity);
+            sprite.remove(entity);
+            collision.instances.remove(entity);
+            instance.instances.remove(entity);
             world.destroy_entity(entity);
         }
     }
@@ -188,3 +354,43 @@ impl State {
         Self::instance_destroy(cx, thread, entity)
     }
 }
+
+impl vm::Serialize for State {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        out.write(&self.next_id)?;
+        out.write(&self.instances)?;
+        out.write(&self.destroyed)
+    }
+}
+
+impl vm::Deserialize for State {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        Ok(State {
+            next_id: input.read()?,
+            instances: input.read()?,
+            destroyed: input.read()?,
+        })
+    }
+}
+
+impl vm::Serialize for Instance {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        out.write(&self.object_index)?;
+        out.write(&self.id)?;
+        out.write(&self.visible)?;
+        out.write(&self.depth)?;
+        out.write(&self.persistent)
+    }
+}
+
+impl vm::Deserialize for Instance {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        Ok(Instance {
+            object_index: input.read()?,
+            id: input.read()?,
+            visible: input.read()?,
+            depth: input.read()?,
+            persistent: input.read()?,
+        })
+    }
+}
@@ -1,7 +1,8 @@
 use std::collections::HashMap;
-use std::io;
+use std::time::{Duration, Instant};
+use std::{io, iter, thread};
 
-use gml::vm;
+use gml::{symbol::Symbol, vm};
 
 pub use crate::world::World;
 
@@ -9,11 +10,20 @@ mod world;
 pub mod real;
 pub mod string;
 pub mod motion;
+pub mod grid;
+pub mod alarm;
+pub mod sprite;
+pub mod collision;
+pub mod input;
 pub mod instance;
 pub mod room;
+pub mod draw;
+pub mod raster;
 pub mod show;
 pub mod control;
 pub mod data;
+pub mod replay;
+pub mod save;
 
 pub struct Context {
     pub world: World,
@@ -23,16 +33,75 @@ pub struct Context {
 #[derive(Default)]
 pub struct Assets {
     pub code: vm::Assets<Context>,
+    pub sprites: Vec<Sprite>,
     pub objects: Vec<Object>,
     pub rooms: Vec<Room>,
     pub next_instance: i32,
+    /// The furthest any sprite's bounding box reaches from its origin, at a scale of 1.
+    pub sprite_reach: f32,
+}
+
+#[derive(Clone)]
+pub struct Sprite {
+    pub name: Symbol,
+    pub origin_x: i32,
+    pub origin_y: i32,
+    pub frames: Vec<Frame>,
+    /// Collision masks, one per frame or one shared by all frames. Sprites without masks
+    /// collide using their bounding box.
+    pub masks: Vec<Mask>,
+    pub bbox_left: i32,
+    pub bbox_right: i32,
+    pub bbox_top: i32,
+    pub bbox_bottom: i32,
+}
+
+impl Sprite {
+    /// The furthest the bounding box reaches from the origin, at a scale of 1.
+    pub fn reach(&self) -> f32 {
+        let (origin_x, origin_y) = (self.origin_x as f32, self.origin_y as f32);
+        let dx = f32::max(
+            (self.bbox_left as f32 - origin_x).abs(),
+            (self.bbox_right as f32 + 1.0 - origin_x).abs(),
+        );
+        let dy = f32::max(
+            (self.bbox_top as f32 - origin_y).abs(),
+            (self.bbox_bottom as f32 + 1.0 - origin_y).abs(),
+        );
+        dx.hypot(dy)
+    }
+}
+
+#[derive(Clone)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    /// Pixels in RGBA order, row by row.
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone)]
+pub struct Mask {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<bool>,
 }
 
 pub struct Object {
+    pub sprite: i32,
+    pub solid: bool,
+    pub visible: bool,
+    pub depth: i32,
     pub persistent: bool,
+    pub parent: i32,
+    pub mask: i32,
 }
 
 pub struct Room {
+    pub width: u32,
+    pub height: u32,
+    pub background_color: u32,
+    pub draw_background_color: bool,
     pub instances: Vec<Instance>,
 }
 
@@ -43,17 +112,126 @@ pub struct Instance {
     pub id: i32,
 }
 
+impl Assets {
+    pub fn sprite(&self, sprite_index: i32) -> Option<&Sprite> {
+        if sprite_index < 0 {
+            return None;
+        }
+        self.sprites.get(sprite_index as usize)
+    }
+
+    pub fn add_sprite(&mut self, sprite: Sprite) -> i32 {
+        self.sprite_reach = f32::max(self.sprite_reach, sprite.reach());
+        self.sprites.push(sprite);
+        self.sprites.len() as i32 - 1
+    }
+
+    pub fn room(&self, room_index: i32) -> Option<&Room> {
+        if room_index < 0 {
+            return None;
+        }
+        self.rooms.get(room_index as usize)
+    }
+
+    /// An object followed by its chain of parents.
+    pub fn ancestors(&self, object_index: i32) -> impl Iterator<Item = i32> + '_ {
+        // Stop after visiting every object, in case the parents form a cycle.
+        let parent = move |&object_index: &i32| {
+            let &Object { parent, .. } = self.objects.get(object_index as usize)?;
+            Some(parent).filter(|&parent| parent >= 0)
+        };
+        iter::successors(Some(object_index), parent).take(self.objects.len())
+    }
+
+    /// Find an object's handler for an event, defined by the object itself or inherited from
+    /// its nearest ancestor.
+    pub fn find_event(&self, object_index: i32, event_type: u32, event_kind: i32) ->
+        Option<gml::Function>
+    {
+        self.ancestors(object_index)
+            .map(|object_index| gml::Function::Event { event_type, event_kind, object_index })
+            .find(|event| self.code.code.contains_key(event))
+    }
+}
+
 /// Build a Game Maker project.
 pub fn build<'a, F: FnMut() -> E, E: io::Write + 'static>(game: &'a project::Game, errors: F) ->
     Result<(Assets, vm::Debug), u32>
 {
+    build_with_options(game, gml::Options::default(), errors)
+}
+
+/// Build a Game Maker project, with non-default compiler options.
+pub fn build_with_options<F: FnMut() -> E, E: io::Write + 'static>(
+    game: &project::Game, options: gml::Options, errors: F
+) -> Result<(Assets, vm::Debug), u32> {
+    let assets = resources(game);
+    let mut items = HashMap::default();
+    World::register(&mut items);
+    match gml::build_with_options(game, &items, options, errors) {
+        Ok((code, debug)) => Ok((Assets { code, ..assets }, debug)),
+        Err(count) => Err(count),
+    }
+}
+
+/// Load a Game Maker project with code compiled ahead of time, as saved by `gml::write_build`.
+pub fn load_build<R: io::Read>(game: &project::Game, read: R) ->
+    io::Result<(Assets, vm::Debug)>
+{
+    let assets = resources(game);
+    let mut items = HashMap::default();
+    World::register(&mut items);
+    let (code, debug) = gml::read_build(&items, read)?;
+    Ok((Assets { code, ..assets }, debug))
+}
+
+/// Convert a project's resources, other than its code.
+fn resources(game: &project::Game) -> Assets {
     let mut assets = Assets::default();
+    assets.sprites = game.sprites.iter()
+        .map(|sprite| {
+            let frames: Vec<_> = sprite.frames.iter()
+                .map(|frame| Frame {
+                    width: frame.width,
+                    height: frame.height,
+                    data: frame.data.chunks(4)
+                        .flat_map(|bgra| vec![bgra[2], bgra[1], bgra[0], bgra[3]])
+                        .collect(),
+                })
+                .collect();
+            let masks = if !sprite.masks.is_empty() {
+                sprite.masks.iter().map(Mask::from_project).collect()
+            } else if sprite.precise {
+                Mask::from_frames(&frames, sprite.separate_masks, sprite.alpha_tolerance)
+            } else {
+                Vec::default()
+            };
+            Sprite {
+                name: Symbol::intern(sprite.name),
+                origin_x: sprite.origin_x,
+                origin_y: sprite.origin_y,
+                frames,
+                masks,
+                bbox_left: sprite.bbox_left,
+                bbox_right: sprite.bbox_right,
+                bbox_top: sprite.bbox_top,
+                bbox_bottom: sprite.bbox_bottom,
+            }
+        })
+        .collect();
+    assets.sprite_reach = assets.sprites.iter().map(Sprite::reach).fold(0.0, f32::max);
     assets.objects = game.objects.iter()
-        .map(|&project::Object { persistent, .. }| Object { persistent })
+        .map(|&project::Object {
+            sprite, solid, visible, depth, persistent, parent, mask, ..
+        }| Object { sprite, solid, visible, depth, persistent, parent, mask })
         .collect();
     assets.rooms = game.rooms.iter()
-        .map(|&project::Room { ref instances, .. }| Room {
-            instances: instances.iter()
+        .map(|room| Room {
+            width: room.width,
+            height: room.height,
+            background_color: room.background_color,
+            draw_background_color: room.draw_background_color,
+            instances: room.instances.iter()
                 .map(|&project::Instance { x, y, object_index, id, .. }| Instance {
                     x, y, object_index, id
                 })
@@ -61,20 +239,1083 @@ pub fn build<'a, F: FnMut() -> E, E: io::Write + 'static>(game: &'a project::Gam
         })
         .collect();
     assets.next_instance = game.last_instance + 1;
+    assets
+}
 
-    let mut items = HashMap::default();
-    World::register(&mut items);
-    match gml::build(game, &items, errors) {
-        Ok((code, debug)) => Ok((Assets { code, ..assets }, debug)),
-        Err(count) => Err(count),
+/// Load the first room of a Game Maker game.
+pub fn start(cx: &mut Context, thread: &mut vm::Thread) -> vm::Result<()> {
+    use project::{event_type, event_kind};
+
+    replay::State::start(cx);
+    room::State::load_room(cx, thread, 0)?;
+
+    instance::State::event(cx, thread, event_type::OTHER, event_kind::GAME_START)?;
+    free_destroyed(cx);
+
+    instance::State::event(cx, thread, event_type::OTHER, event_kind::ROOM_START)?;
+    free_destroyed(cx);
+
+    Ok(())
+}
+
+/// Run a single frame of a Game Maker game.
+pub fn frame(cx: &mut Context, thread: &mut vm::Thread) -> vm::Result<()> {
+    use project::{event_type, event_kind};
+
+    replay::State::frame(cx)?;
+
+    let Context { world, .. } = cx;
+    world.motion.update_previous(&world.world);
+
+    instance::State::event(cx, thread, event_type::STEP, event_kind::STEP_BEGIN)?;
+    free_destroyed(cx);
+
+    alarm::State::step(cx, thread)?;
+    free_destroyed(cx);
+
+    input::State::step(cx, thread)?;
+    free_destroyed(cx);
+
+    instance::State::event(cx, thread, event_type::STEP, event_kind::STEP_NORMAL)?;
+    free_destroyed(cx);
+
+    let Context { world, .. } = cx;
+    world.motion.update_position(&world.world);
+
+    sprite::State::animate(cx, thread)?;
+    free_destroyed(cx);
+
+    collision::State::step(cx, thread)?;
+    free_destroyed(cx);
+
+    instance::State::event(cx, thread, event_type::STEP, event_kind::STEP_END)?;
+    free_destroyed(cx);
+
+    draw::State::draw(cx, thread)?;
+    free_destroyed(cx);
+
+    if cx.world.room.end {
+        instance::State::event(cx, thread, event_type::OTHER, event_kind::GAME_END)?;
+    } else {
+        room::State::change_room(cx, thread)?;
     }
+    free_destroyed(cx);
+
+    save::State::finish_load(cx)?;
+
+    cx.world.input.clear_pressed();
+
+    Ok(())
+}
+
+fn free_destroyed(cx: &mut Context) {
+    instance::State::free_destroyed(&mut cx.world);
 }
 
 // Run a Game Maker game.
-pub fn run(cx: &mut Context) {
+pub fn run(cx: &mut Context) -> vm::Result<()> {
+    run_thread(cx, &mut vm::Thread::default())
+}
+
+/// Run a Game Maker game on a thread set up by the caller, such as one with a debugger attached.
+pub fn run_thread(cx: &mut Context, thread: &mut vm::Thread) -> vm::Result<()> {
+    // TODO: use the room's speed
+    let frame_time = Duration::from_secs(1) / 30;
+
+    start(cx, thread)?;
+    let mut next_frame = Instant::now();
+    while !cx.world.room.end {
+        frame(cx, thread)?;
+
+        next_frame += frame_time;
+        if let Some(delay) = next_frame.checked_duration_since(Instant::now()) {
+            thread::sleep(delay);
+        }
+    }
+    Ok(())
+}
+
+/// Run a Game Maker game for a fixed number of frames, without waiting between them.
+pub fn run_frames(cx: &mut Context, frames: u32) -> vm::Result<()> {
     let mut thread = vm::Thread::default();
-    if let Err(error) = crate::room::State::load_room(cx, &mut thread, 0) {
-        let crate::World { show, .. } = &cx.world;
-        show.show_vm_error(&*error);
+    start(cx, &mut thread)?;
+    for _ in 0..frames {
+        if cx.world.room.end {
+            break;
+        }
+        frame(cx, &mut thread)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code_event<'a>(event_type: u32, event_kind: i32, code: &'a [u8]) -> project::Event<'a> {
+        project::Event {
+            event_type,
+            event_kind,
+            actions: vec![
+                project::Action {
+                    library: 1,
+                    action: 603,
+                    action_kind: project::action_kind::CODE,
+                    has_target: true,
+                    parameters_used: 1,
+                    parameters: vec![project::argument_type::STRING],
+                    target: vm::SELF,
+                    arguments: vec![code],
+                    ..project::Action::default()
+                },
+            ],
+        }
+    }
+
+    /// Build a game and run its first room's creation code.
+    fn start_game(game: &project::Game<'_>) -> vm::Result<(Context, vm::Thread)> {
+        let (assets, debug) = build(game, io::stderr).unwrap_or_else(|_| panic!());
+        let world = World::from_assets(&assets, debug);
+        let mut cx = Context { world, assets };
+        let mut thread = vm::Thread::default();
+        start(&mut cx, &mut thread)?;
+        Ok((cx, thread))
+    }
+
+    /// Build a game with one instance of an object with `events`, and run its room's creation code.
+    fn start_object(events: Vec<project::Event<'_>>) -> vm::Result<(Context, vm::Thread, i32)> {
+        let mut game = project::Game::default();
+        let object_index = game.objects.len() as i32;
+        game.objects.push(project::Object { name: b"obj", events, ..project::Object::default() });
+        game.last_instance += 1;
+        let id = game.last_instance;
+        game.rooms.push(project::Room {
+            instances: vec![project::Instance { x: 0, y: 0, object_index, id, code: b"" }],
+            ..project::Room::default()
+        });
+
+        let (cx, thread) = start_game(&game)?;
+        Ok((cx, thread, id))
+    }
+
+    fn member(cx: &Context, id: i32, name: &[u8]) -> vm::Value {
+        let entity = cx.world.world.instances[id];
+        cx.world.world.members[entity][&gml::symbol::Symbol::intern(name)].clone()
+    }
+
+    /// Run the step events in order, and move instances between step and end step.
+    #[test]
+    fn frame_order() -> vm::Result<()> {
+        use project::{event_type, event_kind};
+
+        let (mut cx, mut thread, id) = start_object(vec![
+            code_event(event_type::CREATE, 0, b"order = 0; hspeed = 2"),
+            code_event(event_type::STEP, event_kind::STEP_BEGIN, b"order = order * 10 + 1"),
+            code_event(event_type::STEP, event_kind::STEP_NORMAL, b"order = order * 10 + 2"),
+            code_event(event_type::STEP, event_kind::STEP_END, b"
+                order = order * 10 + 3
+                moved = x
+            "),
+        ])?;
+        frame(&mut cx, &mut thread)?;
+        frame(&mut cx, &mut thread)?;
+
+        assert_eq!(member(&cx, id, b"order"), vm::Value::from(123123));
+        assert_eq!(member(&cx, id, b"moved"), vm::Value::from(4));
+        let entity = cx.world.world.instances[id];
+        assert_eq!(cx.world.motion.instances[entity].xprevious, 2.0);
+
+        Ok(())
+    }
+
+    /// Fire alarm events when they count down to zero, and not for alarms without events.
+    #[test]
+    fn alarms() -> vm::Result<()> {
+        use project::event_type;
+
+        let (mut cx, mut thread, id) = start_object(vec![
+            code_event(event_type::CREATE, 0, b"count = 0; alarm[0] = 2; alarm[1] = 2"),
+            code_event(event_type::ALARM, 0, b"count += 1; alarm[0] = 2"),
+            code_event(event_type::STEP, 0, b"left = alarm[1]"),
+        ])?;
+        for _ in 0..5 {
+            frame(&mut cx, &mut thread)?;
+        }
+
+        assert_eq!(member(&cx, id, b"count"), vm::Value::from(2));
+        assert_eq!(member(&cx, id, b"left"), vm::Value::from(2));
+
+        Ok(())
+    }
+
+    /// Inherit events from parent objects, and include descendants in a parent's instances.
+    #[test]
+    fn inheritance() -> vm::Result<()> {
+        use project::event_type;
+
+        let mut game = project::Game::default();
+        game.scripts.push(project::Script { name: b"scr_inherit", body: b"event_inherited()" });
+        let base = game.objects.len() as i32;
+        game.objects.push(project::Object {
+            name: b"obj_base",
+            events: vec![
+                code_event(event_type::CREATE, 0, b"hp = 10; steps = 0"),
+                code_event(event_type::STEP, 0, b"steps += 1"),
+            ],
+            ..project::Object::default()
+        });
+        let child = game.objects.len() as i32;
+        game.objects.push(project::Object {
+            name: b"obj_child",
+            parent: base,
+            events: vec![
+                code_event(event_type::CREATE, 0, b"event_inherited(); hp += 5"),
+                code_event(event_type::STEP, 0, b"scr_inherit(); steps += 10"),
+            ],
+            ..project::Object::default()
+        });
+        let counter = game.objects.len() as i32;
+        game.objects.push(project::Object {
+            name: b"obj_counter",
+            events: vec![code_event(event_type::STEP, 0, b"
+                count = instance_number(0)
+                total = 0
+                with (0) other.total += hp
+            ")],
+            ..project::Object::default()
+        });
+        let (base_id, child_id, counter_id) = (100001, 100002, 100003);
+        game.last_instance = counter_id;
+        game.rooms.push(project::Room {
+            instances: vec![
+                project::Instance { x: 0, y: 0, object_index: base, id: base_id, code: b"" },
+                project::Instance { x: 0, y: 0, object_index: child, id: child_id, code: b"" },
+                project::Instance { x: 0, y: 0, object_index: counter, id: counter_id, code: b"" },
+            ],
+            ..project::Room::default()
+        });
+
+        let (mut cx, mut thread) = start_game(&game)?;
+        frame(&mut cx, &mut thread)?;
+
+        assert_eq!(member(&cx, base_id, b"hp"), vm::Value::from(10));
+        assert_eq!(member(&cx, child_id, b"hp"), vm::Value::from(15));
+        assert_eq!(member(&cx, base_id, b"steps"), vm::Value::from(1));
+        assert_eq!(member(&cx, child_id, b"steps"), vm::Value::from(11));
+        assert_eq!(member(&cx, counter_id, b"count"), vm::Value::from(2));
+        assert_eq!(member(&cx, counter_id, b"total"), vm::Value::from(25));
+
+        Ok(())
+    }
+
+    /// Switch rooms at the end of a frame, keeping persistent instances.
+    #[test]
+    fn room_transitions() -> vm::Result<()> {
+        use project::{event_type, event_kind};
+
+        let mut game = project::Game::default();
+        let controller = game.objects.len() as i32;
+        game.objects.push(project::Object {
+            name: b"obj_controller",
+            persistent: true,
+            events: vec![
+                code_event(event_type::CREATE, 0, b"creates = 1; starts = 0; ends = 0"),
+                code_event(event_type::STEP, 0, b"
+                    if room == room_first {
+                        room_goto_next()
+                    } else {
+                        room_restart()
+                    }
+                "),
+                code_event(event_type::OTHER, event_kind::GAME_START, b"game_starts = 1"),
+                code_event(event_type::OTHER, event_kind::ROOM_START, b"starts += 1"),
+                code_event(event_type::OTHER, event_kind::ROOM_END, b"ends += 1"),
+            ],
+            ..project::Object::default()
+        });
+        let temporary = game.objects.len() as i32;
+        game.objects.push(project::Object { name: b"obj_temporary", ..project::Object::default() });
+
+        let (controller_id, first_id, second_id) = (100001, 100002, 100003);
+        game.last_instance = second_id;
+        game.rooms.push(project::Room {
+            name: b"rm_first",
+            instances: vec![
+                project::Instance {
+                    x: 0, y: 0, object_index: controller, id: controller_id, code: b""
+                },
+                project::Instance { x: 0, y: 0, object_index: temporary, id: first_id, code: b"" },
+            ],
+            ..project::Room::default()
+        });
+        game.rooms.push(project::Room {
+            name: b"rm_second",
+            instances: vec![
+                project::Instance {
+                    x: 0, y: 0, object_index: controller, id: controller_id, code: b""
+                },
+                project::Instance { x: 0, y: 0, object_index: temporary, id: second_id, code: b"" },
+            ],
+            ..project::Room::default()
+        });
+
+        let (mut cx, mut thread) = start_game(&game)?;
+        assert_eq!(member(&cx, controller_id, b"starts"), vm::Value::from(1));
+
+        frame(&mut cx, &mut thread)?;
+        assert_eq!(cx.world.room.room, 1);
+        assert!(!cx.world.world.instances.contains_key(first_id));
+        assert!(cx.world.world.instances.contains_key(second_id));
+        assert_eq!(member(&cx, controller_id, b"creates"), vm::Value::from(1));
+        assert_eq!(member(&cx, controller_id, b"starts"), vm::Value::from(2));
+        assert_eq!(member(&cx, controller_id, b"ends"), vm::Value::from(1));
+
+        frame(&mut cx, &mut thread)?;
+        assert_eq!(cx.world.room.room, 1);
+        assert_eq!(cx.world.world.instances.len(), 2);
+        assert_eq!(member(&cx, controller_id, b"game_starts"), vm::Value::from(1));
+        assert_eq!(member(&cx, controller_id, b"starts"), vm::Value::from(3));
+        assert_eq!(member(&cx, controller_id, b"ends"), vm::Value::from(2));
+
+        assert!(room::State::room_goto_next(&mut cx).is_err());
+        assert!(room::State::room_goto(&mut cx, -1).is_err());
+        cx.world.room.transition = Some(2);
+        assert!(room::State::change_room(&mut cx, &mut thread).is_err());
+        assert_eq!(cx.world.room.room, 1);
+
+        Ok(())
+    }
+
+    /// Draw visible instances from back to front, over the room's background color.
+    #[test]
+    fn draw_events() -> vm::Result<()> {
+        use project::event_type;
+
+        let mut game = project::Game::default();
+        let front = game.objects.len() as i32;
+        game.objects.push(project::Object {
+            name: b"obj_front",
+            depth: -10,
+            events: vec![code_event(event_type::DRAW, 0, b"
+                draw_set_color(c_red)
+                draw_rectangle(0, 0, 1, 3, false)
+            ")],
+            ..project::Object::default()
+        });
+        let back = game.objects.len() as i32;
+        game.objects.push(project::Object {
+            name: b"obj_back",
+            depth: 10,
+            events: vec![code_event(event_type::DRAW, 0, b"
+                draw_set_color(c_lime)
+                draw_rectangle(0, 0, 3, 1, false)
+            ")],
+            ..project::Object::default()
+        });
+        let hidden = game.objects.len() as i32;
+        game.objects.push(project::Object {
+            name: b"obj_hidden",
+            visible: false,
+            depth: -20,
+            events: vec![code_event(event_type::DRAW, 0, b"
+                draw_set_color(c_white)
+                draw_rectangle(0, 0, 3, 3, false)
+            ")],
+            ..project::Object::default()
+        });
+        game.last_instance = 100003;
+        game.rooms.push(project::Room {
+            width: 4,
+            height: 4,
+            background_color: 0xFF0000,
+            draw_background_color: true,
+            instances: vec![
+                project::Instance { x: 0, y: 0, object_index: front, id: 100001, code: b"" },
+                project::Instance { x: 0, y: 0, object_index: back, id: 100002, code: b"" },
+                project::Instance { x: 0, y: 0, object_index: hidden, id: 100003, code: b"" },
+            ],
+            ..project::Room::default()
+        });
+
+        let (mut cx, mut thread) = start_game(&game)?;
+        frame(&mut cx, &mut thread)?;
+
+        let canvas = &cx.world.draw.canvas;
+        let pixel = |x: usize, y: usize| &canvas.pixels[(y * 4 + x) * 4..][..4];
+        assert_eq!(pixel(0, 0), [255, 0, 0, 255]);
+        assert_eq!(pixel(3, 0), [0, 255, 0, 255]);
+        assert_eq!(pixel(0, 3), [255, 0, 0, 255]);
+        assert_eq!(pixel(3, 3), [0, 0, 255, 255]);
+
+        Ok(())
+    }
+
+    /// Read sprite properties through instance variables, and animate through subimages.
+    #[test]
+    fn sprites() -> vm::Result<()> {
+        use project::{event_type, event_kind};
+
+        let mut game = project::Game::default();
+        let pixels = [0; 3 * 2 * 4];
+        game.sprites.push(project::Sprite {
+            name: b"spr_main",
+            origin_x: 1,
+            origin_y: 1,
+            frames: vec![
+                project::Frame { width: 3, height: 2, data: &pixels },
+                project::Frame { width: 3, height: 2, data: &pixels },
+            ],
+            bbox_right: 2,
+            bbox_bottom: 1,
+            ..project::Sprite::default()
+        });
+        game.objects.push(project::Object {
+            name: b"obj_main",
+            sprite: 0,
+            events: vec![
+                code_event(event_type::CREATE, 0, b"
+                    ends = 0
+                    image_speed = 0.5
+                    image_xscale = 2
+                    width = sprite_width
+                    number = image_number
+                    offset = sprite_get_xoffset(sprite_index)
+                    copy = sprite_duplicate(sprite_index)
+                    copy_width = sprite_get_width(copy)
+                    missing = sprite_exists(sprite_index + 10)
+                "),
+                code_event(event_type::OTHER, event_kind::ANIMATION_END, b"ends += 1"),
+            ],
+            ..project::Object::default()
+        });
+        game.last_instance = 100001;
+        game.rooms.push(project::Room {
+            instances: vec![
+                project::Instance { x: 0, y: 0, object_index: 0, id: 100001, code: b"" },
+            ],
+            ..project::Room::default()
+        });
+
+        let (mut cx, mut thread) = start_game(&game)?;
+        for _ in 0..5 {
+            frame(&mut cx, &mut thread)?;
+        }
+
+        let id = 100001;
+        assert_eq!(member(&cx, id, b"width"), vm::Value::from(6));
+        assert_eq!(member(&cx, id, b"number"), vm::Value::from(2));
+        assert_eq!(member(&cx, id, b"offset"), vm::Value::from(1));
+        assert_eq!(member(&cx, id, b"copy"), vm::Value::from(1));
+        assert_eq!(member(&cx, id, b"copy_width"), vm::Value::from(3));
+        assert_eq!(member(&cx, id, b"missing"), vm::Value::from(false));
+        assert_eq!(member(&cx, id, b"ends"), vm::Value::from(1));
+        let entity = cx.world.world.instances[id];
+        assert_eq!(cx.world.sprite.instances[entity].image_index, 0.5);
+
+        Ok(())
+    }
+
+    /// Load a sprite strip from a PNG file, keying out the bottom left pixel's color.
+    #[test]
+    fn sprite_add() -> vm::Result<()> {
+        let mut canvas = raster::Canvas::new(4, 2);
+        canvas.clear(0xFF00FF);
+        canvas.point(1, 0, 0x0000FF, 1.0);
+        canvas.point(2, 1, 0x00FF00, 1.0);
+        let path = std::env::temp_dir().join(format!("sprite_add_{}.png", std::process::id()));
+        canvas.write_png(std::fs::File::create(&path).unwrap()).unwrap();
+
+        let mut game = project::Game::default();
+        game.rooms.push(project::Room::default());
+        let (mut cx, _) = start_game(&game)?;
+
+        let fname = Symbol::intern(path.to_str().unwrap().as_bytes());
+        let Context { world, assets } = &mut cx;
+        let replay = &mut world.replay;
+        let ind = sprite::State::sprite_add(
+            assets, replay, fname, 2, false, true, false, false, 0, 0
+        )?;
+        let narrow = sprite::State::sprite_add(
+            assets, replay, fname, 5, false, true, false, false, 0, 0
+        )?;
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(narrow, -1);
+
+        let sprite = &cx.assets.sprites[ind as usize];
+        assert_eq!(sprite.frames.len(), 2);
+        assert_eq!((sprite.frames[0].width, sprite.frames[0].height), (2, 2));
+        assert_eq!(sprite.frames[0].data[4..8], [255, 0, 0, 255]);
+        assert_eq!(sprite.frames[0].data[0..4], [255, 0, 255, 0]);
+        assert_eq!((sprite.bbox_left, sprite.bbox_right), (0, 1));
+        assert_eq!((sprite.bbox_top, sprite.bbox_bottom), (0, 1));
+
+        Ok(())
+    }
+
+    /// Run collision events with `other` bound, stopping solid instances at their previous
+    /// positions.
+    #[test]
+    fn collision_events() -> vm::Result<()> {
+        use project::event_type;
+
+        let mut game = project::Game::default();
+        let pixels = [255; 4 * 4 * 4];
+        game.sprites.push(project::Sprite {
+            name: b"spr_block",
+            frames: vec![project::Frame { width: 4, height: 4, data: &pixels }],
+            bbox_right: 3,
+            bbox_bottom: 3,
+            ..project::Sprite::default()
+        });
+        let wall = game.objects.len() as i32;
+        game.objects.push(project::Object {
+            name: b"obj_wall",
+            sprite: 0,
+            solid: true,
+            ..project::Object::default()
+        });
+        let mover = game.objects.len() as i32;
+        game.objects.push(project::Object {
+            name: b"obj_mover",
+            sprite: 0,
+            events: vec![
+                code_event(event_type::CREATE, 0, b"hits = 0; hspeed = 3"),
+                code_event(event_type::COLLISION, wall, b"
+                    hits += 1
+                    wall = other.id
+                    stopped = x
+                    hspeed = 0
+                "),
+            ],
+            ..project::Object::default()
+        });
+        game.last_instance = 100002;
+        game.rooms.push(project::Room {
+            instances: vec![
+                project::Instance { x: 10, y: 0, object_index: wall, id: 100001, code: b"" },
+                project::Instance { x: 0, y: 2, object_index: mover, id: 100002, code: b"" },
+            ],
+            ..project::Room::default()
+        });
+
+        let (mut cx, mut thread) = start_game(&game)?;
+        for _ in 0..4 {
+            frame(&mut cx, &mut thread)?;
+        }
+
+        let id = 100002;
+        assert_eq!(member(&cx, id, b"hits"), vm::Value::from(1));
+        assert_eq!(member(&cx, id, b"wall"), vm::Value::from(100001));
+        assert_eq!(member(&cx, id, b"stopped"), vm::Value::from(6));
+
+        Ok(())
+    }
+
+    /// Find collision events through the motion grid when the other object has many instances.
+    #[test]
+    fn crowded_collision_events() -> vm::Result<()> {
+        use project::event_type;
+
+        let mut game = project::Game::default();
+        let pixels = [255; 4 * 4 * 4];
+        game.sprites.push(project::Sprite {
+            name: b"spr_block",
+            frames: vec![project::Frame { width: 4, height: 4, data: &pixels }],
+            bbox_right: 3,
+            bbox_bottom: 3,
+            ..project::Sprite::default()
+        });
+        let wall = game.objects.len() as i32;
+        game.objects.push(project::Object {
+            name: b"obj_wall",
+            sprite: 0,
+            solid: true,
+            ..project::Object::default()
+        });
+        let mover = game.objects.len() as i32;
+        game.objects.push(project::Object {
+            name: b"obj_mover",
+            sprite: 0,
+            events: vec![
+                code_event(event_type::CREATE, 0, b"
+                    for (i = 0; i < 40; i += 1) {
+                        instance_create(i * 50, 100, 0)
+                    }
+                    hits = 0
+                    hspeed = 3
+                "),
+                code_event(event_type::COLLISION, wall, b"
+                    hits += 1
+                    wall = other.x
+                    stopped = x
+                    hspeed = 0
+                "),
+            ],
+            ..project::Object::default()
+        });
+        game.last_instance = 100001;
+        game.rooms.push(project::Room {
+            instances: vec![
+                project::Instance { x: 1040, y: 101, object_index: mover, id: 100001, code: b"" },
+            ],
+            ..project::Room::default()
+        });
+
+        let (mut cx, mut thread) = start_game(&game)?;
+        for _ in 0..4 {
+            frame(&mut cx, &mut thread)?;
+        }
+
+        let id = 100001;
+        assert_eq!(member(&cx, id, b"hits"), vm::Value::from(1));
+        assert_eq!(member(&cx, id, b"wall"), vm::Value::from(1050));
+        assert_eq!(member(&cx, id, b"stopped"), vm::Value::from(1046));
+
+        Ok(())
+    }
+
+    /// Query instances by bounding box, and by precise masks built from sprite alpha.
+    #[test]
+    fn collision_queries() -> vm::Result<()> {
+        let mut game = project::Game::default();
+        // A 4x4 sprite with only its diagonal opaque.
+        let mut pixels = [0; 4 * 4 * 4];
+        for i in 0..4 {
+            pixels[(i * 4 + i) * 4 + 3] = 255;
+        }
+        game.sprites.push(project::Sprite {
+            name: b"spr_diagonal",
+            frames: vec![project::Frame { width: 4, height: 4, data: &pixels }],
+            bbox_right: 3,
+            bbox_bottom: 3,
+            precise: true,
+            ..project::Sprite::default()
+        });
+        let target = game.objects.len() as i32;
+        game.objects.push(project::Object {
+            name: b"obj_target",
+            sprite: 0,
+            ..project::Object::default()
+        });
+        game.objects.push(project::Object {
+            name: b"obj_probe",
+            events: vec![code_event(project::event_type::CREATE, 0, b"
+                left = bbox_left
+                diagonal = position_meeting(22, 12, 0)
+                corner = position_meeting(23, 10, 0)
+                rough = collision_point(23, 10, 0, false, true)
+                fine = collision_rectangle(22, 10, 23, 12, 0, true, true)
+                circle = collision_circle(30, 10, 7, 0, true, true)
+                line = collision_line(20, 13, 23, 10, 0, true, true)
+                far_line = collision_line(-1000000000, 12, 1000000000, 12, 0, true, true)
+                empty = place_empty(0, 0)
+            ")],
+            ..project::Object::default()
+        });
+        game.last_instance = 100002;
+        game.rooms.push(project::Room {
+            instances: vec![
+                project::Instance { x: 20, y: 10, object_index: target, id: 100001, code: b"" },
+                project::Instance { x: 0, y: 0, object_index: 1, id: 100002, code: b"" },
+            ],
+            ..project::Room::default()
+        });
+
+        let (cx, _) = start_game(&game)?;
+
+        let id = 100002;
+        assert_eq!(member(&cx, id, b"left"), vm::Value::from(0));
+        assert_eq!(member(&cx, id, b"diagonal"), vm::Value::from(true));
+        assert_eq!(member(&cx, id, b"corner"), vm::Value::from(false));
+        assert_eq!(member(&cx, id, b"rough"), vm::Value::from(100001));
+        assert_eq!(member(&cx, id, b"fine"), vm::Value::from(100001));
+        assert_eq!(member(&cx, id, b"circle"), vm::Value::from(vm::NOONE));
+        assert_eq!(member(&cx, id, b"line"), vm::Value::from(vm::NOONE));
+        assert_eq!(member(&cx, id, b"far_line"), vm::Value::from(100001));
+        assert_eq!(member(&cx, id, b"empty"), vm::Value::from(true));
+
+        Ok(())
+    }
+
+    /// Find the nearest and furthest instances, both by scanning and through the grid.
+    #[test]
+    fn spatial_queries() -> vm::Result<()> {
+        let mut game = project::Game::default();
+        game.objects.push(project::Object { name: b"obj_dot", ..project::Object::default() });
+        game.objects.push(project::Object { name: b"obj_few", ..project::Object::default() });
+        game.objects.push(project::Object {
+            name: b"obj_probe",
+            events: vec![code_event(project::event_type::CREATE, 0, b"
+                for (i = 0; i < 40; i += 1) {
+                    instance_create(i * 50, 0, 0)
+                }
+                first = instance_create(1000, 1000, 1)
+                second = instance_create(-500, 0, 1)
+                near_dot = instance_nearest(260, 10, 0)
+                far_dot = instance_furthest(0, 0, 0)
+                near_few = instance_nearest(0, 500, 1)
+                far_few = instance_furthest(0, 500, 1)
+
+                near_dot.x = 5000
+                moved = instance_furthest(0, 0, 0)
+                with (instance_nearest(1950, 0, 0)) {
+                    instance_destroy()
+                }
+                destroyed = instance_nearest(2000, 0, 0)
+
+                point = distance_to_point(x + 3, y + 4)
+                object = distance_to_object(1)
+            ")],
+            ..project::Object::default()
+        });
+        game.last_instance = 100001;
+        game.rooms.push(project::Room {
+            instances: vec![
+                project::Instance { x: 0, y: 0, object_index: 2, id: 100001, code: b"" },
+            ],
+            ..project::Room::default()
+        });
+
+        let (cx, _) = start_game(&game)?;
+
+        let id = 100001;
+        let dot = |i: i32| vm::Value::from(100002 + i);
+        assert_eq!(member(&cx, id, b"near_dot"), dot(5));
+        assert_eq!(member(&cx, id, b"far_dot"), dot(39));
+        assert_eq!(member(&cx, id, b"near_few"), member(&cx, id, b"second"));
+        assert_eq!(member(&cx, id, b"far_few"), member(&cx, id, b"first"));
+        assert_eq!(member(&cx, id, b"moved"), dot(5));
+        assert_eq!(member(&cx, id, b"destroyed"), dot(38));
+        assert_eq!(member(&cx, id, b"point"), vm::Value::from(5));
+        assert_eq!(member(&cx, id, b"object"), vm::Value::from(500));
+
+        Ok(())
+    }
+
+    /// Run collision queries against enough instances to search them through the grid, with some
+    /// far enough apart that the grid spans more cells than it can visit.
+    #[test]
+    fn crowded_collision_queries() -> vm::Result<()> {
+        let mut game = project::Game::default();
+        let pixels = [255; 4 * 4 * 4];
+        game.sprites.push(project::Sprite {
+            name: b"spr_block",
+            frames: vec![project::Frame { width: 4, height: 4, data: &pixels }],
+            bbox_right: 3,
+            bbox_bottom: 3,
+            ..project::Sprite::default()
+        });
+        game.objects.push(project::Object {
+            name: b"obj_block",
+            sprite: 0,
+            ..project::Object::default()
+        });
+        game.objects.push(project::Object {
+            name: b"obj_probe",
+            events: vec![code_event(project::event_type::CREATE, 0, b"
+                for (i = 0; i < 40; i += 1) {
+                    instance_create(i * 50, 0, 0)
+                }
+                instance_create(1000000000000, 0, 0)
+                instance_create(-1000000000000, -1000000000000, 0)
+                with (instance_nearest(500, 0, 0)) {
+                    image_xscale = 40
+                }
+
+                wide = collision_point(601, 1, 0, false, true)
+                rectangle = collision_rectangle(1040, -5, 1060, 5, 0, false, true)
+                circle = collision_circle(2000, 100, 50, 0, false, true)
+                nearest = instance_nearest(0, 1000, 0)
+                object = distance_to_object(0)
+            ")],
+            ..project::Object::default()
+        });
+        game.last_instance = 100001;
+        game.rooms.push(project::Room {
+            instances: vec![
+                project::Instance { x: 0, y: 1000, object_index: 1, id: 100001, code: b"" },
+            ],
+            ..project::Room::default()
+        });
+
+        let (cx, _) = start_game(&game)?;
+
+        let id = 100001;
+        let block = |i: i32| vm::Value::from(100002 + i);
+        assert_eq!(member(&cx, id, b"wide"), block(10));
+        assert_eq!(member(&cx, id, b"rectangle"), block(21));
+        assert_eq!(member(&cx, id, b"circle"), vm::Value::from(vm::NOONE));
+        assert_eq!(member(&cx, id, b"nearest"), block(0));
+        assert_eq!(member(&cx, id, b"object"), vm::Value::from(997));
+
+        Ok(())
+    }
+
+    /// Drive a game with synthetic input pushed between frames.
+    #[test]
+    fn input() -> vm::Result<()> {
+        use project::{event_type, event_kind};
+
+        let mut game = project::Game::default();
+        let pixels = [255; 4 * 4 * 4];
+        game.sprites.push(project::Sprite {
+            name: b"spr_button",
+            frames: vec![project::Frame { width: 4, height: 4, data: &pixels }],
+            bbox_right: 3,
+            bbox_bottom: 3,
+            ..project::Sprite::default()
+        });
+        let vk_left = 37;
+        let global_left_press = event_kind::GLOBAL_LEFT_BUTTON + 3;
+        let events = vec![
+            code_event(event_type::CREATE, 0, b"
+                held = 0
+                presses = 0
+                releases = 0
+                idle = 0
+                clicks = 0
+                global_clicks = 0
+                checks = 0
+                enters = 0
+                leaves = 0
+            "),
+            code_event(event_type::KEYBOARD, vk_left, b"held += 1"),
+            code_event(event_type::KEY_PRESS, vk_left, b"presses += 1"),
+            code_event(event_type::KEY_RELEASE, vk_left, b"releases += 1"),
+            code_event(event_type::KEYBOARD, 0, b"idle += 1"),
+            code_event(event_type::MOUSE, event_kind::MOUSE_LEFT_PRESS, b"clicks += 1"),
+            code_event(event_type::MOUSE, global_left_press, b"global_clicks += 1"),
+            code_event(event_type::MOUSE, event_kind::MOUSE_ENTER, b"enters += 1"),
+            code_event(event_type::MOUSE, event_kind::MOUSE_LEAVE, b"leaves += 1"),
+            code_event(event_type::STEP, event_kind::STEP_NORMAL, b"
+                if keyboard_check_pressed(vk_left) && keyboard_check(vk_anykey) {
+                    checks += 1
+                }
+                typed = keyboard_string
+                last = keyboard_lastkey
+                mx = mouse_x
+                button = mouse_check_button(mb_left)
+            "),
+        ];
+        game.objects.push(project::Object {
+            name: b"obj_button",
+            sprite: 0,
+            events,
+            ..project::Object::default()
+        });
+        game.last_instance = 100001;
+        game.rooms.push(project::Room {
+            instances: vec![
+                project::Instance { x: 0, y: 0, object_index: 0, id: 100001, code: b"" },
+            ],
+            ..project::Room::default()
+        });
+
+        let (mut cx, mut thread) = start_game(&game)?;
+        let id = 100001;
+
+        cx.world.input.key_press(vk_left as u32);
+        cx.world.input.char_input('a');
+        frame(&mut cx, &mut thread)?;
+        frame(&mut cx, &mut thread)?;
+        cx.world.input.key_release(vk_left as u32);
+        frame(&mut cx, &mut thread)?;
+        assert_eq!(member(&cx, id, b"held"), vm::Value::from(2));
+        assert_eq!(member(&cx, id, b"presses"), vm::Value::from(1));
+        assert_eq!(member(&cx, id, b"releases"), vm::Value::from(1));
+        assert_eq!(member(&cx, id, b"idle"), vm::Value::from(1));
+        assert_eq!(member(&cx, id, b"checks"), vm::Value::from(1));
+        assert_eq!(member(&cx, id, b"typed"), vm::Value::from(gml::symbol::Symbol::intern(b"a")));
+        assert_eq!(member(&cx, id, b"last"), vm::Value::from(vk_left));
+
+        cx.world.input.mouse_move(2.0, 2.0);
+        cx.world.input.mouse_press(input::MB_LEFT);
+        frame(&mut cx, &mut thread)?;
+        assert_eq!(member(&cx, id, b"enters"), vm::Value::from(1));
+        assert_eq!(member(&cx, id, b"leaves"), vm::Value::from(0));
+        assert_eq!(member(&cx, id, b"button"), vm::Value::from(true));
+        cx.world.input.mouse_move(50.0, 50.0);
+        cx.world.input.mouse_release(input::MB_LEFT);
+        cx.world.input.mouse_press(input::MB_LEFT);
+        frame(&mut cx, &mut thread)?;
+        assert_eq!(member(&cx, id, b"clicks"), vm::Value::from(1));
+        assert_eq!(member(&cx, id, b"global_clicks"), vm::Value::from(2));
+        assert_eq!(member(&cx, id, b"mx"), vm::Value::from(50));
+        assert_eq!(member(&cx, id, b"enters"), vm::Value::from(1));
+        assert_eq!(member(&cx, id, b"leaves"), vm::Value::from(1));
+
+        // The host cannot press the keys that stand for no key and any key.
+        assert_eq!(member(&cx, id, b"idle"), vm::Value::from(3));
+        cx.world.input.key_press(input::VK_NOKEY);
+        cx.world.input.key_press(input::VK_ANYKEY);
+        frame(&mut cx, &mut thread)?;
+        assert_eq!(member(&cx, id, b"idle"), vm::Value::from(4));
+        assert_eq!(member(&cx, id, b"last"), vm::Value::from(vk_left));
+
+        Ok(())
+    }
+
+    /// Record a run with random numbers and input, then play it back without the host.
+    #[test]
+    fn replay() -> vm::Result<()> {
+        use project::event_type;
+
+        let mut game = project::Game::default();
+        game.objects.push(project::Object {
+            name: b"obj_player",
+            events: vec![
+                code_event(event_type::CREATE, 0, b"
+                    randomize()
+                    r = irandom(1000000)
+                    presses = 0
+                "),
+                code_event(event_type::KEY_PRESS, 32, b"presses += 1; r += irandom(10)"),
+            ],
+            ..project::Object::default()
+        });
+        game.last_instance = 100001;
+        game.rooms.push(project::Room {
+            instances: vec![
+                project::Instance { x: 0, y: 0, object_index: 0, id: 100001, code: b"" },
+            ],
+            ..project::Room::default()
+        });
+        let id = 100001;
+
+        let (assets, debug) = build(&game, io::stderr).unwrap_or_else(|_| panic!());
+        let world = World::from_assets(&assets, debug);
+        let mut cx = Context { world, assets };
+        cx.world.replay = replay::State::record();
+        let mut thread = vm::Thread::default();
+        start(&mut cx, &mut thread)?;
+        for _ in 0..3 {
+            cx.world.input.key_press(32);
+            frame(&mut cx, &mut thread)?;
+            cx.world.input.key_release(32);
+            frame(&mut cx, &mut thread)?;
+        }
+        let r = member(&cx, id, b"r");
+        assert_eq!(member(&cx, id, b"presses"), vm::Value::from(3));
+
+        let recording = cx.world.replay.recording().unwrap().clone();
+        assert_eq!(recording.frames(), 6);
+        let mut data = Vec::default();
+        recording.write(&mut data)?;
+        assert_eq!(replay::Replay::read(&data[..])?, recording);
+
+        // A length past the end of the file is an error rather than an allocation.
+        let mut huge = data[..12].to_vec();
+        huge.extend_from_slice(&u32::MAX.to_le_bytes());
+        assert!(replay::Replay::read(&huge[..]).is_err());
+
+        let play = |replay| -> vm::Result<Context> {
+            let (assets, debug) = build(&game, io::stderr).unwrap_or_else(|_| panic!());
+            let world = World::from_assets(&assets, debug);
+            let mut cx = Context { world, assets };
+            cx.world.replay = replay::State::play(replay);
+            run_frames(&mut cx, 6)?;
+            Ok(cx)
+        };
+
+        let cx = play(recording.clone())?;
+        assert_eq!(member(&cx, id, b"r"), r);
+        assert_eq!(member(&cx, id, b"presses"), vm::Value::from(3));
+        assert!(cx.world.replay.remaining().is_empty());
+
+        let mut diverged = recording;
+        for entry in &mut diverged.entries {
+            if let replay::Entry::LoadRoom(ref mut num) = *entry {
+                *num = 1;
+            }
+        }
+        assert!(play(diverged).is_err());
+
+        Ok(())
+    }
+
+    /// Save and load a game from GML, and snapshot and restore it from Rust.
+    #[test]
+    fn save_load() -> vm::Result<()> {
+        use project::{event_type, event_kind};
+
+        let path = std::env::temp_dir().join(format!("save_load_{}.sav", std::process::id()));
+        let step = format!("
+            counter += 1
+            global.total += 10
+            first = ds_list_find_value(list, 0)
+            if counter == 2 {{ game_save(\"{0}\") }}
+            if counter == 4 {{ game_load(\"{0}\") }}
+        ", path.to_str().unwrap());
+        let (mut cx, mut thread, id) = start_object(vec![
+            code_event(event_type::CREATE, 0, b"
+                counter = 0
+                global.total = 0
+                list = ds_list_create()
+                ds_list_add(list, 7)
+                hspeed = 1
+            "),
+            code_event(event_type::STEP, event_kind::STEP_NORMAL, step.as_bytes()),
+        ])?;
+        let total = |cx: &Context| {
+            let globals = &cx.world.world.members[vm::world::GLOBAL];
+            globals[&gml::symbol::Symbol::intern(b"total")].clone()
+        };
+        let x = |cx: &Context| cx.world.motion.instances[cx.world.world.instances[id]].x;
+
+        for _ in 0..4 {
+            frame(&mut cx, &mut thread)?;
+        }
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(member(&cx, id, b"counter"), vm::Value::from(2));
+        assert_eq!(total(&cx), vm::Value::from(20));
+        assert_eq!(member(&cx, id, b"first"), vm::Value::from(7));
+        assert_eq!(x(&cx), 1.0);
+
+        let mut snapshot = Vec::default();
+        cx.world.save(&mut snapshot)?;
+        frame(&mut cx, &mut thread)?;
+        assert_eq!(member(&cx, id, b"counter"), vm::Value::from(3));
+        cx.world.load(&cx.assets, &snapshot[..])?;
+        assert_eq!(member(&cx, id, b"counter"), vm::Value::from(2));
+        assert_eq!(total(&cx), vm::Value::from(20));
+        assert_eq!(x(&cx), 1.0);
+
+        frame(&mut cx, &mut thread)?;
+        assert_eq!(member(&cx, id, b"counter"), vm::Value::from(3));
+        assert_eq!(member(&cx, id, b"first"), vm::Value::from(7));
+        assert!(cx.world.load(&cx.assets, &snapshot[..10]).is_err());
+        assert_eq!(member(&cx, id, b"counter"), vm::Value::from(3));
+
+        // Saves that refer to rooms or objects the game does not have are rejected.
+        let rooms = std::mem::take(&mut cx.assets.rooms);
+        assert!(cx.world.load(&cx.assets, &snapshot[..]).is_err());
+        cx.assets.rooms = rooms;
+        let objects = std::mem::take(&mut cx.assets.objects);
+        assert!(cx.world.load(&cx.assets, &snapshot[..]).is_err());
+        cx.assets.objects = objects;
+        assert_eq!(member(&cx, id, b"counter"), vm::Value::from(3));
+
+        // So are saves whose instances disagree with each other or with the entity allocator.
+        let mut snapshot = Vec::default();
+        cx.world.save(&mut snapshot)?;
+        let entity = cx.world.world.instances[id];
+        let corruptions: [fn(&mut World, vm::Entity); 3] = [
+            |world, entity| world.instance.instances[entity].id += 1,
+            |world, _| {
+                let stray = world.world.entities.create();
+                world.world.objects.get_mut(&0).unwrap().push(stray);
+            },
+            |world, entity| world.world.entities.destroy(entity),
+        ];
+        for corrupt in corruptions.iter() {
+            corrupt(&mut cx.world, entity);
+            let mut corrupted = Vec::default();
+            cx.world.save(&mut corrupted)?;
+            cx.world.load(&cx.assets, &snapshot[..])?;
+            assert!(cx.world.load(&cx.assets, &corrupted[..]).is_err());
+        }
+        assert_eq!(member(&cx, id, b"counter"), vm::Value::from(3));
+
+        Ok(())
     }
 }
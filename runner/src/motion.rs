@@ -1,8 +1,13 @@
+use std::io;
+
 use gml::{self, vm};
+use crate::grid::Grid;
 
 #[derive(Default)]
 pub struct State {
     pub instances: vm::EntityMap<Instance>,
+    /// Each instance's position, indexed for spatial queries.
+    pub grid: Grid,
 }
 
 pub struct Instance {
@@ -26,8 +31,63 @@ impl Instance {
         Instance {
             x, y, xprevious: x, yprevious: y, xstart: x, ystart: y,
             hspeed: 0.0, vspeed: 0.0, direction: 0.0, speed: 0.0,
-            friction: 0.0, gravity: 0.0, gravity_direction: 0.0,
+            friction: 0.0, gravity: 0.0, gravity_direction: 270.0,
+        }
+    }
+
+    /// Recompute `hspeed` and `vspeed` after a change to `speed` or `direction`.
+    fn update_components(&mut self) {
+        let (sin, cos) = self.direction.to_radians().sin_cos();
+        self.hspeed = cos * self.speed;
+        self.vspeed = -sin * self.speed;
+    }
+
+    /// Recompute `speed` and `direction` after a change to `hspeed` or `vspeed`.
+    fn update_polar(&mut self) {
+        self.speed = self.hspeed.hypot(self.vspeed);
+        self.direction = (-self.vspeed).atan2(self.hspeed).to_degrees().rem_euclid(360.0);
+    }
+
+    /// Apply friction and gravity, then move by `hspeed` and `vspeed`.
+    pub fn step(&mut self) {
+        if self.friction != 0.0 {
+            if self.speed.abs() > self.friction {
+                self.speed -= self.friction * self.speed.signum();
+            } else {
+                self.speed = 0.0;
+            }
+            self.update_components();
+        }
+
+        if self.gravity != 0.0 {
+            let (sin, cos) = self.gravity_direction.to_radians().sin_cos();
+            self.hspeed += cos * self.gravity;
+            self.vspeed -= sin * self.gravity;
+            self.update_polar();
         }
+
+        self.x += self.hspeed;
+        self.y += self.vspeed;
+    }
+}
+
+impl State {
+    pub fn insert(&mut self, entity: vm::Entity, instance: Instance) {
+        self.grid.insert(entity, instance.x, instance.y);
+        self.instances.insert(entity, instance);
+    }
+
+    pub fn remove(&mut self, entity: vm::Entity) {
+        self.grid.remove(entity);
+        self.instances.remove(entity);
+    }
+
+    /// Move an instance, keeping the grid up to date.
+    pub fn set_position(&mut self, entity: vm::Entity, x: f32, y: f32) {
+        let instance = &mut self.instances[entity];
+        instance.x = x;
+        instance.y = y;
+        self.grid.update(entity, x, y);
     }
 }
 
@@ -36,12 +96,105 @@ impl State {
     #[gml::get(x)]
     pub fn get_x(&self, entity: vm::Entity) -> f32 { self.instances[entity].x }
     #[gml::set(x)]
-    pub fn set_x(&mut self, entity: vm::Entity, value: f32) { self.instances[entity].x = value }
+    pub fn set_x(&mut self, entity: vm::Entity, value: f32) {
+        let y = self.instances[entity].y;
+        self.set_position(entity, value, y);
+    }
 
     #[gml::get(y)]
     pub fn get_y(&self, entity: vm::Entity) -> f32 { self.instances[entity].y }
     #[gml::set(y)]
-    pub fn set_y(&mut self, entity: vm::Entity, value: f32) { self.instances[entity].y = value }
+    pub fn set_y(&mut self, entity: vm::Entity, value: f32) {
+        let x = self.instances[entity].x;
+        self.set_position(entity, x, value);
+    }
+
+    #[gml::get(xprevious)]
+    pub fn get_xprevious(&self, entity: vm::Entity) -> f32 { self.instances[entity].xprevious }
+    #[gml::set(xprevious)]
+    pub fn set_xprevious(&mut self, entity: vm::Entity, value: f32) {
+        self.instances[entity].xprevious = value
+    }
+
+    #[gml::get(yprevious)]
+    pub fn get_yprevious(&self, entity: vm::Entity) -> f32 { self.instances[entity].yprevious }
+    #[gml::set(yprevious)]
+    pub fn set_yprevious(&mut self, entity: vm::Entity, value: f32) {
+        self.instances[entity].yprevious = value
+    }
+
+    #[gml::get(xstart)]
+    pub fn get_xstart(&self, entity: vm::Entity) -> f32 { self.instances[entity].xstart }
+    #[gml::set(xstart)]
+    pub fn set_xstart(&mut self, entity: vm::Entity, value: f32) {
+        self.instances[entity].xstart = value
+    }
+
+    #[gml::get(ystart)]
+    pub fn get_ystart(&self, entity: vm::Entity) -> f32 { self.instances[entity].ystart }
+    #[gml::set(ystart)]
+    pub fn set_ystart(&mut self, entity: vm::Entity, value: f32) {
+        self.instances[entity].ystart = value
+    }
+
+    #[gml::get(hspeed)]
+    pub fn get_hspeed(&self, entity: vm::Entity) -> f32 { self.instances[entity].hspeed }
+    #[gml::set(hspeed)]
+    pub fn set_hspeed(&mut self, entity: vm::Entity, value: f32) {
+        let instance = &mut self.instances[entity];
+        instance.hspeed = value;
+        instance.update_polar();
+    }
+
+    #[gml::get(vspeed)]
+    pub fn get_vspeed(&self, entity: vm::Entity) -> f32 { self.instances[entity].vspeed }
+    #[gml::set(vspeed)]
+    pub fn set_vspeed(&mut self, entity: vm::Entity, value: f32) {
+        let instance = &mut self.instances[entity];
+        instance.vspeed = value;
+        instance.update_polar();
+    }
+
+    #[gml::get(direction)]
+    pub fn get_direction(&self, entity: vm::Entity) -> f32 { self.instances[entity].direction }
+    #[gml::set(direction)]
+    pub fn set_direction(&mut self, entity: vm::Entity, value: f32) {
+        let instance = &mut self.instances[entity];
+        instance.direction = value.rem_euclid(360.0);
+        instance.update_components();
+    }
+
+    #[gml::get(speed)]
+    pub fn get_speed(&self, entity: vm::Entity) -> f32 { self.instances[entity].speed }
+    #[gml::set(speed)]
+    pub fn set_speed(&mut self, entity: vm::Entity, value: f32) {
+        let instance = &mut self.instances[entity];
+        instance.speed = value;
+        instance.update_components();
+    }
+
+    #[gml::get(friction)]
+    pub fn get_friction(&self, entity: vm::Entity) -> f32 { self.instances[entity].friction }
+    #[gml::set(friction)]
+    pub fn set_friction(&mut self, entity: vm::Entity, value: f32) {
+        self.instances[entity].friction = value
+    }
+
+    #[gml::get(gravity)]
+    pub fn get_gravity(&self, entity: vm::Entity) -> f32 { self.instances[entity].gravity }
+    #[gml::set(gravity)]
+    pub fn set_gravity(&mut self, entity: vm::Entity, value: f32) {
+        self.instances[entity].gravity = value
+    }
+
+    #[gml::get(gravity_direction)]
+    pub fn get_gravity_direction(&self, entity: vm::Entity) -> f32 {
+        self.instances[entity].gravity_direction
+    }
+    #[gml::set(gravity_direction)]
+    pub fn set_gravity_direction(&mut self, entity: vm::Entity, value: f32) {
+        self.instances[entity].gravity_direction = value
+    }
 
     #[gml::api]
     pub fn action_move_to(&mut self, entity: vm::Entity, relative: bool, mut x: f32, mut y: f32) {
@@ -49,7 +202,101 @@ impl State {
             x += self.instances[entity].x;
             y += self.instances[entity].y;
         }
-        self.instances[entity].x = x;
-        self.instances[entity].y = y;
+        self.set_position(entity, x, y);
+    }
+
+    /// Record each instance's position before it moves this frame.
+    pub fn update_previous(&mut self, world: &vm::World) {
+        for &entity in world.instances.values().iter() {
+            let instance = &mut self.instances[entity];
+            instance.xprevious = instance.x;
+            instance.yprevious = instance.y;
+        }
+    }
+
+    /// Move each instance according to its speed, friction, and gravity.
+    pub fn update_position(&mut self, world: &vm::World) {
+        for &entity in world.instances.values().iter() {
+            let instance = &mut self.instances[entity];
+            instance.step();
+            self.grid.update(entity, instance.x, instance.y);
+        }
+    }
+}
+
+/// Only the instances are saved. The grid is rebuilt from their positions on load.
+impl vm::Serialize for State {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        out.write(&self.instances)
+    }
+}
+
+impl vm::Deserialize for State {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        Ok(State { instances: input.read()?, grid: Grid::default() })
+    }
+}
+
+impl vm::Serialize for Instance {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        out.write(&self.x)?;
+        out.write(&self.y)?;
+        out.write(&self.xprevious)?;
+        out.write(&self.yprevious)?;
+        out.write(&self.xstart)?;
+        out.write(&self.ystart)?;
+        out.write(&self.hspeed)?;
+        out.write(&self.vspeed)?;
+        out.write(&self.direction)?;
+        out.write(&self.speed)?;
+        out.write(&self.friction)?;
+        out.write(&self.gravity)?;
+        out.write(&self.gravity_direction)
+    }
+}
+
+impl vm::Deserialize for Instance {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        Ok(Instance {
+            x: input.read()?,
+            y: input.read()?,
+            xprevious: input.read()?,
+            yprevious: input.read()?,
+            xstart: input.read()?,
+            ystart: input.read()?,
+            hspeed: input.read()?,
+            vspeed: input.read()?,
+            direction: input.read()?,
+            speed: input.read()?,
+            friction: input.read()?,
+            gravity: input.read()?,
+            gravity_direction: input.read()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn friction_and_gravity() {
+        let mut instance = Instance::from_pos(0.0, 0.0);
+        instance.hspeed = 3.0;
+        instance.update_polar();
+        instance.friction = 1.0;
+        instance.gravity = 0.5;
+
+        instance.step();
+        assert_eq!((instance.hspeed, instance.vspeed), (2.0, 0.5));
+        assert_eq!((instance.x, instance.y), (2.0, 0.5));
+
+        instance.friction = 0.0;
+        instance.gravity = 0.0;
+        instance.speed = 0.0;
+        instance.update_components();
+
+        instance.step();
+        assert_eq!((instance.x, instance.y), (2.0, 0.5));
     }
 }
@@ -0,0 +1,339 @@
+use std::{cmp, io};
+use std::convert::TryFrom;
+
+/// An RGBA image in memory, drawn by the CPU.
+///
+/// Colors are in Game Maker's `0xBBGGRR` format, with a separate alpha from 0 to 1.
+pub struct Canvas {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// The size of a character cell in the built-in font.
+pub const GLYPH_WIDTH: i32 = 6;
+pub const GLYPH_HEIGHT: i32 = 10;
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Canvas {
+        let pixels = vec![0; width as usize * height as usize * 4];
+        Canvas { width, height, pixels }
+    }
+
+    pub fn clear(&mut self, color: u32) {
+        let [r, g, b, _] = color.to_le_bytes();
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[r, g, b, 255]);
+        }
+    }
+
+    /// Blend a single pixel over the canvas. Pixels outside the canvas are ignored.
+    pub fn point(&mut self, x: i32, y: i32, color: u32, alpha: f32) {
+        if x < 0 || y < 0 || x >= self.width as i32 || y >= self.height as i32 {
+            return;
+        }
+        if alpha <= 0.0 || alpha.is_nan() {
+            return;
+        }
+        let alpha = alpha.min(1.0);
+
+        let offset = (y as usize * self.width as usize + x as usize) * 4;
+        let pixel = &mut self.pixels[offset..offset + 4];
+        let [r, g, b, _] = color.to_le_bytes();
+        for (dst, src) in pixel.iter_mut().zip(&[r, g, b]) {
+            let value = *src as f32 * alpha + *dst as f32 * (1.0 - alpha);
+            *dst = value.round() as u8;
+        }
+        let a = 255.0 * alpha + pixel[3] as f32 * (1.0 - alpha);
+        pixel[3] = a.round() as u8;
+    }
+
+    /// Fill the rectangle with corners at `(x1, y1)` and `(x2, y2)`, inclusive.
+    pub fn fill_rectangle(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u32, alpha: f32) {
+        let (x1, x2) = (x1.min(x2), x1.max(x2));
+        let (y1, y2) = (y1.min(y2), y1.max(y2));
+        for y in y1.max(0)..=y2.min(self.height as i32 - 1) {
+            for x in x1.max(0)..=x2.min(self.width as i32 - 1) {
+                self.point(x, y, color, alpha);
+            }
+        }
+    }
+
+    /// Draw the outline of a rectangle, touching each pixel once.
+    pub fn rectangle(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u32, alpha: f32) {
+        let (x1, x2) = (x1.min(x2), x1.max(x2));
+        let (y1, y2) = (y1.min(y2), y1.max(y2));
+        self.fill_rectangle(x1, y1, x2, y1, color, alpha);
+        if y2 > y1 {
+            self.fill_rectangle(x1, y2, x2, y2, color, alpha);
+        }
+        if y2 - y1 > 1 {
+            self.fill_rectangle(x1, y1 + 1, x1, y2 - 1, color, alpha);
+            if x2 > x1 {
+                self.fill_rectangle(x2, y1 + 1, x2, y2 - 1, color, alpha);
+            }
+        }
+    }
+
+    /// Draw a one pixel wide line, including both endpoints.
+    ///
+    /// The line takes one step per pixel along its longer axis, rounding its position on the
+    /// other. Only the steps that can land on the canvas are taken, so endpoints may be anywhere.
+    pub fn line(&mut self, x1: i32, y1: i32, x2: i32, y2: i32, color: u32, alpha: f32) {
+        let (x1, y1) = (i128::from(x1), i128::from(y1));
+        let (dx, dy) = (i128::from(x2) - x1, i128::from(y2) - y1);
+        let steps = cmp::max(dx.abs(), dy.abs());
+        if steps == 0 {
+            self.point(x1 as i32, y1 as i32, color, alpha);
+            return;
+        }
+        let at = |start: i128, delta: i128, i: i128| {
+            start + (2 * i * delta + steps).div_euclid(2 * steps)
+        };
+
+        // The steps at which a coordinate is within `0..size`, widened to cover rounding.
+        let range = |start: i128, delta: i128, size: u32| {
+            let size = i128::from(size);
+            if delta == 0 {
+                return if 0 <= start && start < size { (0, steps) } else { (1, 0) };
+            }
+            let (a, b) = (-start * steps / delta, (size - start) * steps / delta);
+            let slack = steps / (2 * delta.abs()) + 2;
+            (cmp::min(a, b) - slack, cmp::max(a, b) + slack)
+        };
+        let (x_low, x_high) = range(x1, dx, self.width);
+        let (y_low, y_high) = range(y1, dy, self.height);
+        let low = cmp::max(0, cmp::max(x_low, y_low));
+        let high = cmp::min(steps, cmp::min(x_high, y_high));
+        for i in low..=high {
+            if let (Ok(x), Ok(y)) = (i32::try_from(at(x1, dx, i)), i32::try_from(at(y1, dy, i))) {
+                self.point(x, y, color, alpha);
+            }
+        }
+    }
+
+    /// Draw a circle, either filled or as a one pixel wide outline.
+    pub fn circle(&mut self, x: f32, y: f32, r: f32, outline: bool, color: u32, alpha: f32) {
+        let r = r.abs();
+        let (right, bottom) = (self.width as f32 - 1.0, self.height as f32 - 1.0);
+        let x1 = (x - r - 1.0).floor().max(0.0) as i32;
+        let y1 = (y - r - 1.0).floor().max(0.0) as i32;
+        let x2 = (x + r + 1.0).ceil().min(right) as i32;
+        let y2 = (y + r + 1.0).ceil().min(bottom) as i32;
+        for py in y1..=y2 {
+            for px in x1..=x2 {
+                let distance = f32::hypot(px as f32 - x, py as f32 - y);
+                let inside = if outline { (distance - r).abs() < 0.5 } else { distance <= r };
+                if inside {
+                    self.point(px, py, color, alpha);
+                }
+            }
+        }
+    }
+
+    /// Draw text in the built-in font, with its top left corner at `(x, y)`.
+    ///
+    /// As in GM8, `#` starts a new line unless escaped as `\#`.
+    pub fn text(&mut self, x: i32, y: i32, text: &[u8], color: u32, alpha: f32) {
+        let (mut cx, mut cy) = (x, y);
+        let mut chars = text.iter().peekable();
+        while let Some(&c) = chars.next() {
+            let c = match c {
+                b'#' | b'\n' => {
+                    cx = x;
+                    cy += GLYPH_HEIGHT;
+                    continue;
+                }
+                b'\r' => continue,
+                b'\\' if chars.peek() == Some(&&b'#') => *chars.next().unwrap(),
+                c => c,
+            };
+            self.glyph(cx, cy, c, color, alpha);
+            cx += GLYPH_WIDTH;
+        }
+    }
+
+    fn glyph(&mut self, x: i32, y: i32, c: u8, color: u32, alpha: f32) {
+        let c = if (b' '..=b'~').contains(&c) { c } else { b'?' };
+        let glyph = &FONT[(c - b' ') as usize];
+        for (row, &bits) in glyph.iter().enumerate() {
+            for column in 0..GLYPH_WIDTH {
+                if bits & (0x80 >> column) != 0 {
+                    self.point(x + column, y + row as i32, color, alpha);
+                }
+            }
+        }
+    }
+
+    pub fn write_png<W: io::Write>(&self, write: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(write, self.width, self.height);
+        encoder.set_color(png::ColorType::RGBA);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        Ok(())
+    }
+}
+
+/// Printable ASCII glyphs from the public domain 6x10 "fixed" font, one byte per row.
+const FONT: [[u8; GLYPH_HEIGHT as usize]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // space
+    [0x00, 0x20, 0x20, 0x20, 0x20, 0x20, 0x00, 0x20, 0x00, 0x00], // !
+    [0x00, 0x50, 0x50, 0x50, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // "
+    [0x00, 0x50, 0x50, 0xF8, 0x50, 0xF8, 0x50, 0x50, 0x00, 0x00], // #
+    [0x00, 0x20, 0x70, 0xA0, 0x70, 0x28, 0x70, 0x20, 0x00, 0x00], // $
+    [0x00, 0x48, 0xA8, 0x50, 0x20, 0x50, 0xA8, 0x90, 0x00, 0x00], // %
+    [0x00, 0x40, 0xA0, 0xA0, 0x40, 0xA8, 0x90, 0x68, 0x00, 0x00], // &
+    [0x00, 0x20, 0x20, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // '
+    [0x00, 0x10, 0x20, 0x40, 0x40, 0x40, 0x20, 0x10, 0x00, 0x00], // (
+    [0x00, 0x40, 0x20, 0x10, 0x10, 0x10, 0x20, 0x40, 0x00, 0x00], // )
+    [0x00, 0x00, 0x88, 0x50, 0xF8, 0x50, 0x88, 0x00, 0x00, 0x00], // *
+    [0x00, 0x00, 0x20, 0x20, 0xF8, 0x20, 0x20, 0x00, 0x00, 0x00], // +
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x30, 0x20, 0x40, 0x00], // ,
+    [0x00, 0x00, 0x00, 0x00, 0xF8, 0x00, 0x00, 0x00, 0x00, 0x00], // -
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x70, 0x20, 0x00], // .
+    [0x00, 0x08, 0x08, 0x10, 0x20, 0x40, 0x80, 0x80, 0x00, 0x00], // /
+    [0x00, 0x20, 0x50, 0x88, 0x88, 0x88, 0x50, 0x20, 0x00, 0x00], // 0
+    [0x00, 0x20, 0x60, 0xA0, 0x20, 0x20, 0x20, 0xF8, 0x00, 0x00], // 1
+    [0x00, 0x70, 0x88, 0x08, 0x30, 0x40, 0x80, 0xF8, 0x00, 0x00], // 2
+    [0x00, 0xF8, 0x08, 0x10, 0x30, 0x08, 0x88, 0x70, 0x00, 0x00], // 3
+    [0x00, 0x10, 0x30, 0x50, 0x90, 0xF8, 0x10, 0x10, 0x00, 0x00], // 4
+    [0x00, 0xF8, 0x80, 0xB0, 0xC8, 0x08, 0x88, 0x70, 0x00, 0x00], // 5
+    [0x00, 0x30, 0x40, 0x80, 0xB0, 0xC8, 0x88, 0x70, 0x00, 0x00], // 6
+    [0x00, 0xF8, 0x08, 0x10, 0x10, 0x20, 0x40, 0x40, 0x00, 0x00], // 7
+    [0x00, 0x70, 0x88, 0x88, 0x70, 0x88, 0x88, 0x70, 0x00, 0x00], // 8
+    [0x00, 0x70, 0x88, 0x98, 0x68, 0x08, 0x10, 0x60, 0x00, 0x00], // 9
+    [0x00, 0x00, 0x20, 0x70, 0x20, 0x00, 0x20, 0x70, 0x20, 0x00], // :
+    [0x00, 0x00, 0x20, 0x70, 0x20, 0x00, 0x30, 0x20, 0x40, 0x00], // ;
+    [0x00, 0x08, 0x10, 0x20, 0x40, 0x20, 0x10, 0x08, 0x00, 0x00], // <
+    [0x00, 0x00, 0x00, 0xF8, 0x00, 0xF8, 0x00, 0x00, 0x00, 0x00], // =
+    [0x00, 0x40, 0x20, 0x10, 0x08, 0x10, 0x20, 0x40, 0x00, 0x00], // >
+    [0x00, 0x70, 0x88, 0x10, 0x20, 0x20, 0x00, 0x20, 0x00, 0x00], // ?
+    [0x00, 0x70, 0x88, 0x98, 0xA8, 0xB0, 0x80, 0x70, 0x00, 0x00], // @
+    [0x00, 0x20, 0x50, 0x88, 0x88, 0xF8, 0x88, 0x88, 0x00, 0x00], // A
+    [0x00, 0xF0, 0x48, 0x48, 0x70, 0x48, 0x48, 0xF0, 0x00, 0x00], // B
+    [0x00, 0x70, 0x88, 0x80, 0x80, 0x80, 0x88, 0x70, 0x00, 0x00], // C
+    [0x00, 0xF0, 0x48, 0x48, 0x48, 0x48, 0x48, 0xF0, 0x00, 0x00], // D
+    [0x00, 0xF8, 0x80, 0x80, 0xF0, 0x80, 0x80, 0xF8, 0x00, 0x00], // E
+    [0x00, 0xF8, 0x80, 0x80, 0xF0, 0x80, 0x80, 0x80, 0x00, 0x00], // F
+    [0x00, 0x70, 0x88, 0x80, 0x80, 0x98, 0x88, 0x70, 0x00, 0x00], // G
+    [0x00, 0x88, 0x88, 0x88, 0xF8, 0x88, 0x88, 0x88, 0x00, 0x00], // H
+    [0x00, 0x70, 0x20, 0x20, 0x20, 0x20, 0x20, 0x70, 0x00, 0x00], // I
+    [0x00, 0x38, 0x10, 0x10, 0x10, 0x10, 0x90, 0x60, 0x00, 0x00], // J
+    [0x00, 0x88, 0x90, 0xA0, 0xC0, 0xA0, 0x90, 0x88, 0x00, 0x00], // K
+    [0x00, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0xF8, 0x00, 0x00], // L
+    [0x00, 0x88, 0x88, 0xD8, 0xA8, 0x88, 0x88, 0x88, 0x00, 0x00], // M
+    [0x00, 0x88, 0x88, 0xC8, 0xA8, 0x98, 0x88, 0x88, 0x00, 0x00], // N
+    [0x00, 0x70, 0x88, 0x88, 0x88, 0x88, 0x88, 0x70, 0x00, 0x00], // O
+    [0x00, 0xF0, 0x88, 0x88, 0xF0, 0x80, 0x80, 0x80, 0x00, 0x00], // P
+    [0x00, 0x70, 0x88, 0x88, 0x88, 0x88, 0xA8, 0x70, 0x08, 0x00], // Q
+    [0x00, 0xF0, 0x88, 0x88, 0xF0, 0xA0, 0x90, 0x88, 0x00, 0x00], // R
+    [0x00, 0x70, 0x88, 0x80, 0x70, 0x08, 0x88, 0x70, 0x00, 0x00], // S
+    [0x00, 0xF8, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x00, 0x00], // T
+    [0x00, 0x88, 0x88, 0x88, 0x88, 0x88, 0x88, 0x70, 0x00, 0x00], // U
+    [0x00, 0x88, 0x88, 0x88, 0x50, 0x50, 0x50, 0x20, 0x00, 0x00], // V
+    [0x00, 0x88, 0x88, 0x88, 0xA8, 0xA8, 0xD8, 0x88, 0x00, 0x00], // W
+    [0x00, 0x88, 0x88, 0x50, 0x20, 0x50, 0x88, 0x88, 0x00, 0x00], // X
+    [0x00, 0x88, 0x88, 0x50, 0x20, 0x20, 0x20, 0x20, 0x00, 0x00], // Y
+    [0x00, 0xF8, 0x08, 0x10, 0x20, 0x40, 0x80, 0xF8, 0x00, 0x00], // Z
+    [0x00, 0x70, 0x40, 0x40, 0x40, 0x40, 0x40, 0x70, 0x00, 0x00], // [
+    [0x00, 0x80, 0x80, 0x40, 0x20, 0x10, 0x08, 0x08, 0x00, 0x00], // \
+    [0x00, 0x70, 0x10, 0x10, 0x10, 0x10, 0x10, 0x70, 0x00, 0x00], // ]
+    [0x00, 0x20, 0x50, 0x88, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ^
+    [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xF8, 0x00], // _
+    [0x20, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // `
+    [0x00, 0x00, 0x00, 0x70, 0x08, 0x78, 0x88, 0x78, 0x00, 0x00], // a
+    [0x00, 0x80, 0x80, 0xB0, 0xC8, 0x88, 0xC8, 0xB0, 0x00, 0x00], // b
+    [0x00, 0x00, 0x00, 0x70, 0x88, 0x80, 0x88, 0x70, 0x00, 0x00], // c
+    [0x00, 0x08, 0x08, 0x68, 0x98, 0x88, 0x98, 0x68, 0x00, 0x00], // d
+    [0x00, 0x00, 0x00, 0x70, 0x88, 0xF8, 0x80, 0x70, 0x00, 0x00], // e
+    [0x00, 0x30, 0x48, 0x40, 0xF0, 0x40, 0x40, 0x40, 0x00, 0x00], // f
+    [0x00, 0x00, 0x00, 0x78, 0x88, 0x88, 0x78, 0x08, 0x88, 0x70], // g
+    [0x00, 0x80, 0x80, 0xB0, 0xC8, 0x88, 0x88, 0x88, 0x00, 0x00], // h
+    [0x00, 0x20, 0x00, 0x60, 0x20, 0x20, 0x20, 0x70, 0x00, 0x00], // i
+    [0x00, 0x08, 0x00, 0x18, 0x08, 0x08, 0x08, 0x48, 0x48, 0x30], // j
+    [0x00, 0x80, 0x80, 0x88, 0x90, 0xE0, 0x90, 0x88, 0x00, 0x00], // k
+    [0x00, 0x60, 0x20, 0x20, 0x20, 0x20, 0x20, 0x70, 0x00, 0x00], // l
+    [0x00, 0x00, 0x00, 0xD0, 0xA8, 0xA8, 0xA8, 0x88, 0x00, 0x00], // m
+    [0x00, 0x00, 0x00, 0xB0, 0xC8, 0x88, 0x88, 0x88, 0x00, 0x00], // n
+    [0x00, 0x00, 0x00, 0x70, 0x88, 0x88, 0x88, 0x70, 0x00, 0x00], // o
+    [0x00, 0x00, 0x00, 0xB0, 0xC8, 0x88, 0xC8, 0xB0, 0x80, 0x80], // p
+    [0x00, 0x00, 0x00, 0x68, 0x98, 0x88, 0x98, 0x68, 0x08, 0x08], // q
+    [0x00, 0x00, 0x00, 0xB0, 0xC8, 0x80, 0x80, 0x80, 0x00, 0x00], // r
+    [0x00, 0x00, 0x00, 0x70, 0x80, 0x70, 0x08, 0xF0, 0x00, 0x00], // s
+    [0x00, 0x40, 0x40, 0xF0, 0x40, 0x40, 0x48, 0x30, 0x00, 0x00], // t
+    [0x00, 0x00, 0x00, 0x88, 0x88, 0x88, 0x98, 0x68, 0x00, 0x00], // u
+    [0x00, 0x00, 0x00, 0x88, 0x88, 0x50, 0x50, 0x20, 0x00, 0x00], // v
+    [0x00, 0x00, 0x00, 0x88, 0x88, 0xA8, 0xA8, 0x50, 0x00, 0x00], // w
+    [0x00, 0x00, 0x00, 0x88, 0x50, 0x20, 0x50, 0x88, 0x00, 0x00], // x
+    [0x00, 0x00, 0x00, 0x88, 0x88, 0x98, 0x68, 0x08, 0x88, 0x70], // y
+    [0x00, 0x00, 0x00, 0xF8, 0x10, 0x20, 0x40, 0xF8, 0x00, 0x00], // z
+    [0x00, 0x18, 0x20, 0x10, 0x60, 0x10, 0x20, 0x18, 0x00, 0x00], // {
+    [0x00, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x20, 0x00, 0x00], // |
+    [0x00, 0x60, 0x10, 0x20, 0x18, 0x20, 0x10, 0x60, 0x00, 0x00], // }
+    [0x00, 0x48, 0xA8, 0x90, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00], // ~
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pixel(canvas: &Canvas, x: u32, y: u32) -> [u8; 4] {
+        let offset = ((y * canvas.width + x) * 4) as usize;
+        let mut pixel = [0; 4];
+        pixel.copy_from_slice(&canvas.pixels[offset..offset + 4]);
+        pixel
+    }
+
+    #[test]
+    fn shapes() {
+        let mut canvas = Canvas::new(8, 8);
+        canvas.clear(0x000000);
+        canvas.rectangle(1, 1, 6, 6, 0x0000FF, 1.0);
+        canvas.line(0, 7, 7, 0, 0xFF0000, 0.5);
+
+        assert_eq!(pixel(&canvas, 1, 3), [255, 0, 0, 255]);
+        assert_eq!(pixel(&canvas, 3, 3), [0, 0, 0, 255]);
+        assert_eq!(pixel(&canvas, 0, 7), [0, 0, 128, 255]);
+        assert_eq!(pixel(&canvas, 6, 1), [128, 0, 128, 255]);
+    }
+
+    /// Shapes far larger than the canvas are clipped to it rather than drawn in full.
+    #[test]
+    fn clipping() {
+        let mut canvas = Canvas::new(8, 8);
+        canvas.line(i32::MIN, i32::MIN, i32::MAX, i32::MAX, 0xFFFFFF, 1.0);
+        canvas.line(-4, 2, i32::MAX, 2, 0x0000FF, 1.0);
+        assert_eq!(pixel(&canvas, 0, 0), [255, 255, 255, 255]);
+        assert_eq!(pixel(&canvas, 7, 7), [255, 255, 255, 255]);
+        assert_eq!(pixel(&canvas, 7, 2), [255, 0, 0, 255]);
+        assert_eq!(pixel(&canvas, 1, 0), [0, 0, 0, 0]);
+
+        let mut canvas = Canvas::new(8, 8);
+        canvas.circle(4.0, 4.0, 1e9, false, 0xFFFFFF, 1.0);
+        canvas.circle(4.0, 4.0, 1e9, true, 0x0000FF, 1.0);
+        assert_eq!(pixel(&canvas, 0, 0), [255, 255, 255, 255]);
+        assert_eq!(pixel(&canvas, 7, 7), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn text() {
+        let mut canvas = Canvas::new(12, 20);
+        canvas.text(0, 0, b"A#\\#", 0xFFFFFF, 1.0);
+
+        // The crossbar of the A, and the top of the escaped # on the second line.
+        assert_eq!(pixel(&canvas, 0, 5), [255, 255, 255, 255]);
+        assert_eq!(pixel(&canvas, 4, 5), [255, 255, 255, 255]);
+        assert_eq!(pixel(&canvas, 0, 11), [0, 0, 0, 0]);
+        assert_eq!(pixel(&canvas, 1, 11), [255, 255, 255, 255]);
+        assert_eq!(pixel(&canvas, 7, 11), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn png() -> io::Result<()> {
+        let mut data = Vec::default();
+        Canvas::new(2, 2).write_png(&mut data)?;
+        assert_eq!(&data[..8], b"\x89PNG\r\n\x1a\n");
+        Ok(())
+    }
+}
@@ -1,8 +1,9 @@
-use std::f64;
+use std::{f64, io};
 use std::num::Wrapping;
 use std::convert::TryFrom;
 use gml::symbol::Symbol;
 use gml::{self, vm};
+use crate::replay;
 
 pub struct State {
     random_seed: Wrapping<i32>,
@@ -75,8 +76,9 @@ impl State {
     }
 
     #[gml::api]
-    pub fn randomize(&mut self) {
-        // TODO: set the seed to the low bits of QueryPerformanceCounter
+    pub fn randomize(&mut self, replay: &mut replay::State) -> vm::Result<()> {
+        self.random_seed = Wrapping(replay.randomize()?);
+        Ok(())
     }
 
     #[gml::api]
@@ -263,6 +265,18 @@ impl State {
     }
 }
 
+impl vm::Serialize for State {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        out.write(&self.random_seed)
+    }
+}
+
+impl vm::Deserialize for State {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        Ok(State { random_seed: input.read()? })
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,326 @@
+use std::convert::TryFrom;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use std::{error, fmt, fs, io};
+
+use gml::{self, vm};
+use crate::{Context, input};
+
+/// Everything a run depends on from outside the game, in the order the game asked for it.
+///
+/// Playing a replay back feeds these values to the game instead of asking the host, and checks
+/// that the game loads the same rooms and executes the same events that it did when recorded.
+#[derive(Clone, PartialEq, Default, Debug)]
+pub struct Replay {
+    /// The random seed when the game started.
+    pub seed: i32,
+    pub entries: Vec<Entry>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Entry {
+    /// The start of a frame, with the value of `current_time` for that frame.
+    Frame { time: u32 },
+    /// Input delivered by the host before a frame.
+    Input(input::Event),
+    /// The seed chosen by `randomize`.
+    Randomize(i32),
+    /// The contents of a file read by the game, or none if it could not be read.
+    File { path: Vec<u8>, data: Option<Vec<u8>> },
+    LoadRoom(i32),
+    /// An event or creation code run by the runner for an instance.
+    Execute { id: i32, function: gml::Function },
+}
+
+pub struct State {
+    mode: Mode,
+    start: Instant,
+    time: u32,
+}
+
+enum Mode {
+    Live,
+    Record(Replay),
+    Play(Replay, usize),
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State { mode: Mode::Live, start: Instant::now(), time: 0 }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The game did something other than what the replay recorded.
+    Diverged { expected: Option<Entry>, found: Entry },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::Diverged { expected: Some(ref expected), ref found } => {
+                write!(f, "replay diverged: expected {:?}, found {:?}", expected, found)?;
+            }
+            Error::Diverged { expected: None, ref found } => {
+                write!(f, "replay diverged: expected the end, found {:?}", found)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Error {}
+
+impl State {
+    /// Record everything the game gets from the host.
+    pub fn record() -> Self {
+        State { mode: Mode::Record(Replay::default()), ..Self::default() }
+    }
+
+    /// Feed the game from a recording instead of the host.
+    pub fn play(replay: Replay) -> Self {
+        State { mode: Mode::Play(replay, 0), ..Self::default() }
+    }
+
+    /// The recording made so far, if recording.
+    pub fn recording(&self) -> Option<&Replay> {
+        match self.mode {
+            Mode::Record(ref replay) => Some(replay),
+            _ => None,
+        }
+    }
+
+    /// The entries of a recording that have not been played back yet.
+    pub fn remaining(&self) -> &[Entry] {
+        match self.mode {
+            Mode::Play(ref replay, next) => &replay.entries[next..],
+            _ => &[],
+        }
+    }
+
+    /// Get a value from the host, or from the replay when playing one back.
+    ///
+    /// When playing back, the next entry must satisfy `recorded`, and `host` is only called to
+    /// describe a divergence.
+    fn value<P, F>(&mut self, recorded: P, host: F) -> Result<Entry, Error> where
+        P: FnOnce(&Entry) -> bool,
+        F: FnOnce() -> Entry,
+    {
+        match self.mode {
+            Mode::Live => Ok(host()),
+            Mode::Record(ref mut replay) => {
+                let entry = host();
+                replay.entries.push(entry.clone());
+                Ok(entry)
+            }
+            Mode::Play(ref replay, ref mut next) => match replay.entries.get(*next) {
+                Some(entry) if recorded(entry) => {
+                    *next += 1;
+                    Ok(entry.clone())
+                }
+                expected => Err(Error::Diverged { expected: expected.cloned(), found: host() }),
+            },
+        }
+    }
+
+    /// Check that the game did the same thing as in the replay.
+    fn check(&mut self, entry: Entry) -> Result<(), Error> {
+        match self.mode {
+            Mode::Live => {}
+            Mode::Record(ref mut replay) => replay.entries.push(entry),
+            Mode::Play(ref replay, ref mut next) => match replay.entries.get(*next) {
+                Some(expected) if *expected == entry => *next += 1,
+                expected => {
+                    return Err(Error::Diverged { expected: expected.cloned(), found: entry });
+                }
+            },
+        }
+        Ok(())
+    }
+
+    /// Pick a seed for `randomize`.
+    pub fn randomize(&mut self) -> Result<i32, Error> {
+        let entry = self.value(|entry| matches!(entry, Entry::Randomize(_)), || {
+            let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+            Entry::Randomize(time.subsec_nanos() as i32)
+        })?;
+        match entry {
+            Entry::Randomize(seed) => Ok(seed),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Read a file on behalf of the game.
+    pub fn read_file(&mut self, path: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let recorded = |entry: &Entry| match *entry {
+            Entry::File { path: ref recorded, .. } => recorded[..] == *path,
+            _ => false,
+        };
+        let entry = self.value(recorded, || {
+            let data = std::str::from_utf8(path).ok().and_then(|path| fs::read(path).ok());
+            Entry::File { path: path.to_vec(), data }
+        })?;
+        match entry {
+            Entry::File { data, .. } => Ok(data),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Note that the runner loaded a room.
+    pub fn load_room(&mut self, num: i32) -> Result<(), Error> {
+        self.check(Entry::LoadRoom(num))
+    }
+
+    /// Run an event or creation code as the thread's `self`, checking it against the replay.
+    pub fn execute(cx: &mut Context, thread: &mut vm::Thread, function: gml::Function) ->
+        vm::Result<()>
+    {
+        let id = cx.world.instance.instances[thread.self_entity()].id;
+        cx.world.replay.check(Entry::Execute { id, function })?;
+        thread.execute(cx, function, vec![])?;
+        Ok(())
+    }
+
+    /// Record or restore the random seed the game starts with.
+    pub fn start(cx: &mut Context) {
+        let crate::World { real, replay, .. } = &mut cx.world;
+        match replay.mode {
+            Mode::Live => {}
+            Mode::Record(ref mut replay) => replay.seed = real.random_get_seed(),
+            Mode::Play(ref replay, _) => real.random_set_seed(replay.seed),
+        }
+    }
+
+    /// Sample the clock and deliver the host's input at the start of a frame.
+    pub fn frame(cx: &mut Context) -> vm::Result<()> {
+        let crate::World { input, replay, .. } = &mut cx.world;
+
+        let start = replay.start;
+        let time = replay.value(|entry| matches!(entry, Entry::Frame { .. }), || {
+            let time = u32::try_from(start.elapsed().as_millis()).unwrap_or(u32::MAX);
+            Entry::Frame { time }
+        })?;
+        if let Entry::Frame { time } = time {
+            replay.time = time;
+        }
+
+        let mut events = input.take_events();
+        match replay.mode {
+            Mode::Live => {}
+            Mode::Record(ref mut replay) => {
+                replay.entries.extend(events.iter().map(|&event| Entry::Input(event)));
+            }
+            Mode::Play(ref replay, ref mut next) => {
+                events.clear();
+                while let Some(&Entry::Input(event)) = replay.entries.get(*next) {
+                    events.push(event);
+                    *next += 1;
+                }
+            }
+        }
+        input.apply(&events);
+
+        Ok(())
+    }
+}
+
+#[gml::bind]
+impl State {
+    /// The number of milliseconds since the game started, as of the start of the frame.
+    #[gml::get(current_time)]
+    pub fn get_current_time(&self) -> u32 { self.time }
+}
+
+const MAGIC: &[u8; 4] = b"DJVR";
+const VERSION: u32 = 1;
+
+impl Replay {
+    /// The number of frames in the recording.
+    pub fn frames(&self) -> u32 {
+        self.entries.iter().filter(|entry| matches!(entry, Entry::Frame { .. })).count() as u32
+    }
+
+    pub fn write<W: io::Write>(&self, mut write: W) -> io::Result<()> {
+        write.write_all(MAGIC)?;
+        let mut out = vm::Serializer::new(write);
+        out.write(&VERSION)?;
+        out.write(&self.seed)?;
+        out.write(&self.entries)
+    }
+
+    pub fn read<R: io::Read>(mut read: R) -> io::Result<Replay> {
+        let mut magic = [0; 4];
+        read.read_exact(&mut magic)?;
+        let mut input = vm::Deserializer::new(read);
+        if &magic != MAGIC || input.read::<u32>()? != VERSION {
+            return Err(vm::invalid_data());
+        }
+        let seed = input.read()?;
+        let entries = input.read()?;
+        Ok(Replay { seed, entries })
+    }
+}
+
+impl vm::Serialize for Entry {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        match *self {
+            Entry::Frame { time } => {
+                out.write(&0u8)?;
+                out.write(&time)
+            }
+            Entry::Input(ref event) => {
+                out.write(&1u8)?;
+                out.write(event)
+            }
+            Entry::Randomize(seed) => {
+                out.write(&2u8)?;
+                out.write(&seed)
+            }
+            Entry::File { ref path, ref data } => {
+                out.write(&3u8)?;
+                out.write_bytes(path)?;
+                match *data {
+                    Some(ref data) => {
+                        out.write(&true)?;
+                        out.write_bytes(data)
+                    }
+                    None => out.write(&false),
+                }
+            }
+            Entry::LoadRoom(num) => {
+                out.write(&4u8)?;
+                out.write(&num)
+            }
+            Entry::Execute { id, ref function } => {
+                out.write(&5u8)?;
+                out.write(&id)?;
+                out.write(function)
+            }
+        }
+    }
+}
+
+impl vm::Deserialize for Entry {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        match input.read::<u8>()? {
+            0 => Ok(Entry::Frame { time: input.read()? }),
+            1 => Ok(Entry::Input(input.read()?)),
+            2 => Ok(Entry::Randomize(input.read()?)),
+            3 => {
+                let path = input.read_bytes()?;
+                let data = match input.read()? {
+                    false => None,
+                    true => Some(input.read_bytes()?),
+                };
+                Ok(Entry::File { path, data })
+            }
+            4 => Ok(Entry::LoadRoom(input.read()?)),
+            5 => {
+                let id = input.read()?;
+                let function = input.read()?;
+                Ok(Entry::Execute { id, function })
+            }
+            _ => Err(vm::invalid_data()),
+        }
+    }
+}
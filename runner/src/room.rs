@@ -1,43 +1,136 @@
-use crate::{Context, instance, Instance};
+use std::{error, fmt, io};
+
+use crate::{Context, instance, replay, Instance};
 
 use gml::vm;
 
 #[derive(Default)]
 pub struct State {
+    pub room: i32,
+    /// The room to switch to at the end of the frame.
+    pub transition: Option<i32>,
+    pub end: bool,
 }
 
 #[gml::bind]
 impl State {
+    #[gml::get(room)]
+    pub fn get_room(&self) -> i32 { self.room }
+    #[gml::set(room)]
+    pub fn set_room(&mut self, value: i32) { self.transition = Some(value); }
+
+    #[gml::get(room_first)]
+    pub fn get_room_first(_: &Context) -> i32 { 0 }
+
+    #[gml::get(room_last)]
+    pub fn get_room_last(cx: &Context) -> i32 { cx.assets.rooms.len() as i32 - 1 }
+
+    #[gml::api]
+    pub fn room_goto(cx: &mut Context, numb: i32) -> vm::Result<()> {
+        let Context { world, assets } = cx;
+        if assets.room(numb).is_none() {
+            Err(Error::Room(numb))?;
+        }
+        world.room.transition = Some(numb);
+        Ok(())
+    }
+
+    // TODO: use the game's room order rather than room indexes.
+    #[gml::api]
+    pub fn room_goto_next(cx: &mut Context) -> vm::Result<()> {
+        Self::room_goto(cx, cx.world.room.room + 1)
+    }
+
+    #[gml::api]
+    pub fn room_goto_previous(cx: &mut Context) -> vm::Result<()> {
+        Self::room_goto(cx, cx.world.room.room - 1)
+    }
+
+    #[gml::api]
+    pub fn room_restart(cx: &mut Context) -> vm::Result<()> {
+        Self::room_goto(cx, cx.world.room.room)
+    }
+
+    #[gml::api]
+    pub fn game_end(&mut self) {
+        self.end = true;
+    }
+
+    /// Switch to the room requested during this frame, if any.
+    ///
+    /// Requests for rooms that do not exist, such as through `room`, are errors.
+    pub fn change_room(cx: &mut Context, thread: &mut vm::Thread) -> vm::Result<()> {
+        use project::{event_type, event_kind};
+
+        let Context { world, assets } = cx;
+        let num = match world.room.transition.take() {
+            Some(num) if assets.room(num).is_some() => num,
+            Some(num) => Err(Error::Room(num))?,
+            None => return Ok(()),
+        };
+
+        instance::State::event(cx, thread, event_type::OTHER, event_kind::ROOM_END)?;
+
+        // Remove instances that do not persist, without running their destroy events:
+        let Context { world, assets } = cx;
+        let crate::World { world, instance, .. } = world;
+        for &entity in world.instances.values().clone().iter() {
+            let inst = &instance.instances[entity];
+            if !inst.persistent {
+                world.remove_entity(entity, assets.ancestors(inst.object_index), inst.id);
+                instance.destroyed.push(entity);
+            }
+        }
+
+        Self::load_room(cx, thread, num)?;
+        instance::State::event(cx, thread, event_type::OTHER, event_kind::ROOM_START)?;
+        Ok(())
+    }
+
+    /// Create a room's instances, and run their creation code and the room's creation code.
+    ///
+    /// Instances that already exist, because they are persistent, are not created again.
     pub fn load_room(cx: &mut Context, thread: &mut vm::Thread, num: i32) ->
         vm::Result<()>
     {
+        let Context { world, .. } = cx;
+        world.replay.load_room(num)?;
+        world.room.room = num;
+
         // Create instances:
         let Context { assets, .. } = cx;
+        let mut created = Vec::with_capacity(assets.rooms[num as usize].instances.len());
         for i in 0..assets.rooms[num as usize].instances.len() {
-            let Context { assets, .. } = cx;
-            let Instance { x, y, object_index, id } = assets.rooms[0].instances[i];
+            let Context { world, assets } = cx;
+            let Instance { x, y, object_index, id } = assets.rooms[num as usize].instances[i];
+            if world.world.instances.contains_key(id) {
+                continue;
+            }
             instance::State::instance_create_id(cx, x as f32, y as f32, object_index, id);
+            created.push(i);
         }
 
         // Run each instance's creation code and create event:
-        let Context { assets, .. } = cx;
-        for i in 0..assets.rooms[num as usize].instances.len() {
+        for i in created {
             let Context { world, assets, .. } = cx;
-            let Instance { object_index, id, .. } = assets.rooms[0].instances[i];
+            let Instance { object_index, id, .. } = assets.rooms[num as usize].instances[i];
             let crate::World { world, .. } = world;
+            // Skip instances destroyed by earlier creation code.
+            if !world.instances.contains_key(id) {
+                continue;
+            }
             let entity = world.instances[id];
 
             let Context { assets, .. } = cx;
             let create = gml::Function::Instance { id };
             if assets.code.code.contains_key(&create) {
-                thread.with(entity).execute(cx, create, vec![])?;
+                replay::State::execute(cx, &mut thread.with(entity), create)?;
             }
 
             let Context { assets, .. } = cx;
             let event_type = project::event_type::CREATE;
-            let create = gml::Function::Event { event_type, event_kind: 0, object_index };
-            if assets.code.code.contains_key(&create) {
-                thread.with(entity).execute(cx, create, vec![])?;
+            if let Some(create) = assets.find_event(object_index, event_type, 0) {
+                replay::State::execute(cx, &mut thread.with(entity), create)?;
             }
         }
 
@@ -48,8 +141,38 @@ impl State {
             thread.execute(cx, create, vec![])?;
         }
 
-        let Context { world, .. } = cx;
-        world.instance.free_destroyed(&mut world.world, &mut world.motion);
+        instance::State::free_destroyed(&mut cx.world);
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// A request to go to a room that does not exist.
+    Room(i32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::Room(num) => write!(f, "the room with index {} does not exist", num)?,
+        }
         Ok(())
     }
 }
+
+impl error::Error for Error {}
+
+impl vm::Serialize for State {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        out.write(&self.room)?;
+        out.write(&self.transition)?;
+        out.write(&self.end)
+    }
+}
+
+impl vm::Deserialize for State {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        Ok(State { room: input.read()?, transition: input.read()?, end: input.read()? })
+    }
+}
@@ -0,0 +1,159 @@
+use std::{fs, io};
+
+use gml::symbol::Symbol;
+use gml::{self, vm};
+use crate::{Context, Assets, World, real, motion, alarm, sprite, collision, instance, room, data};
+
+#[derive(Default)]
+pub struct State {
+    /// The file to load at the end of the frame.
+    pub load: Option<Symbol>,
+}
+
+const MAGIC: &[u8; 4] = b"DJVS";
+const VERSION: u32 = 2;
+
+#[gml::bind]
+impl State {
+    #[gml::api]
+    pub fn game_save(cx: &mut Context, fname: Symbol) -> vm::Result<()> {
+        let mut write = io::BufWriter::new(fs::File::create(path(&fname[..])?)?);
+        cx.world.save(&mut write)?;
+        io::Write::flush(&mut write)?;
+        Ok(())
+    }
+
+    /// Load a saved game, once the current frame is over.
+    #[gml::api]
+    pub fn game_load(&mut self, fname: Symbol) {
+        self.load = Some(fname);
+    }
+
+    /// Load the game requested during this frame, if any.
+    pub fn finish_load(cx: &mut Context) -> vm::Result<()> {
+        let fname = match cx.world.save.load.take() {
+            Some(fname) => fname,
+            None => return Ok(()),
+        };
+        let read = io::BufReader::new(fs::File::open(path(&fname[..])?)?);
+        let Context { world, assets } = cx;
+        world.load(assets, read)?;
+        Ok(())
+    }
+}
+
+fn path(fname: &[u8]) -> io::Result<&str> {
+    std::str::from_utf8(fname).map_err(|_| io::ErrorKind::InvalidInput.into())
+}
+
+/// Saving and restoring the state of a running game.
+///
+/// This covers instances, their variables, globals, data structures, and the random seed. Like
+/// GM8, it does not cover resources added while the game is running. Input, the contents of the
+/// screen, and any replay in progress are left as they are.
+impl World {
+    pub fn save<W: io::Write>(&self, mut write: W) -> io::Result<()> {
+        write.write_all(MAGIC)?;
+        let mut out = vm::Serializer::new(write);
+        out.write(&VERSION)?;
+        out.write(&self.world)?;
+        out.write(&self.real)?;
+        out.write(&self.motion)?;
+        out.write(&self.alarm)?;
+        out.write(&self.sprite)?;
+        out.write(&self.collision)?;
+        out.write(&self.instance)?;
+        out.write(&self.room)?;
+        out.write(&self.draw.color)?;
+        out.write(&self.draw.alpha)?;
+        out.write(&self.data)
+    }
+
+    /// Restore a game saved with `save`. On failure, the game is left unchanged.
+    ///
+    /// The save must refer only to rooms, objects, and sprites that exist in `assets`.
+    pub fn load<R: io::Read>(&mut self, assets: &Assets, mut read: R) -> io::Result<()> {
+        let mut magic = [0; 4];
+        read.read_exact(&mut magic)?;
+        let mut input = vm::Deserializer::new(read);
+        if &magic != MAGIC || input.read::<u32>()? != VERSION {
+            return Err(vm::invalid_data());
+        }
+        let world: vm::World = input.read()?;
+        let real: real::State = input.read()?;
+        let mut motion: motion::State = input.read()?;
+        let alarm: alarm::State = input.read()?;
+        let sprite: sprite::State = input.read()?;
+        let collision: collision::State = input.read()?;
+        let instance: instance::State = input.read()?;
+        let room: room::State = input.read()?;
+        let color = input.read()?;
+        let alpha = input.read()?;
+        let data: data::State = input.read()?;
+
+        if room.room < 0 || assets.rooms.len() <= room.room as usize {
+            return Err(vm::invalid_data());
+        }
+
+        // Every entity must still be allocated.
+        let allocated = world.members.iter().all(|(entity, _)| world.entities.exists(entity)) &&
+            instance.destroyed.iter().all(|&entity| {
+                world.entities.exists(entity) && instance.instances.contains_key(entity)
+            });
+        if !allocated {
+            return Err(vm::invalid_data());
+        }
+
+        // Every instance needs all of its components, under its own id, and a place in the grid.
+        let object = |object_index: i32| {
+            0 <= object_index && (object_index as usize) < assets.objects.len()
+        };
+        let sprite_or_none = |sprite_index: i32| {
+            sprite_index < 0 || assets.sprite(sprite_index).is_some()
+        };
+        for (position, &entity) in world.instances.values().iter().enumerate() {
+            let complete = world.entities.exists(entity) &&
+                world.members.contains_key(entity) &&
+                alarm.instances.contains_key(entity) &&
+                matches!(sprite.instances.get(entity),
+                    Some(inst) if sprite_or_none(inst.sprite_index)) &&
+                matches!(collision.instances.get(entity),
+                    Some(inst) if sprite_or_none(inst.mask_index)) &&
+                matches!(instance.instances.get(entity), Some(inst) if
+                    object(inst.object_index) &&
+                    world.instances.position(inst.id) == Some(position));
+            let position = motion.instances.get(entity).map(|inst| (inst.x, inst.y));
+            match position {
+                Some((x, y)) if complete => motion.grid.insert(entity, x, y),
+                _ => return Err(vm::invalid_data()),
+            }
+        }
+
+        // Instance lists may only hold live instances of their object or its descendants.
+        let live = |object_index: i32, entity: vm::Entity| match instance.instances.get(entity) {
+            Some(inst) => {
+                world.instances.contains_key(inst.id) && world.instances[inst.id] == entity &&
+                    assets.ancestors(inst.object_index).any(|o| o == object_index)
+            }
+            None => false,
+        };
+        let listed = world.objects.iter()
+            .all(|(&object_index, entities)| entities.iter().all(|&e| live(object_index, e)));
+        if !listed {
+            return Err(vm::invalid_data());
+        }
+
+        self.world = world;
+        self.real = real;
+        self.motion = motion;
+        self.alarm = alarm;
+        self.sprite = sprite;
+        self.collision = collision;
+        self.instance = instance;
+        self.room = room;
+        self.draw.color = color;
+        self.draw.alpha = alpha;
+        self.data = data;
+        Ok(())
+    }
+}
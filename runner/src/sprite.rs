@@ -0,0 +1,512 @@
+use std::collections::BTreeMap;
+use std::{cmp, io};
+
+use gml::symbol::Symbol;
+use gml::{self, vm};
+use crate::{Context, Assets, Sprite, Frame, Mask, replay};
+
+#[derive(Default)]
+pub struct State {
+    pub instances: vm::EntityMap<Instance>,
+    /// How many instances have each scale, by the bits of the larger magnitude of their
+    /// `image_xscale` and `image_yscale`, which bounds how far their bounding boxes can reach.
+    scales: BTreeMap<u32, usize>,
+}
+
+pub struct Instance {
+    pub sprite_index: i32,
+    pub image_index: f32,
+    pub image_speed: f32,
+    pub image_xscale: f32,
+    pub image_yscale: f32,
+    pub image_angle: f32,
+    pub image_blend: u32,
+    pub image_alpha: f32,
+}
+
+impl Instance {
+    pub fn from_sprite(sprite_index: i32) -> Self {
+        Instance {
+            sprite_index,
+            image_index: 0.0,
+            image_speed: 1.0,
+            image_xscale: 1.0,
+            image_yscale: 1.0,
+            image_angle: 0.0,
+            image_blend: 0xFFFFFF,
+            image_alpha: 1.0,
+        }
+    }
+}
+
+impl State {
+    pub fn insert(&mut self, entity: vm::Entity, instance: Instance) {
+        self.instances.insert(entity, instance);
+        self.count_scale(entity);
+    }
+
+    pub fn remove(&mut self, entity: vm::Entity) {
+        if self.instances.contains_key(entity) {
+            self.forget_scale(entity);
+        }
+        self.instances.remove(entity);
+    }
+
+    /// The largest magnitude of any current instance's `image_xscale` or `image_yscale`.
+    pub fn max_scale(&self) -> f32 {
+        self.scales.keys().next_back().map_or(0.0, |&bits| f32::from_bits(bits))
+    }
+
+    fn count_scale(&mut self, entity: vm::Entity) {
+        *self.scales.entry(scale(&self.instances[entity])).or_default() += 1;
+    }
+
+    fn forget_scale(&mut self, entity: vm::Entity) {
+        let bits = scale(&self.instances[entity]);
+        if let Some(count) = self.scales.get_mut(&bits) {
+            *count -= 1;
+            if *count == 0 {
+                self.scales.remove(&bits);
+            }
+        }
+    }
+}
+
+/// The bits of an instance's scale, which order the same way as the scales themselves.
+fn scale(instance: &Instance) -> u32 {
+    let scale = f32::max(instance.image_xscale.abs(), instance.image_yscale.abs());
+    if scale.is_nan() { 0 } else { scale.to_bits() }
+}
+
+#[gml::bind]
+impl State {
+    #[gml::get(sprite_index)]
+    pub fn get_sprite_index(&self, entity: vm::Entity) -> i32 {
+        self.instances[entity].sprite_index
+    }
+    #[gml::set(sprite_index)]
+    pub fn set_sprite_index(&mut self, entity: vm::Entity, value: i32) {
+        self.instances[entity].sprite_index = value;
+    }
+
+    #[gml::get(image_index)]
+    pub fn get_image_index(&self, entity: vm::Entity) -> f32 {
+        self.instances[entity].image_index
+    }
+    #[gml::set(image_index)]
+    pub fn set_image_index(&mut self, entity: vm::Entity, value: f32) {
+        self.instances[entity].image_index = value;
+    }
+
+    #[gml::get(image_speed)]
+    pub fn get_image_speed(&self, entity: vm::Entity) -> f32 {
+        self.instances[entity].image_speed
+    }
+    #[gml::set(image_speed)]
+    pub fn set_image_speed(&mut self, entity: vm::Entity, value: f32) {
+        self.instances[entity].image_speed = value;
+    }
+
+    #[gml::get(image_number)]
+    pub fn get_image_number(&self, assets: &Assets, entity: vm::Entity) -> u32 {
+        let sprite_index = self.instances[entity].sprite_index;
+        assets.sprite(sprite_index).map_or(0, |sprite| sprite.frames.len() as u32)
+    }
+
+    #[gml::get(image_xscale)]
+    pub fn get_image_xscale(&self, entity: vm::Entity) -> f32 {
+        self.instances[entity].image_xscale
+    }
+    #[gml::set(image_xscale)]
+    pub fn set_image_xscale(&mut self, entity: vm::Entity, value: f32) {
+        self.forget_scale(entity);
+        self.instances[entity].image_xscale = value;
+        self.count_scale(entity);
+    }
+
+    #[gml::get(image_yscale)]
+    pub fn get_image_yscale(&self, entity: vm::Entity) -> f32 {
+        self.instances[entity].image_yscale
+    }
+    #[gml::set(image_yscale)]
+    pub fn set_image_yscale(&mut self, entity: vm::Entity, value: f32) {
+        self.forget_scale(entity);
+        self.instances[entity].image_yscale = value;
+        self.count_scale(entity);
+    }
+
+    #[gml::get(image_angle)]
+    pub fn get_image_angle(&self, entity: vm::Entity) -> f32 {
+        self.instances[entity].image_angle
+    }
+    #[gml::set(image_angle)]
+    pub fn set_image_angle(&mut self, entity: vm::Entity, value: f32) {
+        self.instances[entity].image_angle = value;
+    }
+
+    #[gml::get(image_blend)]
+    pub fn get_image_blend(&self, entity: vm::Entity) -> u32 {
+        self.instances[entity].image_blend
+    }
+    #[gml::set(image_blend)]
+    pub fn set_image_blend(&mut self, entity: vm::Entity, value: u32) {
+        self.instances[entity].image_blend = value & 0xFFFFFF;
+    }
+
+    #[gml::get(image_alpha)]
+    pub fn get_image_alpha(&self, entity: vm::Entity) -> f32 {
+        self.instances[entity].image_alpha
+    }
+    #[gml::set(image_alpha)]
+    pub fn set_image_alpha(&mut self, entity: vm::Entity, value: f32) {
+        self.instances[entity].image_alpha = value;
+    }
+
+    #[gml::get(sprite_width)]
+    pub fn get_sprite_width(&self, assets: &Assets, entity: vm::Entity) -> f32 {
+        let instance = &self.instances[entity];
+        let width = assets.sprite(instance.sprite_index).map_or(0, Sprite::width);
+        width as f32 * instance.image_xscale
+    }
+
+    #[gml::get(sprite_height)]
+    pub fn get_sprite_height(&self, assets: &Assets, entity: vm::Entity) -> f32 {
+        let instance = &self.instances[entity];
+        let height = assets.sprite(instance.sprite_index).map_or(0, Sprite::height);
+        height as f32 * instance.image_yscale
+    }
+
+    #[gml::api]
+    pub fn sprite_exists(assets: &mut Assets, ind: i32) -> bool {
+        assets.sprite(ind).is_some()
+    }
+
+    #[gml::api]
+    pub fn sprite_get_name(assets: &mut Assets, ind: i32) -> Symbol {
+        assets.sprite(ind).map_or_else(Symbol::default, |sprite| sprite.name)
+    }
+
+    #[gml::api]
+    pub fn sprite_get_number(assets: &mut Assets, ind: i32) -> i32 {
+        assets.sprite(ind).map_or(-1, |sprite| sprite.frames.len() as i32)
+    }
+
+    #[gml::api]
+    pub fn sprite_get_width(assets: &mut Assets, ind: i32) -> i32 {
+        assets.sprite(ind).map_or(-1, |sprite| sprite.width() as i32)
+    }
+
+    #[gml::api]
+    pub fn sprite_get_height(assets: &mut Assets, ind: i32) -> i32 {
+        assets.sprite(ind).map_or(-1, |sprite| sprite.height() as i32)
+    }
+
+    #[gml::api]
+    pub fn sprite_get_xoffset(assets: &mut Assets, ind: i32) -> i32 {
+        assets.sprite(ind).map_or(-1, |sprite| sprite.origin_x)
+    }
+
+    #[gml::api]
+    pub fn sprite_get_yoffset(assets: &mut Assets, ind: i32) -> i32 {
+        assets.sprite(ind).map_or(-1, |sprite| sprite.origin_y)
+    }
+
+    #[gml::api]
+    pub fn sprite_get_bbox_left(assets: &mut Assets, ind: i32) -> i32 {
+        assets.sprite(ind).map_or(-1, |sprite| sprite.bbox_left)
+    }
+
+    #[gml::api]
+    pub fn sprite_get_bbox_right(assets: &mut Assets, ind: i32) -> i32 {
+        assets.sprite(ind).map_or(-1, |sprite| sprite.bbox_right)
+    }
+
+    #[gml::api]
+    pub fn sprite_get_bbox_top(assets: &mut Assets, ind: i32) -> i32 {
+        assets.sprite(ind).map_or(-1, |sprite| sprite.bbox_top)
+    }
+
+    #[gml::api]
+    pub fn sprite_get_bbox_bottom(assets: &mut Assets, ind: i32) -> i32 {
+        assets.sprite(ind).map_or(-1, |sprite| sprite.bbox_bottom)
+    }
+
+    /// Load a sprite from a PNG strip of `imgnumb` frames, returning its index or -1.
+    ///
+    /// An `imgnumb` below 1 loads a single frame, and one above the width of the image fails.
+    ///
+    /// When `transparent` is set, pixels matching the bottom left pixel are made transparent.
+    // TODO: support the other image formats GM8 accepts, such as BMP and GIF.
+    #[gml::api]
+    #[allow(clippy::too_many_arguments)]
+    pub fn sprite_add(
+        assets: &mut Assets, replay: &mut replay::State, fname: Symbol, imgnumb: i32,
+        precise: bool, transparent: bool, _smooth: bool, _preload: bool, xorig: i32, yorig: i32
+    ) -> vm::Result<i32> {
+        let data = match replay.read_file(&fname[..])? {
+            Some(data) => data,
+            None => return Ok(-1),
+        };
+        let (width, height, mut pixels) = match read_png(&data[..]) {
+            Ok(image) => image,
+            Err(_) => return Ok(-1),
+        };
+
+        if transparent && width > 0 && height > 0 {
+            let offset = ((height - 1) * width * 4) as usize;
+            let mut key = [0; 3];
+            key.copy_from_slice(&pixels[offset..offset + 3]);
+            for pixel in pixels.chunks_mut(4) {
+                if pixel[0..3] == key {
+                    pixel[3] = 0;
+                }
+            }
+        }
+
+        // Split the image into equal horizontal strips, as GM8 does.
+        let count = cmp::max(imgnumb, 1) as u32;
+        if count > width {
+            return Ok(-1);
+        }
+        let frame_width = width / count;
+        let frames = (0..count)
+            .map(|i| {
+                let mut data = Vec::with_capacity((frame_width * height * 4) as usize);
+                for y in 0..height {
+                    let start = ((y * width + i * frame_width) * 4) as usize;
+                    data.extend_from_slice(&pixels[start..start + frame_width as usize * 4]);
+                }
+                Frame { width: frame_width, height, data }
+            })
+            .collect();
+
+        let name = Symbol::intern(format!("__newsprite{}", assets.sprites.len()).as_bytes());
+        let mut sprite = Sprite {
+            name,
+            origin_x: xorig,
+            origin_y: yorig,
+            frames,
+            masks: Vec::default(),
+            bbox_left: 0,
+            bbox_right: 0,
+            bbox_top: 0,
+            bbox_bottom: 0,
+        };
+        sprite.update_bbox();
+        if precise {
+            sprite.masks = Mask::from_frames(&sprite.frames, true, 0);
+        }
+
+        Ok(assets.add_sprite(sprite))
+    }
+
+    #[gml::api]
+    pub fn sprite_duplicate(assets: &mut Assets, ind: i32) -> i32 {
+        let sprite = match assets.sprite(ind) {
+            Some(sprite) => sprite.clone(),
+            None => return -1,
+        };
+        assets.add_sprite(sprite)
+    }
+
+    /// Advance each instance's subimage by its `image_speed`.
+    ///
+    /// Instances whose animation wraps around run their animation end event.
+    pub fn animate(cx: &mut Context, thread: &mut vm::Thread) -> vm::Result<()> {
+        use project::{event_type, event_kind};
+
+        let entities = cx.world.world.instances.values().to_vec();
+        for entity in entities {
+            let Context { world, assets } = cx;
+            let crate::World { world, instance, sprite, .. } = world;
+            let inst = &instance.instances[entity];
+            if !world.instances.contains_key(inst.id) {
+                continue;
+            }
+
+            let image = &mut sprite.instances[entity];
+            let number = match assets.sprite(image.sprite_index) {
+                Some(sprite) if !sprite.frames.is_empty() => sprite.frames.len() as f32,
+                _ => continue,
+            };
+            image.image_index += image.image_speed;
+            if image.image_index >= number {
+                image.image_index -= number;
+            } else if image.image_index < 0.0 {
+                image.image_index += number;
+            } else {
+                continue;
+            }
+
+            let end = event_kind::ANIMATION_END;
+            if let Some(event) = assets.find_event(inst.object_index, event_type::OTHER, end) {
+                replay::State::execute(cx, &mut thread.with(entity), event)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Sprite {
+    pub fn width(&self) -> u32 { self.frames.first().map_or(0, |frame| frame.width) }
+    pub fn height(&self) -> u32 { self.frames.first().map_or(0, |frame| frame.height) }
+
+    /// Compute the bounding box of every frame's opaque pixels.
+    fn update_bbox(&mut self) {
+        let (mut left, mut top) = (self.width() as i32, self.height() as i32);
+        let (mut right, mut bottom) = (-1, -1);
+        for frame in &self.frames {
+            for (i, pixel) in frame.data.chunks(4).enumerate() {
+                if pixel[3] == 0 {
+                    continue;
+                }
+                let (x, y) = ((i as u32 % frame.width) as i32, (i as u32 / frame.width) as i32);
+                left = i32::min(left, x);
+                right = i32::max(right, x);
+                top = i32::min(top, y);
+                bottom = i32::max(bottom, y);
+            }
+        }
+
+        // Fully transparent sprites get an empty box at the origin.
+        if right < left || bottom < top {
+            left = 0;
+            right = 0;
+            top = 0;
+            bottom = 0;
+        }
+        self.bbox_left = left;
+        self.bbox_right = right;
+        self.bbox_top = top;
+        self.bbox_bottom = bottom;
+    }
+}
+
+impl Mask {
+    /// Build masks from the pixels more opaque than `alpha_tolerance`, either one per frame or
+    /// one covering every frame.
+    pub fn from_frames(frames: &[Frame], separate: bool, alpha_tolerance: u32) -> Vec<Mask> {
+        let from_frame = |frame: &Frame| Mask {
+            width: frame.width,
+            height: frame.height,
+            data: frame.data.chunks(4).map(|pixel| pixel[3] as u32 > alpha_tolerance).collect(),
+        };
+        if separate {
+            return frames.iter().map(from_frame).collect();
+        }
+
+        let width = frames.iter().map(|frame| frame.width).max().unwrap_or(0);
+        let height = frames.iter().map(|frame| frame.height).max().unwrap_or(0);
+        let mut mask = Mask { width, height, data: vec![false; (width * height) as usize] };
+        for frame in frames.iter().map(from_frame) {
+            for (i, &solid) in frame.data.iter().enumerate() {
+                let (x, y) = (i as u32 % frame.width, i as u32 / frame.width);
+                mask.data[(y * width + x) as usize] |= solid;
+            }
+        }
+        vec![mask]
+    }
+
+    pub fn from_project(mask: &project::Mask<'_>) -> Mask {
+        Mask {
+            width: mask.width,
+            height: mask.height,
+            data: mask.data.chunks(4).map(|pixel| pixel != [0; 4]).collect(),
+        }
+    }
+}
+
+/// Decode a PNG file into 8-bit RGBA pixels.
+fn read_png(data: &[u8]) -> io::Result<(u32, u32, Vec<u8>)> {
+    use png::{ColorType, BitDepth, Transformations};
+
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+    let (info, mut reader) = decoder.read_info()?;
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf)?;
+    if info.bit_depth != BitDepth::Eight {
+        return Err(io::ErrorKind::InvalidData.into());
+    }
+
+    let pixels = match info.color_type {
+        ColorType::RGBA => buf,
+        ColorType::RGB => buf.chunks(3).flat_map(|p| vec![p[0], p[1], p[2], 255]).collect(),
+        ColorType::Grayscale => buf.iter().flat_map(|&v| vec![v, v, v, 255]).collect(),
+        ColorType::GrayscaleAlpha => buf.chunks(2).flat_map(|p| vec![p[0], p[0], p[0], p[1]])
+            .collect(),
+        ColorType::Indexed => return Err(io::ErrorKind::InvalidData.into()),
+    };
+    Ok((info.width, info.height, pixels))
+}
+
+impl vm::Serialize for State {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        out.write(&self.instances)
+    }
+}
+
+impl vm::Deserialize for State {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        let instances: vm::EntityMap<Instance> = input.read()?;
+        let mut scales = BTreeMap::default();
+        for (_, instance) in instances.iter() {
+            *scales.entry(scale(instance)).or_default() += 1;
+        }
+        Ok(State { instances, scales })
+    }
+}
+
+impl vm::Serialize for Instance {
+    fn serialize<W: io::Write>(&self, out: &mut vm::Serializer<W>) -> io::Result<()> {
+        out.write(&self.sprite_index)?;
+        out.write(&self.image_index)?;
+        out.write(&self.image_speed)?;
+        out.write(&self.image_xscale)?;
+        out.write(&self.image_yscale)?;
+        out.write(&self.image_angle)?;
+        out.write(&self.image_blend)?;
+        out.write(&self.image_alpha)
+    }
+}
+
+impl vm::Deserialize for Instance {
+    fn deserialize<R: io::Read>(input: &mut vm::Deserializer<R>) -> io::Result<Self> {
+        Ok(Instance {
+            sprite_index: input.read()?,
+            image_index: input.read()?,
+            image_speed: input.read()?,
+            image_xscale: input.read()?,
+            image_yscale: input.read()?,
+            image_angle: input.read()?,
+            image_blend: input.read()?,
+            image_alpha: input.read()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_scale() {
+        let mut entities = vm::EntityAllocator::default();
+        let (a, b) = (entities.create(), entities.create());
+        let mut state = State::default();
+        state.insert(a, Instance::from_sprite(0));
+        state.insert(b, Instance::from_sprite(0));
+        assert_eq!(state.max_scale(), 1.0);
+
+        state.set_image_xscale(a, -40.0);
+        assert_eq!(state.max_scale(), 40.0);
+        state.set_image_yscale(b, 2.0);
+        assert_eq!(state.max_scale(), 40.0);
+
+        state.set_image_xscale(a, 1.0);
+        assert_eq!(state.max_scale(), 2.0);
+        state.remove(b);
+        assert_eq!(state.max_scale(), 1.0);
+    }
+}
@@ -10,11 +10,18 @@ pub struct World {
     pub real: real::State,
     pub string: string::State,
     pub motion: motion::State,
+    pub alarm: alarm::State,
+    pub sprite: sprite::State,
+    pub collision: collision::State,
+    pub input: input::State,
     pub instance: instance::State,
     pub room: room::State,
+    pub draw: draw::State,
     pub show: show::State,
     pub control: control::State,
     pub data: data::State,
+    pub replay: replay::State,
+    pub save: save::State,
 }
 
 impl<'r> vm::Project<'r, (&'r mut vm::World,)> for Context {
@@ -30,12 +37,32 @@ impl<'r> vm::Project<'r, (&'r mut vm::World, &'r mut vm::Assets<Self>)> for Cont
     }
 }
 
+impl<'r> vm::Project<'r, (&'r mut Assets,)> for Context {
+    fn fields(&'r mut self) -> (&'r mut Assets,) {
+        let Context { assets, .. } = self;
+        (assets,)
+    }
+}
+
+impl<'r> vm::Project<'r, (&'r mut Assets, &'r mut replay::State)> for Context {
+    fn fields(&'r mut self) -> (&'r mut Assets, &'r mut replay::State) {
+        let Context { world, assets } = self;
+        (assets, &mut world.replay)
+    }
+}
+
 impl<'r> vm::Project<'r, (&'r mut real::State,)> for Context {
     fn fields(&'r mut self) -> (&'r mut real::State,) {
         let Context { world, .. } = self;
         (&mut world.real,)
     }
 }
+impl<'r> vm::Project<'r, (&'r mut real::State, &'r mut replay::State)> for Context {
+    fn fields(&'r mut self) -> (&'r mut real::State, &'r mut replay::State) {
+        let Context { world, .. } = self;
+        (&mut world.real, &mut world.replay)
+    }
+}
 
 impl<'r> vm::Project<'r, (&'r mut string::State,)> for Context {
     fn fields(&'r mut self) -> (&'r mut string::State,) {
@@ -51,6 +78,40 @@ impl<'r> vm::Project<'r, (&'r mut motion::State,)> for Context {
     }
 }
 
+impl<'r> vm::Project<'r, (&'r mut alarm::State,)> for Context {
+    fn fields(&'r mut self) -> (&'r mut alarm::State,) {
+        let Context { world, .. } = self;
+        (&mut world.alarm,)
+    }
+}
+
+impl<'r> vm::Project<'r, (&'r mut sprite::State,)> for Context {
+    fn fields(&'r mut self) -> (&'r mut sprite::State,) {
+        let Context { world, .. } = self;
+        (&mut world.sprite,)
+    }
+}
+impl<'r> vm::Project<'r, (&'r mut sprite::State, &'r mut Assets)> for Context {
+    fn fields(&'r mut self) -> (&'r mut sprite::State, &'r mut Assets) {
+        let Context { world, assets } = self;
+        (&mut world.sprite, assets)
+    }
+}
+
+impl<'r> vm::Project<'r, (&'r mut collision::State,)> for Context {
+    fn fields(&'r mut self) -> (&'r mut collision::State,) {
+        let Context { world, .. } = self;
+        (&mut world.collision,)
+    }
+}
+
+impl<'r> vm::Project<'r, (&'r mut input::State,)> for Context {
+    fn fields(&'r mut self) -> (&'r mut input::State,) {
+        let Context { world, .. } = self;
+        (&mut world.input,)
+    }
+}
+
 impl<'r> vm::Project<'r, (&'r mut instance::State,)> for Context {
     fn fields(&'r mut self) -> (&'r mut instance::State,) {
         let Context { world, .. } = self;
@@ -64,6 +125,20 @@ impl<'r> vm::Project<'r, (&'r mut instance::State, &'r mut vm::World)> for Conte
     }
 }
 
+impl<'r> vm::Project<'r, (&'r mut room::State,)> for Context {
+    fn fields(&'r mut self) -> (&'r mut room::State,) {
+        let Context { world, .. } = self;
+        (&mut world.room,)
+    }
+}
+
+impl<'r> vm::Project<'r, (&'r mut draw::State,)> for Context {
+    fn fields(&'r mut self) -> (&'r mut draw::State,) {
+        let Context { world, .. } = self;
+        (&mut world.draw,)
+    }
+}
+
 impl<'r> vm::Project<'r, (&'r mut show::State,)> for Context {
     fn fields(&'r mut self) -> (&'r mut show::State,) {
         let Context { world, .. } = self;
@@ -78,6 +153,20 @@ impl<'r> vm::Project<'r, (&'r mut data::State,)> for Context {
     }
 }
 
+impl<'r> vm::Project<'r, (&'r mut save::State,)> for Context {
+    fn fields(&'r mut self) -> (&'r mut save::State,) {
+        let Context { world, .. } = self;
+        (&mut world.save,)
+    }
+}
+
+impl<'r> vm::Project<'r, (&'r mut replay::State,)> for Context {
+    fn fields(&'r mut self) -> (&'r mut replay::State,) {
+        let Context { world, .. } = self;
+        (&mut world.replay,)
+    }
+}
+
 impl World {
     pub fn from_assets(assets: &crate::Assets, debug: vm::Debug) -> Self {
         let mut world = Self::default();
@@ -90,9 +179,17 @@ impl World {
         real::State::register(items);
         string::State::register(items);
         motion::State::register(items);
+        alarm::State::register(items);
+        sprite::State::register(items);
+        collision::State::register(items);
+        input::State::register(items);
         instance::State::register(items);
+        room::State::register(items);
+        draw::State::register(items);
         show::State::register(items);
         control::State::register(items);
         data::State::register(items);
+        replay::State::register(items);
+        save::State::register(items);
     }
 }